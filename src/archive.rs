@@ -0,0 +1,119 @@
+//! Archive creation helpers shared by the distribution targets.
+
+use std::path::Path;
+
+use walkdir::WalkDir;
+
+use crate::{staging::format_size, Error, ErrorKind, Result};
+
+/// Create a zip archive at `archive_path` with the whole content of `root`.
+///
+/// Paths in the archive are relative to `root` and Unix permissions are
+/// preserved, when supported by the platform. Symbolic links are stored as
+/// links on these platforms as well, and followed on the others. Files over
+/// 4 GiB are stored with the zip64 extensions.
+///
+/// Files larger than `max_file_size`, if specified, are rejected before they
+/// are archived.
+pub(crate) fn build_zip_archive(
+    root: &Path,
+    archive_path: &Path,
+    max_file_size: Option<u64>,
+) -> Result<()> {
+    let mut archive = zip::ZipWriter::new(
+        std::fs::File::create(archive_path)
+            .map_err(|err| Error::new("failed to create zip archive file").with_source(err))?,
+    );
+
+    for entry in WalkDir::new(root) {
+        let entry =
+            entry.map_err(|err| Error::new("failed to walk root directory").with_source(err))?;
+
+        let file_path = entry
+            .path()
+            .strip_prefix(root)
+            .map_err(|err| Error::new("failed to strip root directory").with_source(err))?
+            .display()
+            .to_string();
+
+        if entry.path_is_symlink() && cfg!(unix) {
+            let target = std::fs::read_link(entry.path())
+                .map_err(|err| Error::new("failed to read symbolic link").with_source(err))?;
+
+            let target = target.to_str().ok_or_else(|| {
+                Error::new("failed to add symbolic link to the archive").with_explanation(format!(
+                    "The target of the symbolic link `{}` is not valid UTF-8.",
+                    file_path
+                ))
+            })?;
+
+            archive
+                .add_symlink(&file_path, target, zip::write::FileOptions::default())
+                .map_err(|err| {
+                    Error::new("failed to add symbolic link to the archive")
+                        .with_source(err)
+                        .with_output(format!("file path: {}", file_path))
+                })?;
+
+            continue;
+        }
+
+        let metadata = std::fs::metadata(entry.path())
+            .map_err(|err| Error::new("failed to get metadata").with_source(err))?;
+
+        let options = zip::write::FileOptions::default();
+
+        #[cfg(not(windows))]
+        let options = {
+            use std::os::unix::prelude::PermissionsExt;
+
+            options.unix_permissions(metadata.permissions().mode())
+        };
+
+        if metadata.is_file() {
+            if let Some(max_file_size) = max_file_size {
+                if metadata.len() > max_file_size {
+                    return Err(Error::new("file too large for the archive")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_explanation(format!(
+                            "The file `{}` weighs {}, which exceeds the limit of {} per file. Exclude it from the archive or reduce its size, for instance by stripping the binaries.",
+                            file_path,
+                            format_size(metadata.len()),
+                            format_size(max_file_size)
+                        )));
+                }
+            }
+
+            // The zip writer requires zip64 for the files whose uncompressed
+            // size exceeds 4 GiB.
+            let options = options.large_file(metadata.len() >= u64::from(u32::MAX));
+
+            archive.start_file(&file_path, options).map_err(|err| {
+                Error::new("failed to start writing file in the archive")
+                    .with_source(err)
+                    .with_output(format!("file path: {}", file_path))
+            })?;
+
+            let mut file = std::fs::File::open(entry.path())
+                .map_err(|err| Error::new("failed to open file").with_source(err))?;
+
+            std::io::copy(&mut file, &mut archive).map_err(|err| {
+                Error::new("failed to write file in the archive")
+                    .with_source(err)
+                    .with_output(format!("file path: {}", file_path))
+            })?;
+        } else if metadata.is_dir() {
+            archive.add_directory(&file_path, options).map_err(|err| {
+                Error::new("failed to add directory to the archive")
+                    .with_source(err)
+                    .with_output(format!("file path: {}", file_path))
+            })?;
+        }
+    }
+
+    archive
+        .finish()
+        .map_err(|err| Error::new("failed to write zip archive file").with_source(err))?;
+
+    Ok(())
+}
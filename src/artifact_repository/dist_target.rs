@@ -0,0 +1,537 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use md5::Md5;
+use reqwest::Method;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    action_step,
+    archive::build_zip_archive,
+    dist_target::{check_channel_name, clean_dir, copy_extra_files, published_artifact},
+    ignore_step,
+    metadata::{artifact_name_context, CopyPlan, Template},
+    rust::build_binaries,
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, Result,
+};
+
+use super::{ArtifactRepositoryKind, ArtifactRepositoryMetadata};
+
+pub struct ArtifactRepositoryDistTarget<'g> {
+    pub name: String,
+    pub package: &'g Package<'g>,
+    pub metadata: ArtifactRepositoryMetadata,
+}
+
+impl Display for ArtifactRepositoryDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => {
+                write!(
+                    f,
+                    "artifact-repository[{}:{}]",
+                    self.package.name(),
+                    target_runtime
+                )
+            }
+            None => write!(f, "artifact-repository[{}]", self.package.name()),
+        }
+    }
+}
+
+/// The checksums of an uploaded file, in lowercase hexadecimal.
+struct Checksums {
+    sha1: String,
+    sha256: String,
+    md5: String,
+}
+
+impl Checksums {
+    fn new(data: &[u8]) -> Self {
+        Self {
+            sha1: format!("{:x}", Sha1::digest(data)),
+            sha256: format!("{:x}", Sha256::digest(data)),
+            md5: format!("{:x}", Md5::digest(data)),
+        }
+    }
+}
+
+impl<'g> ArtifactRepositoryDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    pub fn build(&self) -> Result<()> {
+        clean_dir(&self.archive_root(), "archive root directory")?;
+
+        let copy_plan = self.copy_plan()?;
+        let binaries = build_binaries(
+            self.package,
+            &self.name,
+            self.metadata.target_runtime.as_str(),
+            false,
+        )?;
+        let binaries = self.copy_binaries(&binaries)?;
+        copy_extra_files(self.package, &copy_plan, &binaries)?;
+
+        if self.context().options().no_image {
+            ignore_step!(
+                "Skipping",
+                "archive packaging as `--no-image` was specified"
+            );
+            return Ok(());
+        }
+
+        action_step!("Packaging", "archive `{}`", self.archive_name()?);
+
+        build_zip_archive(&self.archive_root(), &self.archive_path()?, None)
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        if self.context().options().mode.is_debug() && !self.context().options().force {
+            ignore_step!(
+                "Unsupported",
+                "Archives can't be published in debug mode unless `--force` is specified"
+            );
+            return Ok(None);
+        }
+
+        let sha256 = self.upload_archive()?;
+
+        if self.context().options().dry_run {
+            return Ok(None);
+        }
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            self.artifact_reference()?,
+            sha256.as_ref().map(|sha256| format!("sha256:{}", sha256)),
+            sha256.is_none(),
+        )))
+    }
+
+    /// Check whether the archive for the current version exists in the
+    /// artifact repository.
+    pub async fn exists(&self) -> Result<bool> {
+        let url = self.artifact_reference()?;
+
+        let response = self
+            .request(Method::HEAD, &url)?
+            .send()
+            .await
+            .map_err(|err| {
+                Error::new("failed to check for archive existence")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "Could not reach the artifact repository at `{}`.",
+                        &url
+                    ))
+            })?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(Error::new("failed to check for archive existence")
+                .with_kind(ErrorKind::Network)
+                .with_explanation(format!(
+                    "The artifact repository answered with status `{}` for `{}`. Please check \
+                    your credentials and make sure you have the appropriate permissions.",
+                    status, &url
+                ))),
+        }
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(self.artifact_url(&self.artifact_path()?))
+    }
+
+    /// Promote the current version of the archive to the specified channel.
+    ///
+    /// As there is no portable way of copying artifacts server-side, the
+    /// archive is downloaded and uploaded again under the channel path.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        check_channel_name(channel)?;
+
+        let source_url = self.artifact_reference()?;
+        let destination_url = self.artifact_url(&self.artifact_channel_path(channel)?);
+
+        if self.context().options().dry_run {
+            warn!(
+                "`--dry-run` specified, will not really copy `{}` to `{}`",
+                source_url, destination_url
+            );
+
+            return Ok(());
+        }
+
+        action_step!(
+            "Promoting",
+            "archive `{}` to `{}`",
+            &source_url,
+            &destination_url
+        );
+
+        self.context().block_on(async move {
+            let data = self
+                .request(Method::GET, &source_url)?
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|err| {
+                    Error::new("failed to download archive from artifact repository")
+                        .with_kind(ErrorKind::Network)
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "Please check that the archive `{}` was published and that you have the correct permissions.",
+                            &source_url
+                        ))
+                })?
+                .bytes()
+                .await
+                .map_err(|err| {
+                    Error::new("failed to download archive from artifact repository")
+                        .with_kind(ErrorKind::Network)
+                        .with_source(err)
+                })?;
+
+            let checksums = Checksums::new(&data);
+
+            self.upload(&destination_url, data.to_vec(), &checksums)
+                .await
+        })
+    }
+
+    /// Upload the archive to the artifact repository.
+    ///
+    /// Returns the SHA-256 checksum of the archive, or `None` if the archive
+    /// already existed in the repository and was not uploaded.
+    fn upload_archive(&self) -> Result<Option<String>> {
+        let url = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!("`--force` specified: not checking for the archive existence before uploading");
+        } else {
+            if self.context().artifact_exists(&url, self.exists())? {
+                debug!("Archive `{}` already exists: not uploading again", &url);
+
+                ignore_step!("Up-to-date", "archive `{}` already exists", &url);
+
+                return Ok(None);
+            }
+
+            debug!("Archive `{}` does not exist: uploading.", &url);
+        }
+
+        let archive_path = self.archive_path()?;
+        let data = std::fs::read(&archive_path)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read archive on disk",
+                format!(
+                    "The archive `{}` could not be read. Has this target been built before attempting its publication?",
+                    archive_path.display()
+                ),
+            )?;
+
+        let checksums = Checksums::new(&data);
+
+        if self.context().options().dry_run {
+            warn!("`--dry-run` specified, will not really upload the archive to the artifact repository");
+        } else {
+            action_step!("Uploading", "archive `{}`", &url);
+
+            self.context()
+                .block_on(self.upload(&url, data, &checksums))?;
+        }
+
+        Ok(Some(checksums.sha256))
+    }
+
+    /// Upload a file along with its checksums.
+    ///
+    /// Artifactory verifies the checksums sent as headers and rejects corrupted
+    /// uploads. Nexus ignores them, so they are also uploaded as sidecar files
+    /// for consumers to verify against.
+    async fn upload(&self, url: &str, data: Vec<u8>, checksums: &Checksums) -> Result<()> {
+        self.request(Method::PUT, url)?
+            .header("X-Checksum-Sha1", &checksums.sha1)
+            .header("X-Checksum-Sha256", &checksums.sha256)
+            .header("X-Checksum-Md5", &checksums.md5)
+            .body(data)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| self.upload_error(url, err))?;
+
+        if self.metadata.kind == ArtifactRepositoryKind::Nexus {
+            for (extension, checksum) in [
+                ("sha1", &checksums.sha1),
+                ("sha256", &checksums.sha256),
+                ("md5", &checksums.md5),
+            ] {
+                let checksum_url = format!("{}.{}", url, extension);
+
+                self.request(Method::PUT, &checksum_url)?
+                    .body(checksum.clone())
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|err| self.upload_error(&checksum_url, err))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn upload_error(&self, url: &str, err: reqwest::Error) -> Error {
+        Error::new("failed to upload to artifact repository")
+            .with_kind(ErrorKind::Network)
+            .with_source(err)
+            .with_explanation(format!(
+                "Could not upload `{}`. Please check that the repository `{}` exists and that you have the correct permissions.",
+                url, &self.metadata.repository
+            ))
+    }
+
+    fn request(&self, method: Method, url: &str) -> Result<reqwest::RequestBuilder> {
+        let request = reqwest::Client::new().request(method, url);
+
+        Ok(match self.credentials()? {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        })
+    }
+
+    fn credentials(&self) -> Result<Option<(String, String)>> {
+        match (&self.metadata.username_env, &self.metadata.password_env) {
+            (Some(username_env), Some(password_env)) => Ok(Some((
+                read_env_var(username_env)?,
+                read_env_var(password_env)?,
+            ))),
+            (None, None) => Ok(None),
+            _ => Err(Error::new("incomplete artifact repository credentials")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation("Both `username_env` and `password_env` must be specified to authenticate against the artifact repository.")),
+        }
+    }
+
+    fn artifact_url(&self, path: &str) -> String {
+        artifact_url(
+            self.metadata.kind,
+            &self.metadata.url,
+            &self.metadata.repository,
+            path,
+        )
+    }
+
+    fn artifact_path(&self) -> Result<String> {
+        Ok(format!(
+            "{}{}/{}/{}",
+            &self.metadata.path_prefix,
+            self.package.name(),
+            self.package.version(),
+            self.archive_name()?
+        ))
+    }
+
+    fn artifact_channel_path(&self, channel: &str) -> Result<String> {
+        let archive_name = match &self.metadata.archive_name {
+            Some(archive_name) => self.render_archive_name(archive_name, channel)?,
+            None => format!(
+                "{}-{}.zip",
+                self.package.name(),
+                self.metadata.target_runtime.as_str()
+            ),
+        };
+
+        Ok(format!(
+            "{}{}/{}/{}",
+            &self.metadata.path_prefix,
+            self.package.name(),
+            channel,
+            archive_name
+        ))
+    }
+
+    fn archive_name(&self) -> Result<String> {
+        match &self.metadata.archive_name {
+            Some(archive_name) => {
+                self.render_archive_name(archive_name, &self.package.version().to_string())
+            }
+            None => Ok(format!(
+                "{}-{}-{}.zip",
+                self.package.name(),
+                self.package.version(),
+                self.metadata.target_runtime.as_str()
+            )),
+        }
+    }
+
+    /// Render the `archive_name` template for the specified version or
+    /// channel.
+    fn render_archive_name(&self, archive_name: &Template, version: &str) -> Result<String> {
+        let mut context =
+            artifact_name_context(self.package, self.metadata.target_runtime.as_str());
+        context.insert("version", version);
+
+        let archive_name = archive_name.render_for_package(self.package, &context)?;
+
+        if archive_name.is_empty() || archive_name.contains('/') {
+            return Err(Error::new("invalid archive name")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The archive name `{}` rendered by the `archive_name` template of the distribution {} must be non-empty and cannot contain `/`.",
+                    archive_name, self
+                )));
+        }
+
+        Ok(archive_name)
+    }
+
+    fn copy_binaries(&self, binaries: &HashMap<String, PathBuf>) -> Result<Vec<PathBuf>> {
+        debug!("Will now copy the binaries");
+
+        let archive_root = self.archive_root();
+
+        std::fs::create_dir_all(&archive_root)
+            .map_err(Error::from_source)
+            .with_full_context(
+        "could not create the archive root",
+        format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", archive_root.display()),
+            )?;
+
+        let names: Vec<&String> = if self.metadata.binaries.is_empty() {
+            binaries.keys().collect()
+        } else {
+            self.metadata.binaries.iter().collect()
+        };
+
+        names
+            .into_iter()
+            .map(|name| {
+                let source = binaries.get(name).ok_or_else(|| {
+                    Error::new("failed to find the specified binary in the binaries list")
+                        .with_explanation(format!("The configuration requires the archive to contain the `{}` binary but no such binary is declared in the crate. Was the name perhaps mistyped?", name))
+                })?;
+
+                copy_binary(source, &archive_root)
+            })
+            .collect()
+    }
+
+    fn target_dir(&self) -> PathBuf {
+        self.context()
+            .target_root()
+            .unwrap()
+            .join(self.metadata.target_runtime.as_str())
+            .join(self.context().options().mode.to_string())
+            .join("artifact-repository")
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.archive_root()
+    }
+
+    fn archive_root(&self) -> PathBuf {
+        self.target_dir().join(self.package.name())
+    }
+
+    fn archive_path(&self) -> Result<PathBuf> {
+        Ok(self.target_dir().join(self.archive_name()?))
+    }
+
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &self.archive_root(),
+        )
+    }
+}
+
+/// The URL of a path in a repository, which Nexus serves under
+/// `/repository`.
+fn artifact_url(kind: ArtifactRepositoryKind, url: &str, repository: &str, path: &str) -> String {
+    let url = url.trim_end_matches('/');
+
+    match kind {
+        ArtifactRepositoryKind::Artifactory => format!("{}/{}/{}", url, repository, path),
+        ArtifactRepositoryKind::Nexus => format!("{}/repository/{}/{}", url, repository, path),
+    }
+}
+
+fn copy_binary(source: &Path, archive_root: &Path) -> Result<PathBuf> {
+    let target = archive_root.join(source.file_name().unwrap());
+
+    debug!("Copying {} to {}", source.display(), target.display());
+
+    std::fs::copy(source, &target)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to copy binary",
+            format!(
+                "The binary `{}` could not be copied to the archive root. Has this target been built before attempting its packaging?",
+                source.display(),
+            ),
+        )?;
+
+    Ok(target)
+}
+
+fn read_env_var(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|err| {
+        Error::new("failed to read artifact repository credentials")
+            .with_source(err)
+            .with_explanation(format!(
+                "The environment variable `{}` must be set as it is referenced by the artifact repository credentials.",
+                name
+            ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        let checksums = Checksums::new(b"hello");
+
+        assert_eq!(checksums.sha1, "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d");
+        assert_eq!(
+            checksums.sha256,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(checksums.md5, "5d41402abc4b2a76b9719d911017c592");
+    }
+
+    #[test]
+    fn test_artifact_url() {
+        assert_eq!(
+            artifact_url(
+                ArtifactRepositoryKind::Artifactory,
+                "https://artifactory.example.com/artifactory/",
+                "releases",
+                "my-tool/1.0.0/my-tool.zip"
+            ),
+            "https://artifactory.example.com/artifactory/releases/my-tool/1.0.0/my-tool.zip"
+        );
+        assert_eq!(
+            artifact_url(
+                ArtifactRepositoryKind::Nexus,
+                "https://nexus.example.com",
+                "releases",
+                "my-tool/1.0.0/my-tool.zip"
+            ),
+            "https://nexus.example.com/repository/releases/my-tool/1.0.0/my-tool.zip"
+        );
+    }
+}
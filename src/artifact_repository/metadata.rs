@@ -0,0 +1,67 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    artifact_repository::ArtifactRepositoryDistTarget,
+    condition::Enabled,
+    dist_target::DistTarget,
+    metadata::{CopyCommand, TargetRuntime, Template},
+    Package,
+};
+
+/// The flavor of a generic artifact repository.
+///
+/// The flavor determines the layout of the upload URLs and how checksums are
+/// transmitted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactRepositoryKind {
+    Artifactory,
+    Nexus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ArtifactRepositoryMetadata {
+    pub kind: ArtifactRepositoryKind,
+    pub url: String,
+    pub repository: String,
+    #[serde(default)]
+    pub path_prefix: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_name: Option<Template>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    #[serde(default = "default_target_runtime")]
+    pub target_runtime: TargetRuntime,
+    #[serde(default)]
+    pub binaries: Vec<String>,
+    #[serde(default)]
+    pub extra_files: Vec<CopyCommand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+fn default_target_runtime() -> TargetRuntime {
+    TargetRuntime::new("x86_64-unknown-linux-gnu")
+}
+
+impl ArtifactRepositoryMetadata {
+    pub(crate) fn into_dist_target<'g>(
+        self,
+        name: String,
+        package: &'g Package<'g>,
+    ) -> DistTarget<'g> {
+        DistTarget::ArtifactRepository(ArtifactRepositoryDistTarget {
+            name,
+            package,
+            metadata: self,
+        })
+    }
+}
@@ -0,0 +1,5 @@
+mod dist_target;
+mod metadata;
+
+pub use dist_target::ArtifactRepositoryDistTarget;
+pub use metadata::{ArtifactRepositoryKind, ArtifactRepositoryMetadata};
@@ -0,0 +1,209 @@
+//! Audit log of the publication actions, to satisfy release audit
+//! requirements.
+
+use std::{io::Write, path::PathBuf};
+
+use aws_smithy_types::date_time::{DateTime, Format};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action_step, aws::AwsSettings, dist_target::DistTarget, prune::unix_timestamp, Context, Error,
+    ErrorContext, ErrorKind, Package, Provenance, PublishedArtifact, Result,
+};
+
+/// The audit log configuration of the workspace, read from the
+/// `[workspace.metadata.monorepo.audit_log]` section of the root manifest.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditLogMetadata {
+    /// The path of the audit log, relative to the root of the workspace.
+    pub path: PathBuf,
+    /// The S3 bucket the entries of each run are uploaded to, if any.
+    #[serde(default)]
+    pub s3_bucket: Option<String>,
+    #[serde(default)]
+    pub s3_bucket_prefix: String,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// A publication action, as recorded in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    /// The user that triggered the publication, when known.
+    pub actor: Option<String>,
+    pub package: String,
+    pub version: semver::Version,
+    pub package_hash: String,
+    pub dist_target: String,
+    /// The reference of the published artifact.
+    pub destination: String,
+    pub digest: Option<String>,
+    pub dry_run: bool,
+    /// Whether the artifact existed already, in which case nothing was
+    /// published.
+    pub up_to_date: bool,
+    pub git_commit: Option<String>,
+    pub ci_run_url: Option<String>,
+}
+
+impl AuditEntry {
+    /// The entry of the publication of a distribution target, whose artifact
+    /// is `None` in dry-run mode.
+    pub(crate) fn new(
+        package: &Package<'_>,
+        dist_target: &DistTarget<'_>,
+        provenance: &Provenance,
+        artifact: Option<&PublishedArtifact>,
+    ) -> Result<Self> {
+        let destination = match artifact {
+            Some(artifact) => artifact.reference.clone(),
+            None => dist_target.artifact_reference()?,
+        };
+
+        Ok(Self {
+            timestamp: DateTime::from_secs(unix_timestamp())
+                .fmt(Format::DateTime)
+                .map_err(|err| {
+                    Error::new("failed to format the audit timestamp").with_source(err)
+                })?,
+            actor: provenance.builder.clone(),
+            package: package.name().to_string(),
+            version: package.version().clone(),
+            package_hash: provenance.package_hash.clone(),
+            dist_target: dist_target.to_string(),
+            destination,
+            digest: artifact.and_then(|artifact| artifact.digest.clone()),
+            dry_run: package.context().options().dry_run,
+            up_to_date: artifact.map_or(false, |artifact| artifact.up_to_date),
+            git_commit: provenance.git_commit.clone(),
+            ci_run_url: provenance.ci_run_url.clone(),
+        })
+    }
+}
+
+/// The audit log of a run, a JSON Lines file each publication action is
+/// appended to as soon as it is done.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    metadata: Option<AuditLogMetadata>,
+    /// The entries recorded during the run.
+    entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub(crate) fn new(path: PathBuf, metadata: Option<AuditLogMetadata>) -> Self {
+        Self {
+            path,
+            metadata,
+            entries: Vec::new(),
+        }
+    }
+
+    pub(crate) fn record(&mut self, entry: AuditEntry) -> Result<()> {
+        let mut line = serde_json::to_string(&entry)
+            .map_err(Error::from_source)
+            .with_context("failed to serialize audit log entry")?;
+        line.push('\n');
+
+        debug!("Recording audit log entry: {}", line.trim_end());
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(Error::from_source)
+                .with_context("failed to create audit log directory")?;
+        }
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to write audit log",
+                format!(
+                    "The audit log could not be written to `{}`. You may want to verify permissions.",
+                    self.path.display()
+                ),
+            )?;
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    /// Upload the entries recorded during the run to S3, if configured.
+    ///
+    /// As S3 objects can't be appended to, the entries of each run are
+    /// uploaded as a separate object.
+    pub(crate) fn upload(&self, context: &Context) -> Result<()> {
+        let metadata = match &self.metadata {
+            Some(metadata) => metadata,
+            None => return Ok(()),
+        };
+
+        let s3_bucket = match &metadata.s3_bucket {
+            Some(s3_bucket) => s3_bucket,
+            None => return Ok(()),
+        };
+
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+
+        if context.options().dry_run {
+            warn!("`--dry-run` specified, will not upload the audit log to S3");
+
+            return Ok(());
+        }
+
+        let s3_key = format!(
+            "{}{}-{}.jsonl",
+            metadata.s3_bucket_prefix,
+            unix_timestamp(),
+            std::process::id()
+        );
+        let data = self
+            .entries
+            .iter()
+            .map(|entry| serde_json::to_string(entry).map(|line| line + "\n"))
+            .collect::<std::result::Result<String, _>>()
+            .map_err(Error::from_source)
+            .with_context("failed to serialize audit log entries")?;
+
+        action_step!("Uploading", "audit log to `s3://{}/{}`", s3_bucket, s3_key);
+
+        let settings = AwsSettings {
+            region: metadata.region.clone(),
+            ..AwsSettings::default()
+        };
+
+        context.block_on(async {
+            let client = context.aws().s3_client(&settings).await?;
+
+            client
+                .put_object()
+                .bucket(s3_bucket)
+                .key(&s3_key)
+                .content_type("application/x-ndjson")
+                .body(data.into_bytes().into())
+                .send()
+                .await
+                .map_err(|err| {
+                    Error::new("failed to upload audit log to S3")
+                        .with_kind(ErrorKind::Network)
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "Please check that the S3 bucket `{}` exists and that you have the correct permissions.",
+                            s3_bucket
+                        ))
+                })?;
+
+            Ok(())
+        })
+    }
+}
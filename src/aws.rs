@@ -0,0 +1,818 @@
+//! Shared AWS configuration and clients.
+//!
+//! All the AWS code paths go through this module so that region resolution,
+//! credentials and retry configuration are handled consistently and that
+//! clients are only created once per set of settings.
+
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+use aws_config::{
+    default_provider::credentials::DefaultCredentialsChain,
+    meta::region::RegionProviderChain,
+    profile::{ProfileFileCredentialsProvider, ProfileFileRegionProvider},
+    sts::AssumeRoleProvider,
+};
+use aws_smithy_types::{date_time::Format, retry::RetryConfig};
+use aws_types::{
+    config::Config, credentials::SharedCredentialsProvider, region::Region, Credentials,
+};
+use log::{debug, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action_step, aws_lambda::LambdaClient, context::offline_error, Context, Error, ErrorContext,
+    ErrorKind, Options, PublishedVersion, Result,
+};
+
+/// The session name used when assuming roles, as it appears in AWS CloudTrail.
+const ASSUME_ROLE_SESSION_NAME: &str = "cargo-monorepo";
+
+/// Static credentials read from environment variables.
+///
+/// This is mostly useful for S3-compatible storage backends (like MinIO or
+/// Ceph) whose credentials are not managed through the usual AWS mechanisms.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AwsCredentialsEnv {
+    pub access_key_id_env: String,
+    pub secret_access_key_env: String,
+}
+
+impl AwsCredentialsEnv {
+    fn credentials(&self) -> Result<Credentials> {
+        let read_var = |name: &str| {
+            std::env::var(name).map_err(|err| {
+                Error::new("failed to read AWS credentials")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The environment variable `{}` must be set as it is referenced by the `credentials_env` field.",
+                        name
+                    ))
+            })
+        };
+
+        Ok(Credentials::new(
+            read_var(&self.access_key_id_env)?,
+            read_var(&self.secret_access_key_env)?,
+            None,
+            None,
+            "credentials_env",
+        ))
+    }
+}
+
+/// The settings of the S3 buckets created for distribution targets that allow
+/// it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct S3BucketCreation {
+    /// Whether to enable the versioning of the objects.
+    #[serde(default)]
+    pub versioning: bool,
+    /// The default server-side encryption of the objects.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<S3BucketEncryption>,
+    /// The KMS key used with the `aws:kms` encryption, which defaults to the
+    /// AWS managed key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kms_key_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub enum S3BucketEncryption {
+    #[serde(rename = "AES256")]
+    Aes256,
+    #[serde(rename = "aws:kms")]
+    AwsKms,
+}
+
+/// The AWS settings of a distribution target.
+///
+/// Unspecified settings fall back to the global options and then to the
+/// environment.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub(crate) struct AwsSettings {
+    pub region: Option<String>,
+    pub endpoint_url: Option<String>,
+    pub profile: Option<String>,
+    pub assume_role_arn: Option<String>,
+    pub assume_role_external_id: Option<String>,
+    pub credentials_env: Option<AwsCredentialsEnv>,
+}
+
+/// The AWS settings of the metadata of a distribution target, from its
+/// `region`, `aws_endpoint_url`, `aws_profile`, `assume_role_arn`,
+/// `assume_role_external_id` and `credentials_env` fields.
+macro_rules! aws_settings {
+    ($metadata:expr) => {
+        $crate::aws::AwsSettings {
+            region: $metadata.region.clone(),
+            endpoint_url: $metadata.aws_endpoint_url.clone(),
+            profile: $metadata.aws_profile.clone(),
+            assume_role_arn: $metadata.assume_role_arn.clone(),
+            assume_role_external_id: $metadata.assume_role_external_id.clone(),
+            credentials_env: $metadata.credentials_env.clone(),
+        }
+    };
+}
+
+pub(crate) use aws_settings;
+
+impl AwsSettings {
+    /// Fill the unspecified settings with the ones from `defaults`.
+    fn or(&self, defaults: &Self) -> Self {
+        Self {
+            region: self.region.clone().or_else(|| defaults.region.clone()),
+            endpoint_url: self
+                .endpoint_url
+                .clone()
+                .or_else(|| defaults.endpoint_url.clone()),
+            profile: self.profile.clone().or_else(|| defaults.profile.clone()),
+            assume_role_arn: self
+                .assume_role_arn
+                .clone()
+                .or_else(|| defaults.assume_role_arn.clone()),
+            assume_role_external_id: self
+                .assume_role_external_id
+                .clone()
+                .or_else(|| defaults.assume_role_external_id.clone()),
+            credentials_env: self
+                .credentials_env
+                .clone()
+                .or_else(|| defaults.credentials_env.clone()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct AwsContext {
+    defaults: AwsSettings,
+    max_attempts: Option<u32>,
+    offline: bool,
+    shared_configs: RefCell<HashMap<AwsSettings, Arc<Config>>>,
+    s3_clients: RefCell<HashMap<AwsSettings, aws_sdk_s3::Client>>,
+    ecr_clients: RefCell<HashMap<AwsSettings, aws_sdk_ecr::Client>>,
+    cloudfront_clients: RefCell<HashMap<AwsSettings, aws_sdk_cloudfront::Client>>,
+    lambda_clients: RefCell<HashMap<AwsSettings, LambdaClient>>,
+}
+
+impl AwsContext {
+    pub(crate) fn new(options: &Options) -> Self {
+        Self {
+            defaults: AwsSettings {
+                region: options.aws_region.clone(),
+                endpoint_url: options.aws_endpoint_url.clone(),
+                profile: options.aws_profile.clone(),
+                assume_role_arn: options.aws_assume_role_arn.clone(),
+                assume_role_external_id: options.aws_assume_role_external_id.clone(),
+                credentials_env: None,
+            },
+            max_attempts: options.aws_max_attempts,
+            offline: options.offline,
+            shared_configs: RefCell::default(),
+            s3_clients: RefCell::default(),
+            ecr_clients: RefCell::default(),
+            cloudfront_clients: RefCell::default(),
+            lambda_clients: RefCell::default(),
+        }
+    }
+
+    /// Get the shared AWS configuration for the specified resolved settings.
+    ///
+    /// If no region is specified, the region of the profile - if any - or the
+    /// default region of the environment is used.
+    ///
+    /// Credentials come from the credentials environment variables, the profile
+    /// - if any - or the default provider chain of the environment and are
+    /// used to assume the specified role, if any.
+    async fn shared_config(&self, settings: &AwsSettings) -> Result<Arc<Config>> {
+        if let Some(shared_config) = self.shared_configs.borrow().get(settings) {
+            return Ok(Arc::clone(shared_config));
+        }
+
+        if self.offline {
+            return Err(offline_error("accessing AWS"));
+        }
+
+        debug!("Loading AWS configuration for {:?}", settings);
+
+        let region_provider =
+            RegionProviderChain::first_try(settings.region.clone().map(Region::new));
+        let region_provider = match &settings.profile {
+            Some(profile) => region_provider.or_else(
+                ProfileFileRegionProvider::builder()
+                    .profile_name(profile)
+                    .build(),
+            ),
+            None => region_provider.or_default_provider(),
+        };
+        let region = region_provider.region().await;
+
+        let credentials_provider = match (&settings.credentials_env, &settings.profile) {
+            (Some(credentials_env), _) => {
+                SharedCredentialsProvider::new(credentials_env.credentials()?)
+            }
+            (None, Some(profile)) => SharedCredentialsProvider::new(
+                ProfileFileCredentialsProvider::builder()
+                    .profile_name(profile)
+                    .build(),
+            ),
+            (None, None) => {
+                SharedCredentialsProvider::new(DefaultCredentialsChain::builder().build().await)
+            }
+        };
+
+        let credentials_provider = match &settings.assume_role_arn {
+            Some(assume_role_arn) => {
+                debug!("Will assume AWS role `{}`", assume_role_arn);
+
+                let mut builder = AssumeRoleProvider::builder(assume_role_arn)
+                    .session_name(ASSUME_ROLE_SESSION_NAME);
+
+                if let Some(external_id) = &settings.assume_role_external_id {
+                    builder = builder.external_id(external_id);
+                }
+
+                if let Some(region) = &region {
+                    builder = builder.region(region.clone());
+                }
+
+                SharedCredentialsProvider::new(builder.build(credentials_provider))
+            }
+            None => credentials_provider,
+        };
+
+        let mut loader = aws_config::from_env().credentials_provider(credentials_provider);
+
+        if let Some(region) = region {
+            loader = loader.region(region);
+        }
+
+        if let Some(max_attempts) = self.max_attempts {
+            loader = loader.retry_config(RetryConfig::new().with_max_attempts(max_attempts));
+        }
+
+        let shared_config = Arc::new(loader.load().await);
+
+        self.shared_configs
+            .borrow_mut()
+            .insert(settings.clone(), Arc::clone(&shared_config));
+
+        Ok(shared_config)
+    }
+
+    /// Get the region resolved for the specified settings, if any.
+    pub(crate) async fn region(&self, settings: &AwsSettings) -> Result<Option<String>> {
+        let settings = settings.or(&self.defaults);
+
+        Ok(self
+            .shared_config(&settings)
+            .await?
+            .region()
+            .map(ToString::to_string))
+    }
+
+    /// Get a S3 client for the specified settings.
+    pub(crate) async fn s3_client(&self, settings: &AwsSettings) -> Result<aws_sdk_s3::Client> {
+        let settings = settings.or(&self.defaults);
+
+        if let Some(client) = self.s3_clients.borrow().get(&settings) {
+            return Ok(client.clone());
+        }
+
+        let shared_config = self.shared_config(&settings).await?;
+        let mut builder = aws_sdk_s3::config::Builder::from(&*shared_config);
+
+        if let Some(endpoint_url) = &settings.endpoint_url {
+            builder = builder.endpoint_resolver(aws_sdk_s3::Endpoint::immutable(
+                parse_endpoint_url(endpoint_url)?,
+            ));
+        }
+
+        let client = aws_sdk_s3::Client::from_conf(builder.build());
+
+        self.s3_clients
+            .borrow_mut()
+            .insert(settings, client.clone());
+
+        Ok(client)
+    }
+
+    /// Get an ECR client for the specified settings.
+    pub(crate) async fn ecr_client(&self, settings: &AwsSettings) -> Result<aws_sdk_ecr::Client> {
+        let settings = settings.or(&self.defaults);
+
+        if let Some(client) = self.ecr_clients.borrow().get(&settings) {
+            return Ok(client.clone());
+        }
+
+        let shared_config = self.shared_config(&settings).await?;
+        let mut builder = aws_sdk_ecr::config::Builder::from(&*shared_config);
+
+        if let Some(endpoint_url) = &settings.endpoint_url {
+            builder = builder.endpoint_resolver(aws_sdk_ecr::Endpoint::immutable(
+                parse_endpoint_url(endpoint_url)?,
+            ));
+        }
+
+        let client = aws_sdk_ecr::Client::from_conf(builder.build());
+
+        self.ecr_clients
+            .borrow_mut()
+            .insert(settings, client.clone());
+
+        Ok(client)
+    }
+
+    /// Get a CloudFront client for the specified settings.
+    pub(crate) async fn cloudfront_client(
+        &self,
+        settings: &AwsSettings,
+    ) -> Result<aws_sdk_cloudfront::Client> {
+        let settings = settings.or(&self.defaults);
+
+        if let Some(client) = self.cloudfront_clients.borrow().get(&settings) {
+            return Ok(client.clone());
+        }
+
+        let shared_config = self.shared_config(&settings).await?;
+        let mut builder = aws_sdk_cloudfront::config::Builder::from(&*shared_config);
+
+        if let Some(endpoint_url) = &settings.endpoint_url {
+            builder = builder.endpoint_resolver(aws_sdk_cloudfront::Endpoint::immutable(
+                parse_endpoint_url(endpoint_url)?,
+            ));
+        }
+
+        let client = aws_sdk_cloudfront::Client::from_conf(builder.build());
+
+        self.cloudfront_clients
+            .borrow_mut()
+            .insert(settings, client.clone());
+
+        Ok(client)
+    }
+
+    /// Get an AWS Lambda client for the specified settings.
+    pub(crate) async fn lambda_client(&self, settings: &AwsSettings) -> Result<LambdaClient> {
+        let settings = settings.or(&self.defaults);
+
+        if let Some(client) = self.lambda_clients.borrow().get(&settings) {
+            return Ok(client.clone());
+        }
+
+        let shared_config = self.shared_config(&settings).await?;
+        let region = shared_config
+            .region()
+            .map(ToString::to_string)
+            .ok_or_else(|| {
+                Error::new("failed to determine AWS region")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(
+                        "AWS Lambda requests must be signed for a region. Please specify one.",
+                    )
+            })?;
+
+        let endpoint_url = match &settings.endpoint_url {
+            Some(endpoint_url) => {
+                parse_endpoint_url(endpoint_url)?;
+
+                endpoint_url.clone()
+            }
+            None => format!("https://lambda.{}.amazonaws.com", region),
+        };
+
+        let client = LambdaClient::new(shared_config, region, endpoint_url);
+
+        self.lambda_clients
+            .borrow_mut()
+            .insert(settings, client.clone());
+
+        Ok(client)
+    }
+}
+
+fn parse_endpoint_url(endpoint_url: &str) -> Result<http::Uri> {
+    endpoint_url.parse().map_err(|err| {
+        Error::new("invalid AWS endpoint URL")
+            .with_kind(ErrorKind::Configuration)
+            .with_source(err)
+            .with_explanation(format!(
+                "The AWS endpoint URL `{}` could not be parsed. It should look like `http://localhost:4566`.",
+                endpoint_url
+            ))
+    })
+}
+
+/// Check whether a S3 bucket exists.
+pub(crate) async fn s3_bucket_exists(client: &aws_sdk_s3::Client, s3_bucket: &str) -> Result<bool> {
+    match client.head_bucket().bucket(s3_bucket).send().await {
+        Ok(_) => Ok(true),
+        Err(aws_sdk_s3::SdkError::ServiceError { err, .. }) if err.is_not_found() => Ok(false),
+        Err(err) => Err(Error::from_source(err))
+            .with_full_context(
+                "failed to check for S3 bucket existence",
+                format!(
+                    "Could not verify the existence of the S3 bucket `{}`. Please check your credentials and make sure you have the appropriate permissions.",
+                    s3_bucket
+                ),
+            )
+            .with_kind(ErrorKind::Network),
+    }
+}
+
+/// Create a S3 bucket in the specified region, with the specified settings,
+/// unless it exists already.
+pub(crate) async fn ensure_s3_bucket_exists(
+    client: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    region: Option<&str>,
+    creation: &S3BucketCreation,
+) -> Result<()> {
+    if s3_bucket_exists(client, s3_bucket).await? {
+        debug!("S3 bucket `{}` already exists", s3_bucket);
+
+        return Ok(());
+    }
+
+    action_step!("Creating", "S3 bucket `{}`", s3_bucket);
+
+    let mut request = client.create_bucket().bucket(s3_bucket);
+
+    // Buckets are created in `us-east-1` without a location constraint, which
+    // can't be specified for that region.
+    if let Some(region) = region.filter(|region| *region != "us-east-1") {
+        request = request.create_bucket_configuration(
+            aws_sdk_s3::model::CreateBucketConfiguration::builder()
+                .location_constraint(aws_sdk_s3::model::BucketLocationConstraint::from(region))
+                .build(),
+        );
+    }
+
+    request
+        .send()
+        .await
+        .map_err(|err| s3_bucket_creation_error(err, s3_bucket))?;
+
+    if creation.versioning {
+        client
+            .put_bucket_versioning()
+            .bucket(s3_bucket)
+            .versioning_configuration(
+                aws_sdk_s3::model::VersioningConfiguration::builder()
+                    .status(aws_sdk_s3::model::BucketVersioningStatus::Enabled)
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| s3_bucket_creation_error(err, s3_bucket))?;
+    }
+
+    if let Some(encryption) = creation.encryption {
+        let sse_algorithm = match encryption {
+            S3BucketEncryption::Aes256 => aws_sdk_s3::model::ServerSideEncryption::Aes256,
+            S3BucketEncryption::AwsKms => aws_sdk_s3::model::ServerSideEncryption::AwsKms,
+        };
+
+        client
+            .put_bucket_encryption()
+            .bucket(s3_bucket)
+            .server_side_encryption_configuration(
+                aws_sdk_s3::model::ServerSideEncryptionConfiguration::builder()
+                    .rules(
+                        aws_sdk_s3::model::ServerSideEncryptionRule::builder()
+                            .apply_server_side_encryption_by_default(
+                                aws_sdk_s3::model::ServerSideEncryptionByDefault::builder()
+                                    .sse_algorithm(sse_algorithm)
+                                    .set_kms_master_key_id(creation.kms_key_id.clone())
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| s3_bucket_creation_error(err, s3_bucket))?;
+    } else if creation.kms_key_id.is_some() {
+        warn!(
+            "`kms_key_id` is ignored for the S3 bucket `{}` as no `encryption` is specified",
+            s3_bucket
+        );
+    }
+
+    Ok(())
+}
+
+fn s3_bucket_creation_error(err: impl Into<anyhow::Error>, s3_bucket: &str) -> Error {
+    Error::new("failed to create S3 bucket")
+        .with_kind(ErrorKind::Network)
+        .with_source(err)
+        .with_explanation(format!(
+            "Could not create the S3 bucket `{}`. Please check that its name is available and that you have the appropriate permissions.",
+            s3_bucket
+        ))
+}
+
+/// Check that a S3 bucket exists and, if a region is specified, that it is
+/// located in that region.
+pub(crate) async fn validate_s3_bucket(
+    client: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    region: Option<&str>,
+) -> Result<()> {
+    let output = client
+        .get_bucket_location()
+        .bucket(s3_bucket)
+        .send()
+        .await
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to validate S3 bucket",
+            format!(
+                "Could not get the location of the S3 bucket `{}`. Please check that it exists, that your credentials are valid and that you have the appropriate permissions.",
+                s3_bucket
+            ),
+        )
+        .with_kind(ErrorKind::Network)?;
+
+    let region = match region {
+        Some(region) => region,
+        None => return Ok(()),
+    };
+
+    // Buckets of the original regions have legacy location constraints.
+    let bucket_region = match output
+        .location_constraint
+        .as_ref()
+        .map_or("", |location_constraint| location_constraint.as_str())
+    {
+        "" => "us-east-1",
+        "EU" => "eu-west-1",
+        location_constraint => location_constraint,
+    };
+
+    if bucket_region != region {
+        return Err(Error::new("S3 bucket in unexpected region")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(format!(
+                "The S3 bucket `{}` is located in `{}` but the configuration states `{}`. Please fix the `region` of the distribution target.",
+                s3_bucket, bucket_region, region
+            )));
+    }
+
+    Ok(())
+}
+
+/// Check whether an object exists in a S3 bucket.
+pub(crate) async fn s3_object_exists(
+    client: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    s3_key: &str,
+) -> Result<bool> {
+    match client
+        .get_object()
+        .bucket(s3_bucket)
+        .key(s3_key)
+        .send()
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(err) => is_s3_no_such_key(err, s3_key, s3_bucket).map(|()| false),
+    }
+}
+
+/// List the versions of an artifact stored in a S3 bucket under
+/// `<prefix><version><suffix>` keys.
+///
+/// The digests are the entity tags of the objects.
+pub(crate) async fn list_s3_versions(
+    client: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    prefix: &str,
+    suffix: &str,
+) -> Result<Vec<PublishedVersion>> {
+    let mut versions = Vec::new();
+    let mut continuation_token = None;
+
+    loop {
+        let output = client
+            .list_objects_v2()
+            .bucket(s3_bucket)
+            .prefix(prefix)
+            .set_continuation_token(continuation_token)
+            .send()
+            .await
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to list S3 objects",
+                format!(
+                    "Could not list the objects under `{}` in the S3 bucket `{}`. Please check your credentials and make sure you have the appropriate permissions.",
+                    prefix, s3_bucket
+                ),
+            )
+            .with_kind(ErrorKind::Network)?;
+
+        for object in output.contents.unwrap_or_default() {
+            let version = object
+                .key
+                .as_deref()
+                .and_then(|key| key.strip_prefix(prefix))
+                .and_then(|key| key.strip_suffix(suffix))
+                .and_then(|version| semver::Version::parse(version).ok());
+
+            if let Some(version) = version {
+                versions.push(PublishedVersion {
+                    version,
+                    date: object
+                        .last_modified
+                        .as_ref()
+                        .and_then(|date| date.fmt(Format::DateTime).ok()),
+                    timestamp: object.last_modified.as_ref().map(|date| date.secs()),
+                    digest: object
+                        .e_tag
+                        .map(|e_tag| e_tag.trim_matches('"').to_string()),
+                });
+            }
+        }
+
+        continuation_token = output.next_continuation_token;
+
+        if continuation_token.is_none() {
+            return Ok(versions);
+        }
+    }
+}
+
+/// The S3 keys of an artifact stored as one object per version, like
+/// `<prefix><package>/v1.2.3.msi`, and per channel, like
+/// `<prefix><package>/stable.msi`.
+pub(crate) struct S3ArtifactKeys {
+    package_prefix: String,
+    extension: &'static str,
+}
+
+impl S3ArtifactKeys {
+    pub(crate) fn new(s3_bucket_prefix: &str, package_name: &str, extension: &'static str) -> Self {
+        Self {
+            package_prefix: format!("{}{}/", s3_bucket_prefix, package_name),
+            extension,
+        }
+    }
+
+    /// The prefix of the keys of the versions, as `list_s3_versions` expects.
+    pub(crate) fn versions_prefix(&self) -> String {
+        format!("{}v", self.package_prefix)
+    }
+
+    /// The suffix of the keys, as `list_s3_versions` expects.
+    pub(crate) fn suffix(&self) -> String {
+        format!(".{}", self.extension)
+    }
+
+    pub(crate) fn version_key(&self, version: &semver::Version) -> String {
+        format!("{}{}{}", self.versions_prefix(), version, self.suffix())
+    }
+
+    pub(crate) fn channel_key(&self, channel: &str) -> String {
+        format!("{}{}{}", self.package_prefix, channel, self.suffix())
+    }
+}
+
+/// Promote an artifact to a channel by copying its object S3-side, so that it
+/// does not need to be rebuilt.
+pub(crate) fn promote_s3_object(
+    context: &Context,
+    settings: &AwsSettings,
+    s3_bucket: &str,
+    source_s3_key: &str,
+    destination_s3_key: &str,
+    description: &str,
+) -> Result<()> {
+    if context.options().dry_run {
+        warn!(
+            "`--dry-run` specified, will not really copy `{}` to `{}` in S3 bucket `{}`",
+            source_s3_key, destination_s3_key, s3_bucket
+        );
+
+        return Ok(());
+    }
+
+    action_step!(
+        "Promoting",
+        "{} `{}` to `{}` in S3 bucket `{}`",
+        description,
+        source_s3_key,
+        destination_s3_key,
+        s3_bucket
+    );
+
+    context.block_on(async move {
+        let client = context.aws().s3_client(settings).await?;
+
+        client
+            .copy_object()
+            .copy_source(format!("{}/{}", s3_bucket, source_s3_key))
+            .bucket(s3_bucket)
+            .key(destination_s3_key)
+            .send()
+            .await
+            .map_err(|err| {
+                Error::new(format!("failed to promote {} on S3", description))
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "Please check that `{}` was published to the S3 bucket `{}` and that you have the correct permissions.",
+                        source_s3_key, s3_bucket
+                    ))
+            })?;
+
+        Ok(())
+    })
+}
+
+/// Delete an object from a S3 bucket.
+pub(crate) async fn delete_s3_object(
+    client: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    s3_key: &str,
+) -> Result<()> {
+    client
+        .delete_object()
+        .bucket(s3_bucket)
+        .key(s3_key)
+        .send()
+        .await
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to delete S3 object",
+            format!(
+                "Could not delete the object `{}` from the S3 bucket `{}`. Please check your credentials and make sure you have the appropriate permissions.",
+                s3_key, s3_bucket
+            ),
+        )
+        .with_kind(ErrorKind::Network)?;
+
+    Ok(())
+}
+
+fn is_s3_no_such_key(
+    err: aws_sdk_s3::SdkError<aws_sdk_s3::error::GetObjectError>,
+    s3_key: &str,
+    s3_bucket: &str,
+) -> Result<()> {
+    match err {
+        aws_sdk_s3::SdkError::ServiceError { err, .. } => {
+            if !err.is_no_such_key() {
+                Err(Error::from_source(err)).with_full_context(
+                    "failed to check for S3 object existence",
+                    format!(
+                        "Could not verify the existence of the object `{}` in \
+                        the S3 bucket `{}`. Please check your credentials and \
+                        make sure you have the appropriate permissions.",
+                        s3_key, s3_bucket
+                    ),
+                )
+            } else {
+                Ok(())
+            }
+        }
+        _ => Err(Error::from_source(err))
+            .with_full_context(
+                "failed to check for S3 object existence",
+                format!(
+                    "Could not verify the existence of the object `{}` in the S3 \
+                bucket `{}`. Please check your credentials and make sure you \
+                have the appropriate permissions.",
+                    s3_key, s3_bucket
+                ),
+            )
+            .with_kind(ErrorKind::Network),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_artifact_keys() {
+        let keys = S3ArtifactKeys::new("releases/", "my-tool", "msi");
+
+        assert_eq!(keys.versions_prefix(), "releases/my-tool/v");
+        assert_eq!(keys.suffix(), ".msi");
+        assert_eq!(
+            keys.version_key(&semver::Version::parse("1.2.3-rc.1").unwrap()),
+            "releases/my-tool/v1.2.3-rc.1.msi"
+        );
+        assert_eq!(keys.channel_key("stable"), "releases/my-tool/stable.msi");
+
+        let keys = S3ArtifactKeys::new("", "my-module", "wasm");
+
+        assert_eq!(
+            keys.version_key(&semver::Version::new(0, 1, 0)),
+            "my-module/v0.1.0.wasm"
+        );
+    }
+}
@@ -0,0 +1,411 @@
+//! A minimal client for the AWS Lambda API.
+//!
+//! The version of the AWS SDK in use does not cover AWS Lambda, so the few
+//! calls needed to deploy a function are made directly, signed with the
+//! credentials of the shared AWS configuration.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use aws_sigv4::http_request::{sign, SignableRequest, SigningParams, SigningSettings};
+use aws_types::{config::Config, credentials::ProvideCredentials};
+use http::{HeaderMap, Method, StatusCode};
+use serde::Deserialize;
+
+use crate::{Error, ErrorKind, Result};
+
+/// The version of the AWS Lambda API, as it appears in the request paths.
+const API_VERSION: &str = "2015-03-31";
+
+/// The interval between two checks of the status of a function update.
+const UPDATE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The maximum time to wait for a function update to complete.
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone)]
+pub(crate) struct LambdaClient {
+    shared_config: Arc<Config>,
+    region: String,
+    endpoint_url: String,
+    http_client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FunctionConfiguration {
+    version: String,
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    state_reason: Option<String>,
+    #[serde(default)]
+    last_update_status: Option<String>,
+    #[serde(default)]
+    last_update_status_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AliasConfiguration {
+    function_version: String,
+}
+
+struct Response {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl LambdaClient {
+    pub(crate) fn new(shared_config: Arc<Config>, region: String, endpoint_url: String) -> Self {
+        Self {
+            shared_config,
+            region,
+            endpoint_url,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    /// Update the code of a function from an archive in S3 and publish a new
+    /// version of it.
+    ///
+    /// Returns the published version.
+    pub(crate) async fn update_function_code(
+        &self,
+        function_name: &str,
+        s3_bucket: &str,
+        s3_key: &str,
+    ) -> Result<String> {
+        let body = serde_json::json!({
+            "S3Bucket": s3_bucket,
+            "S3Key": s3_key,
+            "Publish": true,
+        });
+
+        let response = self
+            .send(
+                Method::PUT,
+                &["functions", function_name, "code"],
+                None,
+                body.to_string().into_bytes(),
+            )
+            .await?;
+
+        if !response.status.is_success() {
+            return Err(api_error(
+                "failed to update the AWS Lambda function code",
+                function_name,
+                &response,
+            ));
+        }
+
+        Ok(parse_body::<FunctionConfiguration>(&response)?.version)
+    }
+
+    /// Wait for the update of the specified version of a function to complete.
+    pub(crate) async fn wait_for_update(&self, function_name: &str, version: &str) -> Result<()> {
+        let start = Instant::now();
+
+        loop {
+            let response = self
+                .send(
+                    Method::GET,
+                    &["functions", function_name, "configuration"],
+                    Some(version),
+                    Vec::new(),
+                )
+                .await?;
+
+            if !response.status.is_success() {
+                return Err(api_error(
+                    "failed to get the AWS Lambda function configuration",
+                    function_name,
+                    &response,
+                ));
+            }
+
+            let configuration = parse_body::<FunctionConfiguration>(&response)?;
+
+            match (
+                configuration.state.as_deref(),
+                configuration.last_update_status.as_deref(),
+            ) {
+                (Some("Failed"), _) | (_, Some("Failed")) => {
+                    let reason = configuration
+                        .last_update_status_reason
+                        .or(configuration.state_reason)
+                        .unwrap_or_default();
+
+                    return Err(Error::new("failed to update the AWS Lambda function")
+                        .with_kind(ErrorKind::Network)
+                        .with_explanation(format!(
+                            "The update of the version {} of the function `{}` failed: {}",
+                            version, function_name, reason
+                        )));
+                }
+                (Some("Pending"), _) | (_, Some("InProgress")) => {}
+                _ => return Ok(()),
+            }
+
+            if start.elapsed() > UPDATE_TIMEOUT {
+                return Err(
+                    Error::new("timed out waiting for the AWS Lambda function update")
+                        .with_kind(ErrorKind::Network)
+                        .with_explanation(format!(
+                            "The update of the version {} of the function `{}` did not complete within {} seconds.",
+                            version,
+                            function_name,
+                            UPDATE_TIMEOUT.as_secs()
+                        )),
+                );
+            }
+
+            tokio::time::sleep(UPDATE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Get the version of a function an alias points to.
+    pub(crate) async fn alias_version(&self, function_name: &str, alias: &str) -> Result<String> {
+        let response = self
+            .send(
+                Method::GET,
+                &["functions", function_name, "aliases", alias],
+                None,
+                Vec::new(),
+            )
+            .await?;
+
+        if !response.status.is_success() {
+            return Err(api_error(
+                "failed to get the AWS Lambda function alias",
+                function_name,
+                &response,
+            ));
+        }
+
+        Ok(parse_body::<AliasConfiguration>(&response)?.function_version)
+    }
+
+    /// Point an alias of a function to the specified version.
+    pub(crate) async fn update_alias(
+        &self,
+        function_name: &str,
+        alias: &str,
+        version: &str,
+    ) -> Result<()> {
+        let body = serde_json::json!({ "FunctionVersion": version });
+
+        let response = self
+            .send(
+                Method::PUT,
+                &["functions", function_name, "aliases", alias],
+                None,
+                body.to_string().into_bytes(),
+            )
+            .await?;
+
+        if !response.status.is_success() {
+            return Err(api_error(
+                "failed to update the AWS Lambda function alias",
+                function_name,
+                &response,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Invoke a function synchronously with the specified payload.
+    ///
+    /// Invocations that fail or whose function returns an error are reported
+    /// as errors, along with the returned payload.
+    pub(crate) async fn invoke(
+        &self,
+        function_name: &str,
+        qualifier: &str,
+        payload: &str,
+    ) -> Result<()> {
+        let response = self
+            .send(
+                Method::POST,
+                &["functions", function_name, "invocations"],
+                Some(qualifier),
+                payload.as_bytes().to_vec(),
+            )
+            .await?;
+
+        if !response.status.is_success() {
+            return Err(api_error(
+                "failed to invoke the AWS Lambda function",
+                function_name,
+                &response,
+            ));
+        }
+
+        if let Some(function_error) = response.headers.get("X-Amz-Function-Error") {
+            return Err(Error::new("the AWS Lambda function returned an error")
+                .with_kind(ErrorKind::Validation)
+                .with_explanation(format!(
+                    "The function `{}:{}` reported a `{}` error when invoked with the smoke test payload.",
+                    function_name,
+                    qualifier,
+                    function_error.to_str().unwrap_or_default()
+                ))
+                .with_output(String::from_utf8_lossy(&response.body)));
+        }
+
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &[&str],
+        qualifier: Option<&str>,
+        body: Vec<u8>,
+    ) -> Result<Response> {
+        let mut uri = format!(
+            "{}/{}/{}",
+            self.endpoint_url.trim_end_matches('/'),
+            API_VERSION,
+            path.iter()
+                .map(|segment| encode_uri_component(segment))
+                .collect::<Vec<_>>()
+                .join("/")
+        );
+
+        if let Some(qualifier) = qualifier {
+            uri.push_str("?Qualifier=");
+            uri.push_str(&encode_uri_component(qualifier));
+        }
+
+        let mut request = http::Request::builder()
+            .method(method)
+            .uri(&uri)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .map_err(|err| Error::new("failed to build AWS Lambda request").with_source(err))?;
+
+        self.sign(&mut request).await?;
+
+        let request = reqwest::Request::try_from(request)
+            .map_err(|err| Error::new("failed to build AWS Lambda request").with_source(err))?;
+
+        let response = self.http_client.execute(request).await.map_err(|err| {
+            Error::new("failed to reach the AWS Lambda API")
+                .with_kind(ErrorKind::Network)
+                .with_source(err)
+                .with_explanation(format!("Could not send a request to `{}`.", uri))
+        })?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|err| {
+                Error::new("failed to read the AWS Lambda API response")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+            })?
+            .to_vec();
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    async fn sign(&self, request: &mut http::Request<Vec<u8>>) -> Result<()> {
+        let credentials = self
+            .shared_config
+            .credentials_provider()
+            .ok_or_else(|| Error::new("no AWS credentials provider configured"))?
+            .provide_credentials()
+            .await
+            .map_err(|err| {
+                Error::new("failed to get AWS credentials")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation(
+                        "Please check your AWS credentials and make sure they are valid.",
+                    )
+            })?;
+
+        let mut builder = SigningParams::builder()
+            .access_key(credentials.access_key_id())
+            .secret_key(credentials.secret_access_key())
+            .region(&self.region)
+            .service_name("lambda")
+            .time(SystemTime::now())
+            .settings(SigningSettings::default());
+        builder.set_security_token(credentials.session_token());
+
+        let params = builder
+            .build()
+            .map_err(|err| Error::new("failed to sign AWS Lambda request").with_source(err))?;
+
+        let (instructions, _) = sign(SignableRequest::from(&*request), &params)
+            .map_err(|err| {
+                Error::new("failed to sign AWS Lambda request").with_source(anyhow::anyhow!(err))
+            })?
+            .into_parts();
+
+        instructions.apply_to_request(request);
+
+        Ok(())
+    }
+}
+
+fn parse_body<'de, T: Deserialize<'de>>(response: &'de Response) -> Result<T> {
+    serde_json::from_slice(&response.body).map_err(|err| {
+        Error::new("failed to parse the AWS Lambda API response")
+            .with_kind(ErrorKind::Network)
+            .with_source(err)
+            .with_output(String::from_utf8_lossy(&response.body))
+    })
+}
+
+fn api_error(description: &str, function_name: &str, response: &Response) -> Error {
+    Error::new(description)
+        .with_kind(ErrorKind::Network)
+        .with_explanation(format!(
+            "The AWS Lambda API answered with status `{}` for the function `{}`. Please check \
+            that the function exists and that you have the appropriate permissions.",
+            response.status, function_name
+        ))
+        .with_output(String::from_utf8_lossy(&response.body))
+}
+
+/// Percent-encode all the characters but the unreserved ones, as SigV4
+/// expects.
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_uri_component() {
+        assert_eq!(encode_uri_component("my-function_1"), "my-function_1");
+        assert_eq!(
+            encode_uri_component("arn:aws:lambda:eu-west-1:123456789012:function:my-function"),
+            "arn%3Aaws%3Alambda%3Aeu-west-1%3A123456789012%3Afunction%3Amy-function"
+        );
+        assert_eq!(encode_uri_component("a b/c"), "a%20b%2Fc");
+    }
+}
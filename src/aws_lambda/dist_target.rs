@@ -1,27 +1,43 @@
 use std::{
-    collections::HashMap,
     fmt::Display,
-    io::Write,
     path::{Path, PathBuf},
 };
 
-use aws_config::meta::region::RegionProviderChain;
-use cargo::{
-    core::compiler::{CompileMode, CompileTarget},
-    ops::{compile, CompileOptions},
-};
 use log::{debug, warn};
-use walkdir::WalkDir;
 
 use crate::{
-    action_step, ignore_step, rust::is_current_target_runtime, Context, Error, ErrorContext,
-    Package, Result,
+    action_step,
+    archive::build_zip_archive,
+    aws::{
+        delete_s3_object, ensure_s3_bucket_exists, list_s3_versions, s3_bucket_exists,
+        s3_object_exists, validate_s3_bucket, AwsSettings,
+    },
+    ignore_step,
+    metadata::{artifact_name_context, CopyPlan, Template},
+    rust::build_binaries,
+    size::file_size,
+    staging::{directory_size, format_size},
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, PublishedVersion, Result,
+};
+
+use super::{
+    AwsLambdaDeployment, AwsLambdaEnvironment, AwsLambdaFunction, AwsLambdaMetadata,
+    AwsLambdaRuntime,
 };
 
-use super::AwsLambdaMetadata;
+/// The maximum size of the unzipped content of a Lambda deployment package,
+/// layers included.
+const MAX_UNZIPPED_SIZE: u64 = 250 * 1024 * 1024;
+
+/// The maximum size of a Lambda deployment package uploaded directly rather
+/// than from S3.
+const MAX_DIRECT_UPLOAD_SIZE: u64 = 50 * 1024 * 1024;
 
 pub const DEFAULT_AWS_LAMBDA_S3_BUCKET_ENV_VAR_NAME: &str = "CARGO_MONOREPO_AWS_LAMBDA_S3_BUCKET";
 
+/// A placeholder for the version, used to locate it in the S3 keys.
+const VERSION_PLACEHOLDER: &str = "__CARGO_MONOREPO_VERSION__";
+
 pub struct AwsLambdaDistTarget<'g> {
     pub name: String,
     pub package: &'g Package<'g>,
@@ -30,7 +46,16 @@ pub struct AwsLambdaDistTarget<'g> {
 
 impl Display for AwsLambdaDistTarget<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "aws-lambda[{}]", self.package.name())
+        write!(f, "aws-lambda[{}", self.package.name())?;
+
+        if let Some(function) = self.function_name() {
+            write!(f, "/{}", function)?;
+        }
+
+        match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => write!(f, ":{}]", target_runtime),
+            None => write!(f, "]"),
+        }
     }
 }
 
@@ -50,22 +75,43 @@ impl<'g> AwsLambdaDistTarget<'g> {
 
         self.clean()?;
 
-        let binary = self.build_binary()?;
-        self.copy_binary(&binary)?;
-        self.copy_extra_files()?;
+        self.validate_functions()?;
+        self.validate_runtime()?;
+
+        let copy_plan = self.copy_plan()?;
+        let (name, binary) = self.build_binary()?;
+        self.metadata
+            .size_budget
+            .check_binaries(self, [(&name, &binary)])?;
+        let executable = self.copy_binary(&name, &binary)?;
+        self.copy_extra_files(&copy_plan, &executable)?;
+
+        if self.context().options().no_image {
+            ignore_step!(
+                "Skipping",
+                "AWS Lambda packaging as `--no-image` was specified"
+            );
+            return Ok(());
+        }
 
         self.build_zip_archive()?;
+        self.check_archive_size()?;
+        self.metadata.size_budget.check_artifact(
+            self,
+            "archive",
+            file_size(&self.archive_path())?,
+        )?;
 
         Ok(())
     }
 
-    pub fn publish(&self) -> Result<()> {
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
         if cfg!(windows) {
             ignore_step!(
                 "Unsupported",
                 "AWS Lambda publish is not supported on Windows"
             );
-            return Ok(());
+            return Ok(None);
         }
 
         if self.context().options().mode.is_debug() && !self.context().options().force {
@@ -73,71 +119,396 @@ impl<'g> AwsLambdaDistTarget<'g> {
                 "Unsupported",
                 "AWS Lambda can't be published in debug mode unless `--force` is specified"
             );
-            return Ok(());
+            return Ok(None);
         }
 
-        self.upload_archive()?;
+        let up_to_date = !self.upload_archive()?;
 
-        Ok(())
+        if self.context().options().dry_run {
+            return Ok(None);
+        }
+
+        if let Some(deployment) = self.deployment()? {
+            if self.context().options().no_deploy {
+                ignore_step!(
+                    "Skipping",
+                    "deployment of AWS Lambda function `{}` as `--no-deploy` was specified",
+                    &deployment.function_name
+                );
+            } else if up_to_date {
+                ignore_step!(
+                    "Skipping",
+                    "deployment of AWS Lambda function `{}` as its archive was not uploaded",
+                    &deployment.function_name
+                );
+            } else {
+                self.deploy(deployment)?;
+            }
+        }
+
+        Ok(Some(PublishedArtifact {
+            package: self.package.name().to_string(),
+            version: self.package.version().clone(),
+            dist_target: self.to_string(),
+            reference: self.artifact_reference()?,
+            digest: None,
+            hash_reference: None,
+            up_to_date,
+            provenance: None,
+        }))
     }
 
-    fn upload_archive(&self) -> Result<()> {
-        let archive_path = self.archive_path();
-        let runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
+    /// Check whether the AWS Lambda archive for the current version exists in
+    /// its S3 bucket.
+    pub async fn exists(&self) -> Result<bool> {
+        let s3_bucket = self.s3_bucket()?;
+        let client = self
+            .context()
+            .aws()
+            .s3_client(&self.aws_settings()?)
+            .await?;
+
+        // The bucket is only created when publishing.
+        if self.metadata.allow_s3_bucket_creation && !s3_bucket_exists(&client, &s3_bucket).await? {
+            return Ok(false);
+        }
+
+        s3_object_exists(&client, &s3_bucket, &self.s3_key()?).await
+    }
+
+    /// List the versions published in the S3 bucket.
+    ///
+    /// Listing the versions is not supported if the S3 key template does not
+    /// contain the version exactly once.
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        let s3_key = self.s3_version_key(VERSION_PLACEHOLDER)?;
+
+        let (prefix, suffix) = match s3_key.split_once(VERSION_PLACEHOLDER) {
+            Some((prefix, suffix)) if !suffix.contains(VERSION_PLACEHOLDER) => (prefix, suffix),
+            _ => return Ok(None),
+        };
+
+        let client = self
+            .context()
+            .aws()
+            .s3_client(&self.aws_settings()?)
+            .await?;
+
+        list_s3_versions(&client, &self.s3_bucket()?, prefix, suffix)
+            .await
+            .map(Some)
+    }
+
+    /// Delete the specified version of the AWS Lambda archive from the S3 bucket.
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        let client = self
+            .context()
+            .aws()
+            .s3_client(&self.aws_settings()?)
+            .await?;
+
+        delete_s3_object(
+            &client,
+            &self.s3_bucket()?,
+            &self.s3_version_key(&version.to_string())?,
+        )
+        .await
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(format!("s3://{}/{}", self.s3_bucket()?, self.s3_key()?))
+    }
+
+    /// Promote the current version of the AWS Lambda archive to the specified
+    /// channel.
+    ///
+    /// The archive is copied S3-side and does not need to be rebuilt.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        if channel.is_empty() || channel.contains('/') {
+            return Err(Error::new("invalid channel name")
+                .with_kind(ErrorKind::Validation)
+                .with_explanation(format!(
+                    "The channel `{}` cannot be used as an S3 key component. Channels must be non-empty and cannot contain `/`.",
+                    channel
+                )));
+        }
 
-        let region = self.metadata.region.clone();
         let s3_bucket = self.s3_bucket()?;
+        let source_s3_key = self.s3_key()?;
+        let destination_s3_key = self.s3_channel_key(channel)?;
 
-        let fut = async move {
-            let region_provider =
-                RegionProviderChain::first_try(region.map(aws_sdk_s3::Region::new))
-                    .or_default_provider();
-            let shared_config = aws_config::from_env().region(region_provider).load().await;
-            let client = aws_sdk_s3::Client::new(&shared_config);
-
-            let s3_key = format!(
-                "{}{}/v{}.zip",
-                &self.metadata.s3_bucket_prefix,
-                self.package.name(),
-                self.package.version()
+        if self.context().options().dry_run {
+            warn!(
+                "`--dry-run` specified, will not really copy `{}` to `{}` in S3 bucket `{}`",
+                source_s3_key, destination_s3_key, s3_bucket
             );
 
-            if self.context().options().force {
-                debug!("`--force` specified: not checking for the archive existence on S3 before uploading");
-            } else {
-                let resp = client
-                    .get_object()
-                    .bucket(&s3_bucket)
-                    .key(&s3_key)
-                    .send()
-                    .await;
+            return Ok(());
+        }
 
-                match resp {
-                    Ok(_) => {
-                        debug!(
-                            "AWS Lambda archive `{}` already exists in the S3 bucket `{}`: not uploading again",
-                            &s3_key, &s3_bucket
-                        );
+        action_step!(
+            "Promoting",
+            "AWS Lambda archive `{}` to `{}` in S3 bucket `{}`",
+            &source_s3_key,
+            &destination_s3_key,
+            &s3_bucket
+        );
 
-                        ignore_step!(
-                            "Up-to-date",
-                            "AWS Lambda archive `{}` already exists in S3 bucket `{}`",
-                            &s3_key,
-                            &s3_bucket
-                        );
+        self.context().block_on(async move {
+            let client = self
+                .context()
+                .aws()
+                .s3_client(&self.aws_settings()?)
+                .await?;
+
+            client
+                .copy_object()
+                .copy_source(format!("{}/{}", &s3_bucket, &source_s3_key))
+                .bucket(&s3_bucket)
+                .key(&destination_s3_key)
+                .send()
+                .await
+                .map_err(|err| {
+                    Error::new("failed to promote archive on S3")
+                        .with_kind(ErrorKind::Network)
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "Please check that the AWS Lambda archive `{}` was published to the S3 bucket `{}` and that you have the correct permissions.",
+                            &source_s3_key, &s3_bucket
+                        ))
+                })?;
+
+            Ok(())
+        })
+    }
+
+    /// The destination of the archive for the environment profile, if one was
+    /// specified and the distribution target declares environments.
+    fn environment(&self) -> Result<Option<&AwsLambdaEnvironment>> {
+        let env_profile = match &self.context().options().env_profile {
+            Some(env_profile) if !self.metadata.environments.is_empty() => env_profile,
+            _ => return Ok(None),
+        };
+
+        match self.metadata.environments.get(env_profile) {
+            Some(environment) => Ok(Some(environment)),
+            None => Err(Error::new("unknown environment profile")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The distribution {} does not declare the environment `{}`. Declared environments are: {}.",
+                    self,
+                    env_profile,
+                    self.metadata
+                        .environments
+                        .keys()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))),
+        }
+    }
+
+    fn region(&self) -> Result<Option<String>> {
+        Ok(self
+            .environment()?
+            .and_then(|environment| environment.region.clone())
+            .or_else(|| self.metadata.region.clone()))
+    }
+
+    /// The prefix of the S3 keys, qualified with the preview identifier, if
+    /// any.
+    fn s3_bucket_prefix(&self) -> Result<String> {
+        let s3_bucket_prefix = self
+            .environment()?
+            .and_then(|environment| environment.s3_bucket_prefix.as_deref())
+            .unwrap_or(&self.metadata.s3_bucket_prefix);
+
+        Ok(self.context().options().preview_s3_prefix(s3_bucket_prefix))
+    }
+
+    /// The AWS Lambda function to update with the archive, if any.
+    ///
+    /// The functions of a package hosting several handlers can't share a
+    /// deployment and only use their own.
+    fn deployment(&self) -> Result<Option<&AwsLambdaDeployment>> {
+        if let Some((_, function)) = self.function() {
+            return Ok(function.deployment.as_ref());
+        }
+
+        Ok(self
+            .environment()?
+            .and_then(|environment| environment.deployment.as_ref())
+            .or(self.metadata.deployment.as_ref()))
+    }
+
+    fn aws_settings(&self) -> Result<AwsSettings> {
+        Ok(AwsSettings {
+            region: self.region()?,
+            endpoint_url: self.metadata.aws_endpoint_url.clone(),
+            profile: self.metadata.aws_profile.clone(),
+            assume_role_arn: self.metadata.assume_role_arn.clone(),
+            assume_role_external_id: self.metadata.assume_role_external_id.clone(),
+            credentials_env: self.metadata.credentials_env.clone(),
+        })
+    }
 
-                        return Ok(());
+    fn s3_key(&self) -> Result<String> {
+        self.s3_version_key(&self.package.version().to_string())
+    }
+
+    fn s3_version_key(&self, version: &str) -> Result<String> {
+        self.s3_object_key(&format!("v{}", version), version)
+    }
+
+    fn s3_channel_key(&self, channel: &str) -> Result<String> {
+        self.s3_object_key(channel, channel)
+    }
+
+    /// The S3 key of the archive for the specified version or channel.
+    ///
+    /// By default, the archive is named `default_name` in a directory named
+    /// after the package, and after the function if any. An `s3_key` template
+    /// receives the version or channel as `version` and the name of the
+    /// function as `function`.
+    fn s3_object_key(&self, default_name: &str, version: &str) -> Result<String> {
+        let s3_key = match self.s3_key_template() {
+            Some(s3_key) => {
+                let mut context =
+                    artifact_name_context(self.package, self.metadata.target_runtime.as_str());
+                context.insert("version", version);
+                context.insert("function", &self.function_name());
+
+                let s3_key = s3_key.render_for_package(self.package, &context)?;
+
+                if !s3_key.contains(version) {
+                    return Err(Error::new("invalid S3 key template")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_explanation(format!(
+                            "The S3 key `{}` of the distribution {} does not contain the version. Use `{{{{ version }}}}` in the `s3_key` template so that versions don't overwrite each other.",
+                            s3_key, self
+                        )));
+                }
+
+                // The archives of the functions would overwrite each other.
+                if let Some((binary, function)) = self.function() {
+                    let function_name = function.name.as_deref().unwrap_or(binary);
+
+                    if function.s3_key.is_none()
+                        && self.metadata.functions.len() > 1
+                        && !s3_key.contains(function_name)
+                    {
+                        return Err(Error::new("invalid S3 key template")
+                            .with_kind(ErrorKind::Configuration)
+                            .with_explanation(format!(
+                                "The S3 key `{}` of the distribution {} does not contain the name of the function. Use `{{{{ function }}}}` in the `s3_key` template so that functions don't overwrite each other.",
+                                s3_key, self
+                            )));
                     }
-                    Err(err) => is_s3_no_such_key(err, &s3_key, &s3_bucket),
-                }?;
+                }
 
+                s3_key
+            }
+            None => match self.function_name() {
+                Some(function) => format!(
+                    "{}/{}/{}{}",
+                    self.package.name(),
+                    function,
+                    default_name,
+                    self.s3_key_suffix()
+                ),
+                None => format!(
+                    "{}/{}{}",
+                    self.package.name(),
+                    default_name,
+                    self.s3_key_suffix()
+                ),
+            },
+        };
+
+        Ok(format!("{}{}", self.s3_bucket_prefix()?, s3_key))
+    }
+
+    fn s3_key_template(&self) -> Option<&Template> {
+        self.function()
+            .and_then(|(_, function)| function.s3_key.as_ref())
+            .or_else(|| self.metadata.s3_key.as_ref())
+    }
+
+    /// The suffix of the S3 keys, qualified with the target runtime for the
+    /// entries of a matrix.
+    fn s3_key_suffix(&self) -> String {
+        match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => format!("-{}.zip", target_runtime),
+            None => ".zip".to_string(),
+        }
+    }
+
+    /// Upload the archive to S3.
+    ///
+    /// Returns `false` if the archive already existed in the bucket and was
+    /// not uploaded.
+    fn upload_archive(&self) -> Result<bool> {
+        let archive_path = self.archive_path();
+        let s3_bucket = self.s3_bucket()?;
+        let s3_key = self.s3_key()?;
+
+        if self.context().options().force {
+            debug!("`--force` specified: not checking for the archive existence on S3 before uploading");
+        } else {
+            if self
+                .context()
+                .artifact_exists(&self.artifact_reference()?, self.exists())?
+            {
                 debug!(
-                    "The AWS Lambda archive `{}` does not exist in the S3 bucket `{}`: uploading.",
+                    "AWS Lambda archive `{}` already exists in the S3 bucket `{}`: not uploading again",
                     &s3_key, &s3_bucket
                 );
+
+                ignore_step!(
+                    "Up-to-date",
+                    "AWS Lambda archive `{}` already exists in S3 bucket `{}`",
+                    &s3_key,
+                    &s3_bucket
+                );
+
+                return Ok(false);
+            }
+
+            debug!(
+                "The AWS Lambda archive `{}` does not exist in the S3 bucket `{}`: uploading.",
+                &s3_key, &s3_bucket
+            );
+        }
+
+        let metadata = self.package.provenance()?.metadata();
+        let region = self.region()?;
+        let aws_settings = self.aws_settings()?;
+
+        let fut = async move {
+            let client = self.context().aws().s3_client(&aws_settings).await?;
+
+            if self.metadata.allow_s3_bucket_creation {
+                if self.context().options().dry_run {
+                    warn!("`--dry-run` specified, will not really ensure the S3 bucket exists");
+                } else {
+                    // Without a stated region, the bucket is created in the
+                    // region the client resolved.
+                    let bucket_region = match &region {
+                        Some(region) => Some(region.clone()),
+                        None => self.context().aws().region(&aws_settings).await?,
+                    };
+
+                    ensure_s3_bucket_exists(
+                        &client,
+                        &s3_bucket,
+                        bucket_region.as_deref(),
+                        &self.metadata.s3_bucket_creation,
+                    )
+                    .await?;
+                    validate_s3_bucket(&client, &s3_bucket, region.as_deref()).await?;
+                }
+            } else {
+                validate_s3_bucket(&client, &s3_bucket, region.as_deref()).await?;
             }
 
             if self.context().options().dry_run {
@@ -154,10 +525,17 @@ impl<'g> AwsLambdaDistTarget<'g> {
                     &s3_bucket
                 );
 
-                client.put_object().bucket(&s3_bucket).key(&s3_key).body(data).send()
+                client
+                    .put_object()
+                    .bucket(&s3_bucket)
+                    .key(&s3_key)
+                    .set_metadata(Some(metadata.into_iter().collect()))
+                    .body(data)
+                    .send()
                 .await
                 .map_err(|err|
                     Error::new("failed to upload archive on S3")
+                    .with_kind(ErrorKind::Network)
                     .with_source(err)
                     .with_explanation(format!(
                         "Please check that the S3 bucket `{}` exists and that you have the correct permissions.",
@@ -166,120 +544,255 @@ impl<'g> AwsLambdaDistTarget<'g> {
                 )?;
             }
 
-            Ok(())
+            Ok(true)
         };
 
-        runtime.block_on(fut)
+        self.context().block_on(fut)
     }
 
-    fn archive_path(&self) -> PathBuf {
-        self.target_dir().join("aws-lambda.zip")
-    }
-
-    fn build_zip_archive(&self) -> Result<()> {
-        let archive_path = self.archive_path();
+    /// Update the AWS Lambda function with the uploaded archive.
+    ///
+    /// A new version of the function is published and the alias, if any, is
+    /// moved to it. If a smoke test payload is specified, the new version is
+    /// then invoked with it and the alias is rolled back to its previous
+    /// version should the invocation fail.
+    fn deploy(&self, deployment: &AwsLambdaDeployment) -> Result<()> {
+        let s3_bucket = self.s3_bucket()?;
+        let s3_key = self.s3_key()?;
+        let function_name = &deployment.function_name;
 
-        action_step!("Packaging", "AWS Lambda archive");
+        if let Some(payload) = &deployment.smoke_test_payload {
+            serde_json::from_str::<serde_json::Value>(payload).map_err(|err| {
+                Error::new("invalid smoke test payload")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_source(err)
+                    .with_explanation(format!(
+                    "The smoke test payload of the AWS Lambda function `{}` must be valid JSON.",
+                    function_name
+                ))
+            })?;
+        }
 
-        let mut archive = zip::ZipWriter::new(
-            std::fs::File::create(&archive_path)
-                .map_err(|err| Error::new("failed to create zip archive file").with_source(err))?,
-        );
+        let aws_settings = self.aws_settings()?;
 
-        let lambda_root = &self.lambda_root();
+        self.context().block_on(async move {
+            let client = self.context().aws().lambda_client(&aws_settings).await?;
 
-        for entry in WalkDir::new(lambda_root) {
-            let entry = entry.map_err(|err| {
-                Error::new("failed to walk lambda root directory").with_source(err)
-            })?;
+            action_step!(
+                "Deploying",
+                "AWS Lambda archive `{}` to function `{}`",
+                &s3_key,
+                function_name
+            );
 
-            let file_path = entry
-                .path()
-                .strip_prefix(lambda_root)
-                .map_err(|err| {
-                    Error::new("failed to strip lambda root directory").with_source(err)
-                })?
-                .display()
-                .to_string();
+            let version = client
+                .update_function_code(function_name, &s3_bucket, &s3_key)
+                .await?;
+            client.wait_for_update(function_name, &version).await?;
 
-            let metadata = std::fs::metadata(entry.path())
-                .map_err(|err| Error::new("failed to get metadata").with_source(err))?;
+            let previous_version = match &deployment.alias {
+                Some(alias) => {
+                    let previous_version = client.alias_version(function_name, alias).await?;
 
-            let options = zip::write::FileOptions::default();
+                    action_step!(
+                        "Updating",
+                        "alias `{}` of AWS Lambda function `{}` to version {}",
+                        alias,
+                        function_name,
+                        &version
+                    );
 
-            #[cfg(not(windows))]
-            let options = {
-                use std::os::unix::prelude::PermissionsExt;
+                    client.update_alias(function_name, alias, &version).await?;
 
-                options.unix_permissions(metadata.permissions().mode())
+                    Some((alias, previous_version))
+                }
+                None => None,
             };
 
-            if metadata.is_file() {
-                archive.start_file(&file_path, options).map_err(|err| {
-                    Error::new("failed to start writing file in the archive")
-                        .with_source(err)
-                        .with_output(format!("file path: {}", file_path))
-                })?;
+            if let Some(payload) = &deployment.smoke_test_payload {
+                let qualifier = deployment.alias.as_ref().unwrap_or(&version);
 
-                let buf = std::fs::read(entry.path())
-                    .map_err(|err| Error::new("failed to open file").with_source(err))?;
+                action_step!(
+                    "Smoke-testing",
+                    "AWS Lambda function `{}:{}`",
+                    function_name,
+                    qualifier
+                );
 
-                archive.write_all(&buf).map_err(|err| {
-                    Error::new("failed to write file in the archive")
-                        .with_source(err)
-                        .with_output(format!("file path: {}", file_path))
-                })?;
-            } else if metadata.is_dir() {
-                archive.add_directory(&file_path, options).map_err(|err| {
-                    Error::new("failed to add directory to the archive")
-                        .with_source(err)
-                        .with_output(format!("file path: {}", file_path))
-                })?;
+                if let Err(err) = client.invoke(function_name, qualifier, payload).await {
+                    if let Some((alias, previous_version)) = previous_version {
+                        action_step!(
+                            "Rolling back",
+                            "alias `{}` of AWS Lambda function `{}` to version {}",
+                            alias,
+                            function_name,
+                            &previous_version
+                        );
+
+                        client
+                            .update_alias(function_name, alias, &previous_version)
+                            .await?;
+                    }
+
+                    return Err(err);
+                }
             }
+
+            Ok(())
+        })
+    }
+
+    fn archive_path(&self) -> PathBuf {
+        match self.function() {
+            Some((binary, _)) => self.target_dir().join(format!("aws-lambda-{}.zip", binary)),
+            None => self.target_dir().join("aws-lambda.zip"),
+        }
+    }
+
+    fn build_zip_archive(&self) -> Result<()> {
+        action_step!("Packaging", "AWS Lambda archive");
+
+        build_zip_archive(
+            &self.lambda_root(),
+            &self.archive_path(),
+            Some(MAX_UNZIPPED_SIZE),
+        )
+    }
+
+    /// Check the archive against the size limits of AWS Lambda, which would
+    /// otherwise only reject it when deploying the function.
+    fn check_archive_size(&self) -> Result<()> {
+        let unzipped_size = directory_size(&self.lambda_root())?;
+
+        if unzipped_size > MAX_UNZIPPED_SIZE {
+            return Err(Error::new("AWS Lambda archive too large")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The content of the archive weighs {} unzipped, which exceeds the {} limit of AWS Lambda. You may want to strip the binary (`strip = true` in the Cargo profile), remove extra files or move them to a Lambda layer.",
+                    format_size(unzipped_size),
+                    format_size(MAX_UNZIPPED_SIZE)
+                )));
+        }
+
+        let archive_size = std::fs::metadata(self.archive_path())
+            .map_err(Error::from_source)
+            .with_context("failed to read archive metadata")?
+            .len();
+
+        if archive_size > MAX_DIRECT_UPLOAD_SIZE {
+            warn!(
+                "The AWS Lambda archive weighs {}, above the {} limit of direct uploads: the function can only be deployed from S3. You may want to strip the binary (`strip = true` in the Cargo profile), enable `lto` or move extra files to a Lambda layer.",
+                format_size(archive_size),
+                format_size(MAX_DIRECT_UPLOAD_SIZE)
+            );
         }
 
-        archive
-            .finish()
-            .map_err(|err| Error::new("failed to write zip archive file").with_source(err))?;
+        // Layers count towards the unzipped limit as well.
+        if unzipped_size > MAX_UNZIPPED_SIZE / 10 * 8 {
+            warn!(
+                "The content of the AWS Lambda archive weighs {} unzipped, close to the {} limit of AWS Lambda, which includes the layers of the function.",
+                format_size(unzipped_size),
+                format_size(MAX_UNZIPPED_SIZE)
+            );
+        }
 
         Ok(())
     }
 
-    fn build_binary(&self) -> Result<PathBuf> {
-        self.build_binaries()?.remove(&self.metadata.binary).ok_or_else(|| {
-            Error::new("failed to find the specified binary in the binaries list")
-                .with_explanation(format!("The configuration requires this AWS Lambda to use the `{}` binary but no such binary is declared in the crate. Was the name perhaps mistyped?", self.metadata.binary))
-        })
+    /// The function this distribution target packages, with its binary, if
+    /// the package declares several functions.
+    fn function(&self) -> Option<(&String, &AwsLambdaFunction)> {
+        self.metadata
+            .function
+            .as_ref()
+            .and_then(|binary| self.metadata.functions.get_key_value(binary))
     }
 
-    fn build_binaries(&self) -> Result<HashMap<String, PathBuf>> {
-        let ws = self.context().workspace()?;
-        let mut compile_options = CompileOptions::new(ws.config(), CompileMode::Build).unwrap();
+    fn function_name(&self) -> Option<&str> {
+        self.function()
+            .map(|(binary, function)| function.name.as_deref().unwrap_or(binary))
+    }
 
-        compile_options.spec =
-            cargo::ops::Packages::Packages(vec![self.package.name().to_string()]);
-        compile_options.build_config.requested_profile =
-            cargo::util::interning::InternedString::new(&self.context().options().mode.to_string());
+    fn validate_functions(&self) -> Result<()> {
+        if !self.metadata.functions.is_empty() && self.metadata.binary.is_some() {
+            return Err(Error::new("invalid AWS Lambda configuration")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The `binary` and `functions` fields of {} are mutually exclusive: the binaries of the functions are the keys of `functions`.",
+                    self
+                )));
+        }
 
-        if !is_current_target_runtime(&self.metadata.target_runtime)? {
-            compile_options.build_config.requested_kinds =
-                vec![cargo::core::compiler::CompileKind::Target(
-                    CompileTarget::new(&self.metadata.target_runtime).unwrap(),
-                )];
+        Ok(())
+    }
+
+    fn runtime(&self) -> AwsLambdaRuntime {
+        self.metadata.runtime.unwrap_or_default()
+    }
+
+    fn validate_runtime(&self) -> Result<()> {
+        let runtime = self.runtime();
+
+        // Only the custom runtimes support the arm64 architecture.
+        if runtime == AwsLambdaRuntime::Go1x
+            && !self.metadata.target_runtime.as_str().starts_with("x86_64-")
+        {
+            return Err(Error::new("unsupported AWS Lambda runtime")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The `{}` runtime only supports x86_64 binaries but {} targets `{}`. Use a `provided` runtime instead.",
+                    runtime,
+                    self,
+                    self.metadata.target_runtime.as_str()
+                )));
         }
 
-        compile(&ws, &compile_options)
-            .map(|compilation| {
-                compilation
-                    .binaries
-                    .iter()
-                    .map(|b| (b.unit.target.name().to_string(), b.path.clone()))
-                    .collect()
-            })
-            .map_err(|err| Error::new("failed to compile binaries").with_source(err))
+        Ok(())
     }
 
-    fn copy_binary(&self, source: &Path) -> Result<()> {
+    /// Build the binary of the function, returning its name and path.
+    fn build_binary(&self) -> Result<(String, PathBuf)> {
+        let mut binaries = build_binaries(
+            self.package,
+            &self.name,
+            self.metadata.target_runtime.as_str(),
+            false,
+        )?;
+
+        let binary = self
+            .function()
+            .map(|(binary, _)| binary)
+            .or_else(|| self.metadata.binary.as_ref());
+
+        match binary {
+            Some(binary) => binaries.remove_entry(binary).ok_or_else(|| {
+                Error::new("failed to find the specified binary in the binaries list")
+                    .with_explanation(format!("The configuration requires this AWS Lambda to use the `{}` binary but no such binary is declared in the crate. Was the name perhaps mistyped?", binary))
+            }),
+            None if binaries.len() == 1 => Ok(binaries.into_iter().next().unwrap()),
+            None => {
+                let mut names: Vec<_> = binaries.into_keys().collect();
+                names.sort();
+
+                Err(Error::new("ambiguous AWS Lambda binary")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "The `{}` runtime expects exactly one binary but the crate declares {}: {}. Specify the binary of the function with the `binary` field.",
+                        self.runtime(),
+                        names.len(),
+                        names.join(", ")
+                    )))
+            }
+        }
+    }
+
+    /// The path of the binary in the archive.
+    fn executable_path(&self, name: &str) -> PathBuf {
+        self.lambda_root()
+            .join(self.runtime().executable_name().unwrap_or(name))
+    }
+
+    fn copy_binary(&self, name: &str, source: &Path) -> Result<PathBuf> {
         debug!("Will now copy the dependant binary");
 
         let lambda_root = self.lambda_root();
@@ -291,12 +804,11 @@ impl<'g> AwsLambdaDistTarget<'g> {
         format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", lambda_root.display()),
             )?;
 
-        // The name of the target binary is fixed to "bootstrap" by the folks at AWS.
-        let target = lambda_root.join("bootstrap");
+        let target = self.executable_path(name);
 
         debug!("Copying {} to {}", source.display(), target.display());
 
-        std::fs::copy(&source, target)
+        std::fs::copy(&source, &target)
             .map_err(Error::from_source)
             .with_full_context(
                 "failed to copy binary",
@@ -306,7 +818,7 @@ impl<'g> AwsLambdaDistTarget<'g> {
                 ),
             )?;
 
-        Ok(())
+        Ok(target)
     }
 
     fn clean(&self) -> Result<()> {
@@ -321,18 +833,23 @@ impl<'g> AwsLambdaDistTarget<'g> {
     }
 
     fn s3_bucket(&self) -> Result<String> {
-        match &self.metadata.s3_bucket {
+        let s3_bucket = self
+            .environment()?
+            .and_then(|environment| environment.s3_bucket.as_ref())
+            .or_else(|| self.metadata.s3_bucket.as_ref());
+
+        match s3_bucket {
             Some(s3_bucket) => Ok(s3_bucket.clone()),
             None => {
                 if let Ok(s3_bucket) = std::env::var(DEFAULT_AWS_LAMBDA_S3_BUCKET_ENV_VAR_NAME) {
                     Ok(s3_bucket)
                 } else {
-                    Err(
-                        Error::new("failed to determine AWS S3 bucket").with_explanation(format!(
+                    Err(Error::new("failed to determine AWS S3 bucket")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_explanation(format!(
                         "The field s3_bucket is empty and the environment variable {} was not set",
                         DEFAULT_AWS_LAMBDA_S3_BUCKET_ENV_VAR_NAME
-                    )),
-                    )
+                    )))
                 }
             }
         }
@@ -342,58 +859,41 @@ impl<'g> AwsLambdaDistTarget<'g> {
         self.context()
             .target_root()
             .unwrap()
-            .join(&self.metadata.target_runtime)
+            .join(self.metadata.target_runtime.as_str())
             .join(self.context().options().mode.to_string())
     }
 
-    fn lambda_root(&self) -> PathBuf {
-        self.target_dir()
-            .join("aws-lambda")
-            .join(self.package.name())
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.lambda_root()
     }
 
-    fn copy_extra_files(&self) -> Result<()> {
-        debug!("Will now copy all extra files");
+    fn lambda_root(&self) -> PathBuf {
+        let lambda_root = self
+            .target_dir()
+            .join("aws-lambda")
+            .join(self.package.name());
 
-        for copy_command in &self.metadata.extra_files {
-            copy_command.copy_files(self.package.root(), &self.lambda_root())?;
+        match self.function() {
+            Some((binary, _)) => lambda_root.join(binary),
+            None => lambda_root,
         }
+    }
 
-        Ok(())
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &self.lambda_root(),
+        )
     }
-}
 
-fn is_s3_no_such_key(
-    err: aws_sdk_s3::SdkError<aws_sdk_s3::error::GetObjectError>,
-    s3_key: &str,
-    s3_bucket: &str,
-) -> Result<()> {
-    match err {
-        aws_sdk_s3::SdkError::ServiceError { err, .. } => {
-            if !err.is_no_such_key() {
-                Err(Error::from_source(err)).with_full_context(
-                    "failed to check for AWS Lambda archive existence",
-                    format!(
-                        "Could not verify the existence of the AWS Lambda \
-                                        archive `{}` in the S3 bucket `{}`. Please check \
-                                        your credentials and permissions and make sure you \
-                                        have the appropriate permissions.",
-                        s3_key, s3_bucket
-                    ),
-                )
-            } else {
-                Ok(())
-            }
-        }
-        _ => Err(Error::from_source(err)).with_full_context(
-            "failed to check for AWS Lambda archive existence",
-            format!(
-                "Could not verify the existence of the AWS Lambda \
-                                archive `{}` in the S3 bucket `{}`. Please check \
-                                your credentials and permissions and make sure you \
-                                have the appropriate permissions.",
-                s3_key, s3_bucket
-            ),
-        ),
+    fn copy_extra_files(&self, copy_plan: &CopyPlan, executable: &Path) -> Result<()> {
+        debug!("Will now copy all extra files");
+
+        copy_plan.execute(
+            self.context().options().dry_run,
+            &[executable.to_path_buf()],
+        )
     }
 }
@@ -1,29 +1,203 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    aws_lambda::AwsLambdaDistTarget, dist_target::DistTarget, metadata::CopyCommand, Package,
+    aws::{AwsCredentialsEnv, S3BucketCreation},
+    aws_lambda::AwsLambdaDistTarget,
+    condition::Enabled,
+    dist_target::DistTarget,
+    metadata::{CopyCommand, TargetRuntime, Template},
+    size::SizeBudget,
+    Package,
 };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct AwsLambdaMetadata {
     pub s3_bucket: Option<String>,
     #[serde(default)]
     pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assume_role_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assume_role_external_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials_env: Option<AwsCredentialsEnv>,
     #[serde(default)]
     pub s3_bucket_prefix: String,
+    /// Whether to create the S3 bucket if it doesn't exist.
+    #[serde(default)]
+    pub allow_s3_bucket_creation: bool,
+    #[serde(default)]
+    pub s3_bucket_creation: S3BucketCreation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3_key: Option<Template>,
     #[serde(default = "default_target_runtime")]
-    pub target_runtime: String,
+    pub target_runtime: TargetRuntime,
     #[serde(default)]
     pub extra_files: Vec<CopyCommand>,
-    pub binary: String,
+    /// The runtime of the function, which determines the name of the binary
+    /// in the archive. Defaults to `provided.al2`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<AwsLambdaRuntime>,
+    /// The binary of the function, which is required only if the crate
+    /// contains more than one binary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// The maximum sizes of the binaries and of the artifact.
+    #[serde(default, skip_serializing_if = "SizeBudget::is_unlimited")]
+    pub size_budget: SizeBudget,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+    /// The destinations of the archive per environment, selected with
+    /// `--env-profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environments: BTreeMap<String, AwsLambdaEnvironment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<AwsLambdaDeployment>,
+    /// The functions of a package hosting several handlers, keyed by binary,
+    /// each packaged in its own archive.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub functions: BTreeMap<String, AwsLambdaFunction>,
+    /// The binary of the function this distribution target packages, once
+    /// expanded from `functions`.
+    #[serde(skip)]
+    pub function: Option<String>,
+}
+
+/// A function of a package hosting several handlers.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AwsLambdaFunction {
+    /// The name of the function, which defaults to the name of the binary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The S3 key template of the archive of the function, which defaults to
+    /// the `s3_key` of the distribution target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3_key: Option<Template>,
+    /// The AWS Lambda function to update once the archive of the function is
+    /// uploaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<AwsLambdaDeployment>,
+}
+
+/// The destination of the archive for an environment, whose unspecified
+/// fields fall back to the ones of the distribution target.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AwsLambdaEnvironment {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3_bucket: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3_bucket_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<AwsLambdaDeployment>,
+}
+
+/// The AWS Lambda function to update once its archive is uploaded.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AwsLambdaDeployment {
+    /// The name or ARN of the function.
+    pub function_name: String,
+    /// An alias moved to the new version of the function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// A JSON payload the new version of the function is invoked with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoke_test_payload: Option<String>,
+}
+
+/// The runtimes of AWS Lambda that run native binaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AwsLambdaRuntime {
+    #[serde(rename = "provided.al2023")]
+    ProvidedAl2023,
+    #[serde(rename = "provided.al2")]
+    ProvidedAl2,
+    #[serde(rename = "provided")]
+    Provided,
+    #[serde(rename = "go1.x")]
+    Go1x,
+}
+
+impl AwsLambdaRuntime {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ProvidedAl2023 => "provided.al2023",
+            Self::ProvidedAl2 => "provided.al2",
+            Self::Provided => "provided",
+            Self::Go1x => "go1.x",
+        }
+    }
+
+    /// The name the runtime requires for the binary, if any.
+    ///
+    /// The custom runtimes execute a binary named `bootstrap`, while the
+    /// `go1.x` runtime executes the binary named by the handler of the
+    /// function.
+    pub fn executable_name(self) -> Option<&'static str> {
+        match self {
+            Self::ProvidedAl2023 | Self::ProvidedAl2 | Self::Provided => Some("bootstrap"),
+            Self::Go1x => None,
+        }
+    }
 }
 
-fn default_target_runtime() -> String {
-    "x86_64-unknown-linux-musl".to_string()
+impl Default for AwsLambdaRuntime {
+    fn default() -> Self {
+        Self::ProvidedAl2
+    }
+}
+
+impl std::fmt::Display for AwsLambdaRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+fn default_target_runtime() -> TargetRuntime {
+    TargetRuntime::new("x86_64-unknown-linux-musl")
 }
 
 impl AwsLambdaMetadata {
+    /// Expand the distribution target into one per function and entry of its
+    /// target runtime matrix.
+    pub(crate) fn expand(&self) -> Vec<Self> {
+        let functions: Vec<Option<String>> = if self.functions.is_empty() {
+            vec![None]
+        } else {
+            self.functions.keys().cloned().map(Some).collect()
+        };
+
+        functions
+            .into_iter()
+            .flat_map(|function| {
+                self.target_runtime
+                    .expand()
+                    .into_iter()
+                    .map(move |target_runtime| Self {
+                        target_runtime,
+                        function: function.clone(),
+                        ..self.clone()
+                    })
+            })
+            .collect()
+    }
+
     pub(crate) fn into_dist_target<'g>(
         self,
         name: String,
@@ -1,5 +1,10 @@
+mod client;
 mod dist_target;
 mod metadata;
 
+pub(crate) use client::LambdaClient;
 pub use dist_target::AwsLambdaDistTarget;
-pub use metadata::AwsLambdaMetadata;
+pub use metadata::{
+    AwsLambdaDeployment, AwsLambdaEnvironment, AwsLambdaFunction, AwsLambdaMetadata,
+    AwsLambdaRuntime,
+};
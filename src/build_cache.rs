@@ -0,0 +1,107 @@
+//! Integration of shared compilation caches, like `sccache` or `buildcache`.
+
+use std::{collections::BTreeMap, path::Path, process::Command};
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::{action_step, Error, ErrorContext, Result};
+
+/// The build cache configuration of the workspace, read from the
+/// `[workspace.metadata.monorepo.build_cache]` section of the root manifest.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildCacheMetadata {
+    /// The compiler wrapper to use, as a program name or path.
+    #[serde(default = "default_wrapper")]
+    pub wrapper: String,
+    /// Additional environment variables configuring the cache, like
+    /// `SCCACHE_BUCKET` or `SCCACHE_REGION`.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+fn default_wrapper() -> String {
+    "sccache".to_string()
+}
+
+impl BuildCacheMetadata {
+    /// Set the environment variables that make Cargo use the build cache.
+    ///
+    /// This must happen before the Cargo configuration is loaded. An existing
+    /// `RUSTC_WRAPPER` environment variable takes precedence.
+    pub(crate) fn apply(&self) {
+        if let Ok(wrapper) = std::env::var("RUSTC_WRAPPER") {
+            debug!(
+                "`RUSTC_WRAPPER` is already set to `{}`: not using `{}`",
+                wrapper, self.wrapper
+            );
+        } else {
+            debug!("Using `{}` as the compiler wrapper", self.wrapper);
+            std::env::set_var("RUSTC_WRAPPER", &self.wrapper);
+        }
+
+        for (key, value) in &self.env {
+            std::env::set_var(key, value);
+        }
+    }
+
+    /// Reset the statistics of the build cache, so that the ones reported at
+    /// the end of the run only account for its compilations.
+    pub(crate) fn reset_stats(&self) -> Result<()> {
+        match self.stats_args() {
+            Some((reset_arg, _)) => self.run(reset_arg).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Print the statistics of the build cache.
+    pub(crate) fn report_stats(&self) -> Result<()> {
+        if let Some((_, show_arg)) = self.stats_args() {
+            action_step!("Reporting", "`{}` statistics", self.wrapper);
+            print!("{}", self.run(show_arg)?);
+        }
+
+        Ok(())
+    }
+
+    /// The arguments to reset and show the statistics of the known wrappers.
+    fn stats_args(&self) -> Option<(&'static str, &'static str)> {
+        match Path::new(&self.wrapper)
+            .file_stem()
+            .and_then(std::ffi::OsStr::to_str)
+        {
+            Some("sccache" | "buildcache") => Some(("--zero-stats", "--show-stats")),
+            _ => {
+                debug!(
+                    "Unknown compiler wrapper `{}`: not reporting statistics",
+                    self.wrapper
+                );
+
+                None
+            }
+        }
+    }
+
+    fn run(&self, arg: &str) -> Result<String> {
+        let output = Command::new(&self.wrapper)
+            .arg(arg)
+            .output()
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to run the compiler wrapper",
+                format!(
+                    "The compiler wrapper `{}` could not be executed. Is it installed?",
+                    self.wrapper
+                ),
+            )?;
+
+        if !output.status.success() {
+            return Err(Error::new("failed to run the compiler wrapper")
+                .with_explanation(format!("`{} {}` failed.", self.wrapper, arg))
+                .with_output(String::from_utf8_lossy(&output.stderr)));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
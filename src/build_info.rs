@@ -0,0 +1,71 @@
+//! Build information embedded into the binaries of the distribution targets,
+//! through environment variables read at compile time.
+
+use aws_smithy_types::date_time::{DateTime, Format};
+use log::debug;
+use serde::Deserialize;
+
+use crate::{prune::unix_timestamp, Package, Result};
+
+/// The build information configuration of the workspace, read from the
+/// `[workspace.metadata.monorepo.build_info]` section of the root manifest.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildInfoMetadata {
+    /// The prefix of the names of the environment variables, to avoid
+    /// conflicts with the ones read by the dependencies.
+    #[serde(default)]
+    pub prefix: String,
+}
+
+impl BuildInfoMetadata {
+    /// Run a compilation of the binaries of a package with the build
+    /// information in the environment, removing it afterwards so that it does
+    /// not leak into the compilation of other packages.
+    ///
+    /// The variables that cannot be determined, like the Git commit outside
+    /// of a repository, are not set.
+    pub(crate) fn with_build_info<T>(
+        &self,
+        package: &Package<'_>,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let provenance = package.provenance()?;
+
+        // Honor `SOURCE_DATE_EPOCH` for reproducible builds.
+        let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(unix_timestamp);
+
+        let vars = [
+            ("GIT_SHA", provenance.git_commit),
+            ("PACKAGE_HASH", Some(provenance.package_hash)),
+            (
+                "BUILD_TIMESTAMP",
+                DateTime::from_secs(timestamp).fmt(Format::DateTime).ok(),
+            ),
+            ("VERSION", Some(package.version().to_string())),
+        ];
+
+        let vars: Vec<_> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| (format!("{}{}", self.prefix, name), value))
+            })
+            .collect();
+
+        for (name, value) in &vars {
+            debug!("Setting `{}` to `{}` for `{}`", name, value, package.name());
+            std::env::set_var(name, value);
+        }
+
+        let result = f();
+
+        for (name, _) in &vars {
+            std::env::remove_var(name);
+        }
+
+        result
+    }
+}
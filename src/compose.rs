@@ -0,0 +1,80 @@
+//! Generation of `docker-compose.yaml` files for local multi-service
+//! environments.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Serialize;
+
+use crate::{dist_target::DistTarget, Error, ErrorContext, Package, Result};
+
+/// The version of the Compose file format that is generated.
+const COMPOSE_FILE_VERSION: &str = "3.8";
+
+#[derive(Debug, Serialize)]
+struct ComposeFile {
+    version: &'static str,
+    services: BTreeMap<String, ComposeService>,
+}
+
+/// A service of a `docker-compose.yaml` file.
+#[derive(Debug, Serialize)]
+pub(crate) struct ComposeService {
+    pub image: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<String>,
+}
+
+/// Write a `docker-compose.yaml` file with a service for each Docker
+/// distribution target of the specified packages.
+///
+/// Services are named after their distribution targets and reference the
+/// locally-built images, which must have been built beforehand.
+pub fn write_compose_file(packages: &[Package<'_>], path: &Path) -> Result<()> {
+    let mut services = BTreeMap::new();
+
+    for package in packages {
+        for dist_target in package.dist_targets() {
+            if let DistTarget::Docker(docker) = &dist_target {
+                if services.contains_key(&docker.name) {
+                    return Err(Error::new("duplicate Docker Compose service").with_explanation(
+                        format!(
+                            "Several Docker distribution targets are named `{}`, which cannot be used as a service name twice.",
+                            docker.name
+                        ),
+                    ));
+                }
+
+                services.insert(docker.name.clone(), docker.compose_service()?);
+            }
+        }
+    }
+
+    if services.is_empty() {
+        return Err(
+            Error::new("no Docker distribution target").with_explanation(
+                "None of the specified packages has a distribution target of type `docker`.",
+            ),
+        );
+    }
+
+    let data = serde_yaml::to_string(&ComposeFile {
+        version: COMPOSE_FILE_VERSION,
+        services,
+    })
+    .map_err(Error::from_source)
+    .with_context("failed to serialize Docker Compose file")?;
+
+    std::fs::write(path, data)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to write Docker Compose file",
+            format!(
+                "The Docker Compose file could not be written to `{}`. You may want to verify permissions.",
+                path.display()
+            ),
+        )
+}
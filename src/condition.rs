@@ -0,0 +1,484 @@
+//! Conditions enabling distribution targets only in some build modes or on
+//! some platforms.
+
+use std::fmt::Display;
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Options;
+
+/// The variables conditions can compare.
+const VARIABLES: &[&str] = &[
+    "mode",
+    "target_runtime",
+    "target_os",
+    "target_arch",
+    "host_os",
+    "host_arch",
+    "env_profile",
+];
+
+/// Whether a distribution target is enabled, either unconditionally or when
+/// a condition holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Enabled {
+    Bool(bool),
+    Condition(Condition),
+}
+
+impl Default for Enabled {
+    fn default() -> Self {
+        Self::Bool(true)
+    }
+}
+
+impl Enabled {
+    pub(crate) fn is_default(&self) -> bool {
+        matches!(self, Self::Bool(true))
+    }
+
+    /// Whether the distribution target is enabled for the specified options
+    /// and target runtime, if it has one.
+    pub(crate) fn evaluate(&self, options: &Options, target_runtime: Option<&str>) -> bool {
+        match self {
+            Self::Bool(enabled) => *enabled,
+            Self::Condition(condition) => {
+                condition.evaluate(&Variables::new(options, target_runtime))
+            }
+        }
+    }
+}
+
+impl Display for Enabled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool(enabled) => write!(f, "{}", enabled),
+            Self::Condition(condition) => write!(f, "{}", condition),
+        }
+    }
+}
+
+impl Serialize for Enabled {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Bool(enabled) => enabled.serialize(serializer),
+            Self::Condition(condition) => condition.source.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Enabled {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Helper {
+            Bool(bool),
+            Condition(String),
+        }
+
+        match Helper::deserialize(deserializer)? {
+            Helper::Bool(enabled) => Ok(Self::Bool(enabled)),
+            Helper::Condition(source) => {
+                Condition::parse(&source)
+                    .map(Self::Condition)
+                    .map_err(|err| {
+                        serde::de::Error::custom(format!("invalid condition `{}`: {}", source, err))
+                    })
+            }
+        }
+    }
+}
+
+impl JsonSchema for Enabled {
+    fn schema_name() -> String {
+        "Enabled".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut condition = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            ..SchemaObject::default()
+        };
+
+        condition.metadata().description = Some(
+            "A condition, like `mode == \"release\" && target_os != \"windows\"`.".to_string(),
+        );
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![gen.subschema_for::<bool>(), condition.into()]),
+                ..SubschemaValidation::default()
+            })),
+            ..SchemaObject::default()
+        }
+        .into()
+    }
+}
+
+/// A condition expression, like `mode == "release" && target_os != "windows"`.
+///
+/// Conditions compare variables to string literals with `==` and `!=`, and
+/// combine comparisons with `&&`, `||`, `!` and parentheses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Literal(bool),
+    Equals(String, String),
+    NotEquals(String, String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> std::result::Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+
+        let expr = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {}", token));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    fn evaluate(&self, variables: &Variables) -> bool {
+        self.expr.evaluate(variables)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, variables: &Variables) -> bool {
+        match self {
+            Self::Literal(value) => *value,
+            Self::Equals(variable, value) => variables.get(variable) == value,
+            Self::NotEquals(variable, value) => variables.get(variable) != value,
+            Self::Not(expr) => !expr.evaluate(variables),
+            Self::And(lhs, rhs) => lhs.evaluate(variables) && rhs.evaluate(variables),
+            Self::Or(lhs, rhs) => lhs.evaluate(variables) || rhs.evaluate(variables),
+        }
+    }
+}
+
+/// The values of the variables of a condition.
+struct Variables {
+    mode: &'static str,
+    target_runtime: String,
+    target_os: String,
+    target_arch: String,
+    env_profile: String,
+}
+
+impl Variables {
+    fn new(options: &Options, target_runtime: Option<&str>) -> Self {
+        let (target_os, target_arch) = match target_runtime {
+            Some(target_runtime) => (
+                target_os(target_runtime).to_string(),
+                target_runtime
+                    .split('-')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            None => (
+                std::env::consts::OS.to_string(),
+                std::env::consts::ARCH.to_string(),
+            ),
+        };
+
+        Self {
+            mode: if options.mode.is_release() {
+                "release"
+            } else {
+                "debug"
+            },
+            target_runtime: target_runtime.unwrap_or_default().to_string(),
+            target_os,
+            target_arch,
+            env_profile: options.env_profile.clone().unwrap_or_default(),
+        }
+    }
+
+    fn get(&self, variable: &str) -> &str {
+        match variable {
+            "mode" => self.mode,
+            "target_runtime" => &self.target_runtime,
+            "target_os" => &self.target_os,
+            "target_arch" => &self.target_arch,
+            "host_os" => std::env::consts::OS,
+            "host_arch" => std::env::consts::ARCH,
+            "env_profile" => &self.env_profile,
+            // Unknown variables are rejected when parsing.
+            _ => unreachable!("unknown condition variable `{}`", variable),
+        }
+    }
+}
+
+/// The operating system of a target runtime, named like Rust's `target_os`.
+fn target_os(target_runtime: &str) -> &'static str {
+    if target_runtime.contains("android") {
+        "android"
+    } else if target_runtime.contains("windows") {
+        "windows"
+    } else if target_runtime.contains("darwin") {
+        "macos"
+    } else if target_runtime.contains("ios") {
+        "ios"
+    } else if target_runtime.contains("linux") {
+        "linux"
+    } else if target_runtime.contains("freebsd") {
+        "freebsd"
+    } else if target_runtime.contains("wasi") {
+        "wasi"
+    } else {
+        "unknown"
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Equals,
+    NotEquals,
+    And,
+    Or,
+    Not,
+    OpenParen,
+    CloseParen,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ident(ident) => write!(f, "`{}`", ident),
+            Self::String(value) => write!(f, "string \"{}\"", value),
+            Self::Equals => write!(f, "`==`"),
+            Self::NotEquals => write!(f, "`!=`"),
+            Self::And => write!(f, "`&&`"),
+            Self::Or => write!(f, "`||`"),
+            Self::Not => write!(f, "`!`"),
+            Self::OpenParen => write!(f, "`(`"),
+            Self::CloseParen => write!(f, "`)`"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Equals,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::NotEquals,
+            '!' => Token::Not,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '"' | '\'' => {
+                let mut value = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+
+                Token::String(value)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    ident.push(c);
+                }
+
+                Token::Ident(ident)
+            }
+            c => return Err(format!("unexpected character `{}`", c)),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+
+        token
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+
+        while self.next_if(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> std::result::Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+
+        while self.next_if(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> std::result::Result<Expr, String> {
+        match self.advance() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::OpenParen) => {
+                let expr = self.parse_or()?;
+
+                match self.advance() {
+                    Some(Token::CloseParen) => Ok(expr),
+                    Some(token) => Err(format!("expected `)` but found {}", token)),
+                    None => Err("expected `)` but found end of condition".to_string()),
+                }
+            }
+            Some(Token::Ident(ident)) if ident == "true" => Ok(Expr::Literal(true)),
+            Some(Token::Ident(ident)) if ident == "false" => Ok(Expr::Literal(false)),
+            Some(Token::Ident(variable)) => {
+                if !VARIABLES.contains(&variable.as_str()) {
+                    return Err(format!(
+                        "unknown variable `{}`, expected one of: {}",
+                        variable,
+                        VARIABLES.join(", ")
+                    ));
+                }
+
+                let equals = match self.advance() {
+                    Some(Token::Equals) => true,
+                    Some(Token::NotEquals) => false,
+                    Some(token) => {
+                        return Err(format!("expected `==` or `!=` but found {}", token))
+                    }
+                    None => {
+                        return Err("expected `==` or `!=` but found end of condition".to_string())
+                    }
+                };
+
+                let value = match self.advance() {
+                    Some(Token::String(value)) => value,
+                    Some(token) => return Err(format!("expected a string but found {}", token)),
+                    None => return Err("expected a string but found end of condition".to_string()),
+                };
+
+                Ok(if equals {
+                    Expr::Equals(variable, value)
+                } else {
+                    Expr::NotEquals(variable, value)
+                })
+            }
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("unexpected end of condition".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Mode;
+
+    fn evaluate(source: &str, options: &Options, target_runtime: Option<&str>) -> bool {
+        Condition::parse(source)
+            .unwrap()
+            .evaluate(&Variables::new(options, target_runtime))
+    }
+
+    #[test]
+    fn test_condition() {
+        let options = Options {
+            mode: Mode::Release,
+            ..Options::default()
+        };
+
+        assert!(evaluate(r#"mode == "release""#, &options, None));
+        assert!(!evaluate(r#"mode != 'release'"#, &options, None));
+        assert!(evaluate(
+            r#"mode == "debug" || target_os == "linux" && target_arch == "x86_64""#,
+            &options,
+            Some("x86_64-unknown-linux-gnu")
+        ));
+        assert!(!evaluate(
+            r#"!(target_os == "windows" || target_os == "linux")"#,
+            &options,
+            Some("aarch64-unknown-linux-musl")
+        ));
+        assert!(evaluate(
+            r#"target_os == "macos""#,
+            &options,
+            Some("aarch64-apple-darwin")
+        ));
+        assert!(evaluate(r#"env_profile == """#, &options, None));
+        assert!(evaluate("true && !false", &options, None));
+
+        assert!(Condition::parse(r#"os == "linux""#).is_err());
+        assert!(Condition::parse(r#"mode = "release""#).is_err());
+        assert!(Condition::parse(r#"(mode == "release""#).is_err());
+        assert!(Condition::parse(r#"mode == "release"#).is_err());
+        assert!(Condition::parse(r#"mode == "release" mode"#).is_err());
+    }
+}
@@ -1,20 +1,104 @@
 //! Gathers all the environment information and build a Context containing
 //! all relevant information for the rest of the commands.
 
+use futures::StreamExt;
 use git2::Repository;
 use guppy::graph::DependencyDirection;
 use itertools::Itertools;
-use log::debug;
-use std::{fmt::Display, path::PathBuf};
+use log::{debug, warn};
+use once_cell::unsync::OnceCell;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    future::Future,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use crate::{Error, Package, Result};
+use crate::{
+    action_step,
+    audit::{AuditEntry, AuditLog},
+    aws::AwsContext,
+    build_cache::BuildCacheMetadata,
+    build_info::BuildInfoMetadata,
+    dist_target::DistTarget,
+    graph_cache::load_package_graph,
+    ignore_step,
+    licenses::LicensePolicy,
+    metadata::{Metadata, TemplatePartials, WorkspaceMetadata},
+    notify::NotificationsMetadata,
+    registry::{registry_host, RegistryCredentials},
+    report::{Provenance, PublishReport, PublishedArtifact},
+    rust::CompiledBinaries,
+    sources::{comparable_path, SourceOwners},
+    Error, ErrorCode, ErrorContext, ErrorKind, Package, Result,
+};
 
-#[derive(Default, Debug)]
+/// The maximum number of artifact existence checks to run concurrently.
+const MAX_CONCURRENT_EXISTENCE_CHECKS: usize = 16;
+
+#[derive(Default, Debug, Clone)]
 pub struct Options {
     pub dry_run: bool,
     pub force: bool,
     pub verbose: bool,
     pub mode: Mode,
+    pub no_compile: bool,
+    pub no_image: bool,
+    /// Whether to only upload the AWS Lambda archives, without deploying
+    /// them to their functions.
+    pub no_deploy: bool,
+    /// Whether to compare the built Docker images with their latest
+    /// published version.
+    pub diff_published: bool,
+    pub jobs: Option<u32>,
+    pub allow_dirty: bool,
+    pub offline: bool,
+    pub locked: bool,
+    pub frozen: bool,
+    pub aws_region: Option<String>,
+    pub aws_endpoint_url: Option<String>,
+    pub aws_profile: Option<String>,
+    pub aws_assume_role_arn: Option<String>,
+    pub aws_assume_role_external_id: Option<String>,
+    pub aws_max_attempts: Option<u32>,
+    /// The environment profile selecting the destinations of the distribution
+    /// targets that declare several, like `staging` or `production`.
+    pub env_profile: Option<String>,
+    /// The identifier of the preview environment the artifacts are built
+    /// for, like the number of a pull request.
+    pub preview: Option<String>,
+    /// The path of the audit log, overriding the one of the workspace
+    /// configuration.
+    pub audit_log: Option<PathBuf>,
+    /// Whether to ignore the unknown fields of the metadata with a warning
+    /// instead of failing, to read manifests written for newer versions.
+    pub lenient_metadata: bool,
+    /// Whether the untracked files that are not ignored count as changes
+    /// when detecting the changes since a Git reference, like `git status`
+    /// does.
+    pub include_untracked: bool,
+    /// The directory in which the distributable artifacts are staged,
+    /// overriding the Cargo target directory.
+    pub target_root: Option<PathBuf>,
+    /// Whether each distribution target is compiled in its own Cargo target
+    /// directory, so that concurrent builds of a package for several target
+    /// runtimes do not clobber each other's artifacts.
+    pub isolated_target_dirs: bool,
+}
+
+/// The error returned when an action requiring network access is attempted
+/// while `--offline` was specified.
+pub(crate) fn offline_error(action: impl Display) -> Error {
+    Error::new("network access forbidden")
+        .with_kind(ErrorKind::Configuration)
+        .with_code(ErrorCode::NetworkAccessForbidden)
+        .with_explanation(format!(
+            "`--offline` was specified but {} requires network access. Remove `--offline` to proceed.",
+            action
+        ))
 }
 
 /// A build mode that can either be `Debug` or `Release`.
@@ -42,6 +126,44 @@ impl Mode {
     }
 }
 
+impl Options {
+    /// The extra arguments of the Cargo commands run on the packages.
+    pub fn cargo_args(&self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+
+        if self.offline {
+            args.push("--offline");
+        }
+
+        if self.locked {
+            args.push("--locked");
+        }
+
+        if self.frozen {
+            args.push("--frozen");
+        }
+
+        args
+    }
+
+    /// Qualify a Docker tag with the preview identifier, if any.
+    pub(crate) fn preview_tag(&self, tag: String) -> String {
+        match &self.preview {
+            Some(preview) => format!("{}-{}", tag, preview),
+            None => tag,
+        }
+    }
+
+    /// Qualify a S3 prefix with the preview identifier, if any, so that the
+    /// artifacts of preview environments are kept apart from the others.
+    pub(crate) fn preview_s3_prefix(&self, s3_bucket_prefix: &str) -> String {
+        match &self.preview {
+            Some(preview) => format!("{}{}/", s3_bucket_prefix, preview),
+            None => s3_bucket_prefix.to_string(),
+        }
+    }
+}
+
 impl Default for Mode {
     fn default() -> Self {
         Self::Debug
@@ -108,6 +230,22 @@ pub struct Context {
     options: Options,
     config: cargo::util::Config,
     package_graph: guppy::graph::PackageGraph,
+    runtime: tokio::runtime::Runtime,
+    aws: AwsContext,
+    build_cache: Option<BuildCacheMetadata>,
+    build_info: Option<BuildInfoMetadata>,
+    notifications: Option<NotificationsMetadata>,
+    audit_log: Option<RefCell<AuditLog>>,
+    template_partials: TemplatePartials,
+    groups: BTreeMap<String, Vec<String>>,
+    license_policy: LicensePolicy,
+    normalize_line_endings: bool,
+    existence_cache: RefCell<HashMap<String, bool>>,
+    registry_logins: RefCell<HashSet<String>>,
+    ignored_metadata_fields: RefCell<HashSet<String>>,
+    compiled_binaries: RefCell<HashMap<(String, String), CompiledBinaries>>,
+    base_image_dependants: OnceCell<HashMap<String, Vec<guppy::PackageId>>>,
+    dirty_files: OnceCell<Vec<PathBuf>>,
 }
 
 impl Context {
@@ -116,21 +254,81 @@ impl Context {
         ContextBuilder::default()
     }
 
-    fn new(manifest_path: PathBuf, options: Options) -> Result<Self> {
-        let config = cargo::util::config::Config::default()
+    fn new(manifest_path: PathBuf, mut options: Options) -> Result<Self> {
+        let workspace_metadata = WorkspaceMetadata::load(&manifest_path, options.lenient_metadata)?;
+
+        // The workspace configuration can only make the lock file enforcement
+        // stricter than the command line.
+        options.locked |= workspace_metadata.locked;
+        options.frozen |= workspace_metadata.frozen;
+
+        // The build cache is configured through the environment, which Cargo
+        // reads when loading its configuration.
+        let build_cache = workspace_metadata.build_cache;
+
+        if let Some(build_cache) = &build_cache {
+            build_cache.apply();
+        }
+
+        let mut config = cargo::util::config::Config::default()
             .map_err(|err| Error::new("failed to load Cargo configuration").with_source(err))?;
 
-        let mut cmd = guppy::MetadataCommand::new();
-        cmd.manifest_path(&manifest_path);
+        if options.offline || options.locked || options.frozen {
+            config
+                .configure(
+                    0,
+                    false,
+                    None,
+                    options.frozen,
+                    options.locked,
+                    options.offline,
+                    &None,
+                    &[],
+                    &[],
+                )
+                .map_err(|err| Error::new("failed to load Cargo configuration").with_source(err))?;
+        }
+
+        let package_graph = load_package_graph(&manifest_path, &options, &config)?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|err| Error::new("failed to create async runtime").with_source(err))?;
 
-        let package_graph = guppy::graph::PackageGraph::from_command(&mut cmd)
-            .map_err(|err| Error::new("failed to parse package graph").with_source(err))?;
+        let aws = AwsContext::new(&options);
+
+        let workspace_root = package_graph.workspace().root().as_std_path();
+        let audit_log = match (&options.audit_log, workspace_metadata.audit_log) {
+            (Some(path), metadata) => Some(AuditLog::new(path.clone(), metadata)),
+            (None, Some(metadata)) => Some(AuditLog::new(
+                workspace_root.join(&metadata.path),
+                Some(metadata),
+            )),
+            (None, None) => None,
+        };
 
         Ok(Self {
             manifest_path,
             options,
             config,
             package_graph,
+            runtime,
+            aws,
+            build_cache,
+            build_info: workspace_metadata.build_info,
+            notifications: workspace_metadata.notifications,
+            audit_log: audit_log.map(RefCell::new),
+            template_partials: workspace_metadata.partials,
+            groups: workspace_metadata.groups,
+            license_policy: workspace_metadata.licenses,
+            normalize_line_endings: workspace_metadata.normalize_line_endings,
+            existence_cache: RefCell::default(),
+            registry_logins: RefCell::default(),
+            ignored_metadata_fields: RefCell::default(),
+            compiled_binaries: RefCell::default(),
+            base_image_dependants: OnceCell::new(),
+            dirty_files: OnceCell::new(),
         })
     }
 
@@ -138,44 +336,363 @@ impl Context {
         &self.options
     }
 
+    /// The binaries of a package compiled in advance for a target runtime,
+    /// along with other packages.
+    pub(crate) fn compiled_binaries(
+        &self,
+        package_name: &str,
+        target_runtime: &str,
+    ) -> Option<CompiledBinaries> {
+        self.compiled_binaries
+            .borrow()
+            .get(&(package_name.to_string(), target_runtime.to_string()))
+            .cloned()
+    }
+
+    pub(crate) fn record_compiled_binaries(
+        &self,
+        package_name: String,
+        target_runtime: &str,
+        compiled_binaries: CompiledBinaries,
+    ) {
+        self.compiled_binaries.borrow_mut().insert(
+            (package_name, target_runtime.to_string()),
+            compiled_binaries,
+        );
+    }
+
+    /// The workspace packages whose Docker images extend the image of each
+    /// package, by name of that package.
+    ///
+    /// Base images are not part of the package graph, so the metadata of all
+    /// the workspace packages is parsed the first time this is needed.
+    pub(crate) fn base_image_dependants(&self) -> Result<&HashMap<String, Vec<guppy::PackageId>>> {
+        self.base_image_dependants.get_or_try_init(|| {
+            let mut base_image_dependants: HashMap<String, Vec<guppy::PackageId>> = HashMap::new();
+
+            for package_metadata in self.package_graph.workspace().iter() {
+                for dist_target in Metadata::new(self, &package_metadata)?
+                    .dist_targets
+                    .values()
+                {
+                    if let Some(base_image) = dist_target.base_image() {
+                        let ids = base_image_dependants
+                            .entry(base_image.to_string())
+                            .or_default();
+
+                        if !ids.contains(package_metadata.id()) {
+                            ids.push(package_metadata.id().clone());
+                        }
+                    }
+                }
+            }
+
+            Ok(base_image_dependants)
+        })
+    }
+
+    /// Warn about an unknown field of the metadata of a package ignored in
+    /// lenient mode.
+    ///
+    /// As the metadata of a package is parsed every time the package is
+    /// resolved, each field is only reported once per run.
+    pub(crate) fn ignore_metadata_field(&self, package_name: &str, path: &str) {
+        if self
+            .ignored_metadata_fields
+            .borrow_mut()
+            .insert(format!("{}:{}", package_name, path))
+        {
+            ignore_step!(
+                "Ignoring",
+                "unknown field `{}` of the manifest of {}",
+                path,
+                package_name
+            );
+        }
+    }
+
+    /// Fail if `--offline` was specified, as the specified action requires
+    /// network access.
+    pub fn ensure_online(&self, action: &str) -> Result<()> {
+        if self.options.offline {
+            Err(offline_error(action))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Run a future to completion on the shared async runtime.
+    pub(crate) fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub(crate) fn aws(&self) -> &AwsContext {
+        &self.aws
+    }
+
+    pub(crate) fn package_graph(&self) -> &guppy::graph::PackageGraph {
+        &self.package_graph
+    }
+
+    /// The template partials shared by the packages of the workspace.
+    pub(crate) fn template_partials(&self) -> &TemplatePartials {
+        &self.template_partials
+    }
+
+    /// The build information to embed into the binaries of the distribution
+    /// targets, if enabled.
+    pub(crate) fn build_info(&self) -> Option<&BuildInfoMetadata> {
+        self.build_info.as_ref()
+    }
+
+    /// The license policy of the workspace.
+    pub(crate) fn license_policy(&self) -> &LicensePolicy {
+        &self.license_policy
+    }
+
+    /// Whether the line endings of text files are normalized before hashing
+    /// them.
+    pub(crate) fn normalize_line_endings(&self) -> bool {
+        self.normalize_line_endings
+    }
+
+    /// Notify the results of a publication, if notifications are configured.
+    ///
+    /// `error` is the error that interrupted the publication, if any.
+    pub fn notify_publication(
+        &self,
+        report: &PublishReport,
+        error: Option<&Error>,
+        environment: Option<&str>,
+    ) -> Result<()> {
+        match &self.notifications {
+            Some(notifications) => {
+                notifications.notify_publication(self, report, error, environment)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record the publication of a distribution target in the audit log, if
+    /// one is configured.
+    ///
+    /// `artifact` is `None` in dry-run mode, or if the distribution target
+    /// was not published, in which case nothing is recorded.
+    pub(crate) fn audit_publication(
+        &self,
+        package: &Package<'_>,
+        dist_target: &DistTarget<'_>,
+        provenance: &Provenance,
+        artifact: Option<&PublishedArtifact>,
+    ) -> Result<()> {
+        if artifact.is_none() && !self.options.dry_run {
+            return Ok(());
+        }
+
+        match &self.audit_log {
+            Some(audit_log) => audit_log.borrow_mut().record(AuditEntry::new(
+                package,
+                dist_target,
+                provenance,
+                artifact,
+            )?),
+            None => Ok(()),
+        }
+    }
+
+    /// Upload the audit log entries of the run to S3, if configured.
+    pub fn upload_audit_log(&self) -> Result<()> {
+        match &self.audit_log {
+            Some(audit_log) => audit_log.borrow().upload(self),
+            None => Ok(()),
+        }
+    }
+
+    /// Reset the statistics of the build cache, if one is configured.
+    pub fn reset_build_cache_stats(&self) -> Result<()> {
+        match &self.build_cache {
+            Some(build_cache) => build_cache.reset_stats(),
+            None => Ok(()),
+        }
+    }
+
+    /// Print the statistics of the build cache, if one is configured.
+    pub fn report_build_cache_stats(&self) -> Result<()> {
+        match &self.build_cache {
+            Some(build_cache) => build_cache.report_stats(),
+            None => Ok(()),
+        }
+    }
+
+    /// Check concurrently whether the artifacts of all the distribution
+    /// targets of the specified packages exist.
+    ///
+    /// The results are cached and used by subsequent calls to
+    /// `artifact_exists`, which avoids running the checks serially during
+    /// publication.
+    pub fn prefetch_dist_targets_existence(&self, packages: &[Package<'_>]) -> Result<()> {
+        self.ensure_online("checking the existence of artifacts")?;
+
+        let dist_targets: Vec<_> = packages.iter().flat_map(Package::dist_targets).collect();
+
+        if dist_targets.is_empty() {
+            return Ok(());
+        }
+
+        // Private registries may only be inspected once authenticated.
+        for dist_target in &dist_targets {
+            dist_target.registry_login()?;
+        }
+
+        debug!(
+            "Checking the existence of {} distribution target(s) concurrently",
+            dist_targets.len()
+        );
+
+        let results: Vec<Result<(String, bool)>> = self.block_on(
+            futures::stream::iter(dist_targets.iter().map(|dist_target| async move {
+                let reference = dist_target.artifact_reference()?;
+                let exists = dist_target.exists().await?;
+
+                Ok((reference, exists))
+            }))
+            .buffer_unordered(MAX_CONCURRENT_EXISTENCE_CHECKS)
+            .collect(),
+        );
+
+        let mut existence_cache = self.existence_cache.borrow_mut();
+
+        for result in results {
+            let (reference, exists) = result?;
+
+            existence_cache.insert(reference, exists);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the artifact with the specified reference exists.
+    ///
+    /// If the existence of the artifact was prefetched, the cached result is
+    /// used. Otherwise `check` is run.
+    pub(crate) fn artifact_exists(
+        &self,
+        reference: &str,
+        check: impl Future<Output = Result<bool>>,
+    ) -> Result<bool> {
+        if let Some(exists) = self.existence_cache.borrow().get(reference) {
+            debug!("Using prefetched existence of artifact `{}`", reference);
+
+            return Ok(*exists);
+        }
+
+        self.ensure_online(&format!("checking the existence of `{}`", reference))?;
+
+        self.block_on(check)
+    }
+
     pub fn workspace(&self) -> Result<cargo::core::Workspace<'_>> {
         cargo::core::Workspace::new(&self.manifest_path, &self.config)
             .map_err(|err| Error::new("failed to load Cargo workspace").with_source(err))
     }
 
+    /// The directory in which the distributable artifacts are staged and the
+    /// state of the runs is kept: the one of `--target-root` if specified, or
+    /// the Cargo target directory.
     pub fn target_root(&self) -> Result<PathBuf> {
+        match &self.options.target_root {
+            Some(target_root) => Ok(target_root.clone()),
+            None => self.cargo_target_dir(),
+        }
+    }
+
+    /// The Cargo target directory of the workspace, which honors
+    /// `CARGO_TARGET_DIR` and the `build.target-dir` configuration like Cargo
+    /// does.
+    pub(crate) fn cargo_target_dir(&self) -> Result<PathBuf> {
         let workspace = self.workspace()?;
 
         Ok(workspace.target_dir().into_path_unlocked())
     }
 
+    /// All the packages of the workspace, sorted by name.
+    ///
+    /// All the packages are loaded even if some fail to, so that the errors
+    /// of all of them, like invalid metadata, are reported at once.
     pub fn packages(&self) -> Result<Vec<Package<'_>>> {
-        self.package_graph
+        let (packages, errors): (Vec<_>, Vec<_>) = self
+            .package_graph
             .packages()
-            .filter_map(|package_metadata| {
-                if package_metadata.source().is_workspace() {
-                    Some(Package::new(self, package_metadata))
-                } else {
-                    None
-                }
-            })
-            .collect::<Result<Vec<_>>>()
-            .map(|packages| {
-                packages
-                    .into_iter()
-                    .sorted_by(|a, b| a.name().cmp(b.name()))
-                    .collect()
-            })
+            .filter(|package_metadata| package_metadata.source().is_workspace())
+            .sorted_by(|a, b| a.name().cmp(b.name()))
+            .map(|package_metadata| Package::new(self, package_metadata))
+            .partition_result();
+
+        if !errors.is_empty() {
+            return Err(Error::aggregate(
+                format!("failed to load {} packages of the workspace", errors.len()),
+                errors,
+            ));
+        }
+
+        Ok(packages)
+    }
+
+    /// The packages to operate on when none are selected explicitly.
+    ///
+    /// Like Cargo, a manifest path pointing at a member of the workspace
+    /// selects only that member. Otherwise, these are the default members of
+    /// the workspace when its root manifest declares `default-members`, and
+    /// all its members otherwise. Unlike Cargo, the members of a workspace
+    /// with a root package are not reduced to that package.
+    pub fn default_packages(&self) -> Result<Vec<Package<'_>>> {
+        let workspace = self.workspace()?;
+
+        if self.manifest_path != workspace.root_manifest() {
+            if let Some(package) =
+                self.find_package_by_root(self.manifest_path.parent().unwrap())?
+            {
+                debug!(
+                    "Selecting `{}` as the manifest path points at it",
+                    package.name()
+                );
+
+                return Ok(vec![package]);
+            }
+        }
+
+        if !declares_default_members(workspace.root_manifest())? {
+            return self.packages();
+        }
+
+        let default_members: HashSet<_> = workspace
+            .default_members()
+            .map(|package| package.name().to_string())
+            .collect();
+
+        debug!(
+            "Selecting the default members of the workspace: {}",
+            default_members.iter().sorted().join(", ")
+        );
+
+        Ok(self
+            .packages()?
+            .into_iter()
+            .filter(|package| default_members.contains(package.name()))
+            .collect())
     }
 
     pub fn resolve_package_by_name(&self, name: &str) -> Result<Package<'_>> {
         let package_set = self.package_graph.resolve_package_name(name);
 
         if package_set.is_empty() {
-            return Err(Error::new("package not found").with_explanation(format!(
-                "A cargo package with the given name ({}) could not be found.",
-                name
-            )));
+            return Err(Error::new("package not found")
+                .with_kind(ErrorKind::Configuration)
+                .with_code(ErrorCode::PackageNotFound)
+                .with_explanation(format!(
+                    "A cargo package with the given name ({}) could not be found.",
+                    name
+                )));
         }
 
         let package_metadata = package_set
@@ -186,78 +703,511 @@ impl Context {
         Package::new(self, package_metadata)
     }
 
+    /// Resolve the workspace package in the specified directory, or whose
+    /// manifest is the specified file.
+    pub fn resolve_package_by_path(&self, path: &Path) -> Result<Package<'_>> {
+        let not_found = || {
+            Error::new("package not found")
+                .with_kind(ErrorKind::Configuration)
+                .with_code(ErrorCode::PackageNotFound)
+                .with_explanation(format!(
+                    "No package of the workspace could be found at the given path ({}).",
+                    path.display()
+                ))
+        };
+
+        let mut root = std::fs::canonicalize(path).map_err(|err| not_found().with_source(err))?;
+
+        if root.is_file() {
+            root.pop();
+        }
+
+        self.find_package_by_root(&root)?.ok_or_else(not_found)
+    }
+
+    /// Resolve the specified packages, each given either by name or, like
+    /// `./services/auth`, by path.
     pub fn resolve_packages_by_names<'b>(
         &self,
         names: impl IntoIterator<Item = &'b str>,
     ) -> Result<Vec<Package<'_>>> {
         names
             .into_iter()
-            .map(|name| self.resolve_package_by_name(name))
+            .map(|name| {
+                if is_package_path(name) {
+                    self.resolve_package_by_path(Path::new(name))
+                } else {
+                    self.resolve_package_by_name(name)
+                }
+            })
             .collect()
     }
 
+    /// Resolve the packages of the specified groups of the workspace.
+    ///
+    /// The members of a group are given either by name or by path, like the
+    /// packages selected with `--packages`.
+    pub fn resolve_groups<'b>(
+        &self,
+        groups: impl IntoIterator<Item = &'b str>,
+    ) -> Result<Vec<Package<'_>>> {
+        let mut packages = Vec::new();
+
+        for group in groups {
+            let members = self.groups.get(group).ok_or_else(|| {
+                Error::new("package group not found")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(if self.groups.is_empty() {
+                        format!(
+                            "The group `{}` is not declared as the workspace declares no groups in `[workspace.metadata.monorepo.groups]`.",
+                            group
+                        )
+                    } else {
+                        format!(
+                            "The group `{}` is not declared in `[workspace.metadata.monorepo.groups]`. The declared groups are: {}.",
+                            group,
+                            self.groups.keys().join(", ")
+                        )
+                    })
+            })?;
+
+            debug!("Selecting the members of the group `{}`", group);
+
+            packages.extend(
+                self.resolve_packages_by_names(members.iter().map(String::as_str))
+                    .with_context(format!("failed to resolve package group `{}`", group))?,
+            );
+        }
+
+        Ok(packages
+            .into_iter()
+            .unique_by(|package| package.name().to_string())
+            .sorted_by(|a, b| a.name().cmp(b.name()))
+            .collect())
+    }
+
+    /// Find the workspace package whose root is the specified canonical
+    /// directory.
+    fn find_package_by_root(&self, root: &Path) -> Result<Option<Package<'_>>> {
+        for package_metadata in self.package_graph.workspace().iter() {
+            let package_root = package_metadata.manifest_path().parent().unwrap();
+
+            // Cargo does not always report canonical paths.
+            if std::fs::canonicalize(package_root)
+                .map_or(false, |package_root| package_root == root)
+            {
+                return Package::new(self, package_metadata).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The packages with changes since the specified Git reference, and the
+    /// packages that depend on them.
     pub fn resolve_changed_packages(&self, start: &str) -> Result<Vec<Package<'_>>> {
         let changed_files = self.get_changed_files(start)?;
 
-        Ok(self
-            .packages()?
-            .into_iter()
-            .filter_map(|p| {
-                for changed_file in &changed_files {
-                    if p.sources().contains(changed_file) {
-                        return Some(
-                            p.dependant_packages()
-                                .map(|packages| std::iter::once(p).chain(packages)),
-                        );
+        debug!("Resolving the packages changed since `{}`", start);
+
+        self.resolve_packages_with_changed_files(&changed_files)
+    }
+
+    /// The packages with changes in the files listed in the specified file,
+    /// one path per line, and the packages that depend on them.
+    ///
+    /// This supports change detection by CI systems or version control
+    /// systems other than Git. Relative paths are relative to the current
+    /// directory and `-` reads the list from the standard input.
+    pub fn resolve_changed_packages_from_file(&self, path: &Path) -> Result<Vec<Package<'_>>> {
+        // The list is read as bytes, as file names are not necessarily valid
+        // UTF-8.
+        let data = if path == Path::new("-") {
+            let mut data = Vec::new();
+
+            std::io::stdin()
+                .read_to_end(&mut data)
+                .map_err(Error::from_source)
+                .with_context("failed to read changed files from standard input")?;
+
+            data
+        } else {
+            std::fs::read(path)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to read changed files",
+                    format!(
+                        "The list of changed files `{}` could not be read.",
+                        path.display()
+                    ),
+                )?
+        };
+
+        let cwd = std::env::current_dir()
+            .and_then(std::fs::canonicalize)
+            .map_err(|err| Error::new("could not determine current directory").with_source(err))?;
+
+        let changed_files: Vec<_> = data
+            .split(|byte| *byte == b'\n')
+            .map(trim_ascii_whitespace)
+            .filter(|line| !line.is_empty())
+            .map(|line| cwd.join(path_from_bytes(line)))
+            .collect();
+
+        debug!(
+            "Resolving the packages changed in the {} files listed in `{}`",
+            changed_files.len(),
+            path.display()
+        );
+
+        self.resolve_packages_with_changed_files(&changed_files)
+    }
+
+    /// The packages owning the specified changed files, and the packages that
+    /// depend on them.
+    ///
+    /// The changed files are mapped to their packages from the package roots
+    /// and manifests, without listing the sources of all the packages.
+    fn resolve_packages_with_changed_files(
+        &self,
+        changed_files: &[PathBuf],
+    ) -> Result<Vec<Package<'_>>> {
+        let source_owners = SourceOwners::new(self)?;
+
+        let changed_package_names: HashSet<_> = changed_files
+            .iter()
+            .filter_map(|changed_file| source_owners.owner(changed_file))
+            .collect();
+
+        debug!(
+            "Changed packages: {}",
+            changed_package_names.iter().sorted().join(", ")
+        );
+
+        let mut packages: Vec<Package<'_>> = Vec::new();
+
+        for p in self.packages()? {
+            if changed_package_names.contains(p.name()) {
+                let dependant_packages = p.dependant_packages()?;
+
+                // Packages depending on several changed packages are only
+                // returned once.
+                for package in std::iter::once(p).chain(dependant_packages) {
+                    if packages.iter().all(|p| p.id() != package.id()) {
+                        packages.push(package);
                     }
                 }
+            }
+        }
 
-                None
-            })
-            .collect::<Result<Vec<_>>>()?
-            .into_iter()
-            .flatten()
-            .collect())
+        Ok(packages)
     }
 
+    /// Create a context for the workspace as it is at the specified Git
+    /// reference.
+    ///
+    /// The tree of the reference is checked out in `root`, without affecting
+    /// the current working tree or index.
+    pub fn at_git_ref(&self, git_ref: &str, root: &Path) -> Result<Self> {
+        let repo = self.git_repository()?;
+        let tree = repo
+            .revparse_single(git_ref)
+            .map_err(|err| revision_error(git_ref, repo.is_shallow()).with_source(err))?
+            .peel_to_tree()
+            .map_err(|err| {
+                Error::new("reference does not point to a tree")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The Git reference `{}` must point to a commit or a tree.",
+                        git_ref
+                    ))
+            })?;
+
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| Error::new("failed to determine Git repository path"))?;
+        let workdir = std::fs::canonicalize(workdir).map_err(|err| {
+            Error::new("failed to determine Git repository path").with_source(err)
+        })?;
+        let relative_manifest_path = self.manifest_path.strip_prefix(&workdir).map_err(|err| {
+            Error::new("manifest is not in the Git repository")
+                .with_source(err)
+                .with_explanation(format!(
+                    "The manifest `{}` must be in the Git repository `{}`.",
+                    self.manifest_path.display(),
+                    workdir.display()
+                ))
+        })?;
+
+        debug!("Checking out `{}` in `{}`", git_ref, root.display());
+
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.target_dir(root).force().update_index(false);
+
+        repo.checkout_tree(tree.as_object(), Some(&mut checkout))
+            .map_err(|err| {
+                Error::new("failed to check out Git reference")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The tree of `{}` could not be checked out in `{}`.",
+                        git_ref,
+                        root.display()
+                    ))
+            })?;
+
+        Self::new(root.join(relative_manifest_path), self.options.clone())
+    }
+
+    /// Make sure the Docker CLI is authenticated against the registry of the
+    /// specified reference, if credentials are configured for it.
+    ///
+    /// Each registry is logged in to at most once per run. When no credentials
+    /// are configured, the existing Docker sessions are used.
+    pub(crate) fn registry_login(
+        &self,
+        reference: &str,
+        credential_helper: Option<&str>,
+    ) -> Result<()> {
+        let host = match registry_host(reference) {
+            Some(host) => host,
+            None => return Ok(()),
+        };
+
+        if self.registry_logins.borrow().contains(host) {
+            return Ok(());
+        }
+
+        self.ensure_online(&format!("logging in to registry `{}`", host))?;
+
+        if let Some(credentials) = RegistryCredentials::resolve(host, credential_helper)? {
+            if self.options.dry_run {
+                warn!(
+                    "`--dry-run` specified, will not really log in to registry `{}`",
+                    host
+                );
+            } else {
+                action_step!("Logging in", "to registry `{}`", host);
+
+                credentials.docker_login(host)?;
+            }
+        }
+
+        self.registry_logins.borrow_mut().insert(host.to_string());
+
+        Ok(())
+    }
+
+    /// Gather the provenance of the artifacts of a package with the
+    /// specified hash, from the Git repository and the CI environment.
+    ///
+    /// Information that cannot be determined is omitted.
+    pub(crate) fn provenance(&self, package_hash: String) -> Provenance {
+        let repo = self.git_repository().ok();
+        let head = repo.as_ref().and_then(|repo| repo.head().ok());
+
+        Provenance {
+            git_commit: head
+                .as_ref()
+                .and_then(|head| head.peel_to_commit().ok())
+                .map(|commit| commit.id().to_string()),
+            git_branch: head
+                .as_ref()
+                .filter(|head| head.is_branch())
+                .and_then(|head| head.shorthand().map(ToString::to_string))
+                .or_else(|| env_var("GITHUB_REF_NAME"))
+                .or_else(|| env_var("CI_COMMIT_REF_NAME")),
+            ci_run_url: ci_run_url(),
+            builder: env_var("GITHUB_ACTOR")
+                .or_else(|| env_var("GITLAB_USER_LOGIN"))
+                .or_else(|| env_var("USER"))
+                .or_else(|| env_var("USERNAME")),
+            package_hash,
+        }
+    }
+
+    /// Open the Git repository of the workspace.
+    ///
+    /// The repository is discovered from the workspace root, so that
+    /// workspaces in a subdirectory of a repository, in a worktree or in a
+    /// submodule are supported.
     fn git_repository(&self) -> Result<Repository> {
-        Repository::open(self.workspace()?.root())
-            .map_err(|err| Error::new("failed to open Git repository").with_source(err))
+        let root = self.workspace()?.root().to_path_buf();
+
+        Repository::discover(&root).map_err(|err| {
+            Error::new("failed to open Git repository")
+                .with_source(err)
+                .with_explanation(format!(
+                    "No Git repository could be opened at or above `{}`.",
+                    root.display()
+                ))
+        })
     }
 
-    fn get_changed_files(&self, start: &str) -> Result<Vec<PathBuf>> {
+    /// List the files of the working tree that have uncommitted changes,
+    /// including untracked files that are not ignored.
+    ///
+    /// The Git status is only computed once, and its paths are canonical and
+    /// comparable.
+    pub(crate) fn get_dirty_files(&self) -> Result<&[PathBuf]> {
+        self.dirty_files
+            .get_or_try_init(|| self.compute_dirty_files())
+            .map(Vec::as_slice)
+    }
+
+    fn compute_dirty_files(&self) -> Result<Vec<PathBuf>> {
         let repo = self.git_repository()?;
-        let start = repo
+
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| Error::new("failed to determine Git repository path"))?;
+        let workdir = std::fs::canonicalize(workdir).map_err(|err| {
+            Error::new("failed to determine Git repository path").with_source(err)
+        })?;
+
+        let mut status_options = git2::StatusOptions::new();
+        status_options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .include_ignored(false);
+
+        let statuses = repo
+            .statuses(Some(&mut status_options))
+            .map_err(|err| Error::new("failed to get Git status").with_source(err))?;
+
+        Ok(statuses
+            .iter()
+            .filter(|entry| entry.status() != git2::Status::CURRENT)
+            .map(|entry| comparable_path(&workdir.join(path_from_bytes(entry.path_bytes()))))
+            .collect())
+    }
+
+    /// List the files changed in the working tree since the specified Git
+    /// reference.
+    ///
+    /// Repositories that cannot be opened with libgit2, like the ones using
+    /// extensions it does not support, are diffed with the `git` command
+    /// instead.
+    fn get_changed_files(&self, start: &str) -> Result<Vec<PathBuf>> {
+        let repo = match self.git_repository() {
+            Ok(repo) => repo,
+            Err(err) => {
+                debug!("{}: falling back to the `git` command", err);
+
+                return self.get_changed_files_with_git(start).map_err(|git_err| {
+                    debug!("Failed to run the `git` command: {}", git_err);
+
+                    err
+                });
+            }
+        };
+
+        let start_tree = repo
             .revparse_single(start)
-            .map_err(|err| Error::new("failed to parse Git revision").with_source(err))?
-            .as_commit()
-            .ok_or_else(|| Error::new("reference is not a commit"))?
-            .tree()
-            .unwrap();
+            .and_then(|object| object.peel_to_tree())
+            .map_err(|err| revision_error(start, repo.is_shallow()).with_source(err))?;
+
+        let mut diff_options = git2::DiffOptions::new();
+        diff_options
+            .include_untracked(self.options.include_untracked)
+            .recurse_untracked_dirs(self.options.include_untracked);
 
-        let diff = repo
-            .diff_tree_to_workdir(Some(&start), None)
+        // Like `git diff <start>`, the index is taken into account so that the
+        // staged files, like the destinations of `git mv`, are not untracked.
+        let mut diff = repo
+            .diff_tree_to_workdir_with_index(Some(&start_tree), Some(&mut diff_options))
             .map_err(|err| Error::new("failed to generate diff").with_source(err))?;
 
-        let prefix = repo
-            .path()
-            .parent()
-            .ok_or_else(|| Error::new("failed to determine Git repository path"))?;
+        let mut find_options = git2::DiffFindOptions::new();
+        find_options
+            .renames(true)
+            .for_untracked(self.options.include_untracked);
 
-        let mut result = Vec::new();
+        diff.find_similar(Some(&mut find_options))
+            .map_err(|err| Error::new("failed to detect renames").with_source(err))?;
 
-        diff.print(git2::DiffFormat::NameOnly, |_, _, l| {
-            let path = prefix.join(PathBuf::from(
-                std::str::from_utf8(l.content()).unwrap().trim_end(),
-            ));
+        // The paths are relative to the working directory, which is not the
+        // parent of the Git directory in worktrees and submodules.
+        let workdir = repo.workdir().ok_or_else(|| {
+            Error::new("failed to determine Git repository path").with_explanation(
+                "The Git repository is bare: detecting changes requires a working tree.",
+            )
+        })?;
+        let workdir = std::fs::canonicalize(workdir).map_err(|err| {
+            Error::new("failed to determine Git repository path").with_source(err)
+        })?;
 
-            result.push(path);
+        // A renamed file changes both the package it leaves and the one it
+        // joins, which may be the same.
+        Ok(diff
+            .deltas()
+            .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+            .flatten()
+            .map(|path| workdir.join(path))
+            .unique()
+            .collect())
+    }
 
-            true
-        })
-        .map_err(|err| Error::new("failed to print diff").with_source(err))?;
+    /// List the files changed in the working tree since the specified Git
+    /// reference with the `git` command.
+    fn get_changed_files_with_git(&self, start: &str) -> Result<Vec<PathBuf>> {
+        let root = self.workspace()?.root().to_path_buf();
+
+        let workdir = git_output(&root, &["rev-parse", "--show-toplevel"])?;
+        let workdir = std::fs::canonicalize(path_from_bytes(trim_ascii_whitespace(&workdir)))
+            .map_err(|err| {
+                Error::new("failed to determine Git repository path").with_source(err)
+            })?;
+
+        git_output(&root, &["rev-parse", "--verify", "--quiet", start]).map_err(|err| {
+            let is_shallow = git_output(&root, &["rev-parse", "--is-shallow-repository"])
+                .map_or(false, |output| output.starts_with(b"true"));
+
+            revision_error(start, is_shallow).with_source(err)
+        })?;
+
+        let output = git_output(
+            &root,
+            &["diff", "--name-status", "--find-renames", "-z", start, "--"],
+        )?;
+        let untracked_output = if self.options.include_untracked {
+            git_output(
+                &root,
+                &[
+                    "ls-files",
+                    "--others",
+                    "--exclude-standard",
+                    "-z",
+                    "--full-name",
+                ],
+            )?
+        } else {
+            Vec::new()
+        };
+
+        // Each change is its status followed by its path, or by its source and
+        // destination paths for renames and copies.
+        let mut fields = output.split(|byte| *byte == 0);
+        let mut paths = Vec::new();
+
+        while let Some(status) = fields.next().filter(|status| !status.is_empty()) {
+            paths.extend(fields.next());
+
+            if status.starts_with(b"R") || status.starts_with(b"C") {
+                paths.extend(fields.next());
+            }
+        }
+
+        paths.extend(
+            untracked_output
+                .split(|byte| *byte == 0)
+                .filter(|path| !path.is_empty()),
+        );
 
-        Ok(result)
+        Ok(paths
+            .into_iter()
+            .map(|path| workdir.join(path_from_bytes(path)))
+            .unique()
+            .collect())
     }
 
     ///// Build all the collected distribution targets.
@@ -328,3 +1278,134 @@ impl Context {
     //    Ok(())
     //}
 }
+
+/// Whether a package specification is a path rather than a name: package
+/// names can't contain path separators nor start with a dot.
+fn is_package_path(spec: &str) -> bool {
+    spec.starts_with('.') || spec.contains('/') || spec.contains('\\')
+}
+
+/// Whether the specified root manifest declares the default members of its
+/// workspace.
+fn declares_default_members(root_manifest: &Path) -> Result<bool> {
+    let manifest = std::fs::read_to_string(root_manifest)
+        .map_err(|err| {
+            Error::new("failed to read the root manifest")
+                .with_source(err)
+                .with_explanation(format!(
+                    "The root manifest `{}` could not be read.",
+                    root_manifest.display()
+                ))
+        })?
+        .parse::<toml::Value>()
+        .map_err(|err| {
+            Error::new("failed to parse the root manifest")
+                .with_kind(ErrorKind::Configuration)
+                .with_source(err)
+                .with_explanation(format!(
+                    "The root manifest `{}` is not valid TOML.",
+                    root_manifest.display()
+                ))
+        })?;
+
+    Ok(manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("default-members"))
+        .is_some())
+}
+
+/// The error returned when a Git reference cannot be resolved.
+///
+/// Shallow clones, like the default ones of CI systems, only have the most
+/// recent commits, which is the most common cause of missing references.
+fn revision_error(git_ref: &str, is_shallow: bool) -> Error {
+    let error = Error::new("failed to parse Git revision")
+        .with_kind(ErrorKind::Configuration)
+        .with_code(ErrorCode::GitRevisionNotFound);
+
+    if is_shallow {
+        error.with_explanation(format!(
+            "The Git reference `{}` could not be found in this shallow clone. Fetch the history it needs, with `git fetch --unshallow` or with `fetch-depth: 0` for the `actions/checkout` GitHub action, or fetch the reference itself with `git fetch origin {}`.",
+            git_ref, git_ref
+        ))
+    } else {
+        error.with_explanation(format!(
+            "The Git reference `{}` could not be found. You may want to fetch it first.",
+            git_ref
+        ))
+    }
+}
+
+/// Convert a path output by Git, or read from a file, to a path.
+///
+/// Paths are arbitrary bytes on Unix, which may not be valid UTF-8.
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// Convert a path output by Git, or read from a file, to a path.
+///
+/// Git encodes paths as UTF-8 on Windows.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+}
+
+fn trim_ascii_whitespace(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|byte| !byte.is_ascii_whitespace())
+        .map_or(start, |end| end + 1);
+
+    &bytes[start..end]
+}
+
+/// Run a `git` command in the specified directory and return its output.
+fn git_output(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to run git",
+            "The `git` command could not be executed. Make sure Git is installed.",
+        )?;
+
+    if !output.status.success() {
+        return Err(Error::new("failed to run git")
+            .with_explanation(format!("The command `git {}` failed.", args.join(" ")))
+            .with_output(String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(output.stdout)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The URL of the current CI run, for the supported CI systems.
+fn ci_run_url() -> Option<String> {
+    if let (Some(server_url), Some(repository), Some(run_id)) = (
+        env_var("GITHUB_SERVER_URL"),
+        env_var("GITHUB_REPOSITORY"),
+        env_var("GITHUB_RUN_ID"),
+    ) {
+        return Some(format!(
+            "{}/{}/actions/runs/{}",
+            server_url, repository, run_id
+        ));
+    }
+
+    env_var("CI_JOB_URL")
+        .or_else(|| env_var("BUILDKITE_BUILD_URL"))
+        .or_else(|| env_var("BUILD_URL"))
+}
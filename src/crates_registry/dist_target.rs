@@ -0,0 +1,230 @@
+use std::{fmt::Display, path::PathBuf, process::Command};
+
+use log::{debug, warn};
+
+use crate::{
+    action_step, dist_target::published_artifact, ignore_step, process::run_command, Context,
+    Error, ErrorKind, Package, PublishedArtifact, Result,
+};
+
+use super::CratesRegistryMetadata;
+
+pub const CRATES_IO_API_URL: &str = "https://crates.io";
+
+pub struct CratesRegistryDistTarget<'g> {
+    pub package: &'g Package<'g>,
+    pub metadata: CratesRegistryMetadata,
+}
+
+impl Display for CratesRegistryDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "crates-registry[{}]", self.package.name())
+    }
+}
+
+impl<'g> CratesRegistryDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    /// Package the crate, which also verifies that it builds from its
+    /// packaged sources alone.
+    pub fn build(&self) -> Result<()> {
+        let manifest_path = self.manifest_path();
+        let mut cmd = Command::new("cargo");
+
+        cmd.args(["package", "--manifest-path"]);
+        cmd.arg(&manifest_path);
+        cmd.args(self.context().options().cargo_args());
+
+        action_step!("Running", "`cargo package` for `{}`", self.package.name());
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to package crate",
+        )
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!("`--force` specified: not checking for the crate existence before publishing");
+        } else if self.context().artifact_exists(&reference, self.exists())? {
+            ignore_step!(
+                "Up-to-date",
+                "crate `{}` already exists in registry `{}`",
+                &reference,
+                self.registry_name()
+            );
+
+            return Ok(Some(published_artifact(
+                self.package,
+                self,
+                reference,
+                None,
+                true,
+            )));
+        }
+
+        let mut cmd = Command::new("cargo");
+
+        cmd.args(["publish", "--manifest-path"]);
+        cmd.arg(self.manifest_path());
+        cmd.args(self.context().options().cargo_args());
+
+        if let Some(registry) = &self.metadata.registry {
+            cmd.args(["--registry", registry]);
+        }
+
+        if let Some(token_env) = &self.metadata.token_env {
+            let token = std::env::var(token_env).map_err(|err| {
+                Error::new("failed to read registry token")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The environment variable `{}` must be set as it is referenced by the `token_env` field.",
+                        token_env
+                    ))
+            })?;
+
+            cmd.env(token_env_var_name(self.metadata.registry.as_deref()), token);
+        }
+
+        if self.context().options().dry_run {
+            warn!("`--dry-run` specified, will only simulate the publication of the crate");
+
+            cmd.arg("--dry-run");
+        }
+
+        action_step!(
+            "Running",
+            "`cargo publish` for `{}` to registry `{}`",
+            self.package.name(),
+            self.registry_name()
+        );
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to publish crate",
+        )?;
+
+        if self.context().options().dry_run {
+            return Ok(None);
+        }
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            reference,
+            None,
+            false,
+        )))
+    }
+
+    /// Check whether the current version of the crate exists in the registry,
+    /// using its web API.
+    pub async fn exists(&self) -> Result<bool> {
+        let url = format!(
+            "{}/api/v1/crates/{}/{}",
+            self.api_url()?.trim_end_matches('/'),
+            self.package.name(),
+            self.package.version()
+        );
+
+        // crates.io rejects requests without a user agent.
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header(reqwest::header::USER_AGENT, "cargo-monorepo")
+            .send()
+            .await
+            .map_err(|err| {
+                Error::new("failed to check for crate existence")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation(format!("Could not reach the registry API at `{}`.", &url))
+            })?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(Error::new("failed to check for crate existence")
+                .with_kind(ErrorKind::Network)
+                .with_explanation(format!(
+                    "The registry API answered with status `{}` for `{}`.",
+                    status, &url
+                ))),
+        }
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(format!(
+            "{}:{}@{}",
+            self.registry_name(),
+            self.package.name(),
+            self.package.version()
+        ))
+    }
+
+    /// Crates registries have no notion of channels: promotion is a no-op.
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    pub fn promote(&self, _channel: &str) -> Result<()> {
+        ignore_step!(
+            "Unsupported",
+            "crates can't be promoted as registries have no notion of channels"
+        );
+
+        Ok(())
+    }
+
+    fn registry_name(&self) -> &str {
+        self.metadata.registry.as_deref().unwrap_or("crates-io")
+    }
+
+    fn api_url(&self) -> Result<&str> {
+        match (&self.metadata.api_url, &self.metadata.registry) {
+            (Some(api_url), _) => Ok(api_url),
+            (None, None) => Ok(CRATES_IO_API_URL),
+            (None, Some(registry)) => Err(Error::new("failed to determine registry API URL")
+                .with_explanation(format!(
+                    "The field `api_url` must be specified to check for the existence of crates in the registry `{}`.",
+                    registry
+                ))),
+        }
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.package.root().join("Cargo.toml")
+    }
+}
+
+/// The name of the environment variable Cargo reads the token of a registry
+/// from, crates.io if none is specified.
+fn token_env_var_name(registry: Option<&str>) -> String {
+    match registry {
+        Some(registry) => format!(
+            "CARGO_REGISTRIES_{}_TOKEN",
+            registry.to_uppercase().replace('-', "_")
+        ),
+        None => "CARGO_REGISTRY_TOKEN".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_env_var_name() {
+        assert_eq!(token_env_var_name(None), "CARGO_REGISTRY_TOKEN");
+        assert_eq!(
+            token_env_var_name(Some("my-registry")),
+            "CARGO_REGISTRIES_MY_REGISTRY_TOKEN"
+        );
+        assert_eq!(
+            token_env_var_name(Some("internal")),
+            "CARGO_REGISTRIES_INTERNAL_TOKEN"
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    condition::Enabled, crates_registry::CratesRegistryDistTarget, dist_target::DistTarget, Package,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CratesRegistryMetadata {
+    #[serde(default)]
+    pub registry: Option<String>,
+    #[serde(default)]
+    pub api_url: Option<String>,
+    #[serde(default)]
+    pub token_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+impl CratesRegistryMetadata {
+    pub(crate) fn into_dist_target<'g>(self, package: &'g Package<'g>) -> DistTarget<'g> {
+        DistTarget::CratesRegistry(CratesRegistryDistTarget {
+            package,
+            metadata: self,
+        })
+    }
+}
@@ -0,0 +1,5 @@
+mod dist_target;
+mod metadata;
+
+pub use dist_target::CratesRegistryDistTarget;
+pub use metadata::CratesRegistryMetadata;
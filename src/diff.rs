@@ -0,0 +1,176 @@
+//! Comparison of the packages of the workspace between two Git references.
+
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use log::debug;
+
+use crate::{Context, Error, HashComparison, Result};
+
+/// The state of a package at a Git reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageState {
+    pub version: semver::Version,
+    pub hash: String,
+    pub dist_targets: Vec<String>,
+}
+
+/// The difference of a package between two Git references.
+#[derive(Debug, Clone)]
+pub struct PackageDiff {
+    pub name: String,
+    pub from: Option<PackageState>,
+    pub to: Option<PackageState>,
+}
+
+impl PackageDiff {
+    /// Check whether the hash of the package differs between the two
+    /// references.
+    ///
+    /// Hashes computed with different schema versions are not considered
+    /// different.
+    pub fn is_changed(&self) -> bool {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => {
+                HashComparison::new(&from.hash, &to.hash) == HashComparison::Different
+            }
+            _ => true,
+        }
+    }
+
+    /// Check whether the hashes of the package cannot be compared, because
+    /// they were computed with different schema versions.
+    pub fn is_unknown(&self) -> bool {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => {
+                HashComparison::new(&from.hash, &to.hash) == HashComparison::Unknown
+            }
+            _ => false,
+        }
+    }
+
+    /// The distribution targets that would be published again as a result of
+    /// the change.
+    pub fn republished_dist_targets(&self) -> &[String] {
+        match &self.to {
+            Some(to) if self.is_changed() => &to.dist_targets,
+            _ => &[],
+        }
+    }
+}
+
+impl Display for PackageDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.from, &self.to) {
+            (None, None) => write!(f, "{}", self.name),
+            (Some(from), None) => write!(f, "{}: removed (v{})", self.name, from.version),
+            (None, Some(to)) => write!(f, "{}: added (v{})", self.name, to.version),
+            (Some(from), Some(to)) => {
+                if self.is_unknown() {
+                    write!(
+                        f,
+                        "{}: unknown (v{}, hash format changed)",
+                        self.name, to.version
+                    )
+                } else if !self.is_changed() {
+                    write!(f, "{}: unchanged (v{})", self.name, to.version)
+                } else if from.version != to.version {
+                    write!(
+                        f,
+                        "{}: changed (v{} -> v{})",
+                        self.name, from.version, to.version
+                    )
+                } else {
+                    write!(f, "{}: changed (v{})", self.name, to.version)
+                }
+            }
+        }
+    }
+}
+
+/// Compare the packages of the workspace between two Git references.
+///
+/// Each reference is checked out in turn in the same scratch directory, so
+/// that the hashes are computed from identical paths and can be compared.
+pub fn diff_git_refs(context: &Context, from: &str, to: &str) -> Result<Vec<PackageDiff>> {
+    let root = std::env::temp_dir().join(format!("cargo-monorepo-diff-{}", std::process::id()));
+
+    let from_states = package_states_at(context, from, &root)?;
+    let to_states = package_states_at(context, to, &root)?;
+
+    let mut diffs: BTreeMap<String, PackageDiff> = BTreeMap::new();
+
+    for (name, state) in from_states {
+        diffs.insert(
+            name.clone(),
+            PackageDiff {
+                name,
+                from: Some(state),
+                to: None,
+            },
+        );
+    }
+
+    for (name, state) in to_states {
+        diffs
+            .entry(name.clone())
+            .or_insert_with(|| PackageDiff {
+                name,
+                from: None,
+                to: None,
+            })
+            .to = Some(state);
+    }
+
+    Ok(diffs.into_values().collect())
+}
+
+fn package_states_at(
+    context: &Context,
+    git_ref: &str,
+    root: &Path,
+) -> Result<BTreeMap<String, PackageState>> {
+    remove_scratch_dir(root)?;
+
+    let result: Result<BTreeMap<_, _>> = (|| {
+        let context = context.at_git_ref(git_ref, root)?;
+        let packages = context.packages()?;
+
+        let states = packages
+            .into_iter()
+            .map(|package| {
+                debug!("Hashing `{}` at `{}`", package.name(), git_ref);
+
+                Ok((
+                    package.name().to_string(),
+                    PackageState {
+                        version: package.version().clone(),
+                        hash: package.hash()?,
+                        dist_targets: package
+                            .dist_targets()
+                            .iter()
+                            .map(ToString::to_string)
+                            .collect(),
+                    },
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(states)
+    })();
+
+    remove_scratch_dir(root)?;
+
+    result
+}
+
+fn remove_scratch_dir(root: &Path) -> Result<()> {
+    std::fs::remove_dir_all(root).or_else(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => Ok(()),
+        _ => Err(Error::new("failed to clean the scratch directory")
+            .with_source(err)
+            .with_explanation(format!(
+                "The directory `{}` could not be removed. You may want to verify permissions.",
+                root.display()
+            ))),
+    })
+}
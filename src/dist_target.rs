@@ -1,6 +1,30 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use crate::{aws_lambda::AwsLambdaDistTarget, docker::DockerDistTarget, Result};
+use log::debug;
+
+use crate::{
+    action_step,
+    artifact_repository::ArtifactRepositoryDistTarget,
+    aws_lambda::AwsLambdaDistTarget,
+    condition::Enabled,
+    crates_registry::CratesRegistryDistTarget,
+    docker::DockerDistTarget,
+    docker_base::DockerBaseDistTarget,
+    macos_app::MacosAppDistTarget,
+    metadata::CopyPlan,
+    msi::MsiDistTarget,
+    npm::NpmDistTarget,
+    process::run_command,
+    report::{PublishedArtifact, PublishedVersion},
+    s3_sync::S3SyncDistTarget,
+    tools::Tool,
+    wasm::WasmDistTarget,
+    Error, ErrorContext, ErrorKind, Package, Result, StagingDirectories,
+};
 
 // Quite frankly, this structure is not used much and never in a context where
 // its performance is critical. So we don't really care about the size of the
@@ -9,6 +33,14 @@ use crate::{aws_lambda::AwsLambdaDistTarget, docker::DockerDistTarget, Result};
 pub(crate) enum DistTarget<'g> {
     AwsLambda(AwsLambdaDistTarget<'g>),
     Docker(DockerDistTarget<'g>),
+    DockerBase(DockerBaseDistTarget<'g>),
+    ArtifactRepository(ArtifactRepositoryDistTarget<'g>),
+    CratesRegistry(CratesRegistryDistTarget<'g>),
+    Npm(NpmDistTarget<'g>),
+    Wasm(WasmDistTarget<'g>),
+    S3Sync(S3SyncDistTarget<'g>),
+    Msi(MsiDistTarget<'g>),
+    MacosApp(MacosAppDistTarget<'g>),
 }
 
 impl DistTarget<'_> {
@@ -16,13 +48,368 @@ impl DistTarget<'_> {
         match self {
             DistTarget::AwsLambda(dist_target) => dist_target.build(),
             DistTarget::Docker(dist_target) => dist_target.build(),
+            DistTarget::DockerBase(dist_target) => dist_target.build(),
+            DistTarget::ArtifactRepository(dist_target) => dist_target.build(),
+            DistTarget::CratesRegistry(dist_target) => dist_target.build(),
+            DistTarget::Npm(dist_target) => dist_target.build(),
+            DistTarget::Wasm(dist_target) => dist_target.build(),
+            DistTarget::S3Sync(dist_target) => dist_target.build(),
+            DistTarget::Msi(dist_target) => dist_target.build(),
+            DistTarget::MacosApp(dist_target) => dist_target.build(),
+        }
+    }
+
+    /// Whether the distribution target can be built and published on the
+    /// current host.
+    ///
+    /// Unsupported distribution targets are skipped.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            DistTarget::Docker(_) | DistTarget::DockerBase(_) => !cfg!(windows),
+            DistTarget::Msi(_) => cfg!(windows),
+            DistTarget::MacosApp(_) => cfg!(target_os = "macos"),
+            DistTarget::AwsLambda(_)
+            | DistTarget::ArtifactRepository(_)
+            | DistTarget::CratesRegistry(_)
+            | DistTarget::Npm(_)
+            | DistTarget::Wasm(_)
+            | DistTarget::S3Sync(_) => true,
+        }
+    }
+
+    /// Whether the distribution target is enabled for the current build mode,
+    /// environment profile and target runtime.
+    ///
+    /// Disabled distribution targets are ignored, as if they were not
+    /// declared.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled()
+            .evaluate(self.package().context().options(), self.target_runtime())
+    }
+
+    fn package(&self) -> &Package<'_> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.package,
+            DistTarget::Docker(dist_target) => dist_target.package,
+            DistTarget::DockerBase(dist_target) => dist_target.package,
+            DistTarget::ArtifactRepository(dist_target) => dist_target.package,
+            DistTarget::CratesRegistry(dist_target) => dist_target.package,
+            DistTarget::Npm(dist_target) => dist_target.package,
+            DistTarget::Wasm(dist_target) => dist_target.package,
+            DistTarget::S3Sync(dist_target) => dist_target.package,
+            DistTarget::Msi(dist_target) => dist_target.package,
+            DistTarget::MacosApp(dist_target) => dist_target.package,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> &Enabled {
+        match self {
+            DistTarget::AwsLambda(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::Docker(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::DockerBase(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::ArtifactRepository(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::CratesRegistry(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::Npm(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::Wasm(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::S3Sync(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::Msi(dist_target) => &dist_target.metadata.enabled,
+            DistTarget::MacosApp(dist_target) => &dist_target.metadata.enabled,
+        }
+    }
+
+    /// The external tools required to build the distribution target or, if
+    /// `publish` is set, to publish it.
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        match self {
+            DistTarget::Docker(dist_target) => dist_target.required_tools(publish),
+            DistTarget::DockerBase(dist_target) => dist_target.required_tools(publish),
+            DistTarget::Npm(dist_target) => dist_target.required_tools(publish),
+            DistTarget::Wasm(dist_target) => dist_target.required_tools(publish),
+            DistTarget::Msi(dist_target) => dist_target.required_tools(publish),
+            DistTarget::MacosApp(dist_target) => dist_target.required_tools(publish),
+            DistTarget::AwsLambda(_)
+            | DistTarget::ArtifactRepository(_)
+            | DistTarget::CratesRegistry(_)
+            | DistTarget::S3Sync(_) => vec![],
+        }
+    }
+
+    /// The target runtime the distribution target compiles binaries for, if
+    /// any.
+    pub fn target_runtime(&self) -> Option<&str> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => {
+                Some(dist_target.metadata.target_runtime.as_str())
+            }
+            DistTarget::Docker(dist_target) => Some(dist_target.metadata.target_runtime.as_str()),
+            DistTarget::ArtifactRepository(dist_target) => {
+                Some(dist_target.metadata.target_runtime.as_str())
+            }
+            DistTarget::Wasm(dist_target) => Some(&dist_target.metadata.target_runtime),
+            DistTarget::Msi(dist_target) => Some(&dist_target.metadata.target_runtime),
+            DistTarget::MacosApp(dist_target) => Some(&dist_target.metadata.target_runtime),
+            DistTarget::DockerBase(_)
+            | DistTarget::CratesRegistry(_)
+            | DistTarget::Npm(_)
+            | DistTarget::S3Sync(_) => None,
+        }
+    }
+
+    /// The target runtime the distribution target compiles binaries for on
+    /// the host, if any.
+    ///
+    /// Docker images with a `builder_image` compile their binaries in Docker
+    /// instead.
+    pub(crate) fn host_target_runtime(&self) -> Option<&str> {
+        match self {
+            DistTarget::Docker(dist_target) if dist_target.metadata.builder_image.is_some() => None,
+            _ => self.target_runtime(),
+        }
+    }
+
+    /// The directory in which the distribution target stages its artifact,
+    /// if any.
+    pub fn staging_dir(&self) -> Option<PathBuf> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::Docker(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::DockerBase(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::ArtifactRepository(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::CratesRegistry(_) => None,
+            DistTarget::Npm(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::Wasm(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::S3Sync(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::Msi(dist_target) => Some(dist_target.staging_dir()),
+            DistTarget::MacosApp(dist_target) => Some(dist_target.staging_dir()),
+        }
+    }
+
+    /// Check whether the distribution target was built completely from the
+    /// specified package hash and its artifact is still available.
+    ///
+    /// Docker images live in the local image store rather than in their
+    /// staging directory, which may outlive them after a `docker image prune`.
+    pub(crate) fn is_up_to_date(
+        &self,
+        staging_directories: &StagingDirectories,
+        hash: &str,
+    ) -> Result<bool> {
+        let up_to_date = self.staging_dir().map_or(false, |staging_dir| {
+            staging_directories.is_up_to_date(&staging_dir, hash)
+        });
+
+        if !up_to_date {
+            return Ok(false);
+        }
+
+        match self {
+            DistTarget::Docker(dist_target) => dist_target.image_exists_locally(),
+            DistTarget::DockerBase(dist_target) => dist_target.image_exists_locally(),
+            _ => Ok(true),
         }
     }
 
-    pub fn publish(&self) -> Result<()> {
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
         match self {
             DistTarget::AwsLambda(dist_target) => dist_target.publish(),
             DistTarget::Docker(dist_target) => dist_target.publish(),
+            DistTarget::DockerBase(dist_target) => dist_target.publish(),
+            DistTarget::ArtifactRepository(dist_target) => dist_target.publish(),
+            DistTarget::CratesRegistry(dist_target) => dist_target.publish(),
+            DistTarget::Npm(dist_target) => dist_target.publish(),
+            DistTarget::Wasm(dist_target) => dist_target.publish(),
+            DistTarget::S3Sync(dist_target) => dist_target.publish(),
+            DistTarget::Msi(dist_target) => dist_target.publish(),
+            DistTarget::MacosApp(dist_target) => dist_target.publish(),
+        }
+    }
+
+    pub async fn exists(&self) -> Result<bool> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.exists().await,
+            DistTarget::Docker(dist_target) => dist_target.exists().await,
+            DistTarget::DockerBase(dist_target) => dist_target.exists().await,
+            DistTarget::ArtifactRepository(dist_target) => dist_target.exists().await,
+            DistTarget::CratesRegistry(dist_target) => dist_target.exists().await,
+            DistTarget::Npm(dist_target) => dist_target.exists().await,
+            DistTarget::Wasm(dist_target) => dist_target.exists().await,
+            DistTarget::S3Sync(dist_target) => dist_target.exists().await,
+            DistTarget::Msi(dist_target) => dist_target.exists().await,
+            DistTarget::MacosApp(dist_target) => dist_target.exists().await,
+        }
+    }
+
+    /// List the versions of the artifact that exist in its destination.
+    ///
+    /// Returns `None` if listing versions is not supported by the
+    /// distribution target.
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.published_versions().await,
+            DistTarget::Docker(dist_target) => dist_target.published_versions().await,
+            DistTarget::DockerBase(dist_target) => dist_target.published_versions().await,
+            DistTarget::Wasm(dist_target) => dist_target.published_versions().await,
+            DistTarget::Msi(dist_target) => dist_target.published_versions().await,
+            DistTarget::MacosApp(dist_target) => dist_target.published_versions().await,
+            DistTarget::ArtifactRepository(_)
+            | DistTarget::CratesRegistry(_)
+            | DistTarget::Npm(_)
+            | DistTarget::S3Sync(_) => Ok(None),
+        }
+    }
+
+    /// Delete the artifact of the specified version from the destination of
+    /// the distribution target.
+    ///
+    /// Must only be called for distribution targets that support listing
+    /// their published versions.
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => {
+                dist_target.delete_published_version(version).await
+            }
+            DistTarget::Docker(dist_target) => dist_target.delete_published_version(version).await,
+            DistTarget::DockerBase(dist_target) => {
+                dist_target.delete_published_version(version).await
+            }
+            DistTarget::Wasm(dist_target) => dist_target.delete_published_version(version).await,
+            DistTarget::Msi(dist_target) => dist_target.delete_published_version(version).await,
+            DistTarget::MacosApp(dist_target) => {
+                dist_target.delete_published_version(version).await
+            }
+            DistTarget::ArtifactRepository(_)
+            | DistTarget::CratesRegistry(_)
+            | DistTarget::Npm(_)
+            | DistTarget::S3Sync(_) => unreachable!(
+                "distribution {} does not support listing its published versions",
+                self
+            ),
+        }
+    }
+
+    /// Authenticate against the registry of the distribution target, if it
+    /// publishes to one and credentials are configured for it.
+    pub fn registry_login(&self) -> Result<()> {
+        match self {
+            DistTarget::Docker(dist_target) => dist_target.registry_login(),
+            DistTarget::DockerBase(dist_target) => dist_target.registry_login(),
+            DistTarget::Wasm(dist_target) => dist_target.registry_login(),
+            DistTarget::AwsLambda(_)
+            | DistTarget::ArtifactRepository(_)
+            | DistTarget::CratesRegistry(_)
+            | DistTarget::Npm(_)
+            | DistTarget::S3Sync(_)
+            | DistTarget::Msi(_)
+            | DistTarget::MacosApp(_) => Ok(()),
+        }
+    }
+
+    /// A reference that uniquely identifies the artifact of the distribution
+    /// target in its destination.
+    pub fn artifact_reference(&self) -> Result<String> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.artifact_reference(),
+            DistTarget::Docker(dist_target) => dist_target.artifact_reference(),
+            DistTarget::DockerBase(dist_target) => dist_target.artifact_reference(),
+            DistTarget::ArtifactRepository(dist_target) => dist_target.artifact_reference(),
+            DistTarget::CratesRegistry(dist_target) => dist_target.artifact_reference(),
+            DistTarget::Npm(dist_target) => dist_target.artifact_reference(),
+            DistTarget::Wasm(dist_target) => dist_target.artifact_reference(),
+            DistTarget::S3Sync(dist_target) => dist_target.artifact_reference(),
+            DistTarget::Msi(dist_target) => dist_target.artifact_reference(),
+            DistTarget::MacosApp(dist_target) => dist_target.artifact_reference(),
+        }
+    }
+
+    /// Run the test command of the distribution target, if any, against its
+    /// locally built artifact.
+    ///
+    /// The command runs in the package root and gets the package name and
+    /// version and the reference of the artifact through the
+    /// `CARGO_MONOREPO_PACKAGE_NAME`, `CARGO_MONOREPO_PACKAGE_VERSION` and
+    /// `CARGO_MONOREPO_ARTIFACT_REFERENCE` environment variables.
+    pub fn test(&self, package: &Package<'_>) -> Result<()> {
+        let test_command = match self.test_command() {
+            Some(test_command) => test_command,
+            None => return Ok(()),
+        };
+
+        let (program, args) = test_command.split_first().ok_or_else(|| {
+            Error::new("invalid test command")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation("The field `test_command` cannot be empty.")
+        })?;
+
+        let mut cmd = Command::new(program);
+
+        cmd.args(args)
+            .current_dir(package.root())
+            .env("CARGO_MONOREPO_PACKAGE_NAME", package.name())
+            .env(
+                "CARGO_MONOREPO_PACKAGE_VERSION",
+                package.version().to_string(),
+            )
+            .env(
+                "CARGO_MONOREPO_ARTIFACT_REFERENCE",
+                self.artifact_reference()?,
+            );
+
+        action_step!("Testing", "distribution {}", self);
+        action_step!("Running", "`{}`", test_command.join(" "));
+
+        run_command(
+            &mut cmd,
+            package.context().options().verbose,
+            &format!("failed to test distribution {}", self),
+        )
+        .with_kind(ErrorKind::Validation)
+    }
+
+    fn test_command(&self) -> Option<&[String]> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::Docker(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::DockerBase(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::ArtifactRepository(dist_target) => {
+                dist_target.metadata.test_command.as_deref()
+            }
+            DistTarget::CratesRegistry(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::Npm(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::Wasm(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::S3Sync(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::Msi(dist_target) => dist_target.metadata.test_command.as_deref(),
+            DistTarget::MacosApp(dist_target) => dist_target.metadata.test_command.as_deref(),
+        }
+    }
+
+    /// The type of the distribution target, as specified in the metadata.
+    pub fn target_type(&self) -> &'static str {
+        match self {
+            DistTarget::AwsLambda(_) => "aws-lambda",
+            DistTarget::Docker(_) => "docker",
+            DistTarget::DockerBase(_) => "docker-base",
+            DistTarget::ArtifactRepository(_) => "artifact-repository",
+            DistTarget::CratesRegistry(_) => "crates-registry",
+            DistTarget::Npm(_) => "npm",
+            DistTarget::Wasm(_) => "wasm",
+            DistTarget::S3Sync(_) => "s3-sync",
+            DistTarget::Msi(_) => "msi",
+            DistTarget::MacosApp(_) => "macos-app",
+        }
+    }
+
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        match self {
+            DistTarget::AwsLambda(dist_target) => dist_target.promote(channel),
+            DistTarget::Docker(dist_target) => dist_target.promote(channel),
+            DistTarget::DockerBase(dist_target) => dist_target.promote(channel),
+            DistTarget::ArtifactRepository(dist_target) => dist_target.promote(channel),
+            DistTarget::CratesRegistry(dist_target) => dist_target.promote(channel),
+            DistTarget::Npm(dist_target) => dist_target.promote(channel),
+            DistTarget::Wasm(dist_target) => dist_target.promote(channel),
+            DistTarget::S3Sync(dist_target) => dist_target.promote(channel),
+            DistTarget::Msi(dist_target) => dist_target.promote(channel),
+            DistTarget::MacosApp(dist_target) => dist_target.promote(channel),
         }
     }
 }
@@ -32,6 +419,86 @@ impl Display for DistTarget<'_> {
         match self {
             DistTarget::AwsLambda(dist_target) => dist_target.fmt(f),
             DistTarget::Docker(dist_target) => dist_target.fmt(f),
+            DistTarget::DockerBase(dist_target) => dist_target.fmt(f),
+            DistTarget::ArtifactRepository(dist_target) => dist_target.fmt(f),
+            DistTarget::CratesRegistry(dist_target) => dist_target.fmt(f),
+            DistTarget::Npm(dist_target) => dist_target.fmt(f),
+            DistTarget::Wasm(dist_target) => dist_target.fmt(f),
+            DistTarget::S3Sync(dist_target) => dist_target.fmt(f),
+            DistTarget::Msi(dist_target) => dist_target.fmt(f),
+            DistTarget::MacosApp(dist_target) => dist_target.fmt(f),
         }
     }
 }
+
+/// Check that a channel can be used as a component of the path or key of an
+/// artifact.
+pub(crate) fn check_channel_name(channel: &str) -> Result<()> {
+    if channel.is_empty() || channel.contains('/') {
+        return Err(Error::new("invalid channel name")
+            .with_kind(ErrorKind::Validation)
+            .with_explanation(format!(
+                "The channel `{}` cannot be used as a path component. Channels must be non-empty and cannot contain `/`.",
+                channel
+            )));
+    }
+
+    Ok(())
+}
+
+/// The artifact of a distribution target of a package, for the distribution
+/// targets that neither reference it by hash nor record its provenance.
+pub(crate) fn published_artifact(
+    package: &Package<'_>,
+    dist_target: &impl Display,
+    reference: String,
+    digest: Option<String>,
+    up_to_date: bool,
+) -> PublishedArtifact {
+    PublishedArtifact {
+        package: package.name().to_string(),
+        version: package.version().clone(),
+        dist_target: dist_target.to_string(),
+        reference,
+        digest,
+        hash_reference: None,
+        up_to_date,
+        provenance: None,
+    }
+}
+
+/// Remove the build directory of a distribution target, if it exists.
+pub(crate) fn clean_dir(dir: &Path, description: &str) -> Result<()> {
+    debug!("Will now clean the build directory");
+
+    std::fs::remove_dir_all(dir).or_else(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => Ok(()),
+        _ => Err(Error::new(format!("failed to clean the {}", description)).with_source(err)),
+    })
+}
+
+/// Copy the extra files of a distribution target, which must not overwrite
+/// any of the specified build outputs.
+pub(crate) fn copy_extra_files(
+    package: &Package<'_>,
+    copy_plan: &CopyPlan,
+    build_outputs: &[PathBuf],
+) -> Result<()> {
+    debug!("Will now copy all extra files");
+
+    copy_plan.execute(package.context().options().dry_run, build_outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_channel_name() {
+        assert!(check_channel_name("stable").is_ok());
+        assert!(check_channel_name("release-candidate").is_ok());
+
+        assert!(check_channel_name("").is_err());
+        assert!(check_channel_name("beta/1").is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! The builder stage of the Docker images whose binaries are compiled in a
+//! builder image, in a first stage of the generated Dockerfile, rather than on
+//! the host.
+//!
+//! The dependencies are compiled against placeholder sources in their own
+//! layer, which Docker only rebuilds when a manifest or the lock file changes.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use guppy::graph::{BuildTargetId, DependencyDirection};
+use log::debug;
+
+use crate::{
+    hash::is_shipped_dependency, rust::executable_suffix, sources::Sources, Error, ErrorContext,
+    Package, Result,
+};
+
+/// The working directory of the builder stage.
+const BUILDER_WORKDIR: &str = "/usr/src/workspace";
+
+/// The files of the workspace root that affect the compilation, copied along
+/// with the manifests when they exist.
+const WORKSPACE_FILES: &[&str] = &[
+    "Cargo.lock",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+    ".cargo/config",
+    ".cargo/config.toml",
+];
+
+pub(super) struct BuilderStage<'a, 'g> {
+    package: &'a Package<'g>,
+    image: &'a str,
+    target_runtime: &'a str,
+}
+
+impl<'a, 'g> BuilderStage<'a, 'g> {
+    pub(super) fn new(package: &'a Package<'g>, image: &'a str, target_runtime: &'a str) -> Self {
+        Self {
+            package,
+            image,
+            target_runtime,
+        }
+    }
+
+    /// The binaries of the package, by name, as their file name.
+    pub(super) fn binaries(&self) -> HashMap<String, PathBuf> {
+        self.package
+            .package_metadata()
+            .build_targets()
+            .filter_map(|build_target| match build_target.id() {
+                BuildTargetId::Binary(name) => Some((
+                    name.to_string(),
+                    PathBuf::from(format!(
+                        "{}{}",
+                        name,
+                        executable_suffix(self.target_runtime)
+                    )),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The path of a binary in the builder stage.
+    pub(super) fn binary_path(&self, file_name: &Path) -> String {
+        format!(
+            "{}/target/{}/{}/{}",
+            BUILDER_WORKDIR,
+            self.target_runtime,
+            self.package.context().options().mode,
+            file_name.display()
+        )
+    }
+
+    /// Stage the workspace in the `builder` directory of the Docker root,
+    /// returning the staged files.
+    ///
+    /// `builder/manifests` holds the manifests of all the members of the
+    /// workspace, as Cargo needs them to load it, with placeholder sources for
+    /// their targets. `builder/sources` holds the sources of the package and of
+    /// the members of the workspace it depends on.
+    pub(super) fn stage(&self, docker_root: &Path) -> Result<Vec<PathBuf>> {
+        let context = self.package.context();
+        let workspace = context.package_graph().workspace();
+        let workspace_root = workspace.root().as_std_path();
+        let manifests_dir = docker_root.join("builder").join("manifests");
+        let sources_dir = docker_root.join("builder").join("sources");
+        let mut staged = Vec::new();
+
+        debug!("Staging the workspace for the builder stage");
+
+        let mut copy = |source: &Path, destination_dir: &Path| -> Result<()> {
+            if let Ok(relative_path) = source.strip_prefix(workspace_root) {
+                let destination = destination_dir.join(relative_path);
+
+                create_parent_dir(&destination)?;
+
+                std::fs::copy(source, &destination)
+                    .map_err(Error::from_source)
+                    .with_full_context(
+                        "failed to copy file",
+                        format!(
+                            "The file `{}` could not be copied to the builder stage.",
+                            source.display()
+                        ),
+                    )?;
+
+                staged.push(destination);
+            }
+
+            Ok(())
+        };
+
+        copy(&workspace_root.join("Cargo.toml"), &manifests_dir)?;
+
+        for file_name in WORKSPACE_FILES {
+            let path = workspace_root.join(file_name);
+
+            if path.exists() {
+                copy(&path, &manifests_dir)?;
+            }
+        }
+
+        for member in workspace.iter() {
+            copy(member.manifest_path().as_std_path(), &manifests_dir)?;
+        }
+
+        let packages = context
+            .package_graph()
+            .query_forward(std::iter::once(self.package.id()))
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve_with_fn(|_, link| is_shipped_dependency(&link, None));
+
+        for package_metadata in packages
+            .packages(DependencyDirection::Forward)
+            .filter(guppy::graph::PackageMetadata::in_workspace)
+        {
+            let sources = Sources::from_package(context, &package_metadata)?;
+
+            for path in sources.files() {
+                copy(path, &sources_dir)?;
+            }
+        }
+
+        for member in workspace.iter() {
+            for build_target in member.build_targets() {
+                let source = build_target.path().as_std_path();
+
+                if let Ok(relative_path) = source.strip_prefix(workspace_root) {
+                    let placeholder = manifests_dir.join(relative_path);
+
+                    // Libraries can be empty, but not the crates with a
+                    // `main` function.
+                    let content = match build_target.id() {
+                        BuildTargetId::Library => "",
+                        _ => "fn main() {}\n",
+                    };
+
+                    create_parent_dir(&placeholder)?;
+
+                    std::fs::write(&placeholder, content)
+                        .map_err(Error::from_source)
+                        .with_context("failed to write placeholder source")?;
+
+                    staged.push(placeholder);
+                }
+            }
+        }
+
+        Ok(staged)
+    }
+
+    /// The instructions of the builder stage, which come before the ones of
+    /// the Dockerfile template.
+    pub(super) fn dockerfile(&self) -> String {
+        let mut cargo_args = vec![
+            "--package".to_string(),
+            self.package.name().to_string(),
+            "--target".to_string(),
+            self.target_runtime.to_string(),
+        ];
+
+        if self.package.context().options().mode.is_release() {
+            cargo_args.push("--release".to_string());
+        }
+
+        let workspace_root = self.package.context().package_graph().workspace().root();
+
+        if workspace_root.join("Cargo.lock").exists() {
+            cargo_args.push("--locked".to_string());
+        }
+
+        let cargo_args = cargo_args.join(" ");
+
+        format!(
+            "\
+# Compile the binaries in a builder image.
+FROM {image} AS builder
+WORKDIR {workdir}
+RUN rustup target add {target_runtime}
+# Compile the dependencies against placeholder sources first, in a layer that
+# is only rebuilt when the manifests or the lock file change.
+COPY builder/manifests/ ./
+RUN cargo build {cargo_args}
+COPY builder/sources/ ./
+# Make the actual sources newer than the placeholders they replace.
+RUN find . -path ./target -prune -o -type f -name '*.rs' -exec touch {{}} + \\
+    && cargo build {cargo_args}
+# End of the builder stage.
+",
+            image = self.image,
+            workdir = BUILDER_WORKDIR,
+            target_runtime = self.target_runtime,
+            cargo_args = cargo_args,
+        )
+    }
+}
+
+fn create_parent_dir(path: &Path) -> Result<()> {
+    let parent = path.parent().unwrap();
+
+    std::fs::create_dir_all(parent)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to create directory",
+            format!(
+                "The build process needed to create `{}` but it could not. You may want to verify permissions.",
+                parent.display()
+            ),
+        )
+}
@@ -5,20 +5,35 @@ use std::{
     process::Command,
 };
 
-use aws_sdk_ecr::{model::Tag, Region, SdkError};
-use cargo::{
-    core::compiler::{CompileMode, CompileTarget},
-    ops::{compile, CompileOptions},
+use aws_sdk_ecr::{
+    model::{ImageIdentifier, Tag},
+    SdkError,
 };
+use aws_smithy_types::date_time::Format;
 use log::{debug, warn};
 use regex::Regex;
+use serde::Deserialize;
 
 use crate::{
-    action_step, ignore_step, rust::is_current_target_runtime, Context, Error, ErrorContext,
-    Package, Result,
+    action_step,
+    aws::AwsSettings,
+    compose::ComposeService,
+    dist_target::{published_artifact, DistTarget},
+    hash::{is_short_hash, short_hash},
+    ignore_step,
+    metadata::{artifact_name_context, CopyPlan},
+    rust::build_binaries,
+    staging::format_size,
+    tools::{self, Tool},
+    Context, Error, ErrorCode, ErrorContext, ErrorKind, Package, PublishedArtifact,
+    PublishedVersion, Result,
 };
 
-use super::DockerMetadata;
+use super::{
+    builder::BuilderStage,
+    image_diff::{image_layers, image_size, ImageContent, ImageDiff},
+    DockerMetadata,
+};
 
 pub const DEFAULT_DOCKER_REGISTRY_ENV_VAR_NAME: &str = "CARGO_MONOREPO_DOCKER_REGISTRY";
 
@@ -30,7 +45,12 @@ pub struct DockerDistTarget<'g> {
 
 impl Display for DockerDistTarget<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "docker[{}]", self.package.name())
+        match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => {
+                write!(f, "docker[{}:{}]", self.package.name(), target_runtime)
+            }
+            None => write!(f, "docker[{}]", self.package.name()),
+        }
     }
 }
 
@@ -45,22 +65,71 @@ impl<'g> DockerDistTarget<'g> {
             return Ok(());
         }
 
+        if let Some(base) = self.metadata.base {
+            base.check_target_runtime(self, self.metadata.target_runtime.as_str())?;
+        }
+
+        // The binaries compiled in the builder stage are not on the host, so
+        // their size budget cannot be checked.
+        let binaries = match self.builder() {
+            Some(builder) => builder.binaries(),
+            None => {
+                let binaries = build_binaries(
+                    self.package,
+                    &self.name,
+                    self.metadata.target_runtime.as_str(),
+                    false,
+                )?;
+
+                self.metadata.size_budget.check_binaries(self, &binaries)?;
+
+                if let Some(base) = self.metadata.base {
+                    base.check_binaries(self, &binaries)?;
+                }
+
+                binaries
+            }
+        };
+
+        self.build_image(&binaries)
+    }
+
+    /// Generate the Docker root with the specified binaries and build the
+    /// image from it.
+    pub(crate) fn build_image(&self, binaries: &HashMap<String, PathBuf>) -> Result<()> {
         self.clean()?;
 
-        let binaries = self.build_binaries()?;
-        let dockerfile = self.write_dockerfile(&binaries)?;
-        self.copy_binaries(binaries.values())?;
-        self.copy_extra_files()?;
+        let copy_plan = self.copy_plan()?;
+        let dockerfile = self.write_dockerfile(binaries)?;
+        let mut build_outputs = match self.builder() {
+            Some(builder) => builder.stage(&self.docker_root())?,
+            None => self.copy_binaries(binaries.values())?,
+        };
+        build_outputs.push(dockerfile.clone());
+        self.copy_extra_files(&copy_plan, &build_outputs)?;
+
+        if self.context().options().no_image {
+            ignore_step!(
+                "Skipping",
+                "Docker image build as `--no-image` was specified"
+            );
+            return Ok(());
+        }
 
         self.build_dockerfile(&dockerfile)?;
+        self.report_image_size()?;
+
+        if self.context().options().diff_published {
+            self.diff_published_image()?;
+        }
 
         Ok(())
     }
 
-    pub fn publish(&self) -> Result<()> {
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
         if cfg!(windows) {
             ignore_step!("Unsupported", "Docker publish is not supported on Windows");
-            return Ok(());
+            return Ok(None);
         }
 
         if self.context().options().mode.is_debug() && !self.context().options().force {
@@ -68,23 +137,456 @@ impl<'g> DockerDistTarget<'g> {
                 "Unsupported",
                 "Docker images can't be published in debug mode unless `--force` is specified"
             );
-            return Ok(());
+            return Ok(None);
         }
 
-        self.push_docker_image()?;
+        // The hash is computed once, as hashing the sources of the package is
+        // not free.
+        let hash = self.package.hash()?;
+        let up_to_date = !self.push_docker_image(&hash)?;
+
+        if self.context().options().dry_run {
+            return Ok(None);
+        }
+
+        let docker_image_name = self.docker_image_name()?;
+        let digest = self.get_docker_image_digest()?;
+
+        action_step!(
+            "Published",
+            "Docker image `{}` with digest `{}`",
+            docker_image_name,
+            digest
+        );
+
+        // An image that was already published may predate its hash tag, which
+        // is then only reported if it exists.
+        let hash_image_name = self.docker_hash_image_name(&hash)?;
+        let hash_reference = if !up_to_date
+            || self
+                .context()
+                .block_on(self.manifest_exists(&hash_image_name))?
+        {
+            Some(hash_image_name)
+        } else {
+            debug!(
+                "Docker image `{}` has no hash tag `{}`: not reporting it",
+                docker_image_name, hash_image_name
+            );
+
+            None
+        };
+
+        Ok(Some(PublishedArtifact {
+            hash_reference,
+            ..published_artifact(
+                self.package,
+                self,
+                docker_image_name,
+                Some(digest),
+                up_to_date,
+            )
+        }))
+    }
+
+    /// Check whether the Docker image for the current version exists in its
+    /// registry.
+    ///
+    /// The check only queries the image manifest and does not pull the image.
+    pub async fn exists(&self) -> Result<bool> {
+        self.manifest_exists(&self.docker_image_name()?).await
+    }
+
+    /// Check whether the manifest of an image exists in its registry.
+    async fn manifest_exists(&self, docker_image_name: &str) -> Result<bool> {
+        let mut cmd = tokio::process::Command::new("docker");
+
+        let args = vec!["manifest", "inspect", docker_image_name];
+
+        debug!("Running `docker {}`", args.join(" "));
+
+        cmd.args(args);
+
+        let output = cmd.output().await.map_err(Error::from_source).with_full_context(
+            "failed to inspect Docker image manifest",
+            "The inspection of the Docker image manifest failed which could indicate a configuration problem.",
+        )?;
+
+        if !output.status.success() {
+            debug!(
+                "Docker image `{}` manifest could not be inspected: {}",
+                docker_image_name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(output.status.success())
+    }
+
+    /// List the versions published in the AWS ECR repository of the image.
+    ///
+    /// Listing the versions published to other registries is not supported.
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        let aws_ecr_information = match self.get_aws_ecr_information()? {
+            Some(aws_ecr_information) => aws_ecr_information,
+            None => return Ok(None),
+        };
+
+        let client = self
+            .context()
+            .aws()
+            .ecr_client(&self.aws_ecr_settings(&aws_ecr_information))
+            .await?;
+
+        let mut versions = Vec::new();
+        let mut next_token = None;
+
+        loop {
+            let output = client
+                .describe_images()
+                .repository_name(&aws_ecr_information.repository_name)
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to list AWS ECR images",
+                    format!(
+                        "Could not list the images of the AWS ECR repository `{}`. Please check your credentials and make sure you have the appropriate permissions.",
+                        aws_ecr_information
+                    ),
+                )?;
+
+            for image in output.image_details.unwrap_or_default() {
+                for tag in image.image_tags.as_deref().unwrap_or_default() {
+                    if let Some(Ok(version)) = self.tag_base(tag).map(semver::Version::parse) {
+                        versions.push(PublishedVersion {
+                            version,
+                            date: image
+                                .image_pushed_at
+                                .as_ref()
+                                .and_then(|date| date.fmt(Format::DateTime).ok()),
+                            timestamp: image.image_pushed_at.as_ref().map(|date| date.secs()),
+                            digest: image.image_digest.clone(),
+                        });
+                    }
+                }
+            }
+
+            next_token = output.next_token;
+
+            if next_token.is_none() {
+                return Ok(Some(versions));
+            }
+        }
+    }
+
+    /// Delete the tag of the specified version from the AWS ECR repository,
+    /// along with the hash tags of the same image.
+    ///
+    /// The image itself is deleted by AWS ECR once it has no tags left: the
+    /// tags of other versions or channels pointing to it are kept.
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        let aws_ecr_information = self.get_aws_ecr_information()?.ok_or_else(|| {
+            Error::new("unsupported Docker image deletion").with_explanation(
+                "Only Docker images published to AWS ECR registries can be deleted.",
+            )
+        })?;
+
+        let client = self
+            .context()
+            .aws()
+            .ecr_client(&self.aws_ecr_settings(&aws_ecr_information))
+            .await?;
+
+        let version_tag = self.docker_tag(&version.to_string());
+
+        let image_tags = client
+            .describe_images()
+            .repository_name(&aws_ecr_information.repository_name)
+            .image_ids(ImageIdentifier::builder().image_tag(&version_tag).build())
+            .send()
+            .await
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to describe AWS ECR image",
+                format!(
+                    "Could not describe the image `{}:{}`. Please check your credentials and make sure you have the appropriate permissions.",
+                    aws_ecr_information, version
+                ),
+            )?
+            .image_details
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|image| image.image_tags.unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let image_ids = tags_to_delete(&version_tag, &image_tags, |tag| {
+            self.tag_base(tag).map_or(false, is_short_hash)
+        })
+        .into_iter()
+        .map(|tag| ImageIdentifier::builder().image_tag(tag).build())
+        .collect();
+
+        let output = client
+            .batch_delete_image()
+            .repository_name(&aws_ecr_information.repository_name)
+            .set_image_ids(Some(image_ids))
+            .send()
+            .await
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to delete AWS ECR image",
+                format!(
+                    "Could not delete the image `{}:{}`. Please check your credentials and make sure you have the appropriate permissions.",
+                    aws_ecr_information, version
+                ),
+            )?;
+
+        if let Some(failure) = output.failures.unwrap_or_default().into_iter().next() {
+            return Err(
+                Error::new("failed to delete AWS ECR image").with_explanation(format!(
+                    "Could not delete the image `{}:{}`: {}",
+                    aws_ecr_information,
+                    version,
+                    failure
+                        .failure_reason
+                        .as_deref()
+                        .unwrap_or("unknown failure reason"),
+                )),
+            );
+        }
 
         Ok(())
     }
 
-    fn pull_docker_image(&self, docker_image_name: &str) -> Result<bool> {
-        let mut cmd = Command::new("docker");
+    pub fn artifact_reference(&self) -> Result<String> {
+        self.docker_image_name()
+    }
 
-        debug!(
-            "Will now pull docker image `{}` to check for existence",
-            docker_image_name
+    pub(crate) fn registry_login(&self) -> Result<()> {
+        self.context().registry_login(
+            &self.docker_repository_name()?,
+            self.metadata.credential_helper.as_deref(),
+        )
+    }
+
+    /// Promote the current version of the Docker image to the specified
+    /// channel tag.
+    ///
+    /// The promotion happens registry-side and does not require the image to
+    /// be rebuilt nor pulled locally.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        if cfg!(windows) {
+            ignore_step!(
+                "Unsupported",
+                "Docker promotion is not supported on Windows"
+            );
+            return Ok(());
+        }
+
+        if !is_valid_docker_tag(channel) {
+            return Err(Error::new("invalid channel name")
+                .with_kind(ErrorKind::Validation)
+                .with_explanation(format!(
+                    "The channel `{}` is not a valid Docker tag. Tags must only contain alphanumeric characters, `_`, `.` or `-` and cannot start with `.` or `-`.",
+                    channel
+                )));
+        }
+
+        let source = self.docker_image_name()?;
+        let destination = format!(
+            "{}:{}",
+            self.docker_repository_name()?,
+            self.docker_tag(channel)
+        );
+
+        self.registry_login()?;
+
+        let args = vec![
+            "buildx",
+            "imagetools",
+            "create",
+            "--tag",
+            &destination,
+            &source,
+        ];
+
+        if self.context().options().dry_run {
+            warn!("Would now execute: docker {}", args.join(" "));
+            warn!("`--dry-run` specified: not continuing for real");
+
+            return Ok(());
+        }
+
+        action_step!(
+            "Promoting",
+            "Docker image `{}` to `{}`",
+            source,
+            destination
         );
 
-        let args = vec!["pull", docker_image_name];
+        self.run_docker_command(&args, "promote")
+    }
+
+    /// Replace the local container with the specified name by a new one
+    /// running the current image.
+    pub(crate) fn restart_container(&self, container_name: &str) -> Result<()> {
+        let docker_image_name = self.docker_image_name()?;
+
+        // The container may not exist yet, in which case there is nothing to
+        // remove.
+        let output = Command::new("docker")
+            .args(["rm", "--force", container_name])
+            .output()
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to remove Docker container",
+                "The Docker command could not be executed which could indicate a configuration problem.",
+            )?;
+
+        if !output.status.success() {
+            debug!(
+                "Could not remove Docker container `{}`: {}",
+                container_name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let mut args = vec!["run", "--detach", "--name", container_name];
+        let run_args = self.docker_run_args();
+        args.extend(run_args.iter().map(String::as_str));
+        args.push(&docker_image_name);
+
+        self.run_docker_command(&args, "run")
+    }
+
+    /// Run the current image locally in the foreground, with the ports,
+    /// environment variables and volumes declared in the metadata.
+    ///
+    /// The container is removed when it exits.
+    pub(crate) fn run_container(&self) -> Result<()> {
+        let docker_image_name = self.docker_image_name()?;
+
+        let mut args = vec!["run", "--rm", "--interactive"];
+
+        if atty::is(atty::Stream::Stdin) {
+            args.push("--tty");
+        }
+
+        let run_args = self.docker_run_args();
+        args.extend(run_args.iter().map(String::as_str));
+        args.push(&docker_image_name);
+
+        action_step!("Running", "`docker {}`", args.join(" "));
+
+        let status = Command::new("docker")
+            .args(&args)
+            .status()
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to run Docker container",
+                "The Docker command could not be executed which could indicate a configuration problem.",
+            )?;
+
+        if !status.success() {
+            return Err(Error::new("Docker container exited with an error").with_explanation(
+                format!(
+                    "The container of the image `{}` exited with {}. Check the logs above to determine the cause.",
+                    docker_image_name, status
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The service running the locally-built image in a `docker-compose.yaml`
+    /// file.
+    ///
+    /// Ports declared in the metadata take precedence over the ones exposed
+    /// by the generated Dockerfile, which must have been built beforehand.
+    pub(crate) fn compose_service(&self) -> Result<ComposeService> {
+        let dockerfile_path = self.get_dockerfile_name();
+        let dockerfile = std::fs::read_to_string(&dockerfile_path)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read Dockerfile",
+                format!(
+                    "The Dockerfile `{}` could not be read. Was the image built with `cargo monorepo build-dist`?",
+                    dockerfile_path.display()
+                ),
+            )?;
+
+        let run = self.metadata.run.clone().unwrap_or_default();
+
+        let ports = if run.ports.is_empty() {
+            parse_exposed_ports(&dockerfile)
+                .into_iter()
+                .map(|port| match port.split_once('/') {
+                    Some((number, protocol)) => format!("{}:{}/{}", number, number, protocol),
+                    None => format!("{}:{}", port, port),
+                })
+                .collect()
+        } else {
+            run.ports
+        };
+
+        Ok(ComposeService {
+            image: self.docker_image_name()?,
+            ports,
+            environment: run.env,
+            volumes: run
+                .volumes
+                .iter()
+                .map(|volume| self.resolve_volume(volume))
+                .collect(),
+        })
+    }
+
+    /// Make the host path of a volume absolute if it is relative to the
+    /// package root.
+    fn resolve_volume(&self, volume: &str) -> String {
+        match volume.split_once(':') {
+            Some((host, container)) if host.starts_with("./") || host.starts_with("../") => {
+                format!("{}:{}", self.package.root().join(host).display(), container)
+            }
+            _ => volume.to_string(),
+        }
+    }
+
+    /// The `docker run` arguments for the ports, environment variables and
+    /// volumes declared in the metadata.
+    fn docker_run_args(&self) -> Vec<String> {
+        let run = match &self.metadata.run {
+            Some(run) => run,
+            None => return vec![],
+        };
+
+        let mut args = Vec::new();
+
+        for port in &run.ports {
+            args.push("--publish".to_string());
+            args.push(port.clone());
+        }
+
+        for (name, value) in &run.env {
+            args.push("--env".to_string());
+            args.push(format!("{}={}", name, value));
+        }
+
+        for volume in &run.volumes {
+            args.push("--volume".to_string());
+            args.push(self.resolve_volume(volume));
+        }
+
+        args
+    }
+
+    /// Run a `docker` command, streaming its output in verbose mode or
+    /// capturing it to report it in case of failure otherwise.
+    fn run_docker_command(&self, args: &[&str], action: &str) -> Result<()> {
+        let mut cmd = Command::new("docker");
 
         action_step!("Running", "`docker {}`", args.join(" "),);
 
@@ -92,35 +594,118 @@ impl<'g> DockerDistTarget<'g> {
 
         if self.context().options().verbose {
             let status = cmd.status().map_err(Error::from_source).with_full_context(
-                "failed to pull Docker image",
-                "The pull of the Docker image failed which could indicate a configuration problem.",
+                format!("failed to {} Docker image", action),
+                "The Docker command could not be executed which could indicate a configuration problem.",
             )?;
 
-            Ok(status.success())
+            if !status.success() {
+                return Err(Error::new(format!("failed to {} Docker image", action))
+                    .with_explanation(
+                        "The Docker command failed. Check the logs above to determine the cause.",
+                    ));
+            }
         } else {
             let output = cmd.output().map_err(Error::from_source).with_full_context(
-                "failed to pull Docker image",
-                "The pull of the Docker image failed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
+                format!("failed to {} Docker image", action),
+                "The Docker command could not be executed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
             )?;
 
-            Ok(output.status.success())
+            if !output.status.success() {
+                return Err(Error::new(format!("failed to {} Docker image", action))
+                    .with_explanation(
+                        "The Docker command failed. Check the logs below to determine the cause.",
+                    )
+                    .with_output(String::from_utf8_lossy(&output.stderr)));
+            }
         }
+
+        Ok(())
     }
 
-    fn push_docker_image(&self) -> Result<()> {
+    /// Get the repository digest of the Docker image.
+    ///
+    /// The digest is queried from the registry, so the image does not need to
+    /// be present locally.
+    fn get_docker_image_digest(&self) -> Result<String> {
         let mut cmd = Command::new("docker");
         let docker_image_name = self.docker_image_name()?;
 
+        let args = vec![
+            "buildx",
+            "imagetools",
+            "inspect",
+            "--format",
+            "{{json .Manifest}}",
+            &docker_image_name,
+        ];
+
+        debug!("Running `docker {}`", args.join(" "));
+
+        cmd.args(args);
+
+        let output = cmd.output().map_err(Error::from_source).with_full_context(
+            "failed to inspect Docker image",
+            "The inspection of the Docker image failed which could indicate a configuration problem.",
+        )?;
+
+        if !output.status.success() {
+            return Err(Error::new("failed to inspect Docker image")
+                .with_explanation("The inspection of the Docker image failed. Check the logs below to determine the cause.")
+                .with_output(String::from_utf8_lossy(&output.stderr)));
+        }
+
+        #[derive(Deserialize)]
+        struct Manifest {
+            digest: String,
+        }
+
+        serde_json::from_slice::<Manifest>(&output.stdout)
+            .map(|manifest| manifest.digest)
+            .map_err(|err| {
+                Error::new("failed to determine Docker image digest")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The manifest of the Docker image `{}` could not be parsed.",
+                        docker_image_name
+                    ))
+                    .with_output(String::from_utf8_lossy(&output.stdout))
+            })
+    }
+
+    /// Push the Docker image to its registry, under both its version tag and
+    /// the tag of the specified package hash.
+    ///
+    /// Returns `false` if the image already existed in the registry and was
+    /// not pushed.
+    fn push_docker_image(&self, hash: &str) -> Result<bool> {
+        let docker_image_name = self.docker_image_name()?;
+
+        self.registry_login()?;
+
         if self.context().options().force {
             debug!("`--force` specified: not checking for Docker image existence before pushing");
-        } else if self.pull_docker_image(&docker_image_name)? {
+        } else if self
+            .context()
+            .artifact_exists(&docker_image_name, self.exists())?
+        {
             ignore_step!(
                 "Up-to-date",
                 "Docker image `{}` already exists",
                 docker_image_name,
             );
 
-            return Ok(());
+            return Ok(false);
+        }
+
+        if let Some(smoke_check) = &self.metadata.smoke_check {
+            let env = self
+                .metadata
+                .run
+                .as_ref()
+                .map(|run| run.env.clone())
+                .unwrap_or_default();
+
+            smoke_check.run(self.package.name(), &docker_image_name, &env)?;
         }
 
         debug!("Will now push docker image `{}`", docker_image_name);
@@ -149,7 +734,18 @@ impl<'g> DockerDistTarget<'g> {
             );
         }
 
-        let args = vec!["push", &docker_image_name];
+        // The hash tag is pushed first, so that the version tag is never
+        // published without it.
+        for image_name in [self.docker_hash_image_name(hash)?, docker_image_name] {
+            self.push_docker_image_tag(&image_name)?;
+        }
+
+        Ok(true)
+    }
+
+    fn push_docker_image_tag(&self, image_name: &str) -> Result<()> {
+        let mut cmd = Command::new("docker");
+        let args = vec!["push", image_name];
 
         if self.context().options().dry_run {
             warn!("Would now execute: docker {}", args.join(" "));
@@ -169,9 +765,11 @@ impl<'g> DockerDistTarget<'g> {
             )?;
 
             if !status.success() {
-                return Err(Error::new("failed to push Docker image").with_explanation(
-                    "The push of the Docker image failed. Check the logs above to determine the cause.",
-                ));
+                return Err(Error::new("failed to push Docker image")
+                    .with_kind(ErrorKind::Network)
+                    .with_explanation(
+                        "The push of the Docker image failed. Check the logs above to determine the cause.",
+                    ));
             }
         } else {
             let output = cmd.output().map_err(Error::from_source).with_full_context(
@@ -181,6 +779,7 @@ impl<'g> DockerDistTarget<'g> {
 
             if !output.status.success() {
                 return Err(Error::new("failed to push Docker image")
+                    .with_kind(ErrorKind::Network)
                     .with_explanation("The push of the Docker image failed. Check the logs below to determine the cause.")
                     .with_output(String::from_utf8_lossy(&output.stderr)));
             };
@@ -189,6 +788,17 @@ impl<'g> DockerDistTarget<'g> {
         Ok(())
     }
 
+    fn aws_ecr_settings(&self, aws_ecr_information: &AwsEcrInformation) -> AwsSettings {
+        AwsSettings {
+            region: Some(aws_ecr_information.region.clone()),
+            endpoint_url: self.metadata.aws_endpoint_url.clone(),
+            profile: self.metadata.aws_profile.clone(),
+            assume_role_arn: self.metadata.assume_role_arn.clone(),
+            assume_role_external_id: self.metadata.assume_role_external_id.clone(),
+            credentials_env: None,
+        }
+    }
+
     fn ensure_aws_ecr_repository_exists(
         &self,
         aws_ecr_information: &AwsEcrInformation,
@@ -198,15 +808,12 @@ impl<'g> DockerDistTarget<'g> {
             aws_ecr_information.to_string()
         );
 
-        let runtime = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-
-        runtime.block_on(async move {
-            let region_provider = Region::new(aws_ecr_information.region.clone());
-            let shared_config = aws_config::from_env().region(region_provider).load().await;
-            let client = aws_sdk_ecr::Client::new(&shared_config);
+        self.context().block_on(async move {
+            let client = self
+                .context()
+                .aws()
+                .ecr_client(&self.aws_ecr_settings(aws_ecr_information))
+                .await?;
             let output = client
                 .create_repository()
                 .repository_name(&aws_ecr_information.repository_name)
@@ -261,6 +868,7 @@ impl<'g> DockerDistTarget<'g> {
     fn build_dockerfile(&self, docker_file: &Path) -> Result<()> {
         let mut cmd = Command::new("docker");
         let docker_image_name = self.docker_image_name()?;
+        let docker_hash_image_name = self.docker_hash_image_name(&self.package.hash()?)?;
 
         let docker_root = docker_file
             .parent()
@@ -270,7 +878,27 @@ impl<'g> DockerDistTarget<'g> {
 
         cmd.current_dir(docker_root);
 
-        let args = vec!["build", "-t", &docker_image_name, "."];
+        let labels: Vec<String> = self
+            .package
+            .provenance()?
+            .docker_labels()
+            .into_iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+
+        let mut args = vec![
+            "build",
+            "-t",
+            &docker_image_name,
+            "-t",
+            &docker_hash_image_name,
+        ];
+
+        for label in &labels {
+            args.extend(["--label", label.as_str()]);
+        }
+
+        args.push(".");
 
         action_step!("Running", "`docker {}`", args.join(" "),);
 
@@ -286,9 +914,11 @@ impl<'g> DockerDistTarget<'g> {
             )?;
 
             if !status.success() {
-                return Err(Error::new("failed to build Docker image").with_explanation(
-                    "The build of the Docker image failed. Check the logs above to determine the cause.",
-                ));
+                return Err(Error::new("failed to build Docker image")
+                    .with_kind(ErrorKind::Compilation)
+                    .with_explanation(
+                        "The build of the Docker image failed. Check the logs above to determine the cause.",
+                    ));
             }
         } else {
             let output = cmd.output().map_err(Error::from_source).with_full_context(
@@ -298,6 +928,7 @@ impl<'g> DockerDistTarget<'g> {
 
             if !output.status.success() {
                 return Err(Error::new("failed to build Docker image")
+                    .with_kind(ErrorKind::Compilation)
                     .with_explanation("The build of the Docker image failed. Check the logs below to determine the cause.")
                     .with_output(String::from_utf8_lossy(&output.stderr)));
             };
@@ -306,6 +937,78 @@ impl<'g> DockerDistTarget<'g> {
         Ok(())
     }
 
+    /// Report the size of the built image with a breakdown per layer,
+    /// failing if it exceeds its size budget.
+    fn report_image_size(&self) -> Result<()> {
+        let docker_image_name = self.docker_image_name()?;
+        let size = image_size(&docker_image_name)?;
+        let layers = image_layers(&docker_image_name)?;
+
+        let result = self
+            .metadata
+            .size_budget
+            .check_artifact(self, "image", size);
+
+        for (created_by, layer_size) in layers {
+            println!("  {:>10}  {}", format_size(layer_size), created_by);
+        }
+
+        result
+    }
+
+    /// Pull the latest published version of the image and print how the
+    /// built image differs from it.
+    fn diff_published_image(&self) -> Result<()> {
+        self.context()
+            .ensure_online("comparing with the published image")?;
+
+        let versions = match self.context().block_on(self.published_versions())? {
+            Some(versions) => versions,
+            None => {
+                ignore_step!(
+                    "Skipping",
+                    "comparison of {} as listing the published versions is only supported on AWS ECR",
+                    self
+                );
+                return Ok(());
+            }
+        };
+
+        let version = match versions.into_iter().map(|v| v.version).max() {
+            Some(version) => version,
+            None => {
+                ignore_step!(
+                    "Skipping",
+                    "comparison of {} as no version was published yet",
+                    self
+                );
+                return Ok(());
+            }
+        };
+
+        let published_image_name = format!(
+            "{}:{}",
+            self.docker_repository_name()?,
+            self.docker_tag(&version.to_string())
+        );
+
+        self.registry_login()?;
+
+        action_step!("Pulling", "Docker image `{}`", published_image_name);
+        docker_output(&["pull", "--quiet", &published_image_name])?;
+
+        action_step!("Comparing", "{} with `{}`", self, published_image_name);
+
+        let diff = ImageDiff::new(
+            ImageContent::inspect(&published_image_name)?,
+            ImageContent::inspect(&self.docker_image_name()?)?,
+        );
+
+        print!("{}", diff);
+
+        Ok(())
+    }
+
     fn registry(&self) -> Result<String> {
         match self.metadata.registry {
             Some(ref registry) => Ok(registry.clone()),
@@ -313,34 +1016,114 @@ impl<'g> DockerDistTarget<'g> {
                 if let Ok(registry) = std::env::var(DEFAULT_DOCKER_REGISTRY_ENV_VAR_NAME) {
                     Ok(registry)
                 } else {
-                    Err(
-                        Error::new("failed to determine Docker registry").with_explanation(
-                            format!(
+                    Err(Error::new("failed to determine Docker registry")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_code(ErrorCode::RegistryNotConfigured)
+                        .with_explanation(format!(
                         "The field registry is empty and the environment variable {} was not set",
                         DEFAULT_DOCKER_REGISTRY_ENV_VAR_NAME
-                    ),
-                        ),
-                    )
+                    )))
+                }
+            }
+        }
+    }
+
+    /// The repository of the image, which defaults to `<registry>/<name>`.
+    ///
+    /// A `repository` template can use the `registry`, if one is configured.
+    fn docker_repository_name(&self) -> Result<String> {
+        match &self.metadata.repository {
+            Some(repository) => {
+                let mut context =
+                    artifact_name_context(self.package, self.metadata.target_runtime.as_str());
+
+                if let Ok(registry) = self.registry() {
+                    context.insert("registry", &registry);
                 }
+
+                repository.render_for_package(self.package, &context)
             }
+            None => Ok(format!("{}/{}", self.registry()?, self.package.name())),
         }
     }
 
-    fn docker_image_name(&self) -> Result<String> {
+    pub(crate) fn docker_image_name(&self) -> Result<String> {
         Ok(format!(
-            "{}/{}:{}",
-            self.registry()?,
-            self.package.name(),
-            self.package.version(),
+            "{}:{}",
+            self.docker_repository_name()?,
+            self.docker_tag(&self.package.version().to_string()),
         ))
     }
 
+    /// Check whether the image of the current version exists in the local
+    /// image store.
+    pub(crate) fn image_exists_locally(&self) -> Result<bool> {
+        let docker_image_name = self.docker_image_name()?;
+        let args = [
+            "image",
+            "inspect",
+            "--format",
+            "{{.Id}}",
+            &docker_image_name,
+        ];
+
+        debug!("Running `docker {}`", args.join(" "));
+
+        // Docker being unavailable only means that the image must be built.
+        Ok(Command::new("docker")
+            .args(args)
+            .output()
+            .map_or(false, |output| output.status.success()))
+    }
+
+    /// The name of the image under its hash tag, made of the short form of
+    /// the specified package hash, which unlike the version tag never moves to
+    /// different content.
+    fn docker_hash_image_name(&self, hash: &str) -> Result<String> {
+        Ok(format!(
+            "{}:{}",
+            self.docker_repository_name()?,
+            self.docker_tag(short_hash(hash)),
+        ))
+    }
+
+    /// The version or channel of a tag of the image, without the target
+    /// runtime of the entries of a matrix or the preview identifier.
+    ///
+    /// Returns `None` if the tag does not belong to the current target runtime
+    /// or preview.
+    fn tag_base<'t>(&self, tag: &'t str) -> Option<&'t str> {
+        let tag = match &self.context().options().preview {
+            Some(preview) => tag
+                .strip_suffix(preview.as_str())
+                .and_then(|tag| tag.strip_suffix('-'))?,
+            None => tag,
+        };
+
+        match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => tag
+                .strip_suffix(target_runtime)
+                .and_then(|tag| tag.strip_suffix('-')),
+            None => Some(tag),
+        }
+    }
+
+    /// The tag of the image for the specified version or channel, qualified
+    /// with the target runtime for the entries of a matrix and with the
+    /// preview identifier, if any.
+    fn docker_tag(&self, version_or_channel: &str) -> String {
+        let tag = match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => format!("{}-{}", version_or_channel, target_runtime),
+            None => version_or_channel.to_string(),
+        };
+
+        self.context().options().preview_tag(tag)
+    }
+
     fn get_aws_ecr_information(&self) -> Result<Option<AwsEcrInformation>> {
-        Ok(AwsEcrInformation::from_string(&format!(
-            "{}/{}",
-            self.registry()?,
-            self.package.name(),
-        )))
+        Ok(AwsEcrInformation::from_string(
+            &self.docker_repository_name()?,
+        ))
     }
 
     fn target_dir(&self) -> PathBuf {
@@ -350,8 +1133,35 @@ impl<'g> DockerDistTarget<'g> {
             .join(self.context().options().mode.to_string())
     }
 
+    /// The external tools required to build or publish the image.
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        if publish || !self.context().options().no_image {
+            vec![tools::DOCKER]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.docker_root()
+    }
+
     fn docker_root(&self) -> PathBuf {
-        self.target_dir().join("docker").join(self.package.name())
+        let docker_root = self.target_dir().join("docker").join(self.package.name());
+
+        match self.metadata.target_runtime.matrix_target_runtime() {
+            Some(target_runtime) => docker_root.join(target_runtime),
+            None => docker_root,
+        }
+    }
+
+    /// The builder stage compiling the binaries, if the image has a builder
+    /// image.
+    fn builder(&self) -> Option<BuilderStage<'_, 'g>> {
+        self.metadata.builder_image.as_deref().map(|image| {
+            BuilderStage::new(self.package, image, self.metadata.target_runtime.as_str())
+        })
     }
 
     fn docker_target_bin_dir(&self) -> PathBuf {
@@ -364,37 +1174,10 @@ impl<'g> DockerDistTarget<'g> {
         self.docker_root().join(relative_target_bin_dir)
     }
 
-    fn build_binaries(&self) -> Result<HashMap<String, PathBuf>> {
-        let ws = self.context().workspace()?;
-        let mut compile_options = CompileOptions::new(ws.config(), CompileMode::Build).unwrap();
-
-        compile_options.spec =
-            cargo::ops::Packages::Packages(vec![self.package.name().to_string()]);
-        compile_options.build_config.requested_profile =
-            cargo::util::interning::InternedString::new(&self.context().options().mode.to_string());
-
-        if !is_current_target_runtime(&self.metadata.target_runtime)? {
-            compile_options.build_config.requested_kinds =
-                vec![cargo::core::compiler::CompileKind::Target(
-                    CompileTarget::new(&self.metadata.target_runtime).unwrap(),
-                )];
-        }
-
-        compile(&ws, &compile_options)
-            .map(|compilation| {
-                compilation
-                    .binaries
-                    .iter()
-                    .map(|b| (b.unit.target.name().to_string(), b.path.clone()))
-                    .collect()
-            })
-            .map_err(|err| Error::new("failed to compile binaries").with_source(err))
-    }
-
     fn copy_binaries<'p>(
         &self,
         source_binaries: impl IntoIterator<Item = &'p PathBuf>,
-    ) -> Result<()> {
+    ) -> Result<Vec<PathBuf>> {
         debug!("Will now copy all dependant binaries");
 
         let docker_target_bin_dir = self.docker_target_bin_dir();
@@ -406,24 +1189,27 @@ impl<'g> DockerDistTarget<'g> {
         format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", &docker_target_bin_dir.display()),
             )?;
 
-        for source in source_binaries {
-            let binary = source.file_name().unwrap().to_string_lossy().to_string();
-            let target = self.docker_target_bin_dir().join(&binary);
+        source_binaries
+            .into_iter()
+            .map(|source| {
+                let binary = source.file_name().unwrap().to_string_lossy().to_string();
+                let target = self.docker_target_bin_dir().join(&binary);
 
-            debug!("Copying {} to {}", source.display(), target.display());
+                debug!("Copying {} to {}", source.display(), target.display());
 
-            std::fs::copy(source, target)
-                .map_err(Error::from_source)
-                .with_full_context(
-                    "failed to copy binary",
-                    format!(
-                        "The binary `{}` could not be copied to the Docker image.",
-                        binary
-                    ),
-                )?;
-        }
+                std::fs::copy(source, &target)
+                    .map_err(Error::from_source)
+                    .with_full_context(
+                        "failed to copy binary",
+                        format!(
+                            "The binary `{}` could not be copied to the Docker image.",
+                            binary
+                        ),
+                    )?;
 
-        Ok(())
+                Ok(target)
+            })
+            .collect()
     }
 
     fn clean(&self) -> Result<()> {
@@ -437,14 +1223,18 @@ impl<'g> DockerDistTarget<'g> {
         Ok(())
     }
 
-    fn copy_extra_files(&self) -> Result<()> {
-        debug!("Will now copy all extra files");
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &self.docker_root(),
+        )
+    }
 
-        for copy_command in &self.metadata.extra_files {
-            copy_command.copy_files(self.package.root(), &self.docker_root())?;
-        }
+    fn copy_extra_files(&self, copy_plan: &CopyPlan, build_outputs: &[PathBuf]) -> Result<()> {
+        debug!("Will now copy all extra files");
 
-        Ok(())
+        copy_plan.execute(self.context().options().dry_run, build_outputs)
     }
 
     fn write_dockerfile(&self, binaries: &HashMap<String, PathBuf>) -> Result<PathBuf> {
@@ -475,12 +1265,66 @@ impl<'g> DockerDistTarget<'g> {
         self.docker_root().join("Dockerfile")
     }
 
-    fn generate_context(&self, binaries: &HashMap<String, PathBuf>) -> tera::Context {
+    fn generate_context(&self, binaries: &HashMap<String, PathBuf>) -> Result<tera::Context> {
         let mut context = tera::Context::new();
 
         context.insert("package_name", self.package.name());
         context.insert("package_version", self.package.version());
 
+        self.insert_base_image(&mut context)?;
+
+        let builder = self.builder();
+
+        self.insert_binaries(&mut context, binaries, builder.as_ref());
+        self.insert_extra_files(&mut context)?;
+        insert_copy_helpers(&mut context, builder.is_some());
+
+        Ok(context)
+    }
+
+    /// Insert the `base_image` or `from_base` variable of the image, if any.
+    fn insert_base_image(&self, context: &mut tera::Context) -> Result<()> {
+        if let Some(base_image) = &self.metadata.base_image {
+            context.insert("base_image", &self.base_image_name(base_image)?);
+        }
+
+        if let Some(base) = self.metadata.base {
+            if self.metadata.base_image.is_some() {
+                return Err(Error::new("conflicting base images")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "{} specifies both a `base` and a `base_image`, but an image can only be built on one of them.",
+                        self
+                    )));
+            }
+
+            context.insert("from_base", &base.dockerfile());
+        }
+
+        Ok(())
+    }
+
+    /// Insert the `binaries` variable, with the paths of the binaries in the
+    /// image, and the `builder_binaries` one, with their paths in the builder
+    /// stage, if any.
+    fn insert_binaries(
+        &self,
+        context: &mut tera::Context,
+        binaries: &HashMap<String, PathBuf>,
+        builder: Option<&BuilderStage<'_, 'g>>,
+    ) {
+        // The binaries compiled in the builder stage are copied from it.
+        let builder_binaries: HashMap<_, _> = builder
+            .iter()
+            .flat_map(|builder| {
+                binaries
+                    .iter()
+                    .map(move |(name, binary)| (name, builder.binary_path(binary)))
+            })
+            .collect();
+
+        context.insert("builder_binaries", &builder_binaries);
+
         let binaries: HashMap<_, _> = binaries
             .iter()
             .map(|(name, binary)| {
@@ -496,7 +1340,11 @@ impl<'g> DockerDistTarget<'g> {
             .collect();
 
         context.insert("binaries", &binaries);
+    }
 
+    /// Insert the `extra_files` variable and the `extra_files_chown` one, with
+    /// the `--chown` flag of each extra file, if any.
+    fn insert_extra_files(&self, context: &mut tera::Context) -> Result<()> {
         let extra_files: HashSet<String> = self
             .metadata
             .extra_files
@@ -506,8 +1354,119 @@ impl<'g> DockerDistTarget<'g> {
 
         context.insert("extra_files", &extra_files);
 
-        // Add some helpers for common patterns to improve user experience.
-        let copy_all_binaries = tera::Tera::one_off(
+        let mut extra_files_chown: HashMap<String, String> = HashMap::new();
+
+        for copy_command in &self.metadata.extra_files {
+            let destination = copy_command.destination.display().to_string();
+            let chown = copy_command
+                .chown()
+                .map(|chown| format!("--chown={} ", chown))
+                .unwrap_or_default();
+
+            match extra_files_chown.get(&destination) {
+                Some(previous) if previous != &chown => {
+                    return Err(Error::new("conflicting ownership of extra files")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_explanation(format!(
+                            "Several copy commands of {} copy files to `{}` with a different `owner` or `group`, which can't be set separately in the Dockerfile.",
+                            self, destination
+                        )));
+                }
+                _ => {
+                    extra_files_chown.insert(destination, chown);
+                }
+            }
+        }
+
+        context.insert("extra_files_chown", &extra_files_chown);
+
+        Ok(())
+    }
+
+    /// The name of the image of the `docker-base` distribution target of the
+    /// specified package.
+    fn base_image_name(&self, base_image: &str) -> Result<String> {
+        let package = self.context().resolve_package_by_name(base_image)?;
+
+        let docker_base = package
+            .dist_targets()
+            .into_iter()
+            .find_map(|dist_target| match dist_target {
+                DistTarget::DockerBase(docker_base) => Some(docker_base),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                Error::new("invalid base image")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "The package `{}` referenced by the `base_image` field of {} has no `docker-base` distribution target.",
+                        base_image, self
+                    ))
+            })?;
+
+        docker_base.artifact_reference()
+    }
+
+    fn generate_dockerfile(&self, binaries: &HashMap<String, PathBuf>) -> Result<String> {
+        let context = self.generate_context(binaries)?;
+
+        let dockerfile = self
+            .metadata
+            .template
+            .render_for_package(self.package, &context)
+            .map_err(Error::from_source).with_full_context(
+                "failed to render Dockerfile template",
+                "The specified Dockerfile template could not rendered properly, which may indicate a possible syntax error."
+            )?;
+
+        // The builder stage comes first, so that the stage of the template is
+        // the final one.
+        Ok(match self.builder() {
+            Some(builder) => format!("{}\n{}", builder.dockerfile(), dockerfile),
+            None => dockerfile,
+        })
+    }
+}
+
+/// Get the ports exposed by the `EXPOSE` instructions of a Dockerfile.
+///
+/// Ports specified through variables cannot be resolved and are ignored.
+fn parse_exposed_ports(dockerfile: &str) -> Vec<String> {
+    dockerfile
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+
+            words
+                .next()
+                .filter(|instruction| instruction.eq_ignore_ascii_case("EXPOSE"))
+                .map(|_| words)
+        })
+        .flatten()
+        .filter(|port| !port.contains('$'))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Insert the `copy_all_binaries`, `copy_all_extra_files` and `copy_all`
+/// helpers for common patterns, rendered from the `binaries` and `extra_files`
+/// variables of the context, to improve user experience.
+fn insert_copy_helpers(context: &mut tera::Context, from_builder: bool) {
+    let copy_all_binaries = if from_builder {
+        tera::Tera::one_off(
+            "
+# Copy all binaries from the builder stage.
+{% for name, binary in binaries -%}
+# Copy the binary `{{ name }}`.
+COPY --from=builder {{ builder_binaries[name] }} {{ binary }}
+{% endfor -%}
+# End of copy.
+",
+            context,
+            false,
+        )
+    } else {
+        tera::Tera::one_off(
             "
 # Copy all binaries to the Docker image.
 {% for name, binary in binaries -%}
@@ -516,43 +1475,66 @@ ADD {{ binary }} {{ binary }}
 {% endfor -%}
 # End of copy.
 ",
-            &context,
+            context,
             false,
         )
-        .unwrap();
+    }
+    .unwrap();
 
-        context.insert("copy_all_binaries", copy_all_binaries.trim());
+    context.insert("copy_all_binaries", copy_all_binaries.trim());
 
-        let copy_all_extra_files = tera::Tera::one_off(
-            "
+    let copy_all_extra_files = tera::Tera::one_off(
+        "
 # Copy all extra files to the Docker image.
 {% for extra_file in extra_files -%}
-ADD {{ extra_file }} {{ extra_file }}
+ADD {{ extra_files_chown[extra_file] }}{{ extra_file }} {{ extra_file }}
 {% endfor -%}
 # End of copy.
 ",
-            &context,
-            false,
-        )
-        .unwrap();
+        context,
+        false,
+    )
+    .unwrap();
 
-        context.insert("copy_all_extra_files", copy_all_extra_files.trim());
+    context.insert("copy_all_extra_files", copy_all_extra_files.trim());
 
-        let copy_all = [copy_all_binaries, copy_all_extra_files].join("\n");
-        context.insert("copy_all", copy_all.trim());
+    let copy_all = [copy_all_binaries, copy_all_extra_files].join("\n");
+    context.insert("copy_all", copy_all.trim());
+}
 
-        context
+/// Run a Docker command, returning its output.
+pub(super) fn docker_output(args: &[&str]) -> Result<String> {
+    debug!("Running `docker {}`", args.join(" "));
+
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to run Docker command",
+            format!(
+                "`docker {}` could not be executed. Is Docker installed?",
+                args.join(" ")
+            ),
+        )?;
+
+    if !output.status.success() {
+        return Err(Error::new("failed to run Docker command")
+            .with_explanation(format!(
+                "`docker {}` failed. Check the logs below to determine the cause.",
+                args.join(" ")
+            ))
+            .with_output(String::from_utf8_lossy(&output.stderr)));
     }
 
-    fn generate_dockerfile(&self, binaries: &HashMap<String, PathBuf>) -> Result<String> {
-        let context = self.generate_context(binaries);
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
 
-        self.metadata.template.render(&context)
-            .map_err(Error::from_source).with_full_context(
-                "failed to render Dockerfile template",
-                "The specified Dockerfile template could not rendered properly, which may indicate a possible syntax error."
-            )
-    }
+/// Check whether the specified string is a valid Docker tag.
+fn is_valid_docker_tag(tag: &str) -> bool {
+    let re = Regex::new(r"^[a-zA-Z0-9_][a-zA-Z0-9_.-]{0,127}$").unwrap();
+
+    re.is_match(tag)
 }
 
 struct AwsEcrInformation {
@@ -586,10 +1568,97 @@ impl Display for AwsEcrInformation {
     }
 }
 
+/// The tags to delete to remove a version of an image: its version tag and
+/// the hash tags of the same image, which would otherwise keep it alive.
+fn tags_to_delete(
+    version_tag: &str,
+    image_tags: &[String],
+    is_hash_tag: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    std::iter::once(version_tag.to_string())
+        .chain(
+            image_tags
+                .iter()
+                .filter(|tag| tag.as_str() != version_tag && is_hash_tag(tag))
+                .cloned(),
+        )
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_tags_to_delete() {
+        let image_tags = vec![
+            "1.2.0".to_string(),
+            "3f8a2c1d9e4b".to_string(),
+            "stable".to_string(),
+            "1.2.0-hotfix".to_string(),
+        ];
+
+        assert_eq!(
+            tags_to_delete("1.2.0", &image_tags, is_short_hash),
+            vec!["1.2.0", "3f8a2c1d9e4b"]
+        );
+        assert_eq!(tags_to_delete("1.1.0", &[], is_short_hash), vec!["1.1.0"]);
+    }
+
+    #[test]
+    fn test_is_valid_docker_tag() {
+        assert!(is_valid_docker_tag("stable"));
+        assert!(is_valid_docker_tag("1.4.2"));
+        assert!(is_valid_docker_tag("release_candidate-2"));
+
+        assert!(!is_valid_docker_tag(""));
+        assert!(!is_valid_docker_tag("-stable"));
+        assert!(!is_valid_docker_tag(".stable"));
+        assert!(!is_valid_docker_tag("sta/ble"));
+        assert!(!is_valid_docker_tag(&"a".repeat(129)));
+    }
+
+    #[test]
+    fn test_insert_copy_helpers() {
+        let mut context = tera::Context::new();
+        let binaries: HashMap<_, _> = [("my-service", "/usr/local/bin/my-service")].into();
+        let builder_binaries: HashMap<_, _> = [("my-service", "/build/release/my-service")].into();
+        let extra_files: HashSet<_> = ["/etc/my-service/"].into();
+        let extra_files_chown: HashMap<_, _> = [("/etc/my-service/", "--chown=app ")].into();
+
+        context.insert("binaries", &binaries);
+        context.insert("builder_binaries", &builder_binaries);
+        context.insert("extra_files", &extra_files);
+        context.insert("extra_files_chown", &extra_files_chown);
+
+        insert_copy_helpers(&mut context, false);
+
+        let copy_all = context.get("copy_all").unwrap().as_str().unwrap();
+
+        assert!(copy_all.contains("ADD /usr/local/bin/my-service /usr/local/bin/my-service\n"));
+        assert!(copy_all.contains("ADD --chown=app /etc/my-service/ /etc/my-service/\n"));
+
+        insert_copy_helpers(&mut context, true);
+
+        assert!(context
+            .get("copy_all_binaries")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .contains("COPY --from=builder /build/release/my-service /usr/local/bin/my-service\n"));
+    }
+
+    #[test]
+    fn test_parse_exposed_ports() {
+        let dockerfile = "FROM ubuntu:20.04\nEXPOSE 8080\nexpose 9090/tcp 53/udp\nEXPOSE $PORT\nCMD [\"/bin/app\"]\n";
+
+        assert_eq!(
+            parse_exposed_ports(dockerfile),
+            vec!["8080", "9090/tcp", "53/udp"]
+        );
+        assert!(parse_exposed_ports("FROM ubuntu:20.04\n").is_empty());
+    }
+
     #[test]
     fn test_aws_ecr_information_valid() {
         let s = "550877636976.dkr.ecr.ca-central-1.amazonaws.com/my/repo-si_tory";
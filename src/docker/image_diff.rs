@@ -0,0 +1,229 @@
+//! Comparison of a locally built Docker image with a published one, to help
+//! reviewing what a release changes.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use log::{debug, warn};
+
+use crate::{staging::format_size, Error, ErrorContext, Result};
+
+use super::dist_target::docker_output;
+
+/// The layers and files of a local Docker image.
+pub(crate) struct ImageContent {
+    name: String,
+    size: u64,
+    /// The non-empty layers, as the instruction that created them and their
+    /// size.
+    layers: Vec<(String, u64)>,
+    /// The regular files of the filesystem of the image, with their size.
+    files: BTreeMap<PathBuf, u64>,
+}
+
+impl ImageContent {
+    /// Inspect a local image, exporting its filesystem to list its files.
+    pub(crate) fn inspect(name: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            size: image_size(name)?,
+            layers: image_layers(name)?,
+            files: list_files(name)?,
+        })
+    }
+}
+
+/// The size of a local image.
+pub(crate) fn image_size(name: &str) -> Result<u64> {
+    let size = docker_output(&["image", "inspect", "--format", "{{.Size}}", name])?;
+
+    size.trim().parse().map_err(|err| {
+        Error::new("failed to determine Docker image size")
+            .with_source(err)
+            .with_explanation(format!(
+                "The size `{}` of the Docker image `{}` could not be parsed.",
+                size.trim(),
+                name
+            ))
+    })
+}
+
+/// The non-empty layers of a local image, as the instruction that created
+/// them and their size.
+///
+/// The layers of the instructions that only change the configuration of the
+/// image, like `ENV` or `CMD`, are empty and omitted.
+pub(crate) fn image_layers(name: &str) -> Result<Vec<(String, u64)>> {
+    Ok(docker_output(&[
+        "history",
+        "--human=false",
+        "--no-trunc",
+        "--format",
+        "{{.Size}}\t{{.CreatedBy}}",
+        name,
+    ])?
+    .lines()
+    .filter_map(|line| line.split_once('\t'))
+    .filter_map(|(size, created_by)| {
+        size.parse::<u64>()
+            .ok()
+            .map(|size| (created_by.trim().to_string(), size))
+    })
+    .filter(|(_, size)| *size > 0)
+    .collect())
+}
+
+/// The differences between two images.
+pub(crate) struct ImageDiff {
+    from: ImageContent,
+    to: ImageContent,
+}
+
+impl ImageDiff {
+    pub(crate) fn new(from: ImageContent, to: ImageContent) -> Self {
+        Self { from, to }
+    }
+}
+
+impl Display for ImageDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} -> {}: {} -> {} ({})",
+            self.from.name,
+            self.to.name,
+            format_size(self.from.size),
+            format_size(self.to.size),
+            format_size_change(self.from.size, self.to.size)
+        )?;
+
+        // Layers are matched by the instruction that created them, which
+        // is stable across builds unlike their identifiers.
+        let removed_layers = self
+            .from
+            .layers
+            .iter()
+            .filter(|layer| !self.to.layers.contains(layer));
+        let added_layers = self
+            .to
+            .layers
+            .iter()
+            .filter(|layer| !self.from.layers.contains(layer));
+
+        writeln!(f, "Layers:")?;
+
+        for (created_by, size) in removed_layers {
+            writeln!(f, "  - {:>10}  {}", format_size(*size), created_by)?;
+        }
+
+        for (created_by, size) in added_layers {
+            writeln!(f, "  + {:>10}  {}", format_size(*size), created_by)?;
+        }
+
+        writeln!(f, "Files:")?;
+
+        for (path, size) in &self.from.files {
+            if !self.to.files.contains_key(path) {
+                writeln!(f, "  - {:>10}  /{}", format_size(*size), path.display())?;
+            }
+        }
+
+        for (path, size) in &self.to.files {
+            match self.from.files.get(path) {
+                None => writeln!(f, "  + {:>10}  /{}", format_size(*size), path.display())?,
+                Some(from_size) if from_size != size => writeln!(
+                    f,
+                    "  ~ {:>10}  /{} ({})",
+                    format_size(*size),
+                    path.display(),
+                    format_size_change(*from_size, *size)
+                )?,
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn format_size_change(from: u64, to: u64) -> String {
+    if to >= from {
+        format!("+{}", format_size(to - from))
+    } else {
+        format!("-{}", format_size(from - to))
+    }
+}
+
+/// List the regular files of an image, by exporting the filesystem of a
+/// container created from it.
+///
+/// The container is never started.
+fn list_files(name: &str) -> Result<BTreeMap<PathBuf, u64>> {
+    // `docker create` requires a command for images that do not declare one.
+    let container_id = docker_output(&["create", name, "true"])?.trim().to_string();
+
+    let files = export_files(&container_id);
+
+    if let Err(err) = docker_output(&["rm", &container_id]) {
+        warn!(
+            "Failed to remove the temporary container `{}`: {}",
+            container_id, err
+        );
+    }
+
+    files
+}
+
+fn export_files(container_id: &str) -> Result<BTreeMap<PathBuf, u64>> {
+    debug!("Running `docker export {}`", container_id);
+
+    let mut child = Command::new("docker")
+        .args(["export", container_id])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to export Docker container",
+            "The filesystem of the container could not be exported, which could indicate a configuration problem.",
+        )?;
+
+    let mut archive = tar::Archive::new(child.stdout.take().unwrap());
+    let mut files = BTreeMap::new();
+
+    for entry in archive
+        .entries()
+        .map_err(Error::from_source)
+        .with_context("failed to read Docker container export")?
+    {
+        let entry = entry
+            .map_err(Error::from_source)
+            .with_context("failed to read Docker container export")?;
+
+        if entry.header().entry_type().is_file() {
+            let path = entry
+                .path()
+                .map_err(Error::from_source)
+                .with_context("failed to read Docker container export")?
+                .into_owned();
+
+            files.insert(path, entry.size());
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(Error::from_source)
+        .with_context("failed to export Docker container")?;
+
+    if !status.success() {
+        return Err(Error::new("failed to export Docker container")
+            .with_explanation(format!("`docker export {}` failed.", container_id)));
+    }
+
+    Ok(files)
+}
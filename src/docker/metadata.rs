@@ -1,36 +1,145 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
 
-use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
+    condition::Enabled,
     dist_target::DistTarget,
-    metadata::{CopyCommand, Template},
+    metadata::{CopyCommand, TargetRuntime, Template},
+    size::SizeBudget,
     Package,
 };
 
 use super::DockerDistTarget;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct DockerMetadata {
     pub registry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<Template>,
     #[serde(default = "default_target_runtime")]
-    pub target_runtime: String,
+    pub target_runtime: TargetRuntime,
     pub template: Template,
     #[serde(default)]
     pub extra_files: Vec<CopyCommand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_image: Option<String>,
+    /// A minimal base the image is built on, exposed as `{{ from_base }}` in
+    /// the template.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<MinimalBase>,
+    /// The image in which the binaries are compiled, in a first stage of the
+    /// generated Dockerfile, instead of on the host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder_image: Option<String>,
     #[serde(default)]
     pub allow_aws_ecr_creation: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assume_role_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assume_role_external_id: Option<String>,
     #[serde(default = "default_target_bin_dir")]
     pub target_bin_dir: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<DockerRunOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    /// A check that a container of the image starts, run before pushing it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoke_check: Option<DockerSmokeCheck>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// The maximum sizes of the binaries and of the artifact.
+    #[serde(default, skip_serializing_if = "SizeBudget::is_unlimited")]
+    pub size_budget: SizeBudget,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
 }
 
-fn default_target_bin_dir() -> PathBuf {
+/// A minimal base for images that only hold statically-linked binaries.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum MinimalBase {
+    /// The empty image, to which the CA certificates are added.
+    Scratch,
+    /// The static distroless image, which holds the CA certificates, the time
+    /// zone data and a non-root user.
+    Distroless,
+}
+
+/// The options used to run the image locally.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DockerRunOptions {
+    /// The ports to publish, as `<host-port>:<container-port>`.
+    #[serde(default)]
+    pub ports: Vec<String>,
+    /// The environment variables to set in the container.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// The volumes to mount, as `<host-path-or-volume>:<container-path>`.
+    ///
+    /// Host paths starting with `./` or `../` are relative to the package
+    /// root.
+    #[serde(default)]
+    pub volumes: Vec<String>,
+}
+
+/// A check that a container of the image starts, catching the binaries that
+/// are missing or that can't run on the base image before the image is
+/// pushed.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DockerSmokeCheck {
+    /// The command to run in the container instead of its entrypoint, like
+    /// `["/usr/local/bin/my-service", "--version"]`, which must exit
+    /// successfully before the timeout.
+    ///
+    /// If not specified, the entrypoint of the image runs and must either
+    /// exit successfully or still be running after the timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<Vec<String>>,
+    /// The number of seconds to wait for the container, which must not be
+    /// zero.
+    #[serde(
+        default = "default_smoke_check_timeout_secs",
+        deserialize_with = "deserialize_smoke_check_timeout_secs"
+    )]
+    #[schemars(range(min = 1))]
+    pub timeout_secs: u64,
+}
+
+fn default_smoke_check_timeout_secs() -> u64 {
+    10
+}
+
+/// Reject a timeout of zero, which would fail every check with a command and
+/// pass every check without one before the container even starts.
+fn deserialize_smoke_check_timeout_secs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<u64, D::Error> {
+    match u64::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom(
+            "the smoke check timeout must be at least one second",
+        )),
+        timeout_secs => Ok(timeout_secs),
+    }
+}
+
+pub(crate) fn default_target_bin_dir() -> PathBuf {
     PathBuf::from("/usr/local/bin")
 }
 
-fn default_target_runtime() -> String {
-    "x86_64-unknown-linux-gnu".to_string()
+pub(crate) fn default_target_runtime() -> TargetRuntime {
+    TargetRuntime::new("x86_64-unknown-linux-gnu")
 }
 
 impl DockerMetadata {
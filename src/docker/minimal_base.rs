@@ -0,0 +1,191 @@
+//! Minimal bases for the Docker images that only hold statically-linked
+//! binaries, with the instructions each of them needs to be usable.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, ErrorCode, ErrorContext, ErrorKind, Result};
+
+use super::MinimalBase;
+
+/// The static distroless image, which also provides the CA certificates of
+/// the scratch images.
+const DISTROLESS_IMAGE: &str = "gcr.io/distroless/static-debian12";
+
+/// The user and group of the non-root user of the distroless images.
+const NON_ROOT_USER: &str = "65532:65532";
+
+/// The type of the program header of the program interpreter, which only the
+/// dynamically-linked binaries have.
+const PT_INTERP: u64 = 3;
+
+impl MinimalBase {
+    /// The instructions that start the Dockerfile, exposed as `{{ from_base }}`
+    /// in the template.
+    pub(super) fn dockerfile(self) -> String {
+        match self {
+            Self::Scratch => format!(
+                "\
+FROM scratch
+# Add the CA certificates, which the scratch image lacks.
+COPY --from={image} /etc/ssl/certs/ca-certificates.crt /etc/ssl/certs/ca-certificates.crt
+# Run as a non-root user.
+USER {user}",
+                image = DISTROLESS_IMAGE,
+                user = NON_ROOT_USER,
+            ),
+            Self::Distroless => format!(
+                "\
+FROM {image}:nonroot
+# Run as a non-root user.
+USER {user}",
+                image = DISTROLESS_IMAGE,
+                user = NON_ROOT_USER,
+            ),
+        }
+    }
+
+    /// Fail if the target runtime does not produce statically-linked
+    /// binaries.
+    pub(super) fn check_target_runtime(
+        self,
+        dist_target: impl Display,
+        target_runtime: &str,
+    ) -> Result<()> {
+        if target_runtime.contains("musl") {
+            return Ok(());
+        }
+
+        Err(Error::new("binary not statically linked")
+            .with_kind(ErrorKind::Configuration)
+            .with_code(ErrorCode::BinaryNotStaticallyLinked)
+            .with_explanation(format!(
+                "{} is built on a `{}` base, which has no C library, but its target runtime `{}` produces dynamically-linked binaries. Use a musl target runtime instead, like `x86_64-unknown-linux-musl`.",
+                dist_target, self, target_runtime
+            )))
+    }
+
+    /// Fail if any of the binaries is dynamically linked.
+    pub(super) fn check_binaries(
+        self,
+        dist_target: impl Display,
+        binaries: &HashMap<String, PathBuf>,
+    ) -> Result<()> {
+        let mut binaries: Vec<_> = binaries.iter().collect();
+        binaries.sort();
+
+        for (name, path) in binaries {
+            if is_dynamically_linked(path)? {
+                return Err(Error::new("binary not statically linked")
+                    .with_kind(ErrorKind::Validation)
+                    .with_code(ErrorCode::BinaryNotStaticallyLinked)
+                    .with_explanation(format!(
+                        "The binary `{}` of {} is dynamically linked, so it can't run on a `{}` base. Make sure that `-C target-feature=-crt-static` is not set in the `RUSTFLAGS` or in `.cargo/config.toml`.",
+                        name, dist_target, self
+                    )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for MinimalBase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Scratch => write!(f, "scratch"),
+            Self::Distroless => write!(f, "distroless"),
+        }
+    }
+}
+
+fn is_dynamically_linked(path: &Path) -> Result<bool> {
+    let content = std::fs::read(path)
+        .map_err(Error::from_source)
+        .with_context("failed to read binary")?;
+
+    has_program_interpreter(&content).ok_or_else(|| {
+        Error::new("invalid binary").with_explanation(format!(
+            "The binary `{}` is not a valid ELF file.",
+            path.display()
+        ))
+    })
+}
+
+/// Whether an ELF file has a program interpreter, the dynamic loader of the
+/// dynamically-linked binaries.
+///
+/// Returns `None` if the content is not a valid ELF file.
+fn has_program_interpreter(content: &[u8]) -> Option<bool> {
+    if content.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+
+    let is_64_bit = match content.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let is_little_endian = match content.get(5)? {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+
+    let read = |offset: u64, len: usize| -> Option<u64> {
+        let offset = usize::try_from(offset).ok()?;
+        let bytes = content.get(offset..offset.checked_add(len)?)?;
+        let fold = |value: u64, byte: &u8| (value << 8) | u64::from(*byte);
+
+        Some(if is_little_endian {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        })
+    };
+
+    let (program_headers_offset, program_header_size, program_headers_count) = if is_64_bit {
+        (read(0x20, 8)?, read(0x36, 2)?, read(0x38, 2)?)
+    } else {
+        (read(0x1c, 4)?, read(0x2a, 2)?, read(0x2c, 2)?)
+    };
+
+    for i in 0..program_headers_count {
+        let offset = program_headers_offset.checked_add(i * program_header_size)?;
+
+        if read(offset, 4)? == PT_INTERP {
+            return Some(true);
+        }
+    }
+
+    Some(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_program_interpreter() {
+        // A 64-bit little-endian ELF header with two program headers right
+        // after it.
+        let mut content = vec![0; 0x40 + 2 * 0x38];
+        content[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        content[0x20] = 0x40;
+        content[0x36] = 0x38;
+        content[0x38] = 2;
+        // `PT_LOAD`
+        content[0x40] = 1;
+
+        assert_eq!(has_program_interpreter(&content), Some(false));
+
+        content[0x40 + 0x38] = 3;
+
+        assert_eq!(has_program_interpreter(&content), Some(true));
+        assert_eq!(has_program_interpreter(&content[..0x40]), None);
+        assert_eq!(has_program_interpreter(b"#!/bin/sh\n"), None);
+    }
+}
@@ -1,5 +1,10 @@
+mod builder;
 mod dist_target;
+mod image_diff;
 mod metadata;
+mod minimal_base;
+mod smoke_check;
 
 pub use dist_target::DockerDistTarget;
-pub use metadata::DockerMetadata;
+pub(crate) use metadata::{default_target_bin_dir, default_target_runtime};
+pub use metadata::{DockerMetadata, DockerSmokeCheck, MinimalBase};
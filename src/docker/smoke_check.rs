@@ -0,0 +1,245 @@
+//! Smoke check of the Docker images, starting a container before pushing them
+//! so that the images whose binaries can't run never reach the registry.
+
+use std::{
+    collections::BTreeMap,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::{action_step, Error, ErrorCode, ErrorContext, ErrorKind, Result};
+
+use super::{dist_target::docker_output, DockerSmokeCheck};
+
+/// The delay between two checks of the state of the container.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The number of smoke check containers started by the process, keeping
+/// their names unique when several images are checked.
+static CONTAINER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// How a container of the smoke check ended.
+enum Outcome {
+    Exited(i32),
+    TimedOut,
+}
+
+impl DockerSmokeCheck {
+    /// Start a container of the image of a package with the specified
+    /// environment variables, failing if it does not pass the check.
+    pub(super) fn run(
+        &self,
+        package_name: &str,
+        image: &str,
+        env: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let container_name = container_name(package_name);
+
+        let mut args = vec![
+            "run".to_string(),
+            "--detach".to_string(),
+            "--name".to_string(),
+            container_name.clone(),
+        ];
+
+        for (name, value) in env {
+            args.push("--env".to_string());
+            args.push(format!("{}={}", name, value));
+        }
+
+        if let Some(command) = &self.command {
+            let (entrypoint, command_args) = command.split_first().ok_or_else(|| {
+                Error::new("invalid smoke check")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation("The field `smoke_check.command` cannot be empty.")
+            })?;
+
+            args.push("--entrypoint".to_string());
+            args.push(entrypoint.clone());
+            args.push(image.to_string());
+            args.extend(command_args.iter().cloned());
+
+            action_step!(
+                "Checking",
+                "that `{}` runs in `{}`",
+                command.join(" "),
+                image
+            );
+        } else {
+            args.push(image.to_string());
+
+            action_step!("Checking", "that `{}` starts", image);
+        }
+
+        let result = self.start_and_wait(&container_name, &args);
+
+        // The container is created even if its entrypoint can't be started.
+        if let Err(err) = docker_output(&["rm", "--force", &container_name]) {
+            warn!(
+                "Failed to remove the smoke check container `{}`: {}",
+                container_name, err
+            );
+        }
+
+        result
+    }
+
+    fn start_and_wait(&self, container_name: &str, args: &[String]) -> Result<()> {
+        debug!("Running `docker {}`", args.join(" "));
+
+        let output = Command::new("docker")
+            .args(args)
+            .output()
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to run Docker container",
+                "The Docker command could not be executed which could indicate a configuration problem.",
+            )?;
+
+        if !output.status.success() {
+            return Err(smoke_check_failed(
+                "The container could not be started.",
+                &String::from_utf8_lossy(&output.stderr),
+            ));
+        }
+
+        let outcome = wait_for_container(container_name, Duration::from_secs(self.timeout_secs))?;
+
+        match (outcome, &self.command) {
+            (Outcome::Exited(0), _) => Ok(()),
+            (Outcome::TimedOut, None) => {
+                debug!(
+                    "The container is still running after {} seconds: considering it started",
+                    self.timeout_secs
+                );
+
+                Ok(())
+            }
+            (Outcome::Exited(code), _) => Err(smoke_check_failed(
+                &format!("The container exited with code {}.", code),
+                &container_logs(container_name),
+            )),
+            (Outcome::TimedOut, Some(_)) => Err(smoke_check_failed(
+                &format!(
+                    "The command did not exit within {} seconds.",
+                    self.timeout_secs
+                ),
+                &container_logs(container_name),
+            )),
+        }
+    }
+}
+
+/// A name for a smoke check container of a package, unique across the
+/// containers started by the process and the concurrent runs of the tool.
+fn container_name(package_name: &str) -> String {
+    format!(
+        "cargo-monorepo-smoke-check-{}-{}-{}",
+        package_name,
+        std::process::id(),
+        CONTAINER_COUNT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Wait for a container to exit, up to the specified timeout.
+fn wait_for_container(container_name: &str, timeout: Duration) -> Result<Outcome> {
+    let start = Instant::now();
+
+    loop {
+        let state = docker_output(&[
+            "inspect",
+            "--format",
+            "{{.State.Status}} {{.State.ExitCode}}",
+            container_name,
+        ])?;
+
+        if let Some(code) = parse_exit_code(&state) {
+            return Ok(Outcome::Exited(code));
+        }
+
+        if start.elapsed() >= timeout {
+            return Ok(Outcome::TimedOut);
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// The exit code of a container, from its `<status> <exit-code>` state, if it
+/// exited.
+fn parse_exit_code(state: &str) -> Option<i32> {
+    match state.trim().split_once(' ')? {
+        ("exited" | "dead", code) => code.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The output of a container, on both its standard output and error.
+fn container_logs(container_name: &str) -> String {
+    match Command::new("docker")
+        .args(["logs", container_name])
+        .output()
+    {
+        Ok(output) => format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(err) => format!("The logs of the container could not be read: {}", err),
+    }
+}
+
+fn smoke_check_failed(reason: &str, output: &str) -> Error {
+    Error::new("smoke check failed")
+        .with_kind(ErrorKind::Validation)
+        .with_code(ErrorCode::SmokeCheckFailed)
+        .with_explanation(format!(
+            "{} The image was not pushed. Check the logs below to determine the cause.",
+            reason
+        ))
+        .with_output(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exit_code() {
+        assert_eq!(parse_exit_code("exited 0\n"), Some(0));
+        assert_eq!(parse_exit_code("exited 127"), Some(127));
+        assert_eq!(parse_exit_code("dead 137"), Some(137));
+        assert_eq!(parse_exit_code("running 0"), None);
+        assert_eq!(parse_exit_code("created 0"), None);
+    }
+
+    #[test]
+    fn test_timeout_secs() {
+        let smoke_check: DockerSmokeCheck = serde_json::from_str("{}").unwrap();
+        assert_eq!(smoke_check.timeout_secs, 10);
+
+        let smoke_check: DockerSmokeCheck =
+            serde_json::from_str(r#"{ "timeout_secs": 30 }"#).unwrap();
+        assert_eq!(smoke_check.timeout_secs, 30);
+
+        assert!(serde_json::from_str::<DockerSmokeCheck>(r#"{ "timeout_secs": 0 }"#).is_err());
+    }
+
+    #[test]
+    fn test_container_name() {
+        let prefix = format!(
+            "cargo-monorepo-smoke-check-my-service-{}-",
+            std::process::id()
+        );
+
+        let first = container_name("my-service");
+        let second = container_name("my-service");
+
+        assert!(first.starts_with(&prefix));
+        assert!(second.starts_with(&prefix));
+        assert_ne!(first, second);
+    }
+}
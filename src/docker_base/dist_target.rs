@@ -0,0 +1,91 @@
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
+
+use crate::{
+    docker::DockerDistTarget, ignore_step, tools::Tool, Package, PublishedArtifact,
+    PublishedVersion, Result,
+};
+
+use super::DockerBaseMetadata;
+
+/// A shared base image, providing the common runtime dependencies of the
+/// Docker images of other packages.
+///
+/// The image is built and published like the Docker image of a package, except
+/// that it contains no binaries.
+pub struct DockerBaseDistTarget<'g> {
+    pub name: String,
+    pub package: &'g Package<'g>,
+    pub metadata: DockerBaseMetadata,
+}
+
+impl Display for DockerBaseDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "docker-base[{}]", self.package.name())
+    }
+}
+
+impl<'g> DockerBaseDistTarget<'g> {
+    pub fn build(&self) -> Result<()> {
+        if cfg!(windows) {
+            ignore_step!(
+                "Unsupported",
+                "Docker base image build is not supported on Windows"
+            );
+            return Ok(());
+        }
+
+        self.docker().build_image(&HashMap::new())
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        Ok(self.docker().publish()?.map(|artifact| PublishedArtifact {
+            dist_target: self.to_string(),
+            ..artifact
+        }))
+    }
+
+    pub async fn exists(&self) -> Result<bool> {
+        self.docker().exists().await
+    }
+
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        self.docker().published_versions().await
+    }
+
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        self.docker().delete_published_version(version).await
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        self.docker().docker_image_name()
+    }
+
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        self.docker().promote(channel)
+    }
+
+    pub(crate) fn registry_login(&self) -> Result<()> {
+        self.docker().registry_login()
+    }
+
+    pub(crate) fn image_exists_locally(&self) -> Result<bool> {
+        self.docker().image_exists_locally()
+    }
+
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        self.docker().required_tools(publish)
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.docker().staging_dir()
+    }
+
+    fn docker(&self) -> DockerDistTarget<'g> {
+        DockerDistTarget {
+            name: self.name.clone(),
+            package: self.package,
+            metadata: self.metadata.to_docker_metadata(),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    condition::Enabled,
+    dist_target::DistTarget,
+    docker::{default_target_bin_dir, default_target_runtime, DockerMetadata},
+    metadata::{CopyCommand, Template},
+    size::SizeBudget,
+    Package,
+};
+
+use super::DockerBaseDistTarget;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DockerBaseMetadata {
+    pub registry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<Template>,
+    pub template: Template,
+    #[serde(default)]
+    pub extra_files: Vec<CopyCommand>,
+    #[serde(default)]
+    pub allow_aws_ecr_creation: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assume_role_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assume_role_external_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+impl DockerBaseMetadata {
+    pub(crate) fn into_dist_target<'g>(
+        self,
+        name: String,
+        package: &'g Package<'g>,
+    ) -> DistTarget<'g> {
+        DistTarget::DockerBase(DockerBaseDistTarget {
+            name,
+            package,
+            metadata: self,
+        })
+    }
+
+    /// The metadata of an equivalent Docker image without binaries.
+    pub(crate) fn to_docker_metadata(&self) -> DockerMetadata {
+        DockerMetadata {
+            registry: self.registry.clone(),
+            repository: self.repository.clone(),
+            target_runtime: default_target_runtime(),
+            template: self.template.clone(),
+            extra_files: self.extra_files.clone(),
+            base_image: None,
+            base: None,
+            builder_image: None,
+            allow_aws_ecr_creation: self.allow_aws_ecr_creation,
+            credential_helper: self.credential_helper.clone(),
+            aws_endpoint_url: self.aws_endpoint_url.clone(),
+            aws_profile: self.aws_profile.clone(),
+            assume_role_arn: self.assume_role_arn.clone(),
+            assume_role_external_id: self.assume_role_external_id.clone(),
+            target_bin_dir: default_target_bin_dir(),
+            run: None,
+            test_command: self.test_command.clone(),
+            smoke_check: None,
+            depends_on: self.depends_on.clone(),
+            size_budget: SizeBudget::default(),
+            enabled: self.enabled.clone(),
+        }
+    }
+}
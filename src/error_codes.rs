@@ -0,0 +1,348 @@
+//! Stable codes of the common failures, with the extended guidance printed by
+//! `cargo monorepo explain <code>`.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Serialize, Serializer};
+
+/// A stable code identifying a common failure, like `MR0012`.
+///
+/// Codes are never reused nor renumbered, so that scripts and documentation
+/// can rely on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidMetadata,
+    PackageNotFound,
+    UncommittedChanges,
+    NetworkAccessForbidden,
+    MissingPrerequisites,
+    TargetRuntimeNotInstalled,
+    CompilationFailed,
+    GitRevisionNotFound,
+    TagAlreadyExists,
+    RegistryLoginFailed,
+    IncompleteRegistryCredentials,
+    RegistryNotConfigured,
+    SizeBudgetExceeded,
+    LicensePolicyViolated,
+    DependenciesNotFetched,
+    BinaryNotStaticallyLinked,
+    SmokeCheckFailed,
+}
+
+impl ErrorCode {
+    /// All the error codes, in numerical order.
+    pub const ALL: &'static [Self] = &[
+        Self::InvalidMetadata,
+        Self::PackageNotFound,
+        Self::UncommittedChanges,
+        Self::NetworkAccessForbidden,
+        Self::MissingPrerequisites,
+        Self::TargetRuntimeNotInstalled,
+        Self::CompilationFailed,
+        Self::GitRevisionNotFound,
+        Self::TagAlreadyExists,
+        Self::RegistryLoginFailed,
+        Self::IncompleteRegistryCredentials,
+        Self::RegistryNotConfigured,
+        Self::SizeBudgetExceeded,
+        Self::LicensePolicyViolated,
+        Self::DependenciesNotFetched,
+        Self::BinaryNotStaticallyLinked,
+        Self::SmokeCheckFailed,
+    ];
+
+    fn number(self) -> u32 {
+        match self {
+            Self::InvalidMetadata => 1,
+            Self::PackageNotFound => 2,
+            Self::UncommittedChanges => 3,
+            Self::NetworkAccessForbidden => 4,
+            Self::MissingPrerequisites => 5,
+            Self::TargetRuntimeNotInstalled => 6,
+            Self::CompilationFailed => 7,
+            Self::GitRevisionNotFound => 8,
+            Self::TagAlreadyExists => 9,
+            Self::RegistryLoginFailed => 10,
+            Self::IncompleteRegistryCredentials => 11,
+            Self::RegistryNotConfigured => 12,
+            Self::SizeBudgetExceeded => 13,
+            Self::LicensePolicyViolated => 14,
+            Self::DependenciesNotFetched => 15,
+            Self::BinaryNotStaticallyLinked => 16,
+            Self::SmokeCheckFailed => 17,
+        }
+    }
+
+    /// A short summary of the failure.
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::InvalidMetadata => "invalid metadata",
+            Self::PackageNotFound => "package not found",
+            Self::UncommittedChanges => "uncommitted changes",
+            Self::NetworkAccessForbidden => "network access forbidden",
+            Self::MissingPrerequisites => "missing prerequisites",
+            Self::TargetRuntimeNotInstalled => "target runtime not installed",
+            Self::CompilationFailed => "compilation failed",
+            Self::GitRevisionNotFound => "Git revision not found",
+            Self::TagAlreadyExists => "tag already exists",
+            Self::RegistryLoginFailed => "registry login failed",
+            Self::IncompleteRegistryCredentials => "incomplete registry credentials",
+            Self::RegistryNotConfigured => "registry not configured",
+            Self::SizeBudgetExceeded => "size budget exceeded",
+            Self::LicensePolicyViolated => "license policy violated",
+            Self::DependenciesNotFetched => "dependencies not fetched",
+            Self::BinaryNotStaticallyLinked => "binary not statically linked",
+            Self::SmokeCheckFailed => "smoke check failed",
+        }
+    }
+
+    /// The extended guidance on the causes of the failure and how to fix it.
+    pub fn guidance(self) -> &'static str {
+        GUIDANCE
+            .iter()
+            .find(|(code, _)| *code == self)
+            .map(|(_, guidance)| *guidance)
+            .expect("every error code has a guidance")
+    }
+}
+
+/// The extended guidance of each error code, in numerical order.
+const GUIDANCE: &[(ErrorCode, &str)] = &[
+    (
+        ErrorCode::InvalidMetadata,
+        "\
+The `package.metadata.monorepo` section of a manifest, or the
+`workspace.metadata.monorepo` section of the root manifest, could not be
+parsed. The error lists the invalid fields with their path, like
+`my-service.registry`.
+
+Common causes are misspelled field names, which are rejected, and values of
+the wrong type. `cargo monorepo schema` prints the JSON schema of the
+metadata, which editors can use to validate the manifests.
+
+Manifests written for a newer version of `cargo monorepo` may use fields this
+version does not know: `--lenient-metadata` ignores the unknown fields with a
+warning instead of failing.",
+    ),
+    (
+        ErrorCode::PackageNotFound,
+        "\
+A package specified with `-p`, or by path, is not a member of the workspace.
+
+Check the spelling of the package name against `cargo monorepo list`, and that
+the package is listed in the `members` of the workspace. Paths must point to
+the directory or the manifest of a workspace member.",
+    ),
+    (
+        ErrorCode::UncommittedChanges,
+        "\
+The working tree has uncommitted changes in the sources of a package. As the
+hash of the package covers its sources, the artifacts built from them would
+not correspond to any commit.
+
+Commit or stash the changes, or specify `--allow-dirty` to proceed anyway,
+for instance for local builds that are never published.",
+    ),
+    (
+        ErrorCode::NetworkAccessForbidden,
+        "\
+`--offline` was specified, but the command needs network access, for
+instance to check for existing artifacts or to publish them.
+
+Remove `--offline`, or use a command that works offline, like `build-dist`.",
+    ),
+    (
+        ErrorCode::MissingPrerequisites,
+        "\
+The selected distribution targets require tools or target runtimes that are
+not installed, like `docker` for Docker images or `wasm-pack` for WASM
+modules. The error lists all the missing prerequisites at once.
+
+Install them and make sure they are in the `PATH`. Target runtimes are
+installed with `rustup target add <target-runtime>`.",
+    ),
+    (
+        ErrorCode::TargetRuntimeNotInstalled,
+        "\
+The target runtime of a distribution target is not installed for the current
+toolchain, so its binaries cannot be compiled.
+
+Run `rustup target add <target-runtime>` to install it. If the toolchain is
+pinned with a `rust-toolchain.toml` file, list the target runtime in its
+`targets` so that it is installed along with the toolchain.",
+    ),
+    (
+        ErrorCode::CompilationFailed,
+        "\
+Cargo failed to compile the binaries of a distribution target.
+
+The compiler errors are printed above the error. Reproduce the failure with
+`cargo build -p <package> --target <target-runtime>`. Cross-compilation may
+require a linker for the target runtime, configured in `.cargo/config.toml`.",
+    ),
+    (
+        ErrorCode::GitRevisionNotFound,
+        "\
+A Git reference, like the one of `--changed-since-git-ref`, could not be
+resolved to a commit.
+
+CI systems often make shallow clones, which only have the most recent
+commits. Fetch the history with `git fetch --unshallow`, or with
+`fetch-depth: 0` for the `actions/checkout` GitHub action, or fetch the
+reference itself with `git fetch origin <reference>`.",
+    ),
+    (
+        ErrorCode::TagAlreadyExists,
+        "\
+The current version of a package is already tagged with a different hash:
+its sources changed since the version was published.
+
+Increment the version of the package so that the new artifacts are published
+under a new version, or specify `--force` to re-tag the version.",
+    ),
+    (
+        ErrorCode::RegistryLoginFailed,
+        "\
+The authentication against a registry failed. The output of the login command
+is printed below the error.
+
+Check the credentials, set in the `CARGO_MONOREPO_REGISTRY_<HOST>_USERNAME`
+and `CARGO_MONOREPO_REGISTRY_<HOST>_PASSWORD` environment variables or
+returned by the credential helper of the distribution target. For AWS ECR,
+check the AWS credentials and their permissions.",
+    ),
+    (
+        ErrorCode::IncompleteRegistryCredentials,
+        "\
+Only one of the `CARGO_MONOREPO_REGISTRY_<HOST>_USERNAME` and
+`CARGO_MONOREPO_REGISTRY_<HOST>_PASSWORD` environment variables is set.
+
+Set both of them, or neither to rely on the credential helper of the
+distribution target or on an existing `docker login`.",
+    ),
+    (
+        ErrorCode::RegistryNotConfigured,
+        "\
+A Docker distribution target does not specify the registry to push its image
+to.
+
+Set the `registry` field of the distribution target, or the
+`CARGO_MONOREPO_DOCKER_REGISTRY` environment variable to use the same registry
+for all the Docker images, for instance in CI.",
+    ),
+    (
+        ErrorCode::SizeBudgetExceeded,
+        "\
+A binary or an artifact weighs more than the `size_budget` of its
+distribution target.
+
+Reduce its size, for instance by stripping the binaries (`strip = true` in
+the Cargo profile), enabling `lto` or removing extra files. If the growth is
+expected, raise the budget.",
+    ),
+    (
+        ErrorCode::LicensePolicyViolated,
+        "\
+A dependency shipped with a distribution target uses a license that the
+`workspace.metadata.monorepo.licenses` policy does not allow.
+
+Remove the dependency, or allow its license. Dependencies whose license was
+reviewed separately can be listed in the `exceptions` of the policy.",
+    ),
+    (
+        ErrorCode::DependenciesNotFetched,
+        "\
+`--offline` was specified, but some dependencies of the workspace are not in
+the Cargo cache for the target runtimes of the selected distribution targets.
+
+Run `cargo monorepo fetch` with the same packages in a stage with network
+access first, sharing the Cargo home directory (`CARGO_HOME`) with the build
+stage. A lock file that is not up-to-date requires network access as well:
+commit an up-to-date `Cargo.lock`.",
+    ),
+    (
+        ErrorCode::BinaryNotStaticallyLinked,
+        "\
+A Docker image built on a `scratch` or `distroless` base ships a binary that
+is dynamically linked, or targets a runtime that does not produce static
+binaries. These bases have no C library nor dynamic loader, so the binary
+would fail to start.
+
+Set the `target_runtime` of the distribution target to a musl target runtime,
+like `x86_64-unknown-linux-musl`, and install it with `rustup target add`.
+Make sure that `-C target-feature=-crt-static` is not set in the `RUSTFLAGS`
+or in `.cargo/config.toml`.",
+    ),
+    (
+        ErrorCode::SmokeCheckFailed,
+        "\
+A container of a Docker image failed its `smoke_check` before the image was
+pushed: it could not start, exited with an error or, for a smoke check with a
+`command`, did not exit before the timeout. The logs of the container are
+printed below the error.
+
+Common causes are binaries missing from the image, like a wrong path in the
+entrypoint, and binaries linked against a newer C library than the one of the
+base image (`GLIBC_2.xx not found`). Build the binaries against the C library
+of the base image, or statically with a musl target runtime. Run the image
+locally with `cargo monorepo run` to investigate.",
+    ),
+];
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MR{:04}", self.number())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .find(|code| code.to_string().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown error code `{}`", s))
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        for (i, code) in ErrorCode::ALL.iter().enumerate() {
+            assert_eq!(code.number() as usize, i + 1);
+            assert_eq!(code.to_string().parse(), Ok(*code));
+        }
+
+        assert_eq!(
+            ErrorCode::RegistryNotConfigured.to_string(),
+            "MR0012".to_string()
+        );
+        assert_eq!("mr0012".parse(), Ok(ErrorCode::RegistryNotConfigured));
+        assert!("MR9999".parse::<ErrorCode>().is_err());
+    }
+
+    #[test]
+    fn test_guidance() {
+        assert_eq!(GUIDANCE.len(), ErrorCode::ALL.len());
+
+        for (code, (guidance_code, _)) in ErrorCode::ALL.iter().zip(GUIDANCE) {
+            assert_eq!(code, guidance_code);
+            assert!(!code.guidance().is_empty());
+        }
+    }
+}
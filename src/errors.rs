@@ -1,5 +1,63 @@
 use std::fmt::Display;
 
+use serde::Serialize;
+
+use crate::ErrorCode;
+
+/// The kind of an error, which determines the exit code of the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// An error of no particular kind.
+    Other,
+    /// The manifests or the options are invalid or incomplete.
+    Configuration,
+    /// The compilation of the binaries or the build of an image failed.
+    Compilation,
+    /// The authentication against a registry failed.
+    RegistryAuth,
+    /// A remote service could not be reached or failed to answer.
+    Network,
+    /// Something that must not exist already exists.
+    AlreadyExists,
+    /// A check on the workspace or on the artifacts failed.
+    Validation,
+}
+
+impl ErrorKind {
+    /// The exit code of the process for errors of this kind.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Other => 1,
+            Self::Configuration => 3,
+            Self::Compilation => 4,
+            Self::RegistryAuth => 5,
+            Self::Network => 6,
+            Self::AlreadyExists => 7,
+            Self::Validation => 8,
+        }
+    }
+
+    /// Whether retrying could make an error of this kind go away.
+    pub fn is_retryable(self) -> bool {
+        self == Self::Network
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Other => write!(f, "other"),
+            Self::Configuration => write!(f, "configuration"),
+            Self::Compilation => write!(f, "compilation"),
+            Self::RegistryAuth => write!(f, "registry-auth"),
+            Self::Network => write!(f, "network"),
+            Self::AlreadyExists => write!(f, "already-exists"),
+            Self::Validation => write!(f, "validation"),
+        }
+    }
+}
+
 /// An error that can possibly inherit from a parent error.
 ///
 /// Errors can be enriched with additional information, such as the raw output
@@ -7,6 +65,8 @@ use std::fmt::Display;
 #[derive(thiserror::Error, Debug)]
 pub struct Error {
     description: String,
+    kind: Option<ErrorKind>,
+    code: Option<ErrorCode>,
     explanation: Option<String>,
     #[source]
     source: Option<anyhow::Error>,
@@ -17,6 +77,8 @@ impl Error {
     pub fn new(description: impl Into<String>) -> Self {
         Self {
             description: description.into(),
+            kind: None,
+            code: None,
             explanation: None,
             source: None,
             output: None,
@@ -33,6 +95,18 @@ impl Error {
         self
     }
 
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = Some(kind);
+
+        self
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+
+        self
+    }
+
     pub fn with_explanation(mut self, explanation: impl Into<String>) -> Self {
         self.explanation = Some(explanation.into());
 
@@ -53,6 +127,33 @@ impl Error {
         self.source.as_ref()
     }
 
+    /// The kind of the error, inherited from its sources if it has none.
+    pub fn kind(&self) -> ErrorKind {
+        if let Some(kind) = self.kind {
+            return kind;
+        }
+
+        self.source
+            .as_ref()
+            .and_then(|source| {
+                source
+                    .chain()
+                    .find_map(|source| source.downcast_ref::<Self>().and_then(|err| err.kind))
+            })
+            .unwrap_or(ErrorKind::Other)
+    }
+
+    /// The code of the error, inherited from its sources if it has none.
+    pub fn code(&self) -> Option<ErrorCode> {
+        self.code.or_else(|| {
+            self.source.as_ref().and_then(|source| {
+                source
+                    .chain()
+                    .find_map(|source| source.downcast_ref::<Self>().and_then(|err| err.code))
+            })
+        })
+    }
+
     pub fn explanation(&self) -> Option<&str> {
         self.explanation.as_deref()
     }
@@ -70,10 +171,52 @@ impl Error {
             Self::new(description).with_source(self)
         }
     }
+
+    /// Combine several errors into one, so that they are all reported at
+    /// once.
+    ///
+    /// A single error is returned as is. Otherwise, the combined error has the
+    /// kind of the errors if they all share it and lists their explanations.
+    pub(crate) fn aggregate(description: impl Into<String>, mut errors: Vec<Self>) -> Self {
+        if errors.len() == 1 {
+            return errors.remove(0);
+        }
+
+        let kind = errors
+            .iter()
+            .map(Self::kind)
+            .reduce(|a, b| if a == b { a } else { ErrorKind::Other })
+            .unwrap_or(ErrorKind::Other);
+
+        let explanation = errors
+            .iter()
+            .map(|err| match err.explanation() {
+                Some(explanation) => explanation.to_string(),
+                None => err.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        let code = errors
+            .iter()
+            .map(Self::code)
+            .reduce(|a, b| if a == b { a } else { None })
+            .flatten();
+
+        let err = Self::new(description)
+            .with_kind(kind)
+            .with_explanation(explanation);
+
+        match code {
+            Some(code) => err.with_code(code),
+            None => err,
+        }
+    }
 }
 
 pub(crate) trait ErrorContext {
     fn with_context(self, description: impl Into<String>) -> Self;
+    fn with_kind(self, kind: ErrorKind) -> Self;
     fn with_full_context(
         self,
         description: impl Into<String>,
@@ -86,6 +229,10 @@ impl<T> ErrorContext for Result<T> {
         self.map_err(|e| e.with_context(description))
     }
 
+    fn with_kind(self, kind: ErrorKind) -> Self {
+        self.map_err(|e| e.with_kind(kind))
+    }
+
     fn with_full_context(
         self,
         description: impl Into<String>,
@@ -113,3 +260,64 @@ impl Display for Error {
 
 /// A convenience type alias to return `Error`s from functions.
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(Error::new("some error").kind(), ErrorKind::Other);
+
+        let err = Error::new("failed to compile binaries").with_kind(ErrorKind::Compilation);
+        assert_eq!(err.kind(), ErrorKind::Compilation);
+
+        let err = err.with_context("failed to build distribution");
+        assert_eq!(err.kind(), ErrorKind::Compilation);
+
+        let err = err.with_kind(ErrorKind::Validation);
+        assert_eq!(err.kind(), ErrorKind::Validation);
+        assert_eq!(err.code(), None);
+
+        let err = Error::new("failed to compile binaries")
+            .with_code(ErrorCode::CompilationFailed)
+            .with_context("failed to build distribution");
+        assert_eq!(err.code(), Some(ErrorCode::CompilationFailed));
+    }
+
+    #[test]
+    fn test_error_aggregate() {
+        let err = Error::aggregate(
+            "failed to load packages",
+            vec![Error::new("some error").with_kind(ErrorKind::Network)],
+        );
+        assert_eq!(err.description(), "some error");
+
+        let err = Error::aggregate(
+            "failed to load packages",
+            vec![
+                Error::new("failed to parse metadata")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation("The manifest of a is invalid."),
+                Error::new("failed to parse metadata")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation("The manifest of b is invalid."),
+            ],
+        );
+        assert_eq!(err.description(), "failed to load packages");
+        assert_eq!(err.kind(), ErrorKind::Configuration);
+        assert_eq!(
+            err.explanation(),
+            Some("The manifest of a is invalid.\n\nThe manifest of b is invalid.")
+        );
+
+        let err = Error::aggregate(
+            "failed to load packages",
+            vec![
+                Error::new("some error").with_kind(ErrorKind::Configuration),
+                Error::new("other error").with_kind(ErrorKind::Network),
+            ],
+        );
+        assert_eq!(err.kind(), ErrorKind::Other);
+    }
+}
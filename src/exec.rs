@@ -0,0 +1,267 @@
+//! Execution of commands across the packages of the workspace.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use guppy::graph::DependencyDirection;
+
+use crate::{action_step, ignore_step, Context, Error, ErrorContext, Package, Result};
+
+/// The order in which a command is executed across packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecOrder {
+    /// The order of the selection, followed by the packages completing it
+    /// sorted by name.
+    Selection,
+    /// The packages are executed after the packages they depend on.
+    Topological,
+    /// The packages are executed before the packages they depend on.
+    ReverseTopological,
+}
+
+impl Default for ExecOrder {
+    fn default() -> Self {
+        Self::Selection
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExecOptions {
+    pub order: ExecOrder,
+    /// Also execute the command for the workspace packages the selected
+    /// packages depend on, directly or not.
+    pub include_dependencies: bool,
+    /// Also execute the command for the workspace packages that depend on the
+    /// selected packages, directly or not.
+    pub include_dependants: bool,
+    /// The directory to write the output of the command in each package to,
+    /// instead of the console.
+    pub log_dir: Option<PathBuf>,
+    /// Succeed even if the command fails in some packages.
+    pub ignore_failures: bool,
+}
+
+/// Execute a command in the directory of each of the specified packages,
+/// completed and ordered according to the options.
+///
+/// The command is executed in all the packages even if it fails in some of
+/// them, which are listed at the end.
+pub fn exec_packages<'g>(
+    context: &'g Context,
+    packages: Vec<Package<'g>>,
+    args: &[&str],
+    options: &ExecOptions,
+) -> Result<()> {
+    let packages = expand_packages(context, packages, options)?;
+
+    if let Some(log_dir) = &options.log_dir {
+        std::fs::create_dir_all(log_dir)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to create log directory",
+                format!(
+                    "The directory `{}` could not be created.",
+                    log_dir.display()
+                ),
+            )?;
+    }
+
+    let mut failures = Vec::new();
+
+    for package in &packages {
+        let status = match &options.log_dir {
+            Some(log_dir) => execute_logged(
+                package,
+                args,
+                &log_dir.join(format!("{}.log", package.name())),
+            )?,
+            None => package.execute(args)?,
+        };
+
+        if !status.success() {
+            failures.push(format!(
+                "- `{}` {}",
+                package.name(),
+                describe_exit_status(status)
+            ));
+        }
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    if options.ignore_failures {
+        ignore_step!(
+            "Ignoring",
+            "the failures of the command in the following packages:\n{}",
+            failures.join("\n")
+        );
+
+        return Ok(());
+    }
+
+    Err(Error::new("command failed").with_explanation(format!(
+        "The command failed in the following packages:\n{}\nSpecify `--ignore-failures` to ignore them.",
+        failures.join("\n")
+    )))
+}
+
+/// Run a Cargo command once for all the specified packages, from the root of
+/// the workspace.
+///
+/// The packages are passed as `-p` flags, before the arguments following a
+/// `--` if any, like in `clippy --all-targets -- -D warnings`.
+pub fn run_cargo(context: &Context, packages: &[Package<'_>], args: &[&str]) -> Result<()> {
+    if packages.is_empty() {
+        ignore_step!("Skipping", "Cargo command as no packages are selected");
+
+        return Ok(());
+    }
+
+    let (cargo_args, trailing_args) = match args.iter().position(|arg| *arg == "--") {
+        Some(index) => args.split_at(index),
+        None => (args, &[][..]),
+    };
+
+    let mut cmd = Command::new("cargo");
+
+    cmd.args(cargo_args)
+        .args(context.options().cargo_args())
+        .args(packages.iter().flat_map(|package| ["-p", package.name()]))
+        .args(trailing_args)
+        .current_dir(context.workspace()?.root());
+
+    action_step!(
+        "Running",
+        "`cargo {}` for {} package(s)",
+        cargo_args.join(" "),
+        packages.len()
+    );
+
+    let status = cmd.status().map_err(Error::from_source).with_full_context(
+        "failed to run Cargo",
+        "The `cargo` command could not be executed. Is it installed?",
+    )?;
+
+    if !status.success() {
+        return Err(Error::new("Cargo command failed").with_explanation(format!(
+            "`cargo {}` {}. Check the logs above to determine the cause.",
+            args.join(" "),
+            describe_exit_status(status)
+        )));
+    }
+
+    Ok(())
+}
+
+/// Execute a command in the directory of the specified package, writing its
+/// output to the specified log file.
+fn execute_logged(package: &Package<'_>, args: &[&str], log_path: &Path) -> Result<ExitStatus> {
+    let (stdout, stderr) = File::create(log_path)
+        .and_then(|file| Ok((file.try_clone()?, file)))
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to create log file",
+            format!("The file `{}` could not be created.", log_path.display()),
+        )?;
+
+    let status = package
+        .command(args)?
+        .stdout(stdout)
+        .stderr(stderr)
+        .status()
+        .map_err(|err| Error::new("failed to execute command").with_source(err))?;
+
+    if status.success() {
+        action_step!(
+            "Succeeded",
+            "`{}` with exit code 0 - output in `{}`",
+            package.name(),
+            log_path.display()
+        );
+    } else {
+        ignore_step!(
+            "Failed",
+            "`{}` {} - output in `{}`",
+            package.name(),
+            describe_exit_status(status),
+            log_path.display()
+        );
+    }
+
+    Ok(status)
+}
+
+fn describe_exit_status(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("with exit code {}", code),
+        None => "terminated by a signal".to_string(),
+    }
+}
+
+/// Complete the specified packages with their dependencies and dependants,
+/// and order them.
+fn expand_packages<'g>(
+    context: &'g Context,
+    mut packages: Vec<Package<'g>>,
+    options: &ExecOptions,
+) -> Result<Vec<Package<'g>>> {
+    if options.order == ExecOrder::Selection
+        && !options.include_dependencies
+        && !options.include_dependants
+    {
+        return Ok(packages);
+    }
+
+    let package_graph = context.package_graph();
+    let ids: Vec<_> = packages.iter().map(Package::id).collect();
+    let query_error = |err| Error::new("failed to query package graph").with_source(err);
+
+    let mut package_set = package_graph
+        .resolve_ids(ids.iter().copied())
+        .map_err(query_error)?;
+
+    if options.include_dependencies {
+        package_set = package_set.union(
+            &package_graph
+                .query_forward(ids.iter().copied())
+                .map_err(query_error)?
+                .resolve(),
+        );
+    }
+
+    if options.include_dependants {
+        package_set = package_set.union(
+            &package_graph
+                .query_reverse(ids.iter().copied())
+                .map_err(query_error)?
+                .resolve(),
+        );
+    }
+
+    // Forward traversals return the dependants before their dependencies.
+    let direction = match options.order {
+        ExecOrder::Selection | ExecOrder::ReverseTopological => DependencyDirection::Forward,
+        ExecOrder::Topological => DependencyDirection::Reverse,
+    };
+
+    let mut expanded_packages = package_set
+        .packages(direction)
+        .filter(guppy::graph::PackageMetadata::in_workspace)
+        .map(|package_metadata| Package::new(context, package_metadata))
+        .collect::<Result<Vec<_>>>()?;
+
+    if options.order != ExecOrder::Selection {
+        return Ok(expanded_packages);
+    }
+
+    expanded_packages.retain(|package| packages.iter().all(|p| p.id() != package.id()));
+    expanded_packages.sort_by(|a, b| a.name().cmp(b.name()));
+    packages.extend(expanded_packages);
+
+    Ok(packages)
+}
@@ -0,0 +1,307 @@
+//! Cache of the package graph, to avoid running `cargo metadata` on every
+//! invocation.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Options, Result};
+
+/// The directory of the cache files, relative to the target root.
+const GRAPH_CACHE_DIR_NAME: &str = "monorepo";
+
+/// The prefix of the names of the cache files, followed by their key.
+const GRAPH_CACHE_FILE_PREFIX: &str = "package-graph-";
+
+/// Load the package graph of the workspace, from the cache if its manifests
+/// and lock file did not change since it was written.
+///
+/// The cache is a best effort: failing to read or write it only results in
+/// running `cargo metadata` again.
+pub(crate) fn load_package_graph(
+    manifest_path: &Path,
+    options: &Options,
+    config: &cargo::util::Config,
+) -> Result<guppy::graph::PackageGraph> {
+    let cache_path = match cache_path(manifest_path, options, config) {
+        Ok(cache_path) => Some(cache_path),
+        Err(err) => {
+            debug!("Not caching the package graph: {}", err);
+
+            None
+        }
+    };
+
+    if let Some(cache_path) = &cache_path {
+        match std::fs::read_to_string(cache_path) {
+            Ok(json) => match guppy::graph::PackageGraph::from_json(&json) {
+                Ok(package_graph) => {
+                    debug!("Loaded the package graph from `{}`", cache_path.display());
+
+                    return Ok(package_graph);
+                }
+                Err(err) => warn!(
+                    "Ignoring the invalid package graph cache `{}`: {}",
+                    cache_path.display(),
+                    err
+                ),
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!("The package graph cache is outdated or missing");
+            }
+            Err(err) => warn!(
+                "Failed to read the package graph cache `{}`: {}",
+                cache_path.display(),
+                err
+            ),
+        }
+    }
+
+    let mut cmd = guppy::MetadataCommand::new();
+    cmd.manifest_path(manifest_path);
+    cmd.other_options(options.cargo_args());
+
+    let cargo_metadata = cmd
+        .exec()
+        .map_err(|err| Error::new("failed to parse package graph").with_source(err))?;
+
+    if let Some(cache_path) = &cache_path {
+        if let Err(err) = write_cache(cache_path, &cargo_metadata) {
+            warn!(
+                "Failed to write the package graph cache `{}`: {}",
+                cache_path.display(),
+                err
+            );
+        }
+    }
+
+    cargo_metadata
+        .build_graph()
+        .map_err(|err| Error::new("failed to parse package graph").with_source(err))
+}
+
+/// The path of the cache file, named after the hash of everything the output
+/// of `cargo metadata` depends on: the manifests of the workspace and of its
+/// local dependencies, its lock file, the Cargo configuration files, the
+/// version of Cargo and the command line.
+///
+/// Changing any of them changes the path, which invalidates the cache.
+fn cache_path(
+    manifest_path: &Path,
+    options: &Options,
+    config: &cargo::util::Config,
+) -> Result<PathBuf> {
+    let workspace = cargo::core::Workspace::new(manifest_path, config)
+        .map_err(|err| Error::new("failed to load Cargo workspace").with_source(err))?;
+
+    let mut state = Sha256::new();
+    state.update(env!("CARGO_PKG_VERSION"));
+    state.update(cargo_version()?);
+    state.update(manifest_path.to_string_lossy().as_bytes());
+    state.update(options.cargo_args().join(" "));
+
+    let paths = std::iter::once(workspace.root().join("Cargo.lock"))
+        .chain(local_manifest_paths(&workspace, config))
+        .chain(config_paths(config));
+
+    for path in paths {
+        state.update(path.to_string_lossy().as_bytes());
+
+        match std::fs::read(&path) {
+            Ok(data) => state.update(Sha256::digest(&data)),
+            // The lock file does not exist until the first build, and the
+            // configuration files are optional.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => state.update("missing"),
+            Err(err) => {
+                return Err(Error::new("failed to read manifest").with_source(err));
+            }
+        }
+    }
+
+    // The cache lives with the other state of the runs, in the target root.
+    let target_root = options
+        .target_root
+        .clone()
+        .unwrap_or_else(|| workspace.target_dir().into_path_unlocked());
+
+    Ok(target_root.join(GRAPH_CACHE_DIR_NAME).join(format!(
+        "{}{:x}.json",
+        GRAPH_CACHE_FILE_PREFIX,
+        state.finalize()
+    )))
+}
+
+/// The manifests of the workspace and of the local dependencies of its
+/// members, like the path dependencies outside of the workspace, recursively.
+fn local_manifest_paths(
+    workspace: &cargo::core::Workspace<'_>,
+    config: &cargo::util::Config,
+) -> Vec<PathBuf> {
+    let mut manifest_paths: Vec<PathBuf> = std::iter::once(workspace.root_manifest())
+        .chain(workspace.members().map(cargo::core::Package::manifest_path))
+        .map(Path::to_path_buf)
+        .collect();
+    let mut visited: HashSet<PathBuf> = manifest_paths.iter().cloned().collect();
+    let mut pending: Vec<PathBuf> = workspace
+        .members()
+        .flat_map(|package| local_dependency_manifest_paths(package.dependencies()))
+        .collect();
+
+    while let Some(manifest_path) = pending.pop() {
+        if !visited.insert(manifest_path.clone()) {
+            continue;
+        }
+
+        // A dependency that cannot be read fails `cargo metadata` anyway.
+        if let Some(package) = manifest_path.parent().and_then(|root| {
+            let source_id = cargo::core::SourceId::for_path(root).ok()?;
+
+            cargo::ops::read_package(&manifest_path, source_id, config)
+                .map(|(package, _)| package)
+                .ok()
+        }) {
+            pending.extend(local_dependency_manifest_paths(package.dependencies()));
+        }
+
+        manifest_paths.push(manifest_path);
+    }
+
+    manifest_paths
+}
+
+fn local_dependency_manifest_paths(
+    dependencies: &[cargo::core::Dependency],
+) -> impl Iterator<Item = PathBuf> + '_ {
+    dependencies
+        .iter()
+        .filter(|dependency| dependency.source_id().is_path())
+        .filter_map(|dependency| dependency.source_id().url().to_file_path().ok())
+        .map(|root| root.join("Cargo.toml"))
+}
+
+/// The Cargo configuration files that may apply, whether they exist or not,
+/// from the current directory up to the root and in the Cargo home.
+fn config_paths(config: &cargo::util::Config) -> Vec<PathBuf> {
+    config
+        .cwd()
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(std::iter::once(
+            config.home().as_path_unlocked().to_path_buf(),
+        ))
+        .flat_map(|dir| [dir.join("config"), dir.join("config.toml")])
+        .collect()
+}
+
+/// The version of the Cargo that runs `cargo metadata`, whose output format
+/// may change between versions.
+fn cargo_version() -> Result<String> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .arg("--version")
+        .output()
+        .map_err(|err| Error::new("failed to get Cargo version").with_source(err))?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Write the output of `cargo metadata` to the cache, removing the outdated
+/// cache files.
+fn write_cache(cache_path: &Path, cargo_metadata: &guppy::CargoMetadata) -> Result<()> {
+    let cache_dir = cache_path.parent().unwrap();
+
+    std::fs::create_dir_all(cache_dir)
+        .map_err(|err| Error::new("failed to create cache directory").with_source(err))?;
+
+    for entry in std::fs::read_dir(cache_dir)
+        .map_err(|err| Error::new("failed to read cache directory").with_source(err))?
+    {
+        let path = entry
+            .map_err(|err| Error::new("failed to read cache directory").with_source(err))?
+            .path();
+
+        let is_graph_cache = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map_or(false, |file_name| {
+                file_name.starts_with(GRAPH_CACHE_FILE_PREFIX)
+            });
+
+        if is_graph_cache {
+            std::fs::remove_file(&path)
+                .map_err(|err| Error::new("failed to remove cache file").with_source(err))?;
+        }
+    }
+
+    let mut data = Vec::new();
+    cargo_metadata
+        .serialize(&mut data)
+        .map_err(|err| Error::new("failed to serialize package graph").with_source(err))?;
+
+    std::fs::write(cache_path, data)
+        .map_err(|err| Error::new("failed to write cache file").with_source(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_package(root: &Path, name: &str, dependencies: &str) {
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src").join("lib.rs"), "").unwrap();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+                name, dependencies
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_local_manifest_paths() {
+        let root =
+            std::env::temp_dir().join(format!("cargo-monorepo-graph-cache-{}", std::process::id()));
+        let workspace_root = root.join("workspace");
+
+        std::fs::create_dir_all(&workspace_root).unwrap();
+        std::fs::write(
+            workspace_root.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\"]\n",
+        )
+        .unwrap();
+        write_package(
+            &workspace_root.join("app"),
+            "app",
+            "lib = { path = \"../../lib\" }\n",
+        );
+        write_package(&root.join("lib"), "lib", "util = { path = \"../util\" }\n");
+        write_package(&root.join("util"), "util", "");
+
+        let root = root.canonicalize().unwrap();
+        let config = cargo::util::Config::default().unwrap();
+        let workspace =
+            cargo::core::Workspace::new(&root.join("workspace").join("Cargo.toml"), &config)
+                .unwrap();
+        let mut manifest_paths = local_manifest_paths(&workspace, &config);
+        manifest_paths.sort();
+
+        assert_eq!(
+            manifest_paths,
+            vec![
+                root.join("lib").join("Cargo.toml"),
+                root.join("util").join("Cargo.toml"),
+                root.join("workspace").join("Cargo.toml"),
+                root.join("workspace").join("app").join("Cargo.toml"),
+            ]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,10 +1,82 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
+use cargo::core::resolver::ResolveBehavior;
 use cargo_metadata::camino::Utf8Path;
+use guppy::{
+    graph::{
+        cargo::{CargoOptions, CargoResolverVersion},
+        feature::StandardFeatures,
+        DependencyDirection, PackageLink,
+    },
+    platform::{EnabledTernary, Platform, PlatformSpec, TargetFeatures},
+};
+use log::debug;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 
-use crate::{metadata::DistTargetMetadata, sources::Sources, Package, Result};
+use crate::{
+    metadata::DistTargetMetadata, rust::is_target_spec_file, sources::Sources, Error, Package,
+    Result,
+};
+
+/// The version of the schema of `HashSource`.
+///
+/// It must be incremented whenever the hash input changes for identical
+/// packages, so that hashes computed by different versions of the tool are not
+/// mistaken for changes.
+const HASH_SCHEMA_VERSION: u32 = 3;
+
+/// The outcome of the comparison of two hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashComparison {
+    Identical,
+    Different,
+    /// The hashes were computed with different schema versions and cannot be
+    /// compared.
+    Unknown,
+}
+
+impl HashComparison {
+    /// Compare two hashes, taking their schema versions into account.
+    pub fn new(a: &str, b: &str) -> Self {
+        if hash_schema(a) != hash_schema(b) {
+            Self::Unknown
+        } else if a == b {
+            Self::Identical
+        } else {
+            Self::Different
+        }
+    }
+}
+
+/// The algorithm and schema version prefix of a hash, like `sha256.v3`.
+///
+/// Hashes without a schema version were computed with the first one.
+fn hash_schema(hash: &str) -> &str {
+    hash.split_once(':').map_or("", |(schema, _)| schema)
+}
+
+/// The length of the short form of the hashes.
+const SHORT_HASH_LEN: usize = 12;
+
+/// The short form of a hash, made of the first hexadecimal digits of its
+/// digest, like `3f8a2c1d9e4b`.
+pub(crate) fn short_hash(hash: &str) -> &str {
+    let digest = hash.split_once(':').map_or(hash, |(_, digest)| digest);
+
+    digest.get(..SHORT_HASH_LEN).unwrap_or(digest)
+}
+
+/// Whether a value has the form of a short hash.
+pub(crate) fn is_short_hash(value: &str) -> bool {
+    value.len() == SHORT_HASH_LEN
+        && value
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
 
 /// A structure whose sole purpose is to help compute a deterministic hash of a
 /// given package.
@@ -23,15 +95,23 @@ pub(crate) struct HashSource<'g> {
     edition: &'g str,
     links: Option<&'g str>,
     direct_links: Vec<String>,
+    resolved_features: BTreeMap<String, BTreeSet<String>>,
+    /// The hashes of the packages providing base images, so that a change of
+    /// a base image triggers the rebuild of the images extending it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    base_images: Vec<String>,
     sources: &'g Sources,
     dist_targets: &'g BTreeMap<String, DistTargetMetadata>,
 }
 
 impl<'g> HashSource<'g> {
     pub(crate) fn new(package: &'g Package<'g>) -> Result<Self> {
+        let platforms = target_platforms(package);
+
         let direct_links = package
             .package_metadata()
             .direct_links()
+            .filter(|link| is_shipped_dependency(link, platforms.as_deref()))
             .map(|link| {
                 let link_package = link.to();
 
@@ -49,6 +129,24 @@ impl<'g> HashSource<'g> {
             })
             .collect::<Result<Vec<_>>>()?;
 
+        let base_images = package
+            .monorepo_metadata()
+            .dist_targets
+            .values()
+            .filter_map(DistTargetMetadata::base_image)
+            // A package cannot extend its own base image, which is reported
+            // when ordering the publications.
+            .filter(|base_image| *base_image != package.name())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|base_image| {
+                package
+                    .context()
+                    .resolve_package_by_name(base_image)?
+                    .hash()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(Self {
             name: package.package_metadata().name(),
             version: package.package_metadata().version(),
@@ -63,7 +161,9 @@ impl<'g> HashSource<'g> {
             edition: package.package_metadata().edition(),
             links: package.package_metadata().links(),
             direct_links,
-            sources: package.sources(),
+            resolved_features: resolved_features(package)?,
+            base_images,
+            sources: package.sources()?,
             dist_targets: &package.monorepo_metadata().dist_targets,
         })
     }
@@ -74,6 +174,144 @@ impl<'g> HashSource<'g> {
         // There is no reason for this write to ever fail so unwrap is fine.
         serde_json::to_writer(&mut state, &self).unwrap();
 
-        format!("sha256:{:x}", state.finalize())
+        format!("sha256.v{}:{:x}", HASH_SCHEMA_VERSION, state.finalize())
+    }
+}
+
+/// The platforms the distribution targets of a package compile their binaries
+/// for.
+///
+/// Returns `None` if the platforms cannot be determined, in which case
+/// dependencies should not be filtered by platform.
+fn target_platforms(package: &Package<'_>) -> Option<Vec<PlatformSpec>> {
+    let dist_targets = &package.monorepo_metadata().dist_targets;
+
+    if dist_targets.is_empty() {
+        return None;
+    }
+
+    let mut target_runtimes = BTreeSet::new();
+
+    for dist_target_metadata in dist_targets.values() {
+        target_runtimes.extend(dist_target_metadata.target_runtimes()?.iter().cloned());
+    }
+
+    target_runtimes
+        .iter()
+        .map(|target_runtime| target_platform(target_runtime))
+        .collect()
+}
+
+/// The platform of a target runtime.
+///
+/// Returns `None` for the custom target specifications and the triples that
+/// are too recent to be known, whose dependencies should not be filtered by
+/// platform.
+pub(crate) fn target_platform(target_runtime: &str) -> Option<PlatformSpec> {
+    if is_target_spec_file(target_runtime) {
+        debug!(
+            "Not filtering dependencies by platform for the custom target specification `{}`",
+            target_runtime
+        );
+
+        return None;
+    }
+
+    match Platform::new(target_runtime.to_string(), TargetFeatures::Unknown) {
+        Ok(platform) => Some(PlatformSpec::Platform(Arc::new(platform))),
+        Err(err) => {
+            debug!(
+                "Not filtering dependencies by platform for the unknown target triple `{}`: {}",
+                target_runtime, err
+            );
+
+            None
+        }
+    }
+}
+
+/// Check whether a dependency can end up in the binaries of a package.
+///
+/// Dev-dependencies are only used by tests, examples and benchmarks and are
+/// never shipped. Normal dependencies that are disabled on all the `platforms`
+/// are excluded too, but build-dependencies run on the host, whatever the
+/// platforms, and are always included.
+pub(crate) fn is_shipped_dependency(
+    link: &PackageLink<'_>,
+    platforms: Option<&[PlatformSpec]>,
+) -> bool {
+    let normal = link.normal();
+
+    link.build().is_present()
+        || match platforms {
+            Some(platforms) => platforms.iter().any(|platform| {
+                !matches!(
+                    normal.status().enabled_on(platform),
+                    EnabledTernary::Disabled
+                )
+            }),
+            None => normal.is_present(),
+        }
+}
+
+/// Resolve the features of all the dependencies of a package, as Cargo does
+/// when building it.
+///
+/// Because of feature unification, the features of a dependency may change
+/// without any of the manifests along the dependency path changing, which
+/// affects the built binaries nonetheless.
+pub(crate) fn resolved_features(
+    package: &Package<'_>,
+) -> Result<BTreeMap<String, BTreeSet<String>>> {
+    let context = package.context();
+    let resolver = match context.workspace()?.resolve_behavior() {
+        ResolveBehavior::V1 => CargoResolverVersion::V1,
+        ResolveBehavior::V2 => CargoResolverVersion::V2,
+    };
+
+    let mut cargo_options = CargoOptions::new();
+    cargo_options.set_resolver(resolver).set_include_dev(false);
+
+    let cargo_set = context
+        .package_graph()
+        .query_forward(std::iter::once(package.id()))
+        .and_then(|query| {
+            query
+                .resolve()
+                .to_feature_set(StandardFeatures::Default)
+                .into_cargo_set(&cargo_options)
+        })
+        .map_err(|err| {
+            Error::new("failed to resolve features")
+                .with_source(err)
+                .with_explanation(format!(
+                    "The features of the dependencies of `{}` could not be resolved.",
+                    package.name()
+                ))
+        })?;
+
+    let mut resolved_features: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+    for feature_set in [cargo_set.target_features(), cargo_set.host_features()] {
+        for feature_list in feature_set.packages_with_features(DependencyDirection::Forward) {
+            resolved_features
+                .entry(feature_list.package().id().to_string())
+                .or_default()
+                .extend(feature_list.features().iter().map(ToString::to_string));
+        }
+    }
+
+    Ok(resolved_features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_platform() {
+        assert!(target_platform("x86_64-unknown-linux-gnu").is_some());
+        assert!(target_platform("targets/custom-kernel.json").is_none());
+        assert!(target_platform("targets/CUSTOM-KERNEL.JSON").is_none());
     }
 }
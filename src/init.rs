@@ -0,0 +1,174 @@
+//! Scaffolding of the distribution targets of a package.
+
+use guppy::graph::BuildTargetId;
+use log::warn;
+
+use crate::{action_step, Error, ErrorKind, Package, Result};
+
+/// The distribution target types that can be scaffolded.
+pub const INIT_TARGET_TYPES: &[&str] = &["docker", "aws-lambda"];
+
+/// Add a starter distribution target of the specified type to the manifest of
+/// the package, named after the package unless `name` is specified.
+///
+/// The section is appended to the manifest, leaving the rest of it untouched.
+pub fn init_dist_target(
+    package: &Package<'_>,
+    target_type: &str,
+    name: Option<&str>,
+) -> Result<()> {
+    let name = name.unwrap_or_else(|| package.name());
+
+    if name.is_empty()
+        || name == "tags"
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(Error::new("invalid distribution target name")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(format!(
+                "The name `{}` cannot be used for a distribution target: it must only contain ASCII letters, digits, `-` and `_`, and must not be `tags`.",
+                name
+            )));
+    }
+
+    let manifest_path = package.package_metadata().manifest_path();
+    let manifest_data = std::fs::read_to_string(manifest_path)
+        .map_err(|err| Error::new("failed to read manifest").with_source(err))?;
+    let manifest = manifest_data
+        .parse::<toml::Value>()
+        .map_err(|err| Error::new("failed to parse manifest").with_source(err))?;
+
+    if manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("monorepo"))
+        .and_then(|monorepo| monorepo.get(name))
+        .is_some()
+    {
+        return Err(Error::new("distribution target already exists")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(format!(
+                "{} already declares a distribution target named `{}`. Specify another name with `--name`.",
+                package.name(),
+                name
+            )));
+    }
+
+    let binaries: Vec<&str> = package
+        .package_metadata()
+        .build_targets()
+        .filter_map(|build_target| match build_target.id() {
+            BuildTargetId::Binary(binary) => Some(binary),
+            _ => None,
+        })
+        .collect();
+
+    if binaries.is_empty() {
+        return Err(Error::new("package has no binaries")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(format!(
+                "{} has no binaries to distribute as {} target. Add a binary to the package first.",
+                package.name(),
+                target_type
+            )));
+    }
+
+    let section = match target_type {
+        "docker" => docker_section(name, &binaries),
+        "aws-lambda" => aws_lambda_section(name, &binaries),
+        _ => {
+            return Err(Error::new("unsupported distribution target type")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "`{}` targets cannot be scaffolded. Supported types are: {}.",
+                    target_type,
+                    INIT_TARGET_TYPES.join(", ")
+                )))
+        }
+    };
+
+    let mut manifest_data = manifest_data;
+
+    if !manifest_data.is_empty() && !manifest_data.ends_with('\n') {
+        manifest_data.push('\n');
+    }
+
+    manifest_data.push('\n');
+    manifest_data.push_str(&section);
+
+    // The manifest may declare its metadata in a way that conflicts with the
+    // appended tables, like inline tables.
+    manifest_data.parse::<toml::Value>().map_err(|err| {
+        Error::new("failed to add distribution target")
+            .with_kind(ErrorKind::Configuration)
+            .with_source(err)
+            .with_explanation(format!(
+                "The section of the distribution target conflicts with the manifest of {}. Add it manually instead:\n\n{}",
+                package.name(),
+                section
+            ))
+    })?;
+
+    if package.context().options().dry_run {
+        warn!("`--dry-run` specified, will not modify the manifest");
+        println!("{}", section);
+
+        return Ok(());
+    }
+
+    action_step!(
+        "Adding",
+        "{} distribution target `{}` to {}",
+        target_type,
+        name,
+        package.name()
+    );
+
+    std::fs::write(manifest_path, manifest_data)
+        .map_err(|err| Error::new("failed to write manifest").with_source(err))
+}
+
+fn docker_section(name: &str, binaries: &[&str]) -> String {
+    format!(
+        r#"[package.metadata.monorepo.{name}]
+type = "docker"
+# The registry to push the image to. Defaults to the `CARGO_MONOREPO_DOCKER_REGISTRY` environment variable.
+# registry = "1234.dkr.ecr.ca-central-1.amazonaws.com"
+target_runtime = "x86_64-unknown-linux-gnu"
+template = """
+FROM debian:bullseye-slim
+{{{{ copy_all }}}}
+CMD ["{{{{ binaries["{binary}"] }}}}"]
+"""
+"#,
+        name = name,
+        binary = binaries[0],
+    )
+}
+
+fn aws_lambda_section(name: &str, binaries: &[&str]) -> String {
+    let mut section = format!(
+        r#"[package.metadata.monorepo.{name}]
+type = "aws-lambda"
+# The S3 bucket to upload the archive to. Defaults to the `CARGO_MONOREPO_AWS_LAMBDA_S3_BUCKET` environment variable.
+# s3_bucket = "some-s3-bucket"
+target_runtime = "x86_64-unknown-linux-musl"
+runtime = "provided.al2023"
+"#,
+        name = name,
+    );
+
+    // Each binary of a package with several is a function of its own.
+    if binaries.len() > 1 {
+        section.extend(binaries.iter().map(|binary| {
+            format!(
+                "\n[package.metadata.monorepo.{}.functions.{}]\n",
+                name, binary
+            )
+        }));
+    }
+
+    section
+}
@@ -59,19 +59,79 @@
 // crate-specific exceptions:
 #![allow(clippy::implicit_hasher, clippy::missing_errors_doc)]
 
+mod archive;
+mod artifact_repository;
+mod audit;
+mod aws;
 mod aws_lambda;
+mod build_cache;
+mod build_info;
+mod compose;
+mod condition;
 mod context;
+mod crates_registry;
+mod diff;
 mod dist_target;
 mod docker;
+mod docker_base;
+mod error_codes;
 mod errors;
+mod exec;
+mod graph_cache;
 mod hash;
+mod init;
+mod licenses;
+mod lock;
+mod macos_app;
 mod metadata;
+mod msi;
+mod notify;
+mod npm;
 mod package;
+mod process;
+mod prune;
+mod publish_state;
+mod registry;
+mod report;
+mod run;
 mod rust;
+mod s3_sync;
+mod schedule;
+mod size;
 mod sources;
+mod staging;
+mod telemetry;
 mod term;
+mod tools;
+mod wasm;
+mod watch;
 
+pub use audit::{AuditEntry, AuditLogMetadata};
+pub use build_cache::BuildCacheMetadata;
+pub use build_info::BuildInfoMetadata;
+pub use compose::write_compose_file;
 pub use context::{Context, ContextBuilder, Mode, Options};
+pub use diff::{diff_git_refs, PackageDiff, PackageState};
+pub use error_codes::ErrorCode;
 pub(crate) use errors::ErrorContext;
-pub use errors::{Error, Result};
+pub use errors::{Error, ErrorKind, Result};
+pub use exec::{exec_packages, run_cargo, ExecOptions, ExecOrder};
+pub use hash::HashComparison;
+pub use init::{init_dist_target, INIT_TARGET_TYPES};
+pub use licenses::{check_license_policy, DependencyLicense, LicenseInventory, LicensePolicy};
+pub use lock::{LockedArtifact, LockedPackage, Lockfile};
+pub use metadata::metadata_schema;
+pub use notify::NotificationsMetadata;
 pub use package::Package;
+pub use prune::RetentionRules;
+pub use publish_state::PublishState;
+pub use report::{PackageSummary, Provenance, PublishReport, PublishedArtifact, PublishedVersion};
+pub use run::run_docker_dist_target;
+pub use rust::{compile_packages, fetch_dependencies};
+pub use schedule::{publish_packages, Publication};
+pub use staging::{StagingDirectories, StagingDirectory};
+#[cfg(feature = "otlp")]
+pub use telemetry::Telemetry;
+pub use term::{is_github_actions, print_github_error};
+pub use tools::check_prerequisites;
+pub use watch::watch;
@@ -0,0 +1,296 @@
+//! Inventory of the licenses of the dependencies shipped with the
+//! distribution targets, and enforcement of the license policy of the
+//! workspace.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dist_target::DistTarget,
+    hash::{is_shipped_dependency, target_platform},
+    Error, ErrorCode, ErrorKind, Package, Result,
+};
+
+/// The license policy of the workspace, read from the
+/// `[workspace.metadata.monorepo.licenses]` section of the root manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LicensePolicy {
+    /// The licenses the dependencies may use, as SPDX identifiers. All the
+    /// licenses that are not denied are allowed if empty.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// The licenses the dependencies must not use, as SPDX identifiers.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// The names of the dependencies the policy does not apply to, like the
+    /// ones whose license was reviewed separately.
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+}
+
+impl LicensePolicy {
+    /// Whether a dependency with the specified SPDX license expression
+    /// complies with the policy.
+    ///
+    /// Dependencies that do not declare their license only comply when no
+    /// license is explicitly allowed.
+    fn allows(&self, name: &str, license: Option<&str>) -> bool {
+        if self.exceptions.iter().any(|exception| exception == name) {
+            return true;
+        }
+
+        match license {
+            Some(license) => LicenseExpression::new(license)
+                .evaluate(&|license| self.allows_license(license))
+                .unwrap_or(false),
+            None => self.allow.is_empty(),
+        }
+    }
+
+    fn allows_license(&self, license: &str) -> bool {
+        // `GPL-2.0+` means version 2.0 or any later version.
+        let license = license.trim_end_matches('+');
+        let matches = |licenses: &[String]| {
+            licenses
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(license))
+        };
+
+        !matches(&self.deny) && (self.allow.is_empty() || matches(&self.allow))
+    }
+}
+
+/// The licenses of the dependencies shipped with a distribution target.
+#[derive(Debug, Clone, Serialize)]
+pub struct LicenseInventory {
+    pub dist_target: String,
+    pub dependencies: Vec<DependencyLicense>,
+}
+
+impl LicenseInventory {
+    /// Collect the licenses of the dependencies a distribution target ships,
+    /// on its target runtime if it has one.
+    pub(crate) fn new(package: &Package<'_>, dist_target: &DistTarget<'_>) -> Result<Self> {
+        let context = package.context();
+        let policy = context.license_policy();
+
+        let platforms = dist_target
+            .target_runtime()
+            .and_then(target_platform)
+            .map(|platform| vec![platform]);
+
+        let package_set = context
+            .package_graph()
+            .query_forward(std::iter::once(package.id()))
+            .map_err(|err| Error::new("failed to query package graph").with_source(err))?
+            .resolve_with_fn(|_, link| is_shipped_dependency(&link, platforms.as_deref()));
+
+        // The dependencies are sorted by name and version, so that the
+        // inventories can be compared between runs.
+        let dependencies: BTreeMap<_, _> = package_set
+            .packages(guppy::graph::DependencyDirection::Forward)
+            .filter(|package_metadata| !package_metadata.in_workspace())
+            .map(|package_metadata| {
+                let dependency = DependencyLicense {
+                    name: package_metadata.name().to_string(),
+                    version: package_metadata.version().to_string(),
+                    license: package_metadata.license().map(ToString::to_string),
+                    license_file: package_metadata.license_file().map(ToString::to_string),
+                    allowed: policy.allows(package_metadata.name(), package_metadata.license()),
+                };
+
+                (
+                    (dependency.name.clone(), dependency.version.clone()),
+                    dependency,
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            dist_target: dist_target.to_string(),
+            dependencies: dependencies.into_values().collect(),
+        })
+    }
+}
+
+/// The license of a dependency.
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyLicense {
+    pub name: String,
+    pub version: String,
+    /// The SPDX license expression of the dependency, if it declares one.
+    pub license: Option<String>,
+    /// The path of the license file of the dependency, if it declares one.
+    pub license_file: Option<String>,
+    /// Whether the license complies with the policy of the workspace.
+    pub allowed: bool,
+}
+
+impl Display for DependencyLicense {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} v{}: ", self.name, self.version)?;
+
+        match (&self.license, &self.license_file) {
+            (Some(license), _) => write!(f, "{}", license)?,
+            (None, Some(license_file)) => write!(f, "see `{}`", license_file)?,
+            (None, None) => write!(f, "unknown")?,
+        }
+
+        if !self.allowed {
+            write!(f, " (not allowed)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fail if any of the dependencies of the inventories does not comply with
+/// the license policy of the workspace.
+pub fn check_license_policy(inventories: &[LicenseInventory]) -> Result<()> {
+    let violations: Vec<_> = inventories
+        .iter()
+        .flat_map(|inventory| {
+            inventory
+                .dependencies
+                .iter()
+                .filter(|dependency| !dependency.allowed)
+                .map(move |dependency| format!("  - {} in {}", dependency, inventory.dist_target))
+        })
+        .collect();
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::new("license policy violated")
+        .with_kind(ErrorKind::Validation)
+        .with_code(ErrorCode::LicensePolicyViolated)
+        .with_explanation(format!(
+            "The following dependencies do not comply with the license policy of the workspace:\n{}\n\nRemove them, or allow their licenses or list them as exceptions in `workspace.metadata.monorepo.licenses` once reviewed.",
+            violations.join("\n")
+        )))
+}
+
+/// An SPDX license expression, like `MIT OR Apache-2.0`.
+///
+/// The legacy `/` separator, still used by some crates, is read as `OR`.
+struct LicenseExpression {
+    tokens: Vec<String>,
+}
+
+impl LicenseExpression {
+    fn new(expression: &str) -> Self {
+        let tokens = expression
+            .replace('(', " ( ")
+            .replace(')', " ) ")
+            .replace('/', " OR ")
+            .split_whitespace()
+            .map(ToString::to_string)
+            .collect();
+
+        Self { tokens }
+    }
+
+    /// Evaluate the expression with the specified predicate on licenses.
+    ///
+    /// Returns `None` if the expression is invalid.
+    fn evaluate(&self, allowed: &dyn Fn(&str) -> bool) -> Option<bool> {
+        let mut pos = 0;
+        let result = self.or(&mut pos, allowed)?;
+
+        (pos == self.tokens.len()).then(|| result)
+    }
+
+    fn or(&self, pos: &mut usize, allowed: &dyn Fn(&str) -> bool) -> Option<bool> {
+        let mut result = self.and(pos, allowed)?;
+
+        while self.peek(*pos) == Some("OR") {
+            *pos += 1;
+            result |= self.and(pos, allowed)?;
+        }
+
+        Some(result)
+    }
+
+    fn and(&self, pos: &mut usize, allowed: &dyn Fn(&str) -> bool) -> Option<bool> {
+        let mut result = self.atom(pos, allowed)?;
+
+        while self.peek(*pos) == Some("AND") {
+            *pos += 1;
+            result &= self.atom(pos, allowed)?;
+        }
+
+        Some(result)
+    }
+
+    fn atom(&self, pos: &mut usize, allowed: &dyn Fn(&str) -> bool) -> Option<bool> {
+        let token = self.peek(*pos)?;
+        *pos += 1;
+
+        match token {
+            "(" => {
+                let result = self.or(pos, allowed)?;
+
+                if self.peek(*pos) != Some(")") {
+                    return None;
+                }
+
+                *pos += 1;
+
+                Some(result)
+            }
+            ")" | "OR" | "AND" | "WITH" => None,
+            license => {
+                // Exceptions, like `LLVM-exception`, only grant additional
+                // permissions.
+                if self.peek(*pos) == Some("WITH") {
+                    *pos += 2;
+                }
+
+                Some(allowed(license))
+            }
+        }
+    }
+
+    fn peek(&self, pos: usize) -> Option<&str> {
+        self.tokens.get(pos).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_license_policy() {
+        let policy = LicensePolicy {
+            allow: vec!["MIT".to_string(), "Apache-2.0".to_string()],
+            deny: vec![],
+            exceptions: vec!["ring".to_string()],
+        };
+
+        assert!(policy.allows("anyhow", Some("MIT OR Apache-2.0")));
+        assert!(policy.allows("anyhow", Some("MIT/Apache-2.0")));
+        assert!(policy.allows("anyhow", Some("GPL-3.0 OR MIT")));
+        assert!(!policy.allows("anyhow", Some("GPL-3.0 AND MIT")));
+        assert!(policy.allows(
+            "anyhow",
+            Some("(MIT OR GPL-3.0) AND Apache-2.0 WITH LLVM-exception")
+        ));
+        assert!(!policy.allows("anyhow", Some("(MIT OR Apache-2.0")));
+        assert!(!policy.allows("anyhow", None));
+        assert!(policy.allows("ring", None));
+
+        let policy = LicensePolicy {
+            allow: vec![],
+            deny: vec!["GPL-3.0".to_string()],
+            exceptions: vec![],
+        };
+
+        assert!(policy.allows("anyhow", Some("MIT")));
+        assert!(!policy.allows("anyhow", Some("GPL-3.0+")));
+        assert!(policy.allows("anyhow", None));
+    }
+}
@@ -0,0 +1,215 @@
+//! The `monorepo.lock` file, which pins the published artifacts of the
+//! packages for each environment.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, Result};
+
+/// The name of the lock file, relative to the workspace root.
+const LOCK_FILE_NAME: &str = "monorepo.lock";
+
+const LOCK_FILE_HEADER: &str = "# This file is generated by `cargo monorepo`.\n\
+    # It is not intended for manual editing.\n\n";
+
+/// The published artifacts of the packages, per environment.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub environments: BTreeMap<String, BTreeMap<String, LockedPackage>>,
+}
+
+/// The artifacts of a package version.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LockedPackage {
+    pub version: semver::Version,
+    pub hash: String,
+    #[serde(default)]
+    pub artifacts: Vec<LockedArtifact>,
+}
+
+/// A published artifact of a distribution target.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct LockedArtifact {
+    pub dist_target: String,
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+}
+
+impl From<&PublishedArtifact> for LockedArtifact {
+    fn from(artifact: &PublishedArtifact) -> Self {
+        Self {
+            dist_target: artifact.dist_target.clone(),
+            reference: artifact.reference.clone(),
+            digest: artifact.digest.clone(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Load the lock file of the workspace.
+    ///
+    /// If the lock file does not exist, an empty one is returned.
+    pub fn load(context: &Context) -> Result<Self> {
+        let path = Self::path(context)?;
+
+        let data = match std::fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No lock file at `{}`: starting afresh", path.display());
+
+                return Ok(Self::default());
+            }
+            Err(err) => {
+                return Err(Error::new("failed to read lock file")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The lock file could not be read from `{}`. You may want to verify permissions.",
+                        path.display()
+                    )));
+            }
+        };
+
+        toml::from_str(&data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to parse lock file",
+                format!(
+                    "The lock file `{}` is invalid. You may want to regenerate it with `cargo monorepo lock`.",
+                    path.display()
+                ),
+            )
+    }
+
+    /// Write the lock file of the workspace, unless `--dry-run` was
+    /// specified.
+    pub fn save(&self, context: &Context) -> Result<()> {
+        let path = Self::path(context)?;
+
+        if context.options().dry_run {
+            debug!(
+                "`--dry-run` specified, will not write the lock file `{}`",
+                path.display()
+            );
+
+            return Ok(());
+        }
+
+        let data = toml::to_string(self)
+            .map_err(Error::from_source)
+            .with_context("failed to serialize lock file")?;
+
+        std::fs::write(&path, format!("{}{}", LOCK_FILE_HEADER, data))
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to write lock file",
+                format!(
+                    "The lock file could not be written to `{}`. You may want to verify permissions.",
+                    path.display()
+                ),
+            )
+    }
+
+    /// Record the published artifacts of a package in the specified
+    /// environment, replacing any previous entry for the package.
+    pub fn record(
+        &mut self,
+        environment: &str,
+        package: &Package<'_>,
+        artifacts: &[PublishedArtifact],
+    ) -> Result<()> {
+        if artifacts.is_empty() {
+            return Ok(());
+        }
+
+        self.environments
+            .entry(environment.to_string())
+            .or_default()
+            .insert(
+                package.name().to_string(),
+                LockedPackage {
+                    version: package.version().clone(),
+                    hash: package.hash()?,
+                    artifacts: artifacts.iter().map(Into::into).collect(),
+                },
+            );
+
+        Ok(())
+    }
+
+    /// Regenerate the entry of a package in the specified environment from
+    /// its current version.
+    ///
+    /// The artifacts of all the distribution targets of the package must
+    /// exist. Digests are kept from the previous entry when the references
+    /// did not change, as they can only be known at publication time.
+    pub fn relock(&mut self, environment: &str, package: &Package<'_>) -> Result<()> {
+        let dist_targets = package.dist_targets();
+
+        if dist_targets.is_empty() {
+            return Ok(());
+        }
+
+        let packages = self
+            .environments
+            .entry(environment.to_string())
+            .or_default();
+
+        let previous_artifacts = packages
+            .get(package.name())
+            .map(|locked_package| locked_package.artifacts.clone())
+            .unwrap_or_default();
+
+        let mut artifacts = Vec::with_capacity(dist_targets.len());
+
+        for dist_target in dist_targets {
+            let reference = dist_target.artifact_reference()?;
+
+            if !package
+                .context()
+                .artifact_exists(&reference, dist_target.exists())?
+            {
+                return Err(Error::new("cannot lock a missing artifact")
+                    .with_kind(ErrorKind::Validation)
+                    .with_explanation(format!(
+                    "The artifact `{}` of distribution {} v{} does not exist. Was it published?",
+                    reference,
+                    dist_target,
+                    package.version()
+                )));
+            }
+
+            let digest = previous_artifacts
+                .iter()
+                .find(|artifact| artifact.reference == reference)
+                .and_then(|artifact| artifact.digest.clone());
+
+            artifacts.push(LockedArtifact {
+                dist_target: dist_target.to_string(),
+                reference,
+                digest,
+            });
+        }
+
+        packages.insert(
+            package.name().to_string(),
+            LockedPackage {
+                version: package.version().clone(),
+                hash: package.hash()?,
+                artifacts,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn path(context: &Context) -> Result<PathBuf> {
+        Ok(context.workspace()?.root().join(LOCK_FILE_NAME))
+    }
+}
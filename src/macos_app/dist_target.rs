@@ -0,0 +1,545 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    action_step,
+    aws::{
+        aws_settings, delete_s3_object, ensure_s3_bucket_exists, list_s3_versions,
+        promote_s3_object, s3_bucket_exists, s3_object_exists, AwsSettings, S3ArtifactKeys,
+    },
+    dist_target::{check_channel_name, clean_dir, copy_extra_files, published_artifact},
+    ignore_step,
+    metadata::CopyPlan,
+    process::run_command,
+    rust::build_binaries,
+    tools::{self, Tool},
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, PublishedVersion, Result,
+};
+
+use super::MacosAppMetadata;
+
+pub struct MacosAppDistTarget<'g> {
+    pub name: String,
+    pub package: &'g Package<'g>,
+    pub metadata: MacosAppMetadata,
+}
+
+impl Display for MacosAppDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "macos-app[{}]", self.package.name())
+    }
+}
+
+impl<'g> MacosAppDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    pub fn build(&self) -> Result<()> {
+        if !cfg!(target_os = "macos") {
+            ignore_step!(
+                "Unsupported",
+                "macOS application bundles can only be built on macOS"
+            );
+            return Ok(());
+        }
+
+        if self.metadata.notarization.is_some() && self.metadata.signing_identity.is_none() {
+            return Err(Error::new("cannot notarize unsigned application bundle")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation("Notarization requires the application bundle to be signed: the field `signing_identity` must be specified."));
+        }
+
+        clean_dir(&self.target_dir(), "application bundle directory")?;
+
+        let copy_plan = self.copy_plan()?;
+        let binary = self.build_binary()?;
+        self.copy_binary(&binary)?;
+        self.write_info_plist()?;
+        // The resources directory holds no build outputs.
+        copy_extra_files(self.package, &copy_plan, &[])?;
+
+        if self.context().options().no_image {
+            ignore_step!(
+                "Skipping",
+                "macOS application bundle signing and packaging as `--no-image` was specified"
+            );
+            return Ok(());
+        }
+
+        if let Some(signing_identity) = &self.metadata.signing_identity {
+            self.codesign(signing_identity)?;
+        }
+
+        self.build_zip_archive()?;
+
+        if let Some(notarization) = &self.metadata.notarization {
+            self.notarize(&notarization.keychain_profile)?;
+
+            // The notarization ticket is stapled to the bundle, which must
+            // then be archived again.
+            self.build_zip_archive()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        if !cfg!(target_os = "macos") {
+            ignore_step!(
+                "Unsupported",
+                "macOS application bundles can only be published on macOS"
+            );
+            return Ok(None);
+        }
+
+        if self.context().options().mode.is_debug() && !self.context().options().force {
+            ignore_step!(
+                "Unsupported",
+                "macOS application bundles can't be published in debug mode unless `--force` is specified"
+            );
+            return Ok(None);
+        }
+
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!("`--force` specified: not checking for the application bundle existence before uploading");
+        } else if self.context().artifact_exists(&reference, self.exists())? {
+            ignore_step!(
+                "Up-to-date",
+                "macOS application bundle `{}` already exists",
+                &reference
+            );
+
+            return Ok(Some(published_artifact(
+                self.package,
+                self,
+                reference,
+                None,
+                true,
+            )));
+        }
+
+        let archive_path = self.archive_path();
+        let data = std::fs::read(&archive_path)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read application bundle archive on disk",
+                format!(
+                    "The archive `{}` could not be read. Has this target been built before attempting its publication?",
+                    archive_path.display()
+                ),
+            )?;
+
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+        if self.context().options().dry_run {
+            warn!(
+                "`--dry-run` specified, will not really upload the macOS application bundle to S3"
+            );
+
+            return Ok(None);
+        }
+
+        action_step!("Uploading", "macOS application bundle `{}`", &reference);
+
+        let metadata = self.package.provenance()?.metadata();
+
+        self.context().block_on(async move {
+            let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+            if self.metadata.allow_s3_bucket_creation {
+                let region = self.context().aws().region(&self.aws_settings()).await?;
+
+                ensure_s3_bucket_exists(
+                    &client,
+                    &self.metadata.s3_bucket,
+                    region.as_deref(),
+                    &self.metadata.s3_bucket_creation,
+                )
+                .await?;
+            }
+
+            client
+                .put_object()
+                .bucket(&self.metadata.s3_bucket)
+                .key(self.s3_key())
+                .content_type("application/zip")
+                .set_metadata(Some(metadata.into_iter().collect()))
+                .body(data.into())
+                .send()
+                .await
+                .map_err(|err| {
+                    Error::new("failed to upload application bundle archive on S3")
+                        .with_kind(ErrorKind::Network)
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "Please check that the S3 bucket `{}` exists and that you have the correct permissions.",
+                            &self.metadata.s3_bucket
+                        ))
+                })?;
+
+            Ok(())
+        })?;
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            reference,
+            Some(digest),
+            false,
+        )))
+    }
+
+    /// Check whether the application bundle archive for the current version
+    /// exists in its S3 bucket.
+    pub async fn exists(&self) -> Result<bool> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+        // The bucket is only created when publishing.
+        if self.metadata.allow_s3_bucket_creation
+            && !s3_bucket_exists(&client, &self.metadata.s3_bucket).await?
+        {
+            return Ok(false);
+        }
+
+        s3_object_exists(&client, &self.metadata.s3_bucket, &self.s3_key()).await
+    }
+
+    /// List the versions published in the S3 bucket.
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+        let s3_keys = self.s3_keys();
+
+        list_s3_versions(
+            &client,
+            &self.metadata.s3_bucket,
+            &s3_keys.versions_prefix(),
+            &s3_keys.suffix(),
+        )
+        .await
+        .map(Some)
+    }
+
+    /// Delete the specified version of the application bundle archive from the S3 bucket.
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+        delete_s3_object(
+            &client,
+            &self.metadata.s3_bucket,
+            &self.s3_keys().version_key(version),
+        )
+        .await
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(format!(
+            "s3://{}/{}",
+            &self.metadata.s3_bucket,
+            self.s3_key()
+        ))
+    }
+
+    /// Promote the current version of the application bundle archive to the
+    /// specified channel.
+    ///
+    /// The archive is copied S3-side and does not need to be rebuilt.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        check_channel_name(channel)?;
+
+        promote_s3_object(
+            self.context(),
+            &self.aws_settings(),
+            &self.metadata.s3_bucket,
+            &self.s3_key(),
+            &self.s3_keys().channel_key(channel),
+            "application bundle archive",
+        )
+    }
+
+    fn aws_settings(&self) -> AwsSettings {
+        aws_settings!(self.metadata)
+    }
+
+    fn s3_key(&self) -> String {
+        self.s3_keys().version_key(self.package.version())
+    }
+
+    /// The S3 keys of the application bundle archive, prefixed with the
+    /// preview identifier, if any.
+    fn s3_keys(&self) -> S3ArtifactKeys {
+        S3ArtifactKeys::new(
+            &self
+                .context()
+                .options()
+                .preview_s3_prefix(&self.metadata.s3_bucket_prefix),
+            self.package.name(),
+            "zip",
+        )
+    }
+
+    fn codesign(&self, signing_identity: &str) -> Result<()> {
+        let mut cmd = Command::new("codesign");
+
+        // The hardened runtime and secure timestamps are required for
+        // notarization.
+        cmd.args([
+            "--force",
+            "--options",
+            "runtime",
+            "--timestamp",
+            "--sign",
+            signing_identity,
+        ]);
+
+        if let Some(entitlements) = &self.metadata.entitlements {
+            cmd.arg("--entitlements");
+            cmd.arg(self.package.root().join(entitlements));
+        }
+
+        cmd.arg(self.bundle_path());
+
+        action_step!(
+            "Signing",
+            "macOS application bundle with identity `{}`",
+            signing_identity
+        );
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to sign application bundle",
+        )
+    }
+
+    fn notarize(&self, keychain_profile: &str) -> Result<()> {
+        let mut cmd = Command::new("xcrun");
+
+        cmd.args(["notarytool", "submit"]);
+        cmd.arg(self.archive_path());
+        cmd.args(["--keychain-profile", keychain_profile, "--wait"]);
+
+        action_step!("Notarizing", "macOS application bundle");
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to notarize application bundle",
+        )?;
+
+        let mut cmd = Command::new("xcrun");
+
+        cmd.args(["stapler", "staple"]);
+        cmd.arg(self.bundle_path());
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to staple notarization ticket",
+        )
+    }
+
+    /// Archive the application bundle with `ditto`, which preserves the
+    /// symbolic links and extended attributes that signatures rely on.
+    fn build_zip_archive(&self) -> Result<()> {
+        let archive_path = self.archive_path();
+
+        std::fs::remove_file(&archive_path).or_else(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => Ok(()),
+            _ => Err(Error::new("failed to remove previous archive").with_source(err)),
+        })?;
+
+        let mut cmd = Command::new("ditto");
+
+        cmd.args(["-c", "-k", "--keepParent"]);
+        cmd.arg(self.bundle_path());
+        cmd.arg(&archive_path);
+
+        action_step!("Packaging", "macOS application bundle archive");
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to archive application bundle",
+        )
+    }
+
+    fn write_info_plist(&self) -> Result<()> {
+        let version = self.package.version();
+        let info_plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>CFBundleDevelopmentRegion</key>
+    <string>en</string>
+    <key>CFBundleExecutable</key>
+    <string>{}</string>
+    <key>CFBundleIdentifier</key>
+    <string>{}</string>
+    <key>CFBundleInfoDictionaryVersion</key>
+    <string>6.0</string>
+    <key>CFBundleName</key>
+    <string>{}</string>
+    <key>CFBundlePackageType</key>
+    <string>APPL</string>
+    <key>CFBundleShortVersionString</key>
+    <string>{}.{}.{}</string>
+    <key>CFBundleVersion</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+            escape_xml(&self.executable_name()),
+            escape_xml(&self.metadata.bundle_identifier),
+            escape_xml(&self.bundle_name()),
+            version.major,
+            version.minor,
+            version.patch,
+            escape_xml(&version.to_string()),
+        );
+
+        let info_plist_path = self.bundle_path().join("Contents").join("Info.plist");
+
+        debug!("Writing `{}`", info_plist_path.display());
+
+        std::fs::write(&info_plist_path, info_plist)
+            .map_err(Error::from_source)
+            .with_context("failed to write `Info.plist`")
+    }
+
+    fn build_binary(&self) -> Result<PathBuf> {
+        build_binaries(self.package, &self.name, &self.metadata.target_runtime, false)?.remove(&self.metadata.binary).ok_or_else(|| {
+            Error::new("failed to find the specified binary in the binaries list")
+                .with_explanation(format!("The configuration requires this application bundle to use the `{}` binary but no such binary is declared in the crate. Was the name perhaps mistyped?", self.metadata.binary))
+        })
+    }
+
+    fn copy_binary(&self, source: &Path) -> Result<()> {
+        debug!("Will now copy the binary");
+
+        let macos_dir = self.bundle_path().join("Contents").join("MacOS");
+
+        std::fs::create_dir_all(&macos_dir)
+            .map_err(Error::from_source)
+            .with_full_context(
+        "could not create the application bundle",
+        format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", macos_dir.display()),
+            )?;
+
+        let target = macos_dir.join(self.executable_name());
+
+        debug!("Copying {} to {}", source.display(), target.display());
+
+        std::fs::copy(&source, target)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to copy binary",
+                format!(
+                    "The binary `{}` could not be copied to the application bundle.",
+                    source.display(),
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    fn executable_name(&self) -> String {
+        self.metadata.binary.clone()
+    }
+
+    fn bundle_name(&self) -> String {
+        self.metadata
+            .bundle_name
+            .clone()
+            .unwrap_or_else(|| self.package.name().to_string())
+    }
+
+    /// The external tools required to sign and package the application
+    /// bundle.
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        if publish || self.context().options().no_image {
+            return vec![];
+        }
+
+        let mut tools = vec![tools::DITTO];
+
+        if self.metadata.signing_identity.is_some() {
+            tools.push(tools::CODESIGN);
+        }
+
+        if self.metadata.notarization.is_some() {
+            tools.push(tools::XCRUN);
+        }
+
+        tools
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.target_dir()
+    }
+
+    fn target_dir(&self) -> PathBuf {
+        self.context()
+            .target_root()
+            .unwrap()
+            .join(&self.metadata.target_runtime)
+            .join(self.context().options().mode.to_string())
+            .join("macos-app")
+            .join(self.package.name())
+    }
+
+    fn bundle_path(&self) -> PathBuf {
+        self.target_dir()
+            .join(format!("{}.app", self.bundle_name()))
+    }
+
+    fn archive_path(&self) -> PathBuf {
+        self.target_dir().join(format!(
+            "{}-{}.zip",
+            self.bundle_name(),
+            self.package.version()
+        ))
+    }
+
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        let resources_dir = self.bundle_path().join("Contents").join("Resources");
+
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &resources_dir,
+        )
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml("My App"), "My App");
+        assert_eq!(
+            escape_xml(r#"Tom & Jerry's <"App">"#),
+            "Tom &amp; Jerry&apos;s &lt;&quot;App&quot;&gt;"
+        );
+    }
+}
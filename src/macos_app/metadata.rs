@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::{AwsCredentialsEnv, S3BucketCreation},
+    condition::Enabled,
+    dist_target::DistTarget,
+    macos_app::MacosAppDistTarget,
+    metadata::CopyCommand,
+    Package,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MacosAppMetadata {
+    pub binary: String,
+    pub bundle_identifier: String,
+    #[serde(default)]
+    pub bundle_name: Option<String>,
+    #[serde(default = "default_target_runtime")]
+    pub target_runtime: String,
+    #[serde(default)]
+    pub extra_files: Vec<CopyCommand>,
+    #[serde(default)]
+    pub signing_identity: Option<String>,
+    #[serde(default)]
+    pub entitlements: Option<PathBuf>,
+    #[serde(default)]
+    pub notarization: Option<MacosNotarization>,
+    pub s3_bucket: String,
+    #[serde(default)]
+    pub s3_bucket_prefix: String,
+    /// Whether to create the S3 bucket if it doesn't exist.
+    #[serde(default)]
+    pub allow_s3_bucket_creation: bool,
+    #[serde(default)]
+    pub s3_bucket_creation: S3BucketCreation,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default)]
+    pub aws_profile: Option<String>,
+    #[serde(default)]
+    pub assume_role_arn: Option<String>,
+    #[serde(default)]
+    pub assume_role_external_id: Option<String>,
+    #[serde(default)]
+    pub credentials_env: Option<AwsCredentialsEnv>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+/// The notarization settings of an application bundle.
+///
+/// Credentials are read from a keychain profile, as created by `xcrun
+/// notarytool store-credentials`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MacosNotarization {
+    pub keychain_profile: String,
+}
+
+fn default_target_runtime() -> String {
+    "x86_64-apple-darwin".to_string()
+}
+
+impl MacosAppMetadata {
+    pub(crate) fn into_dist_target<'g>(
+        self,
+        name: String,
+        package: &'g Package<'g>,
+    ) -> DistTarget<'g> {
+        DistTarget::MacosApp(MacosAppDistTarget {
+            name,
+            package,
+            metadata: self,
+        })
+    }
+}
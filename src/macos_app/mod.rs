@@ -0,0 +1,5 @@
+mod dist_target;
+mod metadata;
+
+pub use dist_target::MacosAppDistTarget;
+pub use metadata::MacosAppMetadata;
@@ -54,44 +54,119 @@
 // crate-specific exceptions:
 #![allow(clippy::too_many_lines)]
 
-use cargo_monorepo::{Context, Mode, Options, Package};
+use cargo_monorepo::{
+    check_license_policy, check_prerequisites, compile_packages, diff_git_refs, exec_packages,
+    fetch_dependencies, init_dist_target, is_github_actions, metadata_schema, print_github_error,
+    publish_packages, run_cargo, run_docker_dist_target, watch, write_compose_file, Context,
+    ExecOptions, ExecOrder, Lockfile, Mode, Options, Package, PublishReport, PublishState,
+    RetentionRules, StagingDirectories, INIT_TARGET_TYPES,
+};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use log::debug;
+use log::{debug, warn};
 use std::{
     env,
     fmt::{Debug, Formatter},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
-use cargo_monorepo::{Error, Result};
+use cargo_monorepo::{Error, ErrorCode, ErrorKind, Result};
 
 const ARG_DEBUG: &str = "debug";
 const ARG_RELEASE: &str = "release";
 const ARG_MANIFEST_PATH: &str = "manifest-path";
+const ARG_TARGET_ROOT: &str = "target-root";
 const ARG_VERBOSE: &str = "verbose";
 const ARG_DRY_RUN: &str = "dry-run";
+const ARG_ERROR_FORMAT: &str = "error-format";
+const ARG_OFFLINE: &str = "offline";
+const ARG_LOCKED: &str = "locked";
+const ARG_FROZEN: &str = "frozen";
+const ARG_LENIENT_METADATA: &str = "lenient-metadata";
+const ARG_OTLP_ENDPOINT: &str = "otlp-endpoint";
 const ARG_FORCE: &str = "force";
 const ARG_PACKAGE: &str = "package";
 const ARG_PACKAGES: &str = "packages";
+const ARG_WORKSPACE: &str = "workspace";
+const ARG_GROUP: &str = "group";
+const ARG_ORDER: &str = "order";
+const ARG_INCLUDE_DEPENDENCIES: &str = "include-dependencies";
+const ARG_INCLUDE_DEPENDENTS: &str = "include-dependents";
+const ARG_LOG_DIR: &str = "log-dir";
+const ARG_IGNORE_FAILURES: &str = "ignore-failures";
 const ARG_CHANGED_SINCE_GIT_REF: &str = "changed-since-git-ref";
+const ARG_CHANGED_FILES_FROM: &str = "changed-files-from";
+const ARG_INCLUDE_UNTRACKED: &str = "include-untracked";
+const ARG_ONLY_DIST: &str = "only-dist";
 const ARG_COMMAND: &str = "command";
 const ARG_REMAINING_ARGS: &str = "remaining-args";
+const ARG_REPORT: &str = "report";
+const ARG_AUDIT_LOG: &str = "audit-log";
+const ARG_CHANNEL: &str = "channel";
+const ARG_ERROR_CODE: &str = "code";
+const ARG_NO_COMPILE: &str = "no-compile";
+const ARG_NO_IMAGE: &str = "no-image";
+const ARG_NO_DEPLOY: &str = "no-deploy";
+const ARG_DIFF_PUBLISHED: &str = "diff-published";
+const ARG_JOBS: &str = "jobs";
+const ARG_ISOLATED_TARGET_DIRS: &str = "isolated-target-dirs";
+const ARG_RESUME: &str = "resume";
+const ARG_LOCK: &str = "lock";
+const ARG_ENVIRONMENT: &str = "environment";
+const ARG_FROM_GIT_REF: &str = "from-git-ref";
+const ARG_TO_GIT_REF: &str = "to-git-ref";
+const ARG_TARGET_TYPE: &str = "target-type";
+const ARG_RESTART_CONTAINER: &str = "restart-container";
+const ARG_OUTPUT: &str = "output";
+const ARG_KEEP_LAST: &str = "keep-last";
+const ARG_MAX_AGE: &str = "max-age";
+const ARG_GC_MAX_AGE: &str = "gc-max-age";
+const ARG_VERSION: &str = "version";
+const ARG_ALLOW_DIRTY: &str = "allow-dirty";
+const ARG_AWS_REGION: &str = "aws-region";
+const ARG_AWS_MAX_ATTEMPTS: &str = "aws-max-attempts";
+const ARG_AWS_ENDPOINT_URL: &str = "aws-endpoint-url";
+const ARG_AWS_PROFILE: &str = "aws-profile";
+const ARG_AWS_ASSUME_ROLE_ARN: &str = "aws-assume-role-arn";
+const ARG_AWS_ASSUME_ROLE_EXTERNAL_ID: &str = "aws-assume-role-external-id";
+const ARG_ENV_PROFILE: &str = "env-profile";
+const ARG_PREVIEW: &str = "preview";
+const ARG_TYPE: &str = "type";
+const ARG_NAME: &str = "name";
 
 const SUB_COMMAND_HASH: &str = "hash";
 const SUB_COMMAND_LIST: &str = "list";
 const SUB_COMMAND_BUILD: &str = "build";
 const SUB_COMMAND_TEST: &str = "test";
 const SUB_COMMAND_CLIPPY: &str = "clippy";
+const SUB_COMMAND_CARGO: &str = "cargo";
+const SUB_COMMAND_FETCH: &str = "fetch";
 const SUB_COMMAND_BUILD_DIST: &str = "build-dist";
 const SUB_COMMAND_PUBLISH_DIST: &str = "publish-dist";
+const SUB_COMMAND_PROMOTE: &str = "promote";
+const SUB_COMMAND_EXISTS: &str = "exists";
 const SUB_COMMAND_EXEC: &str = "exec";
 const SUB_COMMAND_TAG: &str = "tag";
+const SUB_COMMAND_LOCK: &str = "lock";
+const SUB_COMMAND_DIFF: &str = "diff";
+const SUB_COMMAND_WATCH: &str = "watch";
+const SUB_COMMAND_RUN: &str = "run";
+const SUB_COMMAND_COMPOSE: &str = "compose";
+const SUB_COMMAND_VERSIONS: &str = "versions";
+const SUB_COMMAND_LICENSES: &str = "licenses";
+const SUB_COMMAND_PRUNE: &str = "prune";
+const SUB_COMMAND_GC: &str = "gc";
+const SUB_COMMAND_UNTAG: &str = "untag";
+const SUB_COMMAND_RETAG: &str = "retag";
+const SUB_COMMAND_INIT: &str = "init";
+const SUB_COMMAND_SCHEMA: &str = "schema";
+const SUB_COMMAND_EXPLAIN: &str = "explain";
 
-struct MainError(Error);
+struct MainError<'a>(&'a Error);
 
-impl Debug for MainError {
+impl Debug for MainError<'_> {
     fn fmt(&self, _f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut stderr = StandardStream::stderr(ColorChoice::Always);
         writeln!(&mut stderr, "{}", self.0.description()).unwrap();
@@ -137,12 +212,56 @@ impl Debug for MainError {
             write!(&mut stderr, "{}", output).unwrap();
         }
 
+        if let Some(code) = self.0.code() {
+            write!(
+                &mut stderr,
+                "\n\nFor more information about this error, run `cargo monorepo explain {}`.",
+                code
+            )
+            .unwrap();
+        }
+
         Ok(())
     }
 }
 
-fn main() -> std::result::Result<(), MainError> {
-    run().map_err(MainError)
+/// Print an error as a JSON object on a single line, so that CI systems can
+/// tell retryable failures from fatal ones.
+fn print_json_error(err: &Error) {
+    let kind = err.kind();
+    let error = serde_json::json!({
+        "kind": kind,
+        "code": err.code(),
+        "exit_code": kind.exit_code(),
+        "retryable": kind.is_retryable(),
+        "description": err.description(),
+        "source": err.source().map(ToString::to_string),
+        "explanation": err.explanation(),
+        "output": err.output(),
+    });
+
+    eprintln!("{}", error);
+}
+
+#[allow(clippy::exit)]
+fn main() {
+    let matches = get_matches();
+
+    if let Err(err) = run(&matches) {
+        let exit_code = err.kind().exit_code();
+
+        if matches.value_of(ARG_ERROR_FORMAT) == Some("json") {
+            print_json_error(&err);
+        } else {
+            eprintln!("Error: {:?}", MainError(&err));
+        }
+
+        if is_github_actions() {
+            print_github_error(&err);
+        }
+
+        std::process::exit(exit_code);
+    }
 }
 
 trait PackageSelection {
@@ -158,9 +277,16 @@ impl PackageSelection for clap::App<'_, '_> {
                 .takes_value(true)
                 .multiple(true)
                 .require_delimiter(true)
-                .conflicts_with(ARG_CHANGED_SINCE_GIT_REF)
-                .help("A list of packages to execute the command for, separated by commas"),
+                .conflicts_with_all(&[ARG_CHANGED_SINCE_GIT_REF, ARG_CHANGED_FILES_FROM])
+                .help("A list of packages to execute the command for, by name or path, separated by commas"),
+        )
+        .arg(
+            Arg::with_name(ARG_WORKSPACE)
+                .long(ARG_WORKSPACE)
+                .conflicts_with_all(&[ARG_PACKAGES, ARG_CHANGED_SINCE_GIT_REF, ARG_CHANGED_FILES_FROM])
+                .help("Execute the command for all the packages of the workspace, ignoring its `default-members`"),
         )
+        .arg(group_arg().conflicts_with_all(&[ARG_PACKAGES, ARG_WORKSPACE]))
         .arg(
             Arg::with_name(ARG_CHANGED_SINCE_GIT_REF)
                 .long(ARG_CHANGED_SINCE_GIT_REF)
@@ -171,9 +297,49 @@ impl PackageSelection for clap::App<'_, '_> {
                     "Only operate on the packages with changes since the specified Git reference",
                 ),
         )
+        .arg(changed_files_from_arg().conflicts_with(ARG_PACKAGES))
+        .arg(include_untracked_arg())
+        .arg(only_dist_arg())
     }
 }
 
+fn only_dist_arg() -> Arg<'static, 'static> {
+    Arg::with_name(ARG_ONLY_DIST)
+        .long(ARG_ONLY_DIST)
+        .help("Ignore the selected packages without distribution targets, like libraries")
+}
+
+fn include_untracked_arg() -> Arg<'static, 'static> {
+    Arg::with_name(ARG_INCLUDE_UNTRACKED)
+        .long(ARG_INCLUDE_UNTRACKED)
+        .requires(ARG_CHANGED_SINCE_GIT_REF)
+        .help("Count the untracked files that are not ignored as changes, like `git status` does")
+}
+
+fn changed_files_from_arg() -> Arg<'static, 'static> {
+    Arg::with_name(ARG_CHANGED_FILES_FROM)
+        .long(ARG_CHANGED_FILES_FROM)
+        .takes_value(true)
+        .value_name("FILE")
+        .conflicts_with(ARG_CHANGED_SINCE_GIT_REF)
+        .help("Only operate on the packages with changes in the files listed in the specified file, one path per line, or `-` for the standard input")
+}
+
+fn group_arg() -> Arg<'static, 'static> {
+    Arg::with_name(ARG_GROUP)
+        .long(ARG_GROUP)
+        .takes_value(true)
+        .multiple(true)
+        .require_delimiter(true)
+        .help("A list of package groups of the workspace to execute the command for, separated by commas")
+}
+
+fn allow_dirty_arg() -> Arg<'static, 'static> {
+    Arg::with_name(ARG_ALLOW_DIRTY)
+        .long(ARG_ALLOW_DIRTY)
+        .help("Proceed even if the working tree has uncommitted changes affecting the packages")
+}
+
 fn get_matches() -> clap::ArgMatches<'static> {
     let mut args: Vec<String> = std::env::args().collect();
 
@@ -219,6 +385,58 @@ fn get_matches() -> clap::ArgMatches<'static> {
                 .global(true)
                 .help("Do not really push any artifacts"),
         )
+        .arg(
+            Arg::with_name(ARG_TARGET_ROOT)
+                .long(ARG_TARGET_ROOT)
+                .takes_value(true)
+                .value_name("path")
+                .global(true)
+                .help("The directory in which the distributable artifacts are staged, instead of the Cargo target directory"),
+        )
+        .arg(
+            Arg::with_name(ARG_OFFLINE)
+                .long(ARG_OFFLINE)
+                .required(false)
+                .global(true)
+                .help("Forbid network access: Cargo runs offline and the commands that need the network fail"),
+        )
+        .arg(
+            Arg::with_name(ARG_LOCKED)
+                .long(ARG_LOCKED)
+                .required(false)
+                .global(true)
+                .help("Require Cargo.lock to be up-to-date"),
+        )
+        .arg(
+            Arg::with_name(ARG_FROZEN)
+                .long(ARG_FROZEN)
+                .required(false)
+                .global(true)
+                .help("Require Cargo.lock to be up-to-date and Cargo not to access the network"),
+        )
+        .arg(
+            Arg::with_name(ARG_LENIENT_METADATA)
+                .long(ARG_LENIENT_METADATA)
+                .required(false)
+                .global(true)
+                .help("Ignore the unknown fields of the monorepo metadata with a warning instead of failing"),
+        )
+        .arg(
+            Arg::with_name(ARG_OTLP_ENDPOINT)
+                .long(ARG_OTLP_ENDPOINT)
+                .takes_value(true)
+                .global(true)
+                .help("Export traces of the build and publication phases to the OpenTelemetry collector at the specified OTLP endpoint - requires the `otlp` feature"),
+        )
+        .arg(
+            Arg::with_name(ARG_ERROR_FORMAT)
+                .long(ARG_ERROR_FORMAT)
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .global(true)
+                .help("The format of the error reported on failure; `json` includes the kind of the error, which determines the exit code"),
+        )
         .arg(
             Arg::with_name(ARG_FORCE)
                 .short("f")
@@ -227,6 +445,84 @@ fn get_matches() -> clap::ArgMatches<'static> {
                 .global(true)
                 .help("Push artifacts even if they already exist - this can be dangerous"),
         )
+        .arg(
+            Arg::with_name(ARG_AWS_REGION)
+                .long(ARG_AWS_REGION)
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .help("The AWS region to use for targets that don't specify one - defaults to the region of the environment"),
+        )
+        .arg(
+            Arg::with_name(ARG_AWS_ENDPOINT_URL)
+                .long(ARG_AWS_ENDPOINT_URL)
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .env("CARGO_MONOREPO_AWS_ENDPOINT_URL")
+                .help("A custom endpoint URL to use for all AWS services, for instance to target LocalStack"),
+        )
+        .arg(
+            Arg::with_name(ARG_AWS_PROFILE)
+                .long(ARG_AWS_PROFILE)
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .help("The AWS profile to use for targets that don't specify one"),
+        )
+        .arg(
+            Arg::with_name(ARG_AWS_ASSUME_ROLE_ARN)
+                .long(ARG_AWS_ASSUME_ROLE_ARN)
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .help("The ARN of an AWS role to assume for targets that don't specify one"),
+        )
+        .arg(
+            Arg::with_name(ARG_AWS_ASSUME_ROLE_EXTERNAL_ID)
+                .long(ARG_AWS_ASSUME_ROLE_EXTERNAL_ID)
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .help("The external ID to use when assuming an AWS role"),
+        )
+        .arg(
+            Arg::with_name(ARG_AWS_MAX_ATTEMPTS)
+                .long(ARG_AWS_MAX_ATTEMPTS)
+                .takes_value(true)
+                .required(false)
+                .global(true)
+                .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|err| err.to_string()))
+                .help("The maximum number of attempts for AWS requests, including the initial one"),
+        )
+        .arg(
+            Arg::with_name(ARG_ENV_PROFILE)
+                .long(ARG_ENV_PROFILE)
+                .takes_value(true)
+                .value_name("environment")
+                .required(false)
+                .global(true)
+                .env("CARGO_MONOREPO_ENV_PROFILE")
+                .help("The environment whose destinations to use for the targets that declare several (e.g. `staging` or `production`)"),
+        )
+        .arg(
+            Arg::with_name(ARG_PREVIEW)
+                .long(ARG_PREVIEW)
+                .takes_value(true)
+                .value_name("id")
+                .required(false)
+                .global(true)
+                .validator(|v| {
+                    if !v.is_empty()
+                        && v.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                    {
+                        Ok(())
+                    } else {
+                        Err("must be non-empty and only contain ASCII letters, digits, `-` and `_`".to_string())
+                    }
+                })
+                .help("Suffix the Docker image tags and S3 prefixes with the identifier of a preview environment (e.g. `pr-42`)"),
+        )
         .arg(
             Arg::with_name(ARG_MANIFEST_PATH)
                 .short("m")
@@ -252,17 +548,235 @@ fn get_matches() -> clap::ArgMatches<'static> {
                             "Only list the packages with changes since the specified Git reference",
                         ),
                 )
+                .arg(changed_files_from_arg())
+                .arg(include_untracked_arg())
+                .arg(only_dist_arg())
+                .arg(group_arg())
+                .arg(
+                    Arg::with_name(ARG_OUTPUT)
+                        .long(ARG_OUTPUT)
+                        .takes_value(true)
+                        .possible_values(&["names", "json", "github-matrix"])
+                        .default_value("names")
+                        .help("The output format: `json` lists the name, version, hash and distribution target types of the packages, and `github-matrix` lists them as a GitHub Actions matrix"),
+                )
                 .about("List all the packages in the current workspace"),
         )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_DIFF)
+                .about("Compare the hashes of the packages between two Git references and list the distribution targets that would be published again")
+                .arg(
+                    Arg::with_name(ARG_FROM_GIT_REF)
+                        .required(true)
+                        .help("The Git reference to compare from"),
+                )
+                .arg(
+                    Arg::with_name(ARG_TO_GIT_REF)
+                        .required(true)
+                        .help("The Git reference to compare to"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_FETCH)
+                .about("Download the dependencies needed to build the distribution targets of the specified packages, so that they can be built with `--offline`")
+                .with_package_selection(),
+        )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_BUILD_DIST)
                 .about("Build the distributable artifacts for the specified packages")
                 .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_NO_COMPILE)
+                        .long(ARG_NO_COMPILE)
+                        .help("Do not compile the packages and reuse the binaries of a previous build instead"),
+                )
+                .arg(
+                    Arg::with_name(ARG_NO_IMAGE)
+                        .long(ARG_NO_IMAGE)
+                        .help("Stop after staging the files of the distributable artifacts, without building images or archives"),
+                )
+                .arg(
+                    Arg::with_name(ARG_DIFF_PUBLISHED)
+                        .long(ARG_DIFF_PUBLISHED)
+                        .conflicts_with(ARG_NO_IMAGE)
+                        .help("Pull the latest published version of each Docker image and report how the built image differs from it"),
+                )
+                .arg(
+                    Arg::with_name(ARG_JOBS)
+                        .short("j")
+                        .long(ARG_JOBS)
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|err| err.to_string()))
+                        .help("The number of parallel compilation jobs; with more than one, the target runtimes of a package are compiled together"),
+                )
+                .arg(
+                    Arg::with_name(ARG_ISOLATED_TARGET_DIRS)
+                        .long(ARG_ISOLATED_TARGET_DIRS)
+                        .help("Compile each distribution target in its own Cargo target directory, so that concurrent builds do not clobber each other's artifacts"),
+                )
+                .arg(
+                    Arg::with_name(ARG_GC_MAX_AGE)
+                        .long(ARG_GC_MAX_AGE)
+                        .takes_value(true)
+                        .value_name("days")
+                        .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                        .help("After building, remove the staging directories that were not used for this number of days"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_GC)
+                .about("Report the space used by the staging directories of the distributable artifacts and remove the stale ones")
+                .arg(
+                    Arg::with_name(ARG_MAX_AGE)
+                        .long(ARG_MAX_AGE)
+                        .takes_value(true)
+                        .value_name("days")
+                        .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                        .help("Remove the staging directories that were not used for this number of days"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_WATCH)
+                .about("Watch the sources of a package and rebuild its distributable artifacts - without publishing them - whenever they change")
+                .arg(
+                    Arg::with_name(ARG_PACKAGE)
+                        .long(ARG_PACKAGE)
+                        .short("p")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The package to watch"),
+                )
+                .arg(
+                    Arg::with_name(ARG_TARGET_TYPE)
+                        .long(ARG_TARGET_TYPE)
+                        .takes_value(true)
+                        .help("Only rebuild the distribution targets of the specified type (e.g. `docker`)"),
+                )
+                .arg(
+                    Arg::with_name(ARG_RESTART_CONTAINER)
+                        .long(ARG_RESTART_CONTAINER)
+                        .takes_value(true)
+                        .value_name("name")
+                        .help("Replace the Docker container with the specified name after each successful rebuild of a Docker image"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_RUN)
+                .about("Build the Docker image of a package and run it locally with the ports, environment variables and volumes declared in its metadata")
+                .arg(
+                    Arg::with_name(ARG_PACKAGE)
+                        .long(ARG_PACKAGE)
+                        .short("p")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The package to run"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_COMPOSE)
+                .about("Generate a Docker Compose file referencing the locally-built Docker images of the specified packages")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_OUTPUT)
+                        .long(ARG_OUTPUT)
+                        .short("o")
+                        .takes_value(true)
+                        .value_name("path")
+                        .default_value("docker-compose.yaml")
+                        .help("The path of the Docker Compose file to write"),
+                ),
         )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_PUBLISH_DIST)
                 .about("Publish the distributable artifacts for the specified packages")
                 .with_package_selection()
+                .arg(allow_dirty_arg())
+                .arg(
+                    Arg::with_name(ARG_REPORT)
+                        .long(ARG_REPORT)
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("Write a JSON report of the published artifacts to the specified file"),
+                )
+                .arg(
+                    Arg::with_name(ARG_AUDIT_LOG)
+                        .long(ARG_AUDIT_LOG)
+                        .takes_value(true)
+                        .value_name("path")
+                        .help("Append the publication actions to the specified JSON Lines audit log, instead of the configured one"),
+                )
+                .arg(
+                    Arg::with_name(ARG_RESUME)
+                        .long(ARG_RESUME)
+                        .help("Skip the distribution targets that were published successfully by the previous run"),
+                )
+                .arg(
+                    Arg::with_name(ARG_LOCK)
+                        .long(ARG_LOCK)
+                        .takes_value(true)
+                        .value_name("environment")
+                        .help("Record the published artifacts in `monorepo.lock` for the specified environment"),
+                )
+                .arg(
+                    Arg::with_name(ARG_NO_DEPLOY)
+                        .long(ARG_NO_DEPLOY)
+                        .help("Upload the AWS Lambda archives without updating the functions of their `deployment`"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_VERSIONS)
+                .about("List the versions of the distributable artifacts of the specified packages that exist in their destinations")
+                .with_package_selection()
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_LICENSES)
+                .about("List the licenses of the dependencies shipped with the distribution targets of the specified packages, failing if any is not allowed by the license policy of the workspace")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_OUTPUT)
+                        .long(ARG_OUTPUT)
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("The output format: `json` lists the dependencies of each distribution target with their license"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_PRUNE)
+                .about("Delete the published versions of the distributable artifacts of the specified packages that are not retained, keeping the current and tagged versions")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_KEEP_LAST)
+                        .long(ARG_KEEP_LAST)
+                        .takes_value(true)
+                        .value_name("count")
+                        .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|err| err.to_string()))
+                        .help("Keep the last N published versions"),
+                )
+                .arg(
+                    Arg::with_name(ARG_MAX_AGE)
+                        .long(ARG_MAX_AGE)
+                        .takes_value(true)
+                        .value_name("days")
+                        .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                        .help("Keep the versions published less than this number of days ago"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_EXISTS)
+                .about("Check whether the distributable artifacts of the current version of the specified packages exist, exiting with a non-zero code if any is missing")
+                .with_package_selection()
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_PROMOTE)
+                .about("Promote the published artifacts of the current version of the specified packages to a channel, without rebuilding them")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_CHANNEL)
+                        .required(true)
+                        .help("The channel to promote the artifacts to (e.g. `latest` or `stable`)"),
+                ),
         )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_BUILD)
@@ -300,10 +814,52 @@ fn get_matches() -> clap::ArgMatches<'static> {
                         .help("Invoke `cargo clippy` with these arguments"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_CARGO)
+                .about("Run a Cargo command once for the specified packages, passing them as `-p` flags")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_REMAINING_ARGS)
+                        .value_name("[cargo arguments]")
+                        .required(true)
+                        .allow_hyphen_values(true)
+                        .multiple(true)
+                        .help("The Cargo command to run, like `clippy --all-targets`"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_EXEC)
                 .about("Execute a command in each of the specified packages directory or for all packages if no packages are specified")
                 .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_ORDER)
+                        .long(ARG_ORDER)
+                        .takes_value(true)
+                        .possible_values(&["selection", "topo", "reverse-topo"])
+                        .default_value("selection")
+                        .help("The order of execution: `topo` executes the command in the packages after the packages they depend on, `reverse-topo` before them"),
+                )
+                .arg(
+                    Arg::with_name(ARG_INCLUDE_DEPENDENCIES)
+                        .long(ARG_INCLUDE_DEPENDENCIES)
+                        .help("Also execute the command in the workspace packages the selected packages depend on"),
+                )
+                .arg(
+                    Arg::with_name(ARG_INCLUDE_DEPENDENTS)
+                        .long(ARG_INCLUDE_DEPENDENTS)
+                        .help("Also execute the command in the workspace packages that depend on the selected packages"),
+                )
+                .arg(
+                    Arg::with_name(ARG_LOG_DIR)
+                        .long(ARG_LOG_DIR)
+                        .takes_value(true)
+                        .help("Write the output of the command in each package to `<log-dir>/<package>.log` and only print a summary"),
+                )
+                .arg(
+                    Arg::with_name(ARG_IGNORE_FAILURES)
+                        .long(ARG_IGNORE_FAILURES)
+                        .help("Succeed even if the command fails in some packages"),
+                )
                 .arg(
                     Arg::with_name(ARG_COMMAND)
                         .required(true)
@@ -312,10 +868,78 @@ fn get_matches() -> clap::ArgMatches<'static> {
                         .help("The command to execute in each package"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_LOCK)
+                .about("Regenerate the `monorepo.lock` entries of the specified packages for an environment from their current versions")
+                .with_package_selection()
+                .arg(
+                    Arg::with_name(ARG_ENVIRONMENT)
+                        .required(true)
+                        .help("The environment to lock the artifacts for (e.g. `staging` or `production`)"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name(SUB_COMMAND_TAG)
                 .about("Tag the current version of the package")
-                .arg(Arg::with_name(ARG_PACKAGE).help("A package to tag").required(true)),
+                .arg(Arg::with_name(ARG_PACKAGE).help("A package to tag").required(true))
+                .arg(allow_dirty_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_RETAG)
+                .about("Replace the tag of the current version of the package with its current hash (requires `--force`)")
+                .arg(Arg::with_name(ARG_PACKAGE).help("A package to re-tag").required(true))
+                .arg(allow_dirty_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_UNTAG)
+                .about("Remove the tag of a version of the package (requires `--force`)")
+                .arg(Arg::with_name(ARG_PACKAGE).help("A package to untag").required(true))
+                .arg(
+                    Arg::with_name(ARG_VERSION)
+                        .long(ARG_VERSION)
+                        .takes_value(true)
+                        .validator(|v| v.parse::<semver::Version>().map(|_| ()).map_err(|err| err.to_string()))
+                        .help("The version to untag, instead of the current version"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_SCHEMA)
+                .about("Print the JSON schema of the `[package.metadata.monorepo]` section of the manifests"),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_EXPLAIN)
+                .about("Print the extended guidance of an error code, or list the error codes")
+                .arg(
+                    Arg::with_name(ARG_ERROR_CODE)
+                        .validator(|v| v.parse::<ErrorCode>().map(|_| ()))
+                        .help("The error code to explain (e.g. `MR0012`)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(SUB_COMMAND_INIT)
+                .about("Add a starter distribution target to the manifest of a package")
+                .arg(
+                    Arg::with_name(ARG_PACKAGE)
+                        .long(ARG_PACKAGE)
+                        .short("p")
+                        .takes_value(true)
+                        .required(true)
+                        .help("The package to add the distribution target to, by name or path"),
+                )
+                .arg(
+                    Arg::with_name(ARG_TYPE)
+                        .long(ARG_TYPE)
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(INIT_TARGET_TYPES)
+                        .help("The type of the distribution target"),
+                )
+                .arg(
+                    Arg::with_name(ARG_NAME)
+                        .long(ARG_NAME)
+                        .takes_value(true)
+                        .help("The name of the distribution target, which defaults to the name of the package"),
+                ),
         )
         .get_matches_from(args)
 }
@@ -373,27 +997,145 @@ fn make_options(matches: &ArgMatches<'_>) -> Options {
         }
     }
 
+    let build_dist_matches = matches.subcommand_matches(SUB_COMMAND_BUILD_DIST);
+    let publish_dist_matches = matches.subcommand_matches(SUB_COMMAND_PUBLISH_DIST);
+
     Options {
         dry_run: matches.is_present(ARG_DRY_RUN),
         force: matches.is_present(ARG_FORCE),
         verbose: matches.is_present(ARG_VERBOSE),
         mode,
+        no_compile: build_dist_matches.map_or(false, |m| m.is_present(ARG_NO_COMPILE)),
+        no_image: build_dist_matches.map_or(false, |m| m.is_present(ARG_NO_IMAGE)),
+        no_deploy: publish_dist_matches.map_or(false, |m| m.is_present(ARG_NO_DEPLOY)),
+        diff_published: build_dist_matches.map_or(false, |m| m.is_present(ARG_DIFF_PUBLISHED)),
+        jobs: build_dist_matches
+            .and_then(|m| m.value_of(ARG_JOBS))
+            .map(|v| v.parse().unwrap()),
+        allow_dirty: matches
+            .subcommand()
+            .1
+            .map_or(false, |m| m.is_present(ARG_ALLOW_DIRTY)),
+        offline: matches.is_present(ARG_OFFLINE),
+        locked: matches.is_present(ARG_LOCKED),
+        frozen: matches.is_present(ARG_FROZEN),
+        aws_region: matches.value_of(ARG_AWS_REGION).map(ToString::to_string),
+        aws_endpoint_url: matches
+            .value_of(ARG_AWS_ENDPOINT_URL)
+            .map(ToString::to_string),
+        aws_profile: matches.value_of(ARG_AWS_PROFILE).map(ToString::to_string),
+        aws_assume_role_arn: matches
+            .value_of(ARG_AWS_ASSUME_ROLE_ARN)
+            .map(ToString::to_string),
+        aws_assume_role_external_id: matches
+            .value_of(ARG_AWS_ASSUME_ROLE_EXTERNAL_ID)
+            .map(ToString::to_string),
+        aws_max_attempts: matches
+            .value_of(ARG_AWS_MAX_ATTEMPTS)
+            .map(|v| v.parse().unwrap()),
+        env_profile: matches.value_of(ARG_ENV_PROFILE).map(ToString::to_string),
+        preview: matches.value_of(ARG_PREVIEW).map(ToString::to_string),
+        audit_log: publish_dist_matches
+            .and_then(|m| m.value_of(ARG_AUDIT_LOG))
+            .map(PathBuf::from),
+        lenient_metadata: matches.is_present(ARG_LENIENT_METADATA),
+        include_untracked: matches
+            .subcommand()
+            .1
+            .map_or(false, |m| m.is_present(ARG_INCLUDE_UNTRACKED)),
+        // Relative paths are resolved now, as some commands run in other
+        // directories.
+        target_root: matches.value_of(ARG_TARGET_ROOT).map(|target_root| {
+            env::current_dir()
+                .map_or_else(|_| PathBuf::from(target_root), |cwd| cwd.join(target_root))
+        }),
+        isolated_target_dirs: build_dist_matches
+            .map_or(false, |m| m.is_present(ARG_ISOLATED_TARGET_DIRS)),
     }
 }
 
+/// Parse a number of days validated by the command line parser.
+fn parse_days(value: &str) -> Duration {
+    Duration::from_secs(value.parse::<u64>().unwrap() * 24 * 3600)
+}
+
 fn select_packages<'g>(context: &'g Context, matches: &ArgMatches<'_>) -> Result<Vec<Package<'g>>> {
-    match matches.value_of(ARG_CHANGED_SINCE_GIT_REF) {
-        Some(git_ref) => context.resolve_changed_packages(git_ref),
+    let packages = match select_changed_packages(context, matches)? {
+        Some(packages) => filter_groups(context, matches, packages)?,
         None => match matches.values_of(ARG_PACKAGES) {
-            Some(packages_names) => context.resolve_packages_by_names(packages_names),
-            None => context.packages(),
+            Some(packages_names) => context.resolve_packages_by_names(packages_names)?,
+            None if matches.is_present(ARG_WORKSPACE) => context.packages()?,
+            None => match matches.values_of(ARG_GROUP) {
+                Some(groups) => context.resolve_groups(groups)?,
+                None => context.default_packages()?,
+            },
         },
+    };
+
+    Ok(filter_only_dist(matches, packages))
+}
+
+/// Only keep the packages with distribution targets if `--only-dist` was
+/// specified.
+fn filter_only_dist<'g>(matches: &ArgMatches<'_>, packages: Vec<Package<'g>>) -> Vec<Package<'g>> {
+    if !matches.is_present(ARG_ONLY_DIST) {
+        return packages;
     }
+
+    packages
+        .into_iter()
+        .filter(|package| {
+            let has_dist_targets = package.has_dist_targets();
+
+            if !has_dist_targets {
+                debug!(
+                    "Ignoring {} as it has no distribution targets",
+                    package.name()
+                );
+            }
+
+            has_dist_targets
+        })
+        .collect()
 }
 
-fn run() -> Result<()> {
-    let matches = get_matches();
+/// The changed packages, if `--changed-since-git-ref` or
+/// `--changed-files-from` was specified.
+fn select_changed_packages<'g>(
+    context: &'g Context,
+    matches: &ArgMatches<'_>,
+) -> Result<Option<Vec<Package<'g>>>> {
+    if let Some(git_ref) = matches.value_of(ARG_CHANGED_SINCE_GIT_REF) {
+        return context.resolve_changed_packages(git_ref).map(Some);
+    }
+
+    if let Some(path) = matches.value_of(ARG_CHANGED_FILES_FROM) {
+        return context
+            .resolve_changed_packages_from_file(Path::new(path))
+            .map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Only keep the packages of the groups specified with `--group`, if any.
+fn filter_groups<'g>(
+    context: &'g Context,
+    matches: &ArgMatches<'_>,
+    packages: Vec<Package<'g>>,
+) -> Result<Vec<Package<'g>>> {
+    let groups = match matches.values_of(ARG_GROUP) {
+        Some(groups) => context.resolve_groups(groups)?,
+        None => return Ok(packages),
+    };
 
+    Ok(packages
+        .into_iter()
+        .filter(|package| groups.iter().any(|member| member.name() == package.name()))
+        .collect())
+}
+
+fn run(matches: &ArgMatches<'static>) -> Result<()> {
     let mut log_level = log::LevelFilter::Off;
 
     if matches.is_present(ARG_DEBUG) {
@@ -404,7 +1146,52 @@ fn run() -> Result<()> {
 
     debug!("Log level set to: {}", log_level);
 
-    let context = make_context(&matches)?;
+    #[cfg(feature = "otlp")]
+    let _telemetry = matches
+        .value_of(ARG_OTLP_ENDPOINT)
+        .map(cargo_monorepo::Telemetry::init)
+        .transpose()?;
+
+    #[cfg(not(feature = "otlp"))]
+    if matches.is_present(ARG_OTLP_ENDPOINT) {
+        return Err(Error::new("OpenTelemetry support disabled")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(
+                "`cargo monorepo` must be built with the `otlp` feature to export traces.",
+            ));
+    }
+
+    let span = tracing::info_span!(
+        "cargo-monorepo",
+        command = matches.subcommand_name().unwrap_or_default()
+    );
+    let _entered = span.enter();
+
+    // The schema does not depend on the workspace.
+    if matches.subcommand_name() == Some(SUB_COMMAND_SCHEMA) {
+        println!("{}", metadata_schema());
+
+        return Ok(());
+    }
+
+    if let (SUB_COMMAND_EXPLAIN, Some(sub_matches)) = matches.subcommand() {
+        match sub_matches.value_of(ARG_ERROR_CODE) {
+            Some(code) => {
+                let code: ErrorCode = code.parse().unwrap();
+
+                println!("{}: {}\n\n{}", code, code.title(), code.guidance());
+            }
+            None => {
+                for code in ErrorCode::ALL {
+                    println!("{}: {}", code, code.title());
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    let context = make_context(matches)?;
 
     match matches.subcommand() {
         (SUB_COMMAND_HASH, Some(sub_matches)) => {
@@ -417,31 +1204,325 @@ fn run() -> Result<()> {
             Ok(())
         }
         (SUB_COMMAND_LIST, Some(sub_matches)) => {
-            let packages = match sub_matches.value_of(ARG_CHANGED_SINCE_GIT_REF) {
-                Some(git_ref) => context.resolve_changed_packages(git_ref)?,
+            let packages = match select_changed_packages(&context, sub_matches)? {
+                Some(packages) => packages,
                 None => context.packages()?,
             };
+            let packages = filter_groups(&context, sub_matches, packages)?;
+            let packages = filter_only_dist(sub_matches, packages);
 
-            for package in packages {
-                println!("{}", package.name());
+            match sub_matches.value_of(ARG_OUTPUT) {
+                Some("json") => {
+                    let summaries = packages
+                        .iter()
+                        .map(Package::summary)
+                        .collect::<Result<Vec<_>>>()?;
+
+                    println!("{}", serde_json::to_string_pretty(&summaries).unwrap());
+                }
+                Some("github-matrix") => {
+                    let summaries = packages
+                        .iter()
+                        .map(Package::summary)
+                        .collect::<Result<Vec<_>>>()?;
+
+                    println!("{}", serde_json::json!({ "include": summaries }));
+                }
+                _ => {
+                    for package in packages {
+                        println!("{}", package.name());
+                    }
+                }
+            }
+
+            Ok(())
+        }
+        (SUB_COMMAND_DIFF, Some(sub_matches)) => {
+            let from = sub_matches.value_of(ARG_FROM_GIT_REF).unwrap();
+            let to = sub_matches.value_of(ARG_TO_GIT_REF).unwrap();
+
+            for package_diff in diff_git_refs(&context, from, to)? {
+                println!("{}", package_diff);
+
+                for dist_target in package_diff.republished_dist_targets() {
+                    println!("  would publish {}", dist_target);
+                }
             }
 
             Ok(())
         }
+        (SUB_COMMAND_FETCH, Some(sub_matches)) => {
+            context.ensure_online("fetching dependencies")?;
+
+            let packages = select_packages(&context, sub_matches)?;
+
+            fetch_dependencies(&context, &packages)
+        }
         (SUB_COMMAND_BUILD_DIST, Some(sub_matches)) => {
             let packages = select_packages(&context, sub_matches)?;
 
+            check_prerequisites(&packages, false)?;
+
+            // Report the missing dependencies before building anything.
+            if context.options().offline && !context.options().no_compile {
+                fetch_dependencies(&context, &packages)?;
+            }
+
+            context.reset_build_cache_stats()?;
+
+            compile_packages(&context, &packages)?;
+
             for package in packages {
                 package.build_dist_targets()?;
             }
 
+            context.report_build_cache_stats()?;
+
+            if let Some(max_age) = sub_matches.value_of(ARG_GC_MAX_AGE) {
+                StagingDirectories::load(&context)?.collect_garbage(parse_days(max_age))?;
+            }
+
+            Ok(())
+        }
+        (SUB_COMMAND_GC, Some(sub_matches)) => {
+            let mut staging_directories = StagingDirectories::load(&context)?;
+
+            staging_directories.report()?;
+
+            if let Some(max_age) = sub_matches.value_of(ARG_MAX_AGE) {
+                staging_directories.collect_garbage(parse_days(max_age))?;
+            }
+
             Ok(())
         }
+        (SUB_COMMAND_WATCH, Some(sub_matches)) => {
+            let package_name = sub_matches.value_of(ARG_PACKAGE).unwrap();
+            let package = context.resolve_package_by_name(package_name)?;
+
+            watch(
+                &package,
+                sub_matches.value_of(ARG_TARGET_TYPE),
+                sub_matches.value_of(ARG_RESTART_CONTAINER),
+            )
+        }
+        (SUB_COMMAND_RUN, Some(sub_matches)) => {
+            let package_name = sub_matches.value_of(ARG_PACKAGE).unwrap();
+            let package = context.resolve_package_by_name(package_name)?;
+
+            run_docker_dist_target(&package)
+        }
+        (SUB_COMMAND_COMPOSE, Some(sub_matches)) => {
+            let packages = select_packages(&context, sub_matches)?;
+            let output = sub_matches.value_of(ARG_OUTPUT).unwrap();
+
+            write_compose_file(&packages, Path::new(output))
+        }
         (SUB_COMMAND_PUBLISH_DIST, Some(sub_matches)) => {
+            context.ensure_online("publishing")?;
+
+            let packages = select_packages(&context, sub_matches)?;
+            let mut report = PublishReport::default();
+
+            for package in &packages {
+                package.ensure_clean_working_tree()?;
+            }
+
+            check_prerequisites(&packages, true)?;
+
+            let mut state = if sub_matches.is_present(ARG_RESUME) {
+                PublishState::resume(&context)?
+            } else {
+                PublishState::new(&context)?
+            };
+
+            if context.options().force {
+                debug!(
+                    "`--force` specified: not checking for artifacts existence before publishing"
+                );
+            } else {
+                context.prefetch_dist_targets_existence(&packages)?;
+            }
+
+            let lock_environment = sub_matches.value_of(ARG_LOCK);
+            let mut lockfile = match lock_environment {
+                Some(_) => Some(Lockfile::load(&context)?),
+                None => None,
+            };
+
+            context.reset_build_cache_stats()?;
+
+            let result: Result<()> = (|| {
+                let publication = publish_packages(&packages, &mut state)?;
+
+                // The artifacts published before a failure are recorded too,
+                // as they exist in their destinations.
+                let outputs: Result<()> = (|| {
+                    for (package, artifacts) in packages.iter().zip(publication.artifacts) {
+                        if let (Some(environment), Some(lockfile)) =
+                            (lock_environment, &mut lockfile)
+                        {
+                            lockfile.record(environment, package, &artifacts)?;
+                        }
+
+                        report.artifacts.extend(artifacts);
+                    }
+
+                    context.report_build_cache_stats()?;
+
+                    if let Some(lockfile) = lockfile {
+                        lockfile.save(&context)?;
+                    }
+
+                    if let Some(report_path) = sub_matches.value_of(ARG_REPORT) {
+                        report.write_to(Path::new(report_path))?;
+                    }
+
+                    Ok(())
+                })();
+
+                match publication.error {
+                    Some(err) => {
+                        if let Err(outputs_err) = outputs {
+                            warn!(
+                                "failed to record the published artifacts: {}",
+                                outputs_err.description()
+                            );
+                        }
+
+                        Err(err)
+                    }
+                    None => outputs,
+                }
+            })();
+
+            // The audit log entries are uploaded even if the publication
+            // failed, as some artifacts may have been published.
+            let audit_log_upload = context.upload_audit_log();
+            let notification =
+                context.notify_publication(&report, result.as_ref().err(), lock_environment);
+
+            match result {
+                // The artifacts are published at this point: a failed
+                // notification must not fail the publication.
+                Ok(()) => {
+                    if let Err(notification_err) = notification {
+                        warn!(
+                            "failed to send publication notification: {}",
+                            notification_err.description()
+                        );
+                    }
+
+                    audit_log_upload
+                }
+                Err(err) => {
+                    if let Err(audit_log_upload_err) = audit_log_upload {
+                        warn!(
+                            "failed to upload audit log: {}",
+                            audit_log_upload_err.description()
+                        );
+                    }
+
+                    if let Err(notification_err) = notification {
+                        warn!(
+                            "failed to send publication notification: {}",
+                            notification_err.description()
+                        );
+                    }
+
+                    Err(err)
+                }
+            }
+        }
+        (SUB_COMMAND_EXISTS, Some(sub_matches)) => {
+            context.ensure_online("checking the existence of artifacts")?;
+
+            let packages = select_packages(&context, sub_matches)?;
+            let mut missing_packages = Vec::new();
+
+            context.prefetch_dist_targets_existence(&packages)?;
+
+            for package in packages {
+                if !package.dist_targets_exist()? {
+                    missing_packages.push(package.name().to_string());
+                }
+            }
+
+            if missing_packages.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::new("some artifacts do not exist")
+                    .with_kind(ErrorKind::Validation)
+                    .with_explanation(format!(
+                        "The artifacts of the following packages are missing: {}",
+                        missing_packages.join(", ")
+                    )))
+            }
+        }
+        (SUB_COMMAND_VERSIONS, Some(sub_matches)) => {
+            context.ensure_online("listing published versions")?;
+
             let packages = select_packages(&context, sub_matches)?;
 
             for package in packages {
-                package.publish_dist_targets()?;
+                package.list_published_versions()?;
+            }
+
+            Ok(())
+        }
+        (SUB_COMMAND_LICENSES, Some(sub_matches)) => {
+            let packages = select_packages(&context, sub_matches)?;
+
+            let mut inventories = Vec::new();
+
+            for package in packages {
+                inventories.extend(package.license_inventories()?);
+            }
+
+            match sub_matches.value_of(ARG_OUTPUT) {
+                Some("json") => {
+                    println!("{}", serde_json::to_string_pretty(&inventories).unwrap());
+                }
+                _ => {
+                    for inventory in &inventories {
+                        println!("{}", inventory.dist_target);
+
+                        for dependency in &inventory.dependencies {
+                            println!("  {}", dependency);
+                        }
+                    }
+                }
+            }
+
+            check_license_policy(&inventories)
+        }
+        (SUB_COMMAND_PRUNE, Some(sub_matches)) => {
+            context.ensure_online("pruning published versions")?;
+
+            let rules = RetentionRules {
+                keep_last: sub_matches
+                    .value_of(ARG_KEEP_LAST)
+                    .map(|v| v.parse().unwrap()),
+                max_age: sub_matches.value_of(ARG_MAX_AGE).map(parse_days),
+            };
+
+            rules.validate()?;
+
+            let packages = select_packages(&context, sub_matches)?;
+
+            for package in packages {
+                package.prune_dist_targets(&rules)?;
+            }
+
+            Ok(())
+        }
+        (SUB_COMMAND_PROMOTE, Some(sub_matches)) => {
+            context.ensure_online("promoting artifacts")?;
+
+            let packages = select_packages(&context, sub_matches)?;
+            let channel = sub_matches.value_of(ARG_CHANNEL).unwrap();
+
+            for package in packages {
+                package.promote_dist_targets(channel)?;
             }
 
             Ok(())
@@ -451,6 +1532,7 @@ fn run() -> Result<()> {
 
             let args: Vec<&str> = vec!["cargo", "build"]
                 .into_iter()
+                .chain(context.options().cargo_args())
                 .chain(
                     sub_matches
                         .values_of(ARG_REMAINING_ARGS)
@@ -469,6 +1551,7 @@ fn run() -> Result<()> {
 
             let args: Vec<&str> = vec!["cargo", "test"]
                 .into_iter()
+                .chain(context.options().cargo_args())
                 .chain(
                     sub_matches
                         .values_of(ARG_REMAINING_ARGS)
@@ -487,6 +1570,7 @@ fn run() -> Result<()> {
 
             let args: Vec<&str> = vec!["cargo", "clippy"]
                 .into_iter()
+                .chain(context.options().cargo_args())
                 .chain(
                     sub_matches
                         .values_of(ARG_REMAINING_ARGS)
@@ -500,23 +1584,81 @@ fn run() -> Result<()> {
 
             Ok(())
         }
+        (SUB_COMMAND_CARGO, Some(sub_matches)) => {
+            let packages = select_packages(&context, sub_matches)?;
+            let args: Vec<&str> = sub_matches.values_of(ARG_REMAINING_ARGS).unwrap().collect();
+
+            run_cargo(&context, &packages, &args)
+        }
         (SUB_COMMAND_EXEC, Some(sub_matches)) => {
             let packages = select_packages(&context, sub_matches)?;
 
             let args: Vec<&str> = sub_matches.values_of(ARG_COMMAND).unwrap().collect();
+            let options = ExecOptions {
+                order: match sub_matches.value_of(ARG_ORDER) {
+                    Some("topo") => ExecOrder::Topological,
+                    Some("reverse-topo") => ExecOrder::ReverseTopological,
+                    _ => ExecOrder::Selection,
+                },
+                include_dependencies: sub_matches.is_present(ARG_INCLUDE_DEPENDENCIES),
+                include_dependants: sub_matches.is_present(ARG_INCLUDE_DEPENDENTS),
+                log_dir: sub_matches.value_of(ARG_LOG_DIR).map(PathBuf::from),
+                ignore_failures: sub_matches.is_present(ARG_IGNORE_FAILURES),
+            };
+
+            exec_packages(&context, packages, &args, &options)
+        }
+        (SUB_COMMAND_LOCK, Some(sub_matches)) => {
+            context.ensure_online("locking artifacts")?;
+
+            let packages = select_packages(&context, sub_matches)?;
+            let environment = sub_matches.value_of(ARG_ENVIRONMENT).unwrap();
+            let mut lockfile = Lockfile::load(&context)?;
+
+            context.prefetch_dist_targets_existence(&packages)?;
 
             for package in packages {
-                package.execute(&args)?;
+                lockfile.relock(environment, &package)?;
             }
 
-            Ok(())
+            lockfile.save(&context)
         }
         (SUB_COMMAND_TAG, Some(sub_matches)) => {
             let package_name = sub_matches.value_of(ARG_PACKAGE).unwrap();
             let package = context.resolve_package_by_name(package_name)?;
 
+            package.ensure_clean_working_tree()?;
             package.tag()
         }
+        (SUB_COMMAND_RETAG, Some(sub_matches)) => {
+            let package_name = sub_matches.value_of(ARG_PACKAGE).unwrap();
+            let package = context.resolve_package_by_name(package_name)?;
+
+            package.ensure_clean_working_tree()?;
+            package.retag()
+        }
+        (SUB_COMMAND_UNTAG, Some(sub_matches)) => {
+            let package_name = sub_matches.value_of(ARG_PACKAGE).unwrap();
+            let package = context.resolve_package_by_name(package_name)?;
+            let version = match sub_matches.value_of(ARG_VERSION) {
+                Some(version) => semver::Version::parse(version).unwrap(),
+                None => package.version().clone(),
+            };
+
+            package.untag(&version)
+        }
+        (SUB_COMMAND_INIT, Some(sub_matches)) => {
+            let package_name = sub_matches.value_of(ARG_PACKAGE).unwrap();
+            let package = context
+                .resolve_packages_by_names(std::iter::once(package_name))?
+                .remove(0);
+
+            init_dist_target(
+                &package,
+                sub_matches.value_of(ARG_TYPE).unwrap(),
+                sub_matches.value_of(ARG_NAME),
+            )
+        }
         (cmd, _) => Err(
             Error::new("Unknown subcommand specified").with_explanation(format!(
                 "Please specify a valid subcommand: `{}` is not a valid subcommand",
@@ -1,17 +1,44 @@
 //! Metadata structures for the various targets.
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap},
     fmt::Display,
     path::{Path, PathBuf},
 };
 
+use guppy::graph::BuildTargetId;
 use log::debug;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{
+        ArrayValidation, InstanceType, Schema, SchemaObject, SingleOrVec, SubschemaValidation,
+    },
+    JsonSchema,
+};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
 
 use crate::{
-    aws_lambda::AwsLambdaMetadata, dist_target::DistTarget, docker::DockerMetadata, Error,
-    ErrorContext, Package, Result,
+    action_step,
+    artifact_repository::ArtifactRepositoryMetadata,
+    audit::AuditLogMetadata,
+    aws_lambda::AwsLambdaMetadata,
+    build_cache::BuildCacheMetadata,
+    build_info::BuildInfoMetadata,
+    crates_registry::CratesRegistryMetadata,
+    dist_target::DistTarget,
+    docker::DockerMetadata,
+    docker_base::DockerBaseMetadata,
+    ignore_step,
+    licenses::LicensePolicy,
+    macos_app::MacosAppMetadata,
+    msi::MsiMetadata,
+    notify::NotificationsMetadata,
+    npm::NpmMetadata,
+    rust::{executable_suffix, is_current_target_runtime},
+    s3_sync::S3SyncMetadata,
+    wasm::WasmMetadata,
+    Context, Error, ErrorCode, ErrorContext, ErrorKind, Package, Result,
 };
 
 /// The root metadata structure.
@@ -24,42 +51,324 @@ pub(crate) struct Metadata {
 }
 
 impl Metadata {
-    pub(crate) fn new(package_metadata: &guppy::graph::PackageMetadata<'_>) -> Result<Self> {
-        #[derive(Debug, Deserialize)]
-        struct RootMetadata {
-            #[serde(default)]
-            monorepo: Metadata,
+    /// Parse the `[package.metadata.monorepo]` section of the manifest of a
+    /// package.
+    ///
+    /// Each distribution target is parsed on its own, so that errors point
+    /// at the exact field of the manifest.
+    pub(crate) fn new(
+        context: &Context,
+        package_metadata: &guppy::graph::PackageMetadata<'_>,
+    ) -> Result<Self> {
+        let monorepo = match package_metadata.metadata_table().get("monorepo") {
+            Some(monorepo) => monorepo,
+            None => return Ok(Self::default()),
+        };
+
+        let entries = monorepo.as_object().ok_or_else(|| {
+            Error::new("failed to parse metadata")
+                .with_kind(ErrorKind::Configuration)
+                .with_code(ErrorCode::InvalidMetadata)
+                .with_explanation(format!(
+                    "The `package.metadata.monorepo` section of the manifest of {} must be a table.",
+                    package_metadata.name()
+                ))
+        })?;
+
+        let mut metadata = Self::default();
+        let mut invalid_fields = Vec::new();
+
+        for (key, value) in entries {
+            let prefix = format!("package.metadata.monorepo.{}", key);
+            let ignore = |path: &str| {
+                context.ignore_metadata_field(
+                    package_metadata.name(),
+                    &join_metadata_path(&prefix, path),
+                );
+            };
+
+            let result = if key == "tags" {
+                deserialize_metadata(
+                    value,
+                    context.options().lenient_metadata,
+                    |value| serde_path_to_error::deserialize(value),
+                    ignore,
+                )
+                .map(|tags| metadata.tags = tags)
+            } else {
+                deserialize_metadata(
+                    value,
+                    context.options().lenient_metadata,
+                    DistTargetMetadata::from_value,
+                    ignore,
+                )
+                .map(|dist_target| {
+                    metadata.dist_targets.insert(key.clone(), dist_target);
+                })
+            };
+
+            // Keep parsing the other targets, to report all the invalid
+            // fields at once.
+            if let Err(err) = result {
+                invalid_fields.push(format!(
+                    "`{}`: {}",
+                    join_metadata_path(&prefix, &err.path().to_string()),
+                    err.inner()
+                ));
+            }
         }
 
-        let metadata: Option<RootMetadata> =
-            serde_path_to_error::deserialize(package_metadata.metadata_table()).map_err(|err| {
-                Error::new("failed to parse metadata")
-                    .with_source(err)
-                    .with_explanation(format!(
-                        "failed to parse the Cargo metadata for package {}",
-                        package_metadata.id()
-                    ))
-            })?;
+        if !invalid_fields.is_empty() {
+            return Err(Error::new("failed to parse metadata")
+                .with_kind(ErrorKind::Configuration)
+                .with_code(ErrorCode::InvalidMetadata)
+                .with_explanation(format!(
+                    "The manifest of {} has invalid fields:\n{}",
+                    package_metadata.name(),
+                    invalid_fields
+                        .iter()
+                        .map(|field| format!("  - {}", field))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                )));
+        }
 
-        Ok(metadata
-            .map(|metadata| metadata.monorepo)
-            .unwrap_or_default())
+        Ok(metadata)
     }
 
+    /// The distribution targets of the package, without the disabled ones.
     pub(crate) fn dist_targets<'g>(&self, package: &'g Package<'g>) -> Vec<DistTarget<'g>> {
         self.dist_targets
             .iter()
-            .map(|(name, dist_target_metadata)| {
-                dist_target_metadata.to_dist_target(name.clone(), package)
+            .flat_map(|(name, dist_target_metadata)| {
+                dist_target_metadata
+                    .expand()
+                    .into_iter()
+                    .map(|dist_target_metadata| {
+                        dist_target_metadata.to_dist_target(name.clone(), package)
+                    })
+            })
+            .filter(|dist_target| {
+                let enabled = dist_target.is_enabled();
+
+                if !enabled {
+                    debug!(
+                        "Ignoring distribution {} as it is disabled by `enabled = {}`",
+                        dist_target,
+                        dist_target.enabled()
+                    );
+                }
+
+                enabled
             })
             .collect()
     }
 }
 
+impl JsonSchema for Metadata {
+    fn schema_name() -> String {
+        "Metadata".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            ..SchemaObject::default()
+        };
+
+        schema.metadata().description = Some(
+            "The distribution targets of a package, by name, and the hashes of its tagged versions."
+                .to_string(),
+        );
+
+        let object = schema.object();
+        object.properties.insert(
+            "tags".to_string(),
+            gen.subschema_for::<BTreeMap<String, String>>(),
+        );
+        object.additional_properties = Some(Box::new(gen.subschema_for::<DistTargetMetadata>()));
+
+        schema.into()
+    }
+}
+
+/// The JSON schema of the `[package.metadata.monorepo]` section of the
+/// manifests, for editors and linters to validate it.
+pub fn metadata_schema() -> String {
+    let mut schema = SchemaGenerator::default().into_root_schema_for::<Metadata>();
+
+    schema.schema.metadata().title = Some("cargo-monorepo package metadata".to_string());
+
+    serde_json::to_string_pretty(&schema).unwrap()
+}
+
+type PathError = serde_path_to_error::Error<serde_json::Error>;
+
+/// Deserialize a metadata value, tracking the path of the invalid fields.
+///
+/// In lenient mode, unknown fields are removed and reported to `ignore` by
+/// path instead of failing, so that manifests written for newer versions of
+/// the tool can still be read.
+pub(crate) fn deserialize_metadata<T>(
+    value: &serde_json::Value,
+    lenient: bool,
+    deserialize: impl Fn(&serde_json::Value) -> std::result::Result<T, PathError>,
+    mut ignore: impl FnMut(&str),
+) -> std::result::Result<T, PathError> {
+    let mut value = std::borrow::Cow::Borrowed(value);
+
+    loop {
+        match deserialize(&value) {
+            Ok(result) => return Ok(result),
+            Err(err) if lenient && remove_unknown_field(value.to_mut(), &err) => {
+                ignore(&err.path().to_string());
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Remove the field reported as unknown by the error from the value.
+///
+/// Returns whether the error was about an unknown field that was removed.
+fn remove_unknown_field(value: &mut serde_json::Value, err: &PathError) -> bool {
+    let segments: Vec<_> = err.path().iter().collect();
+
+    let (field, parents) = match segments.split_last() {
+        Some((serde_path_to_error::Segment::Map { key }, parents)) => (key, parents),
+        _ => return false,
+    };
+
+    if !err
+        .inner()
+        .to_string()
+        .starts_with(&format!("unknown field `{}`", field))
+    {
+        return false;
+    }
+
+    let mut parent = value;
+
+    for segment in parents {
+        let child = match segment {
+            serde_path_to_error::Segment::Map { key } => parent.get_mut(key.as_str()),
+            serde_path_to_error::Segment::Seq { index } => parent.get_mut(*index),
+            _ => None,
+        };
+
+        parent = match child {
+            Some(child) => child,
+            None => return false,
+        };
+    }
+
+    parent
+        .as_object_mut()
+        .map_or(false, |object| object.remove(field.as_str()).is_some())
+}
+
+/// Join the path of a metadata section and the path of a field in it, as
+/// reported by `serde_path_to_error`.
+fn join_metadata_path(prefix: &str, path: &str) -> String {
+    match path {
+        "." => prefix.to_string(),
+        path if path.starts_with('[') => format!("{}{}", prefix, path),
+        path => format!("{}.{}", prefix, path),
+    }
+}
+
+/// The configuration of the workspace, read from the
+/// `[workspace.metadata.monorepo]` section of the root manifest.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub(crate) struct WorkspaceMetadata {
+    #[serde(default)]
+    pub build_cache: Option<BuildCacheMetadata>,
+    /// The build information passed to the compilation of the distribution
+    /// targets, if enabled.
+    #[serde(default)]
+    pub build_info: Option<BuildInfoMetadata>,
+    #[serde(default)]
+    pub notifications: Option<NotificationsMetadata>,
+    #[serde(default)]
+    pub audit_log: Option<AuditLogMetadata>,
+    #[serde(default)]
+    pub partials: TemplatePartials,
+    /// The named groups of packages, selectable with `--group`.
+    #[serde(default, alias = "group")]
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// The licenses the shipped dependencies may use, checked by `licenses`.
+    #[serde(default)]
+    pub licenses: LicensePolicy,
+    /// Whether the line endings of text files are normalized to `\n` before
+    /// hashing them.
+    #[serde(default)]
+    pub normalize_line_endings: bool,
+    /// Whether Cargo must fail instead of updating the lock file.
+    #[serde(default)]
+    pub locked: bool,
+    /// Whether Cargo must fail instead of updating the lock file or accessing
+    /// the network.
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+impl WorkspaceMetadata {
+    /// Read the configuration of the workspace of the specified manifest.
+    ///
+    /// This happens before the dependencies are resolved, so that the
+    /// configuration can affect their resolution.
+    pub(crate) fn load(manifest_path: &Path, lenient: bool) -> Result<Self> {
+        #[derive(Debug, Deserialize)]
+        struct RootMetadata {
+            #[serde(default)]
+            monorepo: WorkspaceMetadata,
+        }
+
+        let metadata = cargo_metadata::MetadataCommand::new()
+            .manifest_path(manifest_path)
+            .no_deps()
+            .exec()
+            .map_err(|err| Error::new("failed to read workspace metadata").with_source(err))?;
+
+        let metadata: Option<RootMetadata> = deserialize_metadata(
+            &metadata.workspace_metadata,
+            lenient,
+            |value| serde_path_to_error::deserialize(value),
+            |path| {
+                ignore_step!(
+                    "Ignoring",
+                    "unknown field `{}` of the root manifest",
+                    join_metadata_path("workspace.metadata", path)
+                );
+            },
+        )
+        .map_err(|err| {
+            Error::new("failed to parse workspace metadata")
+                .with_kind(ErrorKind::Configuration)
+                .with_source(err)
+                .with_explanation(
+                    "failed to parse the `workspace.metadata.monorepo` section of the root Cargo manifest",
+                )
+        })?;
+
+        Ok(metadata
+            .map(|metadata| metadata.monorepo)
+            .unwrap_or_default())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum DistTargetMetadata {
     Docker(DockerMetadata),
+    DockerBase(DockerBaseMetadata),
     AwsLambda(AwsLambdaMetadata),
+    ArtifactRepository(ArtifactRepositoryMetadata),
+    CratesRegistry(CratesRegistryMetadata),
+    Npm(NpmMetadata),
+    Wasm(WasmMetadata),
+    S3Sync(S3SyncMetadata),
+    Msi(MsiMetadata),
+    MacosApp(MacosAppMetadata),
 }
 
 impl DistTargetMetadata {
@@ -70,7 +379,115 @@ impl DistTargetMetadata {
     ) -> DistTarget<'g> {
         match self {
             DistTargetMetadata::Docker(docker) => docker.clone().into_dist_target(name, package),
+            DistTargetMetadata::DockerBase(docker_base) => {
+                docker_base.clone().into_dist_target(name, package)
+            }
             DistTargetMetadata::AwsLambda(lambda) => lambda.clone().into_dist_target(name, package),
+            DistTargetMetadata::ArtifactRepository(artifact_repository) => {
+                artifact_repository.clone().into_dist_target(name, package)
+            }
+            DistTargetMetadata::CratesRegistry(crates_registry) => {
+                crates_registry.clone().into_dist_target(package)
+            }
+            DistTargetMetadata::Npm(npm) => npm.clone().into_dist_target(package),
+            DistTargetMetadata::Wasm(wasm) => wasm.clone().into_dist_target(name, package),
+            DistTargetMetadata::S3Sync(s3_sync) => s3_sync.clone().into_dist_target(package),
+            DistTargetMetadata::Msi(msi) => msi.clone().into_dist_target(name, package),
+            DistTargetMetadata::MacosApp(macos_app) => {
+                macos_app.clone().into_dist_target(name, package)
+            }
+        }
+    }
+
+    /// Expand the distribution target into one per entry of its target
+    /// runtime matrix, if it has one, and per function for AWS Lambdas.
+    pub(crate) fn expand(&self) -> Vec<Self> {
+        match self {
+            DistTargetMetadata::Docker(docker) => docker
+                .target_runtime
+                .expand()
+                .into_iter()
+                .map(|target_runtime| {
+                    DistTargetMetadata::Docker(DockerMetadata {
+                        target_runtime,
+                        ..docker.clone()
+                    })
+                })
+                .collect(),
+            DistTargetMetadata::AwsLambda(lambda) => lambda
+                .expand()
+                .into_iter()
+                .map(DistTargetMetadata::AwsLambda)
+                .collect(),
+            DistTargetMetadata::ArtifactRepository(artifact_repository) => artifact_repository
+                .target_runtime
+                .expand()
+                .into_iter()
+                .map(|target_runtime| {
+                    DistTargetMetadata::ArtifactRepository(ArtifactRepositoryMetadata {
+                        target_runtime,
+                        ..artifact_repository.clone()
+                    })
+                })
+                .collect(),
+            _ => vec![self.clone()],
+        }
+    }
+
+    /// The target runtimes the distribution target compiles its binaries for,
+    /// if it has configurable ones.
+    pub(crate) fn target_runtimes(&self) -> Option<&[String]> {
+        match self {
+            DistTargetMetadata::Docker(docker) => Some(docker.target_runtime.target_runtimes()),
+            DistTargetMetadata::AwsLambda(lambda) => Some(lambda.target_runtime.target_runtimes()),
+            DistTargetMetadata::ArtifactRepository(artifact_repository) => {
+                Some(artifact_repository.target_runtime.target_runtimes())
+            }
+            DistTargetMetadata::Wasm(wasm) => Some(std::slice::from_ref(&wasm.target_runtime)),
+            DistTargetMetadata::Msi(msi) => Some(std::slice::from_ref(&msi.target_runtime)),
+            DistTargetMetadata::MacosApp(macos_app) => {
+                Some(std::slice::from_ref(&macos_app.target_runtime))
+            }
+            DistTargetMetadata::DockerBase(_)
+            | DistTargetMetadata::CratesRegistry(_)
+            | DistTargetMetadata::Npm(_)
+            | DistTargetMetadata::S3Sync(_) => None,
+        }
+    }
+
+    /// The distribution targets that must be published before this one, as
+    /// `<package>` or `<package>/<target>`.
+    ///
+    /// Docker images depend on the package providing their base image.
+    pub(crate) fn depends_on(&self) -> Vec<&str> {
+        let depends_on = match self {
+            DistTargetMetadata::Docker(docker) => &docker.depends_on,
+            DistTargetMetadata::DockerBase(docker_base) => &docker_base.depends_on,
+            DistTargetMetadata::AwsLambda(lambda) => &lambda.depends_on,
+            DistTargetMetadata::ArtifactRepository(artifact_repository) => {
+                &artifact_repository.depends_on
+            }
+            DistTargetMetadata::CratesRegistry(crates_registry) => &crates_registry.depends_on,
+            DistTargetMetadata::Npm(npm) => &npm.depends_on,
+            DistTargetMetadata::Wasm(wasm) => &wasm.depends_on,
+            DistTargetMetadata::S3Sync(s3_sync) => &s3_sync.depends_on,
+            DistTargetMetadata::Msi(msi) => &msi.depends_on,
+            DistTargetMetadata::MacosApp(macos_app) => &macos_app.depends_on,
+        };
+
+        depends_on
+            .iter()
+            .map(String::as_str)
+            .chain(self.base_image())
+            .collect()
+    }
+
+    /// The package providing the base image of the distribution target, if
+    /// any.
+    pub(crate) fn base_image(&self) -> Option<&str> {
+        match self {
+            DistTargetMetadata::Docker(docker) => docker.base_image.as_deref(),
+            _ => None,
         }
     }
 }
@@ -85,10 +502,42 @@ impl Serialize for DistTargetMetadata {
                 target_type: TargetType::Docker,
                 data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
             },
+            Self::DockerBase(metadata) => TargetHelper {
+                target_type: TargetType::DockerBase,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
             Self::AwsLambda(metadata) => TargetHelper {
                 target_type: TargetType::AwsLambda,
                 data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
             },
+            Self::ArtifactRepository(metadata) => TargetHelper {
+                target_type: TargetType::ArtifactRepository,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
+            Self::CratesRegistry(metadata) => TargetHelper {
+                target_type: TargetType::CratesRegistry,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
+            Self::Npm(metadata) => TargetHelper {
+                target_type: TargetType::Npm,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
+            Self::Wasm(metadata) => TargetHelper {
+                target_type: TargetType::Wasm,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
+            Self::S3Sync(metadata) => TargetHelper {
+                target_type: TargetType::S3Sync,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
+            Self::Msi(metadata) => TargetHelper {
+                target_type: TargetType::Msi,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
+            Self::MacosApp(metadata) => TargetHelper {
+                target_type: TargetType::MacosApp,
+                data: serde_json::to_value(metadata).map_err(serde::ser::Error::custom)?,
+            },
         }
         .serialize(serializer)
     }
@@ -99,15 +548,81 @@ impl<'de> Deserialize<'de> for DistTargetMetadata {
     where
         D: Deserializer<'de>,
     {
-        let helper = TargetHelper::deserialize(deserializer)?;
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        Self::from_value(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+impl DistTargetMetadata {
+    /// Deserialize the metadata of a distribution target, according to its
+    /// `type`, tracking the path of the invalid fields.
+    pub(crate) fn from_value(value: &serde_json::Value) -> std::result::Result<Self, PathError> {
+        let helper: TargetHelper = serde_path_to_error::deserialize(value)?;
+        let data = &helper.data;
+
         match helper.target_type {
-            TargetType::Docker => DockerMetadata::deserialize(helper.data)
-                .map(DistTargetMetadata::Docker)
-                .map_err(serde::de::Error::custom),
-            TargetType::AwsLambda => AwsLambdaMetadata::deserialize(helper.data)
-                .map(DistTargetMetadata::AwsLambda)
-                .map_err(serde::de::Error::custom),
+            TargetType::Docker => serde_path_to_error::deserialize(data).map(Self::Docker),
+            TargetType::DockerBase => serde_path_to_error::deserialize(data).map(Self::DockerBase),
+            TargetType::AwsLambda => serde_path_to_error::deserialize(data).map(Self::AwsLambda),
+            TargetType::ArtifactRepository => {
+                serde_path_to_error::deserialize(data).map(Self::ArtifactRepository)
+            }
+            TargetType::CratesRegistry => {
+                serde_path_to_error::deserialize(data).map(Self::CratesRegistry)
+            }
+            TargetType::Npm => serde_path_to_error::deserialize(data).map(Self::Npm),
+            TargetType::Wasm => serde_path_to_error::deserialize(data).map(Self::Wasm),
+            TargetType::S3Sync => serde_path_to_error::deserialize(data).map(Self::S3Sync),
+            TargetType::Msi => serde_path_to_error::deserialize(data).map(Self::Msi),
+            TargetType::MacosApp => serde_path_to_error::deserialize(data).map(Self::MacosApp),
+        }
+    }
+}
+
+impl JsonSchema for DistTargetMetadata {
+    fn schema_name() -> String {
+        "DistTargetMetadata".to_string()
+    }
+
+    /// One schema per target type, each requiring its `type` field.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        fn target_schema<T: JsonSchema>(gen: &mut SchemaGenerator, target_type: &str) -> Schema {
+            let mut schema = T::json_schema(gen).into_object();
+            let object = schema.object();
+
+            object.properties.insert(
+                "type".to_string(),
+                SchemaObject {
+                    const_value: Some(target_type.into()),
+                    ..SchemaObject::default()
+                }
+                .into(),
+            );
+            object.required.insert("type".to_string());
+
+            schema.into()
         }
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                one_of: Some(vec![
+                    target_schema::<DockerMetadata>(gen, "docker"),
+                    target_schema::<DockerBaseMetadata>(gen, "docker-base"),
+                    target_schema::<AwsLambdaMetadata>(gen, "aws-lambda"),
+                    target_schema::<ArtifactRepositoryMetadata>(gen, "artifact-repository"),
+                    target_schema::<CratesRegistryMetadata>(gen, "crates-registry"),
+                    target_schema::<NpmMetadata>(gen, "npm"),
+                    target_schema::<WasmMetadata>(gen, "wasm"),
+                    target_schema::<S3SyncMetadata>(gen, "s3-sync"),
+                    target_schema::<MsiMetadata>(gen, "msi"),
+                    target_schema::<MacosAppMetadata>(gen, "macos-app"),
+                ]),
+                ..SubschemaValidation::default()
+            })),
+            ..SchemaObject::default()
+        }
+        .into()
     }
 }
 
@@ -115,8 +630,24 @@ impl<'de> Deserialize<'de> for DistTargetMetadata {
 enum TargetType {
     #[serde(rename = "docker")]
     Docker,
+    #[serde(rename = "docker-base")]
+    DockerBase,
     #[serde(rename = "aws-lambda")]
     AwsLambda,
+    #[serde(rename = "artifact-repository")]
+    ArtifactRepository,
+    #[serde(rename = "crates-registry")]
+    CratesRegistry,
+    #[serde(rename = "npm")]
+    Npm,
+    #[serde(rename = "wasm")]
+    Wasm,
+    #[serde(rename = "s3-sync")]
+    S3Sync,
+    #[serde(rename = "msi")]
+    Msi,
+    #[serde(rename = "macos-app")]
+    MacosApp,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -127,6 +658,127 @@ struct TargetHelper {
     data: serde_json::Value,
 }
 
+/// The target runtime of a distribution target.
+///
+/// A list of target runtimes can be specified instead of a single one, in
+/// which case the distribution target is a matrix: it expands to one
+/// distribution target per target runtime, whose artifacts have
+/// runtime-qualified names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetRuntime {
+    target_runtimes: Vec<String>,
+    matrix: bool,
+}
+
+impl TargetRuntime {
+    pub fn new(target_runtime: impl Into<String>) -> Self {
+        Self {
+            target_runtimes: vec![target_runtime.into()],
+            matrix: false,
+        }
+    }
+
+    /// The target runtime, which is the first one for matrices that were not
+    /// expanded.
+    pub fn as_str(&self) -> &str {
+        &self.target_runtimes[0]
+    }
+
+    /// All the target runtimes.
+    pub fn target_runtimes(&self) -> &[String] {
+        &self.target_runtimes
+    }
+
+    /// The target runtime qualifying the names of the artifacts, for the
+    /// distribution targets expanded from a matrix.
+    pub fn matrix_target_runtime(&self) -> Option<&str> {
+        self.matrix.then(|| self.as_str())
+    }
+
+    /// Expand the target runtime into one per entry of the matrix.
+    pub(crate) fn expand(&self) -> Vec<Self> {
+        self.target_runtimes
+            .iter()
+            .map(|target_runtime| Self {
+                target_runtimes: vec![target_runtime.clone()],
+                matrix: self.matrix,
+            })
+            .collect()
+    }
+}
+
+impl Display for TargetRuntime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target_runtimes.join(", "))
+    }
+}
+
+impl Serialize for TargetRuntime {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.matrix {
+            self.target_runtimes.serialize(serializer)
+        } else {
+            self.as_str().serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TargetRuntime {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Helper {
+            Single(String),
+            Matrix(Vec<String>),
+        }
+
+        match Helper::deserialize(deserializer)? {
+            Helper::Single(target_runtime) => Ok(Self::new(target_runtime)),
+            Helper::Matrix(target_runtimes) if target_runtimes.is_empty() => Err(
+                serde::de::Error::custom("at least one target runtime must be specified"),
+            ),
+            Helper::Matrix(target_runtimes) => Ok(Self {
+                target_runtimes,
+                matrix: true,
+            }),
+        }
+    }
+}
+
+impl JsonSchema for TargetRuntime {
+    fn schema_name() -> String {
+        "TargetRuntime".to_string()
+    }
+
+    /// A target runtime, or a non-empty matrix of target runtimes.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let matrix = SchemaObject {
+            instance_type: Some(InstanceType::Array.into()),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(SingleOrVec::Single(Box::new(gen.subschema_for::<String>()))),
+                min_items: Some(1),
+                ..ArrayValidation::default()
+            })),
+            ..SchemaObject::default()
+        };
+
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![gen.subschema_for::<String>(), matrix.into()]),
+                ..SubschemaValidation::default()
+            })),
+            ..SchemaObject::default()
+        }
+        .into()
+    }
+}
+
 /// A copy command instruction.
 ///
 /// `source` indicate the files or folders to copy, possibly using glob patterns.
@@ -135,11 +787,38 @@ struct TargetHelper {
 /// If `source` is a relative path, it is relative to the current package root.
 /// If `destination` is always made relative to the target root.
 ///
-/// A copy never renames files.
-#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+/// `rename` gives a new name to the copied file or folder, in which case
+/// `source` must match exactly one of them. `flatten` copies the files of the
+/// matched folders directly into `destination`, dropping their hierarchy.
+/// The files matching one of the `exclude` patterns, relative to the package
+/// root as well, are not copied. Symbolic links are copied as links.
+///
+/// The permissions of the copied files are preserved, unless `mode` is set.
+/// `dir_mode` sets the permissions of the folders created by the copy. Both
+/// are ignored on platforms without Unix permissions. `owner` and `group` set
+/// the ownership of the copied files in Docker images.
+///
+/// A `source` that matches no files is an error, unless `allow_empty` is set.
+#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, JsonSchema)]
 pub struct CopyCommand {
     pub source: PathBuf,
     pub destination: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rename: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flatten: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_empty: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
 }
 
 impl CopyCommand {
@@ -177,49 +856,477 @@ impl CopyCommand {
         target_root.join(destination)
     }
 
-    pub fn copy_files(&self, source_root: &Path, target_root: &Path) -> crate::Result<()> {
-        let source_files = self.source_files(source_root)?;
+    fn exclude_patterns(&self, package_root: &Path) -> Result<Vec<glob::Pattern>> {
+        self.exclude
+            .iter()
+            .map(|exclude| {
+                glob::Pattern::new(&package_root.join(exclude).display().to_string()).map_err(
+                    |err| {
+                        Error::new("failed to read glob pattern")
+                            .with_kind(ErrorKind::Configuration)
+                            .with_source(err)
+                            .with_explanation(format!(
+                                "The exclude pattern `{}` could not be parsed. You may want to double-check for syntax errors.",
+                                exclude
+                            ))
+                            .with_output(format!("Copy command: {}", self))
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// The ownership of the copied files, in the `user:group` syntax of
+    /// Docker's `--chown` flag.
+    pub(crate) fn chown(&self) -> Option<String> {
+        match (&self.owner, &self.group) {
+            (Some(owner), Some(group)) => Some(format!("{}:{}", owner, group)),
+            (Some(owner), None) => Some(owner.clone()),
+            (None, _) => None,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (field, mode) in [("mode", self.mode), ("dir_mode", self.dir_mode)] {
+            if matches!(mode, Some(mode) if mode > 0o7777) {
+                return Err(Error::new("invalid copy command")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "The `{}` field of `{}` is not a valid permission mode. Use an octal literal, like `0o644`.",
+                        field, self
+                    )));
+            }
+        }
+
+        if self.group.is_some() && self.owner.is_none() {
+            return Err(Error::new("invalid copy command")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The `group` field of `{}` requires the `owner` field to be set as well.",
+                    self
+                )));
+        }
+
+        if let Some(rename) = &self.rename {
+            if Path::new(rename).file_name() != Some(std::ffi::OsStr::new(rename)) {
+                return Err(Error::new("invalid copy command")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "The `rename` field of `{}` must be a file name, without any path separator.",
+                        self
+                    )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A file, symbolic link or empty directory to copy, resolved from a copy
+/// command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CopyEntry {
+    source: PathBuf,
+    destination: PathBuf,
+    /// The permissions of the copy, or of the empty directory.
+    mode: Option<u32>,
+}
+
+/// The resolved copy commands of a distribution target.
+///
+/// Resolving the plan before building makes the configuration errors, like
+/// patterns that match nothing or files that would overwrite each other, fail
+/// early rather than after a lengthy compilation.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CopyPlan {
+    entries: Vec<CopyEntry>,
+    /// The permissions of the directories created by the copy.
+    directories: BTreeMap<PathBuf, u32>,
+}
+
+impl CopyPlan {
+    pub(crate) fn new(
+        copy_commands: &[CopyCommand],
+        source_root: &Path,
+        target_root: &Path,
+    ) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut directories = BTreeMap::new();
+
+        for copy_command in copy_commands {
+            copy_command.validate()?;
+
+            let exclude = copy_command.exclude_patterns(source_root)?;
+            let source_files: Vec<_> = copy_command
+                .source_files(source_root)?
+                .into_iter()
+                .filter(|source_file| !is_excluded(&exclude, source_file))
+                .collect();
+
+            let destination = copy_command.destination(target_root);
+            let count = entries.len();
+
+            if copy_command.rename.is_some() && source_files.len() > 1 {
+                return Err(Error::new("invalid copy command")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_explanation(format!(
+                            "The source of `{}` matches {} files but `rename` requires it to match exactly one.",
+                            copy_command,
+                            source_files.len()
+                        )));
+            }
+
+            for source_file in source_files {
+                // Glob entries always have a file name.
+                let file_name = match &copy_command.rename {
+                    Some(rename) => rename.into(),
+                    None => source_file.file_name().unwrap().to_owned(),
+                };
+
+                resolve_entries(
+                    copy_command,
+                    &exclude,
+                    source_file,
+                    destination.join(file_name),
+                    &mut entries,
+                )?;
+            }
+
+            if entries.len() == count {
+                if copy_command.allow_empty {
+                    debug!("No files to copy for `{}`. Moving on.", copy_command);
+                    continue;
+                }
+
+                return Err(Error::new("copy command matches no files")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "The source of `{}` does not match any file. Fix the pattern or set `allow_empty = true` if the files are optional.",
+                        copy_command
+                    )));
+            }
+
+            if let Some(dir_mode) = copy_command.dir_mode {
+                for entry in &entries[count..] {
+                    for directory in entry.destination.ancestors().skip(1) {
+                        if !directory.starts_with(&destination) || directory == target_root {
+                            break;
+                        }
+
+                        match directories.insert(directory.to_path_buf(), dir_mode) {
+                            Some(previous) if previous != dir_mode => {
+                                return Err(Error::new("copy commands have conflicting permissions")
+                                    .with_kind(ErrorKind::Configuration)
+                                    .with_explanation(format!(
+                                        "The directory `{}` is created by several copy commands with different `dir_mode` values.",
+                                        directory.display()
+                                    )));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut sources = BTreeMap::new();
+
+        for entry in &entries {
+            if let Some(source) = sources.insert(&entry.destination, &entry.source) {
+                return Err(Error::new("copy commands overwrite each other")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "Both `{}` and `{}` would be copied to `{}`. Change the destination of one of the copy commands.",
+                        source.display(),
+                        entry.source.display(),
+                        entry.destination.display()
+                    )));
+            }
+        }
+
+        Ok(Self {
+            entries,
+            directories,
+        })
+    }
+
+    /// Copy the files, refusing to overwrite the `protected` files that were
+    /// produced by the build, like binaries.
+    ///
+    /// In dry-run mode, the files are still copied but each of them is
+    /// reported.
+    pub(crate) fn execute(&self, dry_run: bool, protected: &[PathBuf]) -> Result<()> {
+        for entry in &self.entries {
+            if protected.contains(&entry.destination) {
+                return Err(Error::new("copy command overwrites a build output")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_explanation(format!(
+                        "`{}` would overwrite `{}`, which is produced by the build. Change the destination of the copy command.",
+                        entry.source.display(),
+                        entry.destination.display()
+                    )));
+            }
+        }
+
+        debug!("Copying {} file(s)", self.entries.len());
+
+        for entry in &self.entries {
+            if dry_run {
+                action_step!(
+                    "Copying",
+                    "`{}` to `{}`",
+                    entry.source.display(),
+                    entry.destination.display()
+                );
+            }
+
+            if let Some(parent) = entry.destination.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(Error::from_source)
+                    .with_full_context(
+                        "failed to create directory",
+                        format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", parent.display()),
+                    )?;
+            }
+
+            copy_path(&entry.source, &entry.destination)?;
+
+            if let Some(mode) = entry.mode {
+                // The permissions of a symbolic link are those of its target.
+                let is_symlink = std::fs::symlink_metadata(&entry.destination)
+                    .map_or(false, |metadata| metadata.file_type().is_symlink());
+
+                if !is_symlink {
+                    set_mode(&entry.destination, mode)?;
+                }
+            }
+        }
+
+        // Set the permissions of the directories last, and children first, so
+        // that read-only directories are still writable while copying.
+        for (directory, mode) in self.directories.iter().rev() {
+            set_mode(directory, *mode)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to set permissions",
+            format!(
+                "Could not set the permissions of `{}` to `{:o}`. You may want to verify permissions.",
+                path.display(),
+                mode
+            ),
+        )
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    // Unix permissions have no equivalent on this platform.
+    Ok(())
+}
+
+/// Whether the source matches one of the exclude patterns of a copy command.
+fn is_excluded(exclude: &[glob::Pattern], source: &Path) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..glob::MatchOptions::new()
+    };
+
+    exclude
+        .iter()
+        .any(|pattern| pattern.matches_path_with(source, options))
+}
+
+/// Resolve the entries to copy from `source` to `destination`, expanding
+/// directories down to their files so that collisions can be detected.
+fn resolve_entries(
+    copy_command: &CopyCommand,
+    exclude: &[glob::Pattern],
+    source: PathBuf,
+    destination: PathBuf,
+    entries: &mut Vec<CopyEntry>,
+) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(&source)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to read file metadata",
+            format!("Could not read the metadata of `{}`.", source.display()),
+        )?;
+
+    // Symbolic links are followed on the platforms that don't support them.
+    let is_dir = if metadata.file_type().is_symlink() {
+        cfg!(not(unix)) && source.is_dir()
+    } else {
+        metadata.is_dir()
+    };
+
+    if is_dir {
+        let mut children = std::fs::read_dir(&source)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read directory",
+                format!("Could not list the content of `{}`.", source.display()),
+            )?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(Error::from_source)
+            .with_context("failed to read directory entry")?;
+
+        children.retain(|child| !is_excluded(exclude, &source.join(child)));
+
+        // Flattened folders are never copied, even when empty.
+        if !children.is_empty() || copy_command.flatten {
+            children.sort();
+
+            for child in children {
+                let child_destination = if copy_command.flatten {
+                    destination.with_file_name(&child)
+                } else {
+                    destination.join(&child)
+                };
+
+                resolve_entries(
+                    copy_command,
+                    exclude,
+                    source.join(&child),
+                    child_destination,
+                    entries,
+                )?;
+            }
 
-        if source_files.is_empty() {
-            debug!("No files to copy for `{}`. Moving on.", self);
             return Ok(());
         }
+    }
 
-        let destination = self.destination(target_root);
+    // Empty directories are created with the permissions of directories.
+    let mode = if is_dir {
+        copy_command.dir_mode
+    } else {
+        copy_command.mode
+    };
 
-        debug!(
-            "Copying {} file(s) to to `{}`",
-            source_files.len(),
-            destination.display()
-        );
+    entries.push(CopyEntry {
+        source,
+        destination,
+        mode,
+    });
+
+    Ok(())
+}
+
+/// Copy a file or a directory recursively, overwriting existing files.
+///
+/// Symbolic links are copied as links rather than followed, on the platforms
+/// that support them, and file permissions are preserved.
+fn copy_path(source: &Path, destination: &Path) -> crate::Result<()> {
+    let metadata = std::fs::symlink_metadata(source)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to read file metadata",
+            format!("Could not read the metadata of `{}`.", source.display()),
+        )?;
 
-        std::fs::create_dir_all(&destination)
+    if metadata.file_type().is_symlink() && cfg!(unix) {
+        let target = std::fs::read_link(source)
             .map_err(Error::from_source)
             .with_full_context(
-            "could not create target directory in Docker root",
-            format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", &destination.display()),
+                "failed to read symbolic link",
+                format!("Could not read the target of `{}`.", source.display()),
             )?;
 
-        let options = fs_extra::dir::CopyOptions {
-            overwrite: true,
-            ..fs_extra::dir::CopyOptions::default()
-        };
+        if std::fs::symlink_metadata(destination).is_ok() {
+            std::fs::remove_file(destination)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to replace file",
+                    format!("The file `{}` could not be removed to be replaced by a symbolic link. You may want to verify permissions.", destination.display()),
+                )?;
+        }
 
-        fs_extra::copy_items(&source_files, &destination, &options)
-            .map_err(|err| Error::new("failed to copy file or directory").with_source(err))?;
+        create_symlink(&target, destination)
+    } else if source.is_dir() {
+        std::fs::create_dir_all(destination)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to create directory",
+                format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", destination.display()),
+            )?;
+
+        let entries = std::fs::read_dir(source)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read directory",
+                format!("Could not list the content of `{}`.", source.display()),
+            )?;
+
+        for entry in entries {
+            let entry = entry
+                .map_err(Error::from_source)
+                .with_context("failed to read directory entry")?;
+
+            copy_path(&entry.path(), &destination.join(entry.file_name()))?;
+        }
 
         Ok(())
+    } else {
+        std::fs::copy(source, destination)
+            .map(|_| ())
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to copy file",
+                format!(
+                    "Could not copy `{}` to `{}`. You may want to verify permissions.",
+                    source.display(),
+                    destination.display()
+                ),
+            )
     }
 }
 
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> crate::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to create symbolic link",
+            format!(
+                "Could not create the symbolic link `{}` to `{}`. You may want to verify permissions.",
+                link.display(),
+                target.display()
+            ),
+        )
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, _link: &Path) -> crate::Result<()> {
+    unreachable!("symbolic links are followed on this platform")
+}
+
 impl Display for CopyCommand {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "copy '{}' -> '{}'",
-            self.source.display(),
-            self.destination.display()
-        )
+        match &self.rename {
+            Some(rename) => write!(
+                f,
+                "copy '{}' -> '{}'",
+                self.source.display(),
+                self.destination.join(rename).display()
+            ),
+            None => write!(
+                f,
+                "copy '{}' -> '{}'",
+                self.source.display(),
+                self.destination.display()
+            ),
+        }
     }
 }
 
@@ -232,8 +1339,43 @@ pub struct Template {
 impl Template {
     const TEMPLATE_NAME: &'static str = "__template";
 
-    pub(crate) fn render(&self, context: &tera::Context) -> Result<String> {
-        self.tera.render(Self::TEMPLATE_NAME, context)
+    /// Render the template, which can include the specified partials.
+    pub(crate) fn render(
+        &self,
+        partials: &TemplatePartials,
+        context: &tera::Context,
+    ) -> Result<String> {
+        Self::render_with(self.tera.clone(), partials, context)
+    }
+
+    /// Render the template of a distribution target of the specified package,
+    /// which can include the partials of the workspace.
+    ///
+    /// The relative paths given to `sha256` are resolved from the root of the
+    /// package and `binaries_for` lists the binaries of the package.
+    pub(crate) fn render_for_package(
+        &self,
+        package: &Package<'_>,
+        context: &tera::Context,
+    ) -> Result<String> {
+        let mut tera = self.tera.clone();
+
+        tera.register_filter("sha256", sha256_filter(Some(package.root().to_path_buf())));
+        tera.register_function("binaries_for", binaries_for_function(package)?);
+
+        Self::render_with(tera, package.context().template_partials(), context)
+    }
+
+    fn render_with(
+        mut tera: tera::Tera,
+        partials: &TemplatePartials,
+        context: &tera::Context,
+    ) -> Result<String> {
+        tera.extend(&partials.tera)
+            .map_err(Error::from_source)
+            .with_context("failed to load template partials")?;
+
+        tera.render(Self::TEMPLATE_NAME, context)
             .map_err(Error::from_source).with_full_context(
                 "failed to render template",
                 "The specified template could not rendered properly, which may indicate a possible syntax error."
@@ -241,6 +1383,41 @@ impl Template {
     }
 }
 
+/// The context of the templates customizing the names of the artifacts of a
+/// package.
+///
+/// Templates can use the `name` of the package and the `target_runtime` of
+/// the distribution target.
+pub(crate) fn artifact_name_context(package: &Package<'_>, target_runtime: &str) -> tera::Context {
+    let mut context = tera::Context::new();
+
+    context.insert("name", package.name());
+    context.insert("target_runtime", target_runtime);
+
+    if let Some(preview) = &package.context().options().preview {
+        context.insert("preview", preview);
+    }
+
+    context
+}
+
+impl JsonSchema for Template {
+    fn schema_name() -> String {
+        "Template".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let mut schema = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            ..SchemaObject::default()
+        };
+
+        schema.metadata().description = Some("A Tera template.".to_string());
+
+        schema.into()
+    }
+}
+
 impl<'de> Deserialize<'de> for Template {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
@@ -250,6 +1427,7 @@ impl<'de> Deserialize<'de> for Template {
 
         let mut tera = tera::Tera::default();
 
+        register_template_helpers(&mut tera);
         tera.add_raw_template(Self::TEMPLATE_NAME, &source)
             .map_err(serde::de::Error::custom)?;
 
@@ -257,6 +1435,172 @@ impl<'de> Deserialize<'de> for Template {
     }
 }
 
+/// Register the filters available to all templates.
+///
+/// - `sha256`: the hexadecimal SHA-256 digest of the file at the path.
+/// - `basename`: the last component of the path.
+/// - `env`: the value of the environment variable, or the `default` argument
+///   if it is not set.
+/// - `semver_major` and `semver_minor`: the major and minor components of the
+///   version.
+fn register_template_helpers(tera: &mut tera::Tera) {
+    tera.register_filter("sha256", sha256_filter(None));
+    tera.register_filter("basename", basename_filter);
+    tera.register_filter("env", env_filter);
+    tera.register_filter(
+        "semver_major",
+        |value: &tera::Value, _: &HashMap<String, tera::Value>| {
+            parse_version(value, "semver_major").map(|version| version.major.into())
+        },
+    );
+    tera.register_filter(
+        "semver_minor",
+        |value: &tera::Value, _: &HashMap<String, tera::Value>| {
+            parse_version(value, "semver_minor").map(|version| version.minor.into())
+        },
+    );
+}
+
+/// The `sha256` filter, resolving relative paths from the specified root or
+/// from the current directory.
+fn sha256_filter(root: Option<PathBuf>) -> impl tera::Filter {
+    move |value: &tera::Value, _: &HashMap<String, tera::Value>| {
+        let path = template_str(value, "sha256")?;
+        let path = match &root {
+            Some(root) => root.join(path),
+            None => PathBuf::from(path),
+        };
+
+        let data = std::fs::read(&path).map_err(|err| {
+            tera::Error::chain(format!("failed to read `{}`", path.display()), err)
+        })?;
+
+        Ok(format!("{:x}", Sha256::digest(&data)).into())
+    }
+}
+
+fn basename_filter(
+    value: &tera::Value,
+    _: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let path = template_str(value, "basename")?;
+
+    Ok(Path::new(path)
+        .file_name()
+        .map_or_else(String::new, |file_name| {
+            file_name.to_string_lossy().into_owned()
+        })
+        .into())
+}
+
+fn env_filter(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let name = template_str(value, "env")?;
+
+    match (std::env::var(name), args.get("default")) {
+        (Ok(value), _) => Ok(value.into()),
+        (Err(_), Some(default)) => Ok(default.clone()),
+        (Err(err), None) => Err(tera::Error::chain(
+            format!("failed to read environment variable `{}`", name),
+            err,
+        )),
+    }
+}
+
+/// The `binaries_for` function, returning the paths of the binaries of the
+/// package built for the `target` runtime, by name.
+///
+/// The binaries are looked up in the shared Cargo target directory, even with
+/// `--isolated-target-dirs`.
+fn binaries_for_function(package: &Package<'_>) -> Result<impl tera::Function> {
+    let target_root = package.context().cargo_target_dir()?;
+    let mode = package.context().options().mode.to_string();
+    let binaries: Vec<String> = package
+        .package_metadata()
+        .build_targets()
+        .filter_map(|build_target| match build_target.id() {
+            BuildTargetId::Binary(name) => Some(name.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    Ok(move |args: &HashMap<String, tera::Value>| {
+        let target_runtime = args
+            .get("target")
+            .and_then(tera::Value::as_str)
+            .ok_or_else(|| tera::Error::msg("`binaries_for` expects a `target` argument"))?;
+
+        let mut output_dir = target_root.clone();
+
+        if !is_current_target_runtime(target_runtime)
+            .map_err(|err| tera::Error::msg(err.description()))?
+        {
+            output_dir = output_dir.join(target_runtime);
+        }
+
+        let output_dir = output_dir.join(&mode);
+
+        Ok(binaries
+            .iter()
+            .map(|name| {
+                let file_name = format!("{}{}", name, executable_suffix(target_runtime));
+
+                (
+                    name.clone(),
+                    output_dir.join(file_name).display().to_string().into(),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into())
+    })
+}
+
+fn template_str<'a>(value: &'a tera::Value, filter: &str) -> tera::Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| tera::Error::msg(format!("`{}` expects a string", filter)))
+}
+
+fn parse_version(value: &tera::Value, filter: &str) -> tera::Result<semver::Version> {
+    semver::Version::parse(template_str(value, filter)?)
+        .map_err(|err| tera::Error::chain(format!("`{}` expects a semantic version", filter), err))
+}
+
+/// Named templates shared by the packages of the workspace, that their
+/// templates can use with `{% include "<name>" %}`.
+///
+/// They are read from the `[workspace.metadata.monorepo.partials]` section of
+/// the root manifest.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TemplatePartials {
+    tera: tera::Tera,
+}
+
+impl<'de> Deserialize<'de> for TemplatePartials {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let sources = BTreeMap::<String, String>::deserialize(deserializer)?;
+
+        if sources.contains_key(Template::TEMPLATE_NAME) {
+            return Err(serde::de::Error::custom(format!(
+                "`{}` is reserved and cannot be used as the name of a partial",
+                Template::TEMPLATE_NAME
+            )));
+        }
+
+        let mut tera = tera::Tera::default();
+
+        tera.add_raw_templates(sources)
+            .map_err(serde::de::Error::custom)?;
+
+        Ok(Self { tera })
+    }
+}
+
 impl Serialize for Template {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
@@ -265,3 +1609,254 @@ impl Serialize for Template {
         serializer.serialize_str(&self.source)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_runtime() {
+        let target_runtime: TargetRuntime =
+            serde_json::from_str(r#""x86_64-unknown-linux-gnu""#).unwrap();
+        assert_eq!(target_runtime.as_str(), "x86_64-unknown-linux-gnu");
+        assert_eq!(target_runtime.matrix_target_runtime(), None);
+        assert_eq!(target_runtime.expand(), vec![target_runtime.clone()]);
+
+        let target_runtime: TargetRuntime =
+            serde_json::from_str(r#"["x86_64-unknown-linux-musl", "aarch64-unknown-linux-musl"]"#)
+                .unwrap();
+        let expanded = target_runtime.expand();
+        assert_eq!(expanded.len(), 2);
+        assert_eq!(
+            expanded[1].matrix_target_runtime(),
+            Some("aarch64-unknown-linux-musl")
+        );
+        assert_eq!(
+            serde_json::to_string(&target_runtime).unwrap(),
+            r#"["x86_64-unknown-linux-musl","aarch64-unknown-linux-musl"]"#
+        );
+
+        assert!(serde_json::from_str::<TargetRuntime>("[]").is_err());
+    }
+
+    #[test]
+    fn test_metadata_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&metadata_schema()).unwrap();
+        let targets = schema["definitions"]["DistTargetMetadata"]["oneOf"]
+            .as_array()
+            .unwrap();
+
+        assert_eq!(targets.len(), 10);
+        assert_eq!(targets[0]["properties"]["type"]["const"], "docker");
+        assert!(targets.iter().all(|target| target["required"]
+            .as_array()
+            .unwrap()
+            .contains(&"type".into())));
+        assert_eq!(
+            schema["additionalProperties"]["$ref"],
+            "#/definitions/DistTargetMetadata"
+        );
+    }
+
+    #[test]
+    fn test_lenient_metadata() {
+        let value = serde_json::json!({
+            "type": "docker",
+            "template": "FROM scratch",
+            "new_field": true,
+            "run": { "new_option": 1 },
+        });
+
+        let err = deserialize_metadata(
+            &value,
+            false,
+            DistTargetMetadata::from_value,
+            |_| unreachable!(),
+        )
+        .unwrap_err();
+        assert_eq!(err.path().to_string(), "new_field");
+
+        let mut ignored = vec![];
+        let metadata = deserialize_metadata(&value, true, DistTargetMetadata::from_value, |path| {
+            ignored.push(join_metadata_path("package.metadata.monorepo.image", path));
+        })
+        .unwrap();
+
+        assert!(matches!(metadata, DistTargetMetadata::Docker(_)));
+        assert_eq!(
+            ignored,
+            vec![
+                "package.metadata.monorepo.image.new_field",
+                "package.metadata.monorepo.image.run.new_option",
+            ]
+        );
+
+        let value = serde_json::json!({ "type": "docker", "template": 1 });
+        assert!(
+            deserialize_metadata(&value, true, DistTargetMetadata::from_value, |_| {}).is_err()
+        );
+    }
+
+    #[test]
+    fn test_template_partials() {
+        let partials: TemplatePartials =
+            serde_json::from_str(r#"{"greeting": "Hello {{ name }}"}"#).unwrap();
+        let template: Template = serde_json::from_str(r#""{% include \"greeting\" %}!""#).unwrap();
+
+        let mut context = tera::Context::new();
+        context.insert("name", "world");
+
+        assert_eq!(
+            template.render(&partials, &context).unwrap(),
+            "Hello world!"
+        );
+        assert!(template
+            .render(&TemplatePartials::default(), &context)
+            .is_err());
+        assert!(serde_json::from_str::<TemplatePartials>(r#"{"__template": ""}"#).is_err());
+    }
+
+    #[test]
+    fn test_template_filters() {
+        let template: Template = serde_json::from_str(
+            r#""{{ path | basename }} {{ version | semver_major }}.{{ version | semver_minor }} {{ 'CARGO_MONOREPO_UNSET_VARIABLE' | env(default='none') }}""#,
+        )
+        .unwrap();
+
+        let mut context = tera::Context::new();
+        context.insert("path", "config/prod.yaml");
+        context.insert("version", "1.4.2");
+
+        assert_eq!(
+            template
+                .render(&TemplatePartials::default(), &context)
+                .unwrap(),
+            "prod.yaml 1.4 none"
+        );
+    }
+
+    #[test]
+    fn test_copy_plan() {
+        let root = std::env::temp_dir().join(format!("cargo-monorepo-copy-{}", std::process::id()));
+        let source_root = root.join("package");
+        std::fs::create_dir_all(source_root.join("config")).unwrap();
+        std::fs::write(source_root.join("config").join("app.yaml"), "").unwrap();
+        std::fs::write(source_root.join("app.yaml"), "").unwrap();
+
+        let copy_command = |source: &str, destination: &str| CopyCommand {
+            source: source.into(),
+            destination: destination.into(),
+            rename: None,
+            flatten: false,
+            exclude: Vec::new(),
+            allow_empty: false,
+            mode: None,
+            dir_mode: None,
+            owner: None,
+            group: None,
+        };
+
+        let plan = CopyPlan::new(
+            &[copy_command("config", "/"), copy_command("*.yaml", "/etc")],
+            &source_root,
+            &root.join("target"),
+        )
+        .unwrap();
+        assert_eq!(
+            plan.entries,
+            vec![
+                CopyEntry {
+                    source: source_root.join("config").join("app.yaml"),
+                    destination: root.join("target").join("config").join("app.yaml"),
+                    mode: None,
+                },
+                CopyEntry {
+                    source: source_root.join("app.yaml"),
+                    destination: root.join("target").join("etc").join("app.yaml"),
+                    mode: None,
+                },
+            ]
+        );
+
+        // Both files end up in `/config/app.yaml`.
+        assert!(CopyPlan::new(
+            &[
+                copy_command("config", "/"),
+                copy_command("*.yaml", "/config")
+            ],
+            &source_root,
+            &root.join("target"),
+        )
+        .is_err());
+
+        assert!(CopyPlan::new(
+            &[copy_command("*.json", "/")],
+            &source_root,
+            &root.join("target"),
+        )
+        .is_err());
+        assert!(CopyPlan::new(
+            &[CopyCommand {
+                allow_empty: true,
+                ..copy_command("*.json", "/")
+            }],
+            &source_root,
+            &root.join("target"),
+        )
+        .unwrap()
+        .entries
+        .is_empty());
+
+        let plan = CopyPlan::new(
+            &[
+                CopyCommand {
+                    rename: Some("config.yaml".to_string()),
+                    ..copy_command("config/app.yaml", "/etc")
+                },
+                CopyCommand {
+                    flatten: true,
+                    exclude: vec!["config/*.yaml".to_string()],
+                    allow_empty: true,
+                    ..copy_command("config", "/")
+                },
+            ],
+            &source_root,
+            &root.join("target"),
+        )
+        .unwrap();
+        assert_eq!(
+            plan.entries,
+            vec![CopyEntry {
+                source: source_root.join("config").join("app.yaml"),
+                destination: root.join("target").join("etc").join("config.yaml"),
+                mode: None,
+            }]
+        );
+
+        // Flattening `config` and copying `app.yaml` at the root collide.
+        assert!(CopyPlan::new(
+            &[
+                CopyCommand {
+                    flatten: true,
+                    ..copy_command("config", "/")
+                },
+                copy_command("app.yaml", "/"),
+            ],
+            &source_root,
+            &root.join("target"),
+        )
+        .is_err());
+
+        assert!(CopyPlan::new(
+            &[CopyCommand {
+                rename: Some("app.yaml".to_string()),
+                ..copy_command("**/*.yaml", "/")
+            }],
+            &source_root,
+            &root.join("target"),
+        )
+        .is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    action_step,
+    aws::{
+        aws_settings, delete_s3_object, list_s3_versions, promote_s3_object, s3_object_exists,
+        AwsSettings, S3ArtifactKeys,
+    },
+    dist_target::{check_channel_name, clean_dir, copy_extra_files, published_artifact},
+    ignore_step,
+    metadata::CopyPlan,
+    process::run_command,
+    rust::build_binaries,
+    tools::{self, Tool},
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, PublishedVersion, Result,
+};
+
+use super::MsiMetadata;
+
+pub struct MsiDistTarget<'g> {
+    pub name: String,
+    pub package: &'g Package<'g>,
+    pub metadata: MsiMetadata,
+}
+
+impl Display for MsiDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "msi[{}]", self.package.name())
+    }
+}
+
+impl<'g> MsiDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    pub fn build(&self) -> Result<()> {
+        if !cfg!(windows) {
+            ignore_step!("Unsupported", "MSI installers can only be built on Windows");
+            return Ok(());
+        }
+
+        if let Some(signing) = &self.metadata.signing {
+            check_certificate_thumbprint(&signing.certificate_thumbprint)?;
+        }
+
+        clean_dir(&self.target_dir(), "MSI target directory")?;
+
+        let copy_plan = self.copy_plan()?;
+        let binaries = build_binaries(
+            self.package,
+            &self.name,
+            &self.metadata.target_runtime,
+            false,
+        )?;
+        let binaries = self.copy_binaries(&binaries)?;
+        copy_extra_files(
+            self.package,
+            &copy_plan,
+            &binaries.values().cloned().collect::<Vec<_>>(),
+        )?;
+
+        let wxs_path = self.write_wxs(&binaries)?;
+
+        if self.context().options().no_image {
+            ignore_step!(
+                "Skipping",
+                "MSI installer build as `--no-image` was specified"
+            );
+            return Ok(());
+        }
+
+        self.build_msi(&wxs_path)?;
+
+        if self.metadata.signing.is_some() {
+            self.sign_msi()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        if !cfg!(windows) {
+            ignore_step!(
+                "Unsupported",
+                "MSI installers can only be published on Windows"
+            );
+            return Ok(None);
+        }
+
+        if self.context().options().mode.is_debug() && !self.context().options().force {
+            ignore_step!(
+                "Unsupported",
+                "MSI installers can't be published in debug mode unless `--force` is specified"
+            );
+            return Ok(None);
+        }
+
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!("`--force` specified: not checking for the MSI installer existence before uploading");
+        } else if self.context().artifact_exists(&reference, self.exists())? {
+            ignore_step!(
+                "Up-to-date",
+                "MSI installer `{}` already exists",
+                &reference
+            );
+
+            return Ok(Some(published_artifact(
+                self.package,
+                self,
+                reference,
+                None,
+                true,
+            )));
+        }
+
+        let msi_path = self.msi_path();
+        let data = std::fs::read(&msi_path)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read MSI installer on disk",
+                format!(
+                    "The MSI installer `{}` could not be read. Has this target been built before attempting its publication?",
+                    msi_path.display()
+                ),
+            )?;
+
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+        if self.context().options().dry_run {
+            warn!("`--dry-run` specified, will not really upload the MSI installer to S3");
+
+            return Ok(None);
+        }
+
+        action_step!("Uploading", "MSI installer `{}`", &reference);
+
+        let metadata = self.package.provenance()?.metadata();
+
+        self.context().block_on(async move {
+            let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+            client
+                .put_object()
+                .bucket(&self.metadata.s3_bucket)
+                .key(self.s3_key())
+                .content_type("application/x-msi")
+                .set_metadata(Some(metadata.into_iter().collect()))
+                .body(data.into())
+                .send()
+                .await
+                .map_err(|err| {
+                    Error::new("failed to upload MSI installer on S3")
+                        .with_kind(ErrorKind::Network)
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "Please check that the S3 bucket `{}` exists and that you have the correct permissions.",
+                            &self.metadata.s3_bucket
+                        ))
+                })?;
+
+            Ok(())
+        })?;
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            reference,
+            Some(digest),
+            false,
+        )))
+    }
+
+    /// Check whether the MSI installer for the current version exists in its
+    /// S3 bucket.
+    pub async fn exists(&self) -> Result<bool> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+        s3_object_exists(&client, &self.metadata.s3_bucket, &self.s3_key()).await
+    }
+
+    /// List the versions published in the S3 bucket.
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+        let s3_keys = self.s3_keys();
+
+        list_s3_versions(
+            &client,
+            &self.metadata.s3_bucket,
+            &s3_keys.versions_prefix(),
+            &s3_keys.suffix(),
+        )
+        .await
+        .map(Some)
+    }
+
+    /// Delete the specified version of the MSI installer from the S3 bucket.
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+        delete_s3_object(
+            &client,
+            &self.metadata.s3_bucket,
+            &self.s3_keys().version_key(version),
+        )
+        .await
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(format!(
+            "s3://{}/{}",
+            &self.metadata.s3_bucket,
+            self.s3_key()
+        ))
+    }
+
+    /// Promote the current version of the MSI installer to the specified
+    /// channel.
+    ///
+    /// The installer is copied S3-side and does not need to be rebuilt.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        check_channel_name(channel)?;
+
+        promote_s3_object(
+            self.context(),
+            &self.aws_settings(),
+            &self.metadata.s3_bucket,
+            &self.s3_key(),
+            &self.s3_keys().channel_key(channel),
+            "MSI installer",
+        )
+    }
+
+    fn aws_settings(&self) -> AwsSettings {
+        aws_settings!(self.metadata)
+    }
+
+    fn s3_key(&self) -> String {
+        self.s3_keys().version_key(self.package.version())
+    }
+
+    /// The S3 keys of the installer, prefixed with the preview identifier, if
+    /// any.
+    fn s3_keys(&self) -> S3ArtifactKeys {
+        S3ArtifactKeys::new(
+            &self
+                .context()
+                .options()
+                .preview_s3_prefix(&self.metadata.s3_bucket_prefix),
+            self.package.name(),
+            "msi",
+        )
+    }
+
+    fn write_wxs(&self, binaries: &HashMap<String, PathBuf>) -> Result<PathBuf> {
+        let wxs = self.metadata
+            .template
+            .render_for_package(self.package, &self.generate_context(binaries))
+            .map_err(Error::from_source).with_full_context(
+                "failed to render WiX template",
+                "The specified WiX template could not rendered properly, which may indicate a possible syntax error."
+            )?;
+
+        debug!("Generated WiX source:\n{}", wxs);
+
+        let wxs_path = self.target_dir().join("product.wxs");
+
+        std::fs::write(&wxs_path, wxs)
+            .map_err(Error::from_source)
+            .with_context("failed to write WiX source")?;
+
+        Ok(wxs_path)
+    }
+
+    fn generate_context(&self, binaries: &HashMap<String, PathBuf>) -> tera::Context {
+        let mut context = tera::Context::new();
+        let version = self.package.version();
+
+        context.insert("package_name", self.package.name());
+        context.insert("package_version", version);
+        context.insert("product_version", &product_version(version));
+
+        let binaries: HashMap<_, _> = binaries
+            .iter()
+            .map(|(name, binary)| (name, binary.display().to_string()))
+            .collect();
+
+        context.insert("binaries", &binaries);
+        context.insert("source_dir", &self.source_dir().display().to_string());
+
+        context
+    }
+
+    fn build_msi(&self, wxs_path: &Path) -> Result<()> {
+        let wixobj_path = self.target_dir().join("product.wixobj");
+
+        let mut cmd = Command::new("candle");
+
+        cmd.arg("-nologo");
+        cmd.arg("-out");
+        cmd.arg(&wixobj_path);
+        cmd.arg(wxs_path);
+
+        action_step!("Running", "`candle` for `{}`", self.package.name());
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to compile WiX source",
+        )?;
+
+        let mut cmd = Command::new("light");
+
+        cmd.arg("-nologo");
+        cmd.arg("-out");
+        cmd.arg(self.msi_path());
+        cmd.arg(&wixobj_path);
+
+        action_step!("Running", "`light` for `{}`", self.package.name());
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to link MSI installer",
+        )
+    }
+
+    fn sign_msi(&self) -> Result<()> {
+        let signing = self.metadata.signing.as_ref().unwrap();
+        let mut cmd = Command::new("signtool");
+
+        cmd.args(["sign", "/fd", "SHA256", "/td", "SHA256"]);
+        cmd.arg("/sha1");
+        cmd.arg(&signing.certificate_thumbprint);
+
+        if let Some(certificate_store) = &signing.certificate_store {
+            cmd.arg("/s");
+            cmd.arg(certificate_store);
+        }
+
+        if signing.machine_store {
+            cmd.arg("/sm");
+        }
+
+        cmd.arg("/tr");
+        cmd.arg(&signing.timestamp_url);
+        cmd.arg(self.msi_path());
+
+        action_step!("Signing", "MSI installer for `{}`", self.package.name());
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to sign MSI installer",
+        )
+    }
+
+    /// Copy the selected binaries to the source directory, returning their
+    /// new paths.
+    fn copy_binaries(
+        &self,
+        binaries: &HashMap<String, PathBuf>,
+    ) -> Result<HashMap<String, PathBuf>> {
+        debug!("Will now copy the binaries");
+
+        let source_dir = self.source_dir();
+
+        std::fs::create_dir_all(&source_dir)
+            .map_err(Error::from_source)
+            .with_full_context(
+        "could not create the MSI source directory",
+        format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", source_dir.display()),
+            )?;
+
+        let names: Vec<&String> = if self.metadata.binaries.is_empty() {
+            binaries.keys().collect()
+        } else {
+            self.metadata.binaries.iter().collect()
+        };
+
+        names
+            .into_iter()
+            .map(|name| {
+                let source = binaries.get(name).ok_or_else(|| {
+                    Error::new("failed to find the specified binary in the binaries list")
+                        .with_explanation(format!("The configuration requires the installer to contain the `{}` binary but no such binary is declared in the crate. Was the name perhaps mistyped?", name))
+                })?;
+
+                let target = source_dir.join(source.file_name().unwrap());
+
+                debug!("Copying {} to {}", source.display(), target.display());
+
+                std::fs::copy(source, &target)
+                    .map_err(Error::from_source)
+                    .with_full_context(
+                        "failed to copy binary",
+                        format!(
+                            "The binary `{}` could not be copied to the MSI source directory.",
+                            source.display()
+                        ),
+                    )?;
+
+                Ok((name.clone(), target))
+            })
+            .collect()
+    }
+
+    /// The external tools required to build the installer.
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        if publish || self.context().options().no_image {
+            return vec![];
+        }
+
+        let mut tools = vec![tools::CANDLE, tools::LIGHT];
+
+        if self.metadata.signing.is_some() {
+            tools.push(tools::SIGNTOOL);
+        }
+
+        tools
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.target_dir()
+    }
+
+    fn target_dir(&self) -> PathBuf {
+        self.context()
+            .target_root()
+            .unwrap()
+            .join(&self.metadata.target_runtime)
+            .join(self.context().options().mode.to_string())
+            .join("msi")
+            .join(self.package.name())
+    }
+
+    fn source_dir(&self) -> PathBuf {
+        self.target_dir().join("source")
+    }
+
+    fn msi_path(&self) -> PathBuf {
+        self.target_dir().join(format!(
+            "{}-{}.msi",
+            self.package.name(),
+            self.package.version()
+        ))
+    }
+
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &self.source_dir(),
+        )
+    }
+}
+
+/// Check that a certificate thumbprint is a SHA-1 hash, as `signtool`
+/// expects.
+fn check_certificate_thumbprint(certificate_thumbprint: &str) -> Result<()> {
+    if certificate_thumbprint.len() == 40
+        && certificate_thumbprint
+            .chars()
+            .all(|c| c.is_ascii_hexdigit())
+    {
+        return Ok(());
+    }
+
+    Err(Error::new("invalid certificate thumbprint")
+        .with_kind(ErrorKind::Configuration)
+        .with_explanation(format!(
+            "The `certificate_thumbprint` `{}` is not the 40 hexadecimal digits of a SHA-1 hash. It can be read from the `Thumbprint` column of `Get-ChildItem Cert:\\CurrentUser\\My` in PowerShell.",
+            certificate_thumbprint
+        )))
+}
+
+/// The MSI product version of a package version.
+///
+/// MSI product versions are purely numeric: pre-release and build metadata
+/// cannot be represented.
+fn product_version(version: &semver::Version) -> String {
+    format!("{}.{}.{}", version.major, version.minor, version.patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_certificate_thumbprint() {
+        assert!(check_certificate_thumbprint("0123456789abcdef0123456789ABCDEF01234567").is_ok());
+        assert!(check_certificate_thumbprint("0123456789abcdef").is_err());
+        assert!(check_certificate_thumbprint("0123456789abcdef0123456789abcdef0123456g").is_err());
+        assert!(check_certificate_thumbprint("").is_err());
+    }
+
+    #[test]
+    fn test_product_version() {
+        assert_eq!(product_version(&semver::Version::new(1, 2, 3)), "1.2.3");
+        assert_eq!(
+            product_version(&semver::Version::parse("1.2.3-rc.1+build.5").unwrap()),
+            "1.2.3"
+        );
+    }
+}
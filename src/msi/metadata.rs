@@ -0,0 +1,84 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::AwsCredentialsEnv,
+    condition::Enabled,
+    dist_target::DistTarget,
+    metadata::{CopyCommand, Template},
+    msi::MsiDistTarget,
+    Package,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MsiMetadata {
+    pub template: Template,
+    #[serde(default = "default_target_runtime")]
+    pub target_runtime: String,
+    #[serde(default)]
+    pub binaries: Vec<String>,
+    #[serde(default)]
+    pub extra_files: Vec<CopyCommand>,
+    #[serde(default)]
+    pub signing: Option<MsiSigning>,
+    pub s3_bucket: String,
+    #[serde(default)]
+    pub s3_bucket_prefix: String,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default)]
+    pub aws_profile: Option<String>,
+    #[serde(default)]
+    pub assume_role_arn: Option<String>,
+    #[serde(default)]
+    pub assume_role_external_id: Option<String>,
+    #[serde(default)]
+    pub credentials_env: Option<AwsCredentialsEnv>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+/// The code-signing settings of an installer.
+///
+/// The certificate is selected by its SHA-1 thumbprint in the certificate
+/// store, so that no private key password ever appears on a command line.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MsiSigning {
+    pub certificate_thumbprint: String,
+    #[serde(default)]
+    pub certificate_store: Option<String>,
+    #[serde(default)]
+    pub machine_store: bool,
+    #[serde(default = "default_timestamp_url")]
+    pub timestamp_url: String,
+}
+
+fn default_target_runtime() -> String {
+    "x86_64-pc-windows-msvc".to_string()
+}
+
+fn default_timestamp_url() -> String {
+    "http://timestamp.digicert.com".to_string()
+}
+
+impl MsiMetadata {
+    pub(crate) fn into_dist_target<'g>(
+        self,
+        name: String,
+        package: &'g Package<'g>,
+    ) -> DistTarget<'g> {
+        DistTarget::Msi(MsiDistTarget {
+            name,
+            package,
+            metadata: self,
+        })
+    }
+}
@@ -0,0 +1,150 @@
+//! Notification of the results of publications to a webhook, like the ones of
+//! Slack or Microsoft Teams.
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::{action_step, metadata::Template, Context, Error, ErrorKind, PublishReport, Result};
+
+/// The notification configuration of the workspace, read from the
+/// `[workspace.metadata.monorepo.notifications]` section of the root manifest.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationsMetadata {
+    /// The URL of the webhook.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// The environment variable holding the URL of the webhook, for webhooks
+    /// whose URL is a secret.
+    #[serde(default)]
+    pub webhook_url_env: Option<String>,
+    /// The template of the body posted to the webhook, which defaults to a
+    /// Slack message.
+    #[serde(default)]
+    pub template: Option<Template>,
+}
+
+impl NotificationsMetadata {
+    /// Post a summary of the published artifacts and of the error that
+    /// interrupted the publication, if any, to the webhook.
+    pub(crate) fn notify_publication(
+        &self,
+        context: &Context,
+        report: &PublishReport,
+        error: Option<&Error>,
+        environment: Option<&str>,
+    ) -> Result<()> {
+        if context.options().dry_run {
+            warn!("`--dry-run` specified, will not send the publication notification");
+
+            return Ok(());
+        }
+
+        let url = self.webhook_url()?;
+        let summary = summary(report, error, environment);
+
+        let body = match &self.template {
+            Some(template) => {
+                let mut template_context = tera::Context::new();
+                template_context.insert("artifacts", &report.artifacts);
+                template_context.insert("environment", &environment);
+                template_context.insert("error", &error.map(describe_error));
+                template_context.insert("summary", &summary);
+
+                template.render(context.template_partials(), &template_context)?
+            }
+            None => serde_json::json!({ "text": summary }).to_string(),
+        };
+
+        debug!("Posting publication notification: {}", body);
+        action_step!("Notifying", "publication results");
+
+        let response = context
+            .block_on(
+                reqwest::Client::new()
+                    .post(&url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send(),
+            )
+            .map_err(|err| {
+                Error::new("failed to send publication notification")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation("The webhook could not be reached.")
+            })?;
+
+        if !response.status().is_success() {
+            return Err(Error::new("failed to send publication notification")
+                .with_kind(ErrorKind::Network)
+                .with_explanation(format!(
+                    "The webhook answered with status `{}`.",
+                    response.status()
+                )));
+        }
+
+        Ok(())
+    }
+
+    fn webhook_url(&self) -> Result<String> {
+        match (&self.webhook_url, &self.webhook_url_env) {
+            (Some(webhook_url), None) => Ok(webhook_url.clone()),
+            (None, Some(webhook_url_env)) => std::env::var(webhook_url_env).map_err(|err| {
+                Error::new("failed to read webhook URL")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The environment variable `{}` must be set as it is referenced by the `webhook_url_env` field.",
+                        webhook_url_env
+                    ))
+            }),
+            _ => Err(Error::new("invalid notification configuration")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(
+                    "Exactly one of `webhook_url` and `webhook_url_env` must be specified.",
+                )),
+        }
+    }
+}
+
+/// A human-readable summary of a publication.
+fn summary(report: &PublishReport, error: Option<&Error>, environment: Option<&str>) -> String {
+    let mut lines = vec![match (error, environment) {
+        (None, Some(environment)) => format!("Publication to `{}` succeeded", environment),
+        (None, None) => "Publication succeeded".to_string(),
+        (Some(_), Some(environment)) => format!("Publication to `{}` failed", environment),
+        (Some(_), None) => "Publication failed".to_string(),
+    }];
+
+    for artifact in &report.artifacts {
+        lines.push(format!(
+            "- {} v{}: `{}`{}{}",
+            artifact.package,
+            artifact.version,
+            artifact.reference,
+            artifact
+                .digest
+                .as_ref()
+                .map(|digest| format!(" ({})", digest))
+                .unwrap_or_default(),
+            if artifact.up_to_date {
+                " - up-to-date"
+            } else {
+                ""
+            }
+        ));
+    }
+
+    if let Some(error) = error {
+        lines.push(format!("Error: {}", describe_error(error)));
+    }
+
+    lines.join("\n")
+}
+
+fn describe_error(error: &Error) -> String {
+    match error.explanation() {
+        Some(explanation) => format!("{}: {}", error.description(), explanation),
+        None => error.description().to_string(),
+    }
+}
@@ -0,0 +1,502 @@
+use std::{fmt::Display, path::PathBuf, process::Command};
+
+use log::{debug, warn};
+
+use crate::{
+    action_step,
+    dist_target::{clean_dir, copy_extra_files, published_artifact},
+    ignore_step,
+    metadata::CopyPlan,
+    process::run_command,
+    tools::{self, Tool},
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, Result,
+};
+
+use super::NpmMetadata;
+
+/// The environment variable that holds the output directory for custom build
+/// commands.
+pub const NPM_OUT_DIR_ENV_VAR_NAME: &str = "CARGO_MONOREPO_NPM_OUT_DIR";
+
+pub struct NpmDistTarget<'g> {
+    pub package: &'g Package<'g>,
+    pub metadata: NpmMetadata,
+}
+
+impl Display for NpmDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "npm[{}]", self.package.name())
+    }
+}
+
+impl<'g> NpmDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    pub fn build(&self) -> Result<()> {
+        clean_dir(&self.out_dir(), "npm output directory")?;
+
+        let copy_plan = self.copy_plan()?;
+        self.build_package()?;
+
+        // Extra files may purposely replace the ones generated by `wasm-pack`,
+        // like the `README.md`.
+        copy_extra_files(self.package, &copy_plan, &[])?;
+        self.write_package_json()?;
+
+        Ok(())
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!(
+                "`--force` specified: not checking for the npm package existence before publishing"
+            );
+        } else if self.context().artifact_exists(&reference, self.exists())? {
+            ignore_step!(
+                "Up-to-date",
+                "npm package `{}` already exists in registry `{}`",
+                &reference,
+                &self.metadata.registry
+            );
+
+            return Ok(Some(published_artifact(
+                self.package,
+                self,
+                reference,
+                None,
+                true,
+            )));
+        }
+
+        let mut cmd = self.npm_command()?;
+
+        cmd.current_dir(self.out_dir());
+        cmd.arg("publish");
+
+        if self.metadata.provenance {
+            cmd.arg("--provenance");
+        }
+
+        if let Some(access) = &self.metadata.access {
+            cmd.args(["--access", access]);
+        }
+
+        if self.context().options().dry_run {
+            warn!("`--dry-run` specified, will only simulate the publication of the npm package");
+
+            cmd.arg("--dry-run");
+        }
+
+        action_step!(
+            "Running",
+            "`npm publish` for `{}` to registry `{}`",
+            &reference,
+            &self.metadata.registry
+        );
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to publish npm package",
+        )?;
+
+        if self.context().options().dry_run {
+            return Ok(None);
+        }
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            reference,
+            None,
+            false,
+        )))
+    }
+
+    /// Check whether the current version of the npm package exists in the
+    /// registry.
+    pub async fn exists(&self) -> Result<bool> {
+        let url = registry_package_url(
+            &self.metadata.registry,
+            &self.npm_package_name(),
+            self.package.version(),
+        );
+
+        let response = reqwest::Client::new()
+            .get(&url)
+            .send()
+            .await
+            .map_err(|err| {
+                Error::new("failed to check for npm package existence")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation(format!("Could not reach the npm registry at `{}`.", &url))
+            })?;
+
+        match response.status() {
+            reqwest::StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => Err(Error::new("failed to check for npm package existence")
+                .with_kind(ErrorKind::Network)
+                .with_explanation(format!(
+                    "The npm registry answered with status `{}` for `{}`.",
+                    status, &url
+                ))),
+        }
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(format!(
+            "{}@{}",
+            self.npm_package_name(),
+            self.package.version()
+        ))
+    }
+
+    /// Promote the current version of the npm package to the specified
+    /// channel, which is used as a dist-tag.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().dry_run {
+            warn!(
+                "`--dry-run` specified, will not really add the dist-tag `{}` to `{}`",
+                channel, reference
+            );
+
+            return Ok(());
+        }
+
+        let mut cmd = self.npm_command()?;
+
+        cmd.args(["dist-tag", "add", &reference, channel]);
+
+        action_step!("Promoting", "npm package `{}` to `{}`", &reference, channel);
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to promote npm package",
+        )
+    }
+
+    fn build_package(&self) -> Result<()> {
+        let out_dir = self.out_dir();
+
+        let mut cmd = match &self.metadata.build_command {
+            Some(build_command) => {
+                let (program, args) = build_command.split_first().ok_or_else(|| {
+                    Error::new("invalid npm build command")
+                        .with_kind(ErrorKind::Configuration)
+                        .with_explanation("The field `build_command` cannot be empty.")
+                })?;
+
+                let mut cmd = Command::new(program);
+
+                cmd.args(args);
+                cmd.current_dir(self.package.root());
+                cmd.env(NPM_OUT_DIR_ENV_VAR_NAME, &out_dir);
+
+                action_step!("Running", "`{}`", build_command.join(" "));
+
+                cmd
+            }
+            None => {
+                let mut cmd = Command::new("wasm-pack");
+
+                cmd.arg("build");
+                cmd.arg(self.package.root());
+                cmd.args(["--target", &self.metadata.wasm_pack_target]);
+                cmd.arg("--out-dir");
+                cmd.arg(&out_dir);
+
+                if self.context().options().mode.is_release() {
+                    cmd.arg("--release");
+                } else {
+                    cmd.arg("--dev");
+                }
+
+                let cargo_args = self.context().options().cargo_args();
+
+                if !cargo_args.is_empty() {
+                    cmd.arg("--");
+                    cmd.args(cargo_args);
+                }
+
+                action_step!("Running", "`wasm-pack build` for `{}`", self.package.name());
+
+                cmd
+            }
+        };
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to build npm package",
+        )
+    }
+
+    /// Write the `package.json` of the npm package.
+    ///
+    /// The fields generated by the build, if any, are preserved but the
+    /// identity and description of the package always come from the Cargo
+    /// metadata.
+    fn write_package_json(&self) -> Result<()> {
+        let path = self.out_dir().join("package.json");
+
+        let mut package_json: serde_json::Map<String, serde_json::Value> =
+            match std::fs::read(&path) {
+                Ok(data) => serde_json::from_slice(&data)
+                    .map_err(Error::from_source)
+                    .with_full_context(
+                        "failed to parse `package.json`",
+                        format!(
+                    "The `package.json` generated by the build at `{}` is not a valid JSON object.",
+                    path.display()
+                ),
+                    )?,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => serde_json::Map::new(),
+                Err(err) => {
+                    return Err(Error::new("failed to read `package.json`").with_source(err))
+                }
+            };
+
+        let package_metadata = self.package.package_metadata();
+
+        PackageJsonFields {
+            name: self.npm_package_name(),
+            version: self.package.version(),
+            description: package_metadata.description(),
+            license: package_metadata.license(),
+            homepage: package_metadata.homepage(),
+            repository: package_metadata.repository(),
+            keywords: package_metadata.keywords(),
+            authors: package_metadata.authors(),
+        }
+        .apply(&mut package_json);
+
+        debug!("Writing `{}`", path.display());
+
+        let data = serde_json::to_string_pretty(&package_json)
+            .map_err(Error::from_source)
+            .with_context("failed to serialize `package.json`")?;
+
+        std::fs::write(&path, data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to write `package.json`",
+                format!(
+                    "The file `{}` could not be written. You may want to verify permissions.",
+                    path.display()
+                ),
+            )
+    }
+
+    /// Get a `npm` command configured for the registry.
+    ///
+    /// If a token is specified, a dedicated user configuration referencing it
+    /// is generated so that the token never appears on the command line.
+    fn npm_command(&self) -> Result<Command> {
+        let mut cmd = Command::new("npm");
+
+        cmd.args(["--registry", &self.metadata.registry]);
+
+        if let Some(token_env) = &self.metadata.token_env {
+            let npmrc_path = self.target_dir().join("npmrc");
+            let registry = self
+                .metadata
+                .registry
+                .split_once("://")
+                .map_or(self.metadata.registry.as_str(), |(_, registry)| registry)
+                .trim_end_matches('/');
+
+            std::fs::create_dir_all(self.target_dir())
+                .and_then(|()| {
+                    std::fs::write(
+                        &npmrc_path,
+                        format!("//{}/:_authToken=${{{}}}\n", registry, token_env),
+                    )
+                })
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to write npm configuration",
+                    format!(
+                        "The file `{}` could not be written. You may want to verify permissions.",
+                        npmrc_path.display()
+                    ),
+                )?;
+
+            cmd.arg("--userconfig");
+            cmd.arg(npmrc_path);
+        }
+
+        Ok(cmd)
+    }
+
+    fn npm_package_name(&self) -> String {
+        self.metadata
+            .package_name
+            .clone()
+            .unwrap_or_else(|| self.package.name().to_string())
+    }
+
+    /// The external tools required to build or publish the package.
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        if publish {
+            vec![tools::NPM]
+        } else if self.metadata.build_command.is_none() {
+            vec![tools::WASM_PACK]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.target_dir()
+    }
+
+    fn target_dir(&self) -> PathBuf {
+        self.context()
+            .target_root()
+            .unwrap()
+            .join("npm")
+            .join(self.context().options().mode.to_string())
+            .join(self.package.name())
+    }
+
+    fn out_dir(&self) -> PathBuf {
+        self.target_dir().join("pkg")
+    }
+
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &self.out_dir(),
+        )
+    }
+}
+
+/// The fields of the `package.json` that come from the Cargo metadata.
+struct PackageJsonFields<'a> {
+    name: String,
+    version: &'a semver::Version,
+    description: Option<&'a str>,
+    license: Option<&'a str>,
+    homepage: Option<&'a str>,
+    repository: Option<&'a str>,
+    keywords: &'a [String],
+    authors: &'a [String],
+}
+
+impl PackageJsonFields<'_> {
+    /// Set the fields in a `package.json`, replacing the existing ones.
+    fn apply(self, package_json: &mut serde_json::Map<String, serde_json::Value>) {
+        package_json.insert("name".to_string(), self.name.into());
+        package_json.insert("version".to_string(), self.version.to_string().into());
+
+        if let Some(description) = self.description {
+            package_json.insert("description".to_string(), description.into());
+        }
+
+        if let Some(license) = self.license {
+            package_json.insert("license".to_string(), license.into());
+        }
+
+        if let Some(homepage) = self.homepage {
+            package_json.insert("homepage".to_string(), homepage.into());
+        }
+
+        if let Some(repository) = self.repository {
+            package_json.insert(
+                "repository".to_string(),
+                serde_json::json!({ "type": "git", "url": repository }),
+            );
+        }
+
+        if !self.keywords.is_empty() {
+            package_json.insert("keywords".to_string(), self.keywords.into());
+        }
+
+        if !self.authors.is_empty() {
+            package_json.insert("contributors".to_string(), self.authors.into());
+        }
+    }
+}
+
+/// The URL of the metadata of a version of a package in a npm registry, with
+/// the `/` of scoped package names escaped.
+fn registry_package_url(registry: &str, package_name: &str, version: &semver::Version) -> String {
+    format!(
+        "{}/{}/{}",
+        registry.trim_end_matches('/'),
+        package_name.replace('/', "%2f"),
+        version
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_json_fields() {
+        let version = semver::Version::new(1, 2, 3);
+        let keywords = vec!["wasm".to_string()];
+        let authors = vec!["Jane Doe <jane@example.com>".to_string()];
+
+        let mut package_json = serde_json::json!({
+            "name": "generated-name",
+            "version": "0.0.0",
+            "files": ["my_lib_bg.wasm", "my_lib.js"],
+        })
+        .as_object()
+        .unwrap()
+        .clone();
+
+        PackageJsonFields {
+            name: "@my-scope/my-lib".to_string(),
+            version: &version,
+            description: Some("My library"),
+            license: Some("MIT"),
+            homepage: None,
+            repository: Some("https://github.com/me/my-lib"),
+            keywords: &keywords,
+            authors: &authors,
+        }
+        .apply(&mut package_json);
+
+        assert_eq!(
+            serde_json::Value::Object(package_json),
+            serde_json::json!({
+                "name": "@my-scope/my-lib",
+                "version": "1.2.3",
+                "files": ["my_lib_bg.wasm", "my_lib.js"],
+                "description": "My library",
+                "license": "MIT",
+                "repository": { "type": "git", "url": "https://github.com/me/my-lib" },
+                "keywords": ["wasm"],
+                "contributors": ["Jane Doe <jane@example.com>"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_registry_package_url() {
+        let version = semver::Version::new(1, 2, 3);
+
+        assert_eq!(
+            registry_package_url("https://registry.npmjs.org/", "my-lib", &version),
+            "https://registry.npmjs.org/my-lib/1.2.3"
+        );
+        assert_eq!(
+            registry_package_url("https://registry.npmjs.org", "@my-scope/my-lib", &version),
+            "https://registry.npmjs.org/@my-scope%2fmy-lib/1.2.3"
+        );
+    }
+}
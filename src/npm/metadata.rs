@@ -0,0 +1,54 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    condition::Enabled, dist_target::DistTarget, metadata::CopyCommand, npm::NpmDistTarget, Package,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NpmMetadata {
+    #[serde(default)]
+    pub package_name: Option<String>,
+    #[serde(default = "default_registry")]
+    pub registry: String,
+    #[serde(default)]
+    pub token_env: Option<String>,
+    #[serde(default)]
+    pub build_command: Option<Vec<String>>,
+    #[serde(default = "default_wasm_pack_target")]
+    pub wasm_pack_target: String,
+    #[serde(default)]
+    pub access: Option<String>,
+    #[serde(default = "default_provenance")]
+    pub provenance: bool,
+    #[serde(default)]
+    pub extra_files: Vec<CopyCommand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+fn default_registry() -> String {
+    "https://registry.npmjs.org".to_string()
+}
+
+fn default_wasm_pack_target() -> String {
+    "bundler".to_string()
+}
+
+fn default_provenance() -> bool {
+    true
+}
+
+impl NpmMetadata {
+    pub(crate) fn into_dist_target<'g>(self, package: &'g Package<'g>) -> DistTarget<'g> {
+        DistTarget::Npm(NpmDistTarget {
+            package,
+            metadata: self,
+        })
+    }
+}
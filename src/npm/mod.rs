@@ -0,0 +1,5 @@
+mod dist_target;
+mod metadata;
+
+pub use dist_target::NpmDistTarget;
+pub use metadata::NpmMetadata;
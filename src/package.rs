@@ -1,15 +1,29 @@
 use std::{
+    collections::HashSet,
     ffi::OsStr,
     io::{Read, Seek, Write},
     path::Path,
     process::Command,
 };
 
+use guppy::graph::DependencyDirection;
 use itertools::Itertools;
+use log::warn;
+use once_cell::unsync::OnceCell;
 
 use crate::{
-    action_step, hash::HashSource, ignore_step, metadata::Metadata, sources::Sources, Context,
-    Error, Result,
+    action_step,
+    dist_target::DistTarget,
+    hash::HashSource,
+    ignore_step,
+    metadata::Metadata,
+    prune::versions_to_prune,
+    report::Provenance,
+    sources::{comparable_path, Sources},
+    telemetry::{in_span, phase_span},
+    term::LogGroup,
+    Context, Error, ErrorCode, ErrorKind, HashComparison, LicenseInventory, PackageSummary,
+    PublishState, PublishedArtifact, Result, RetentionRules, StagingDirectories,
 };
 
 /// A package in the workspace.
@@ -18,7 +32,7 @@ pub struct Package<'g> {
     context: &'g Context,
     package_metadata: guppy::graph::PackageMetadata<'g>,
     monorepo_metadata: Metadata,
-    sources: Sources,
+    sources: OnceCell<Sources>,
 }
 
 impl<'g> Package<'g> {
@@ -31,14 +45,13 @@ impl<'g> Package<'g> {
             "cannot build a Package instance from a non-workspace package"
         );
 
-        let monorepo_metadata = Metadata::new(&package_metadata)?;
-        let sources = Sources::from_package(context, &package_metadata)?;
+        let monorepo_metadata = Metadata::new(context, &package_metadata)?;
 
         Ok(Self {
             context,
             package_metadata,
             monorepo_metadata,
-            sources,
+            sources: OnceCell::new(),
         })
     }
 
@@ -54,6 +67,24 @@ impl<'g> Package<'g> {
         &self.monorepo_metadata
     }
 
+    pub(crate) fn dist_targets(&self) -> Vec<DistTarget<'_>> {
+        self.monorepo_metadata.dist_targets(self)
+    }
+
+    /// Whether the package has enabled distribution targets.
+    pub fn has_dist_targets(&self) -> bool {
+        !self.dist_targets().is_empty()
+    }
+
+    /// The licenses of the dependencies shipped with each of the enabled
+    /// distribution targets of the package.
+    pub fn license_inventories(&self) -> Result<Vec<LicenseInventory>> {
+        self.dist_targets()
+            .iter()
+            .map(|dist_target| LicenseInventory::new(self, dist_target))
+            .collect()
+    }
+
     pub fn id(&self) -> &guppy::PackageId {
         self.package_metadata.id()
     }
@@ -66,27 +97,89 @@ impl<'g> Package<'g> {
         self.package_metadata.version()
     }
 
+    /// The packages that depend directly on this one, either as a Cargo
+    /// dependency or through the base image of their Docker images.
     pub fn directly_dependant_packages(&self) -> Result<Vec<Package<'g>>> {
-        self.package_metadata
+        let mut packages = self
+            .package_metadata
             .reverse_direct_links()
             .map(|package_link| Package::new(self.context, package_link.from()))
-            .collect()
+            .collect::<Result<Vec<_>>>()?;
+
+        for id in self
+            .context
+            .base_image_dependants()?
+            .get(self.name())
+            .into_iter()
+            .flatten()
+        {
+            if packages.iter().all(|p| p.id() != id) {
+                let package_metadata =
+                    self.context.package_graph().metadata(id).map_err(|err| {
+                        Error::new("failed to query package graph").with_source(err)
+                    })?;
+
+                packages.push(Package::new(self.context, package_metadata)?);
+            }
+        }
+
+        Ok(packages)
     }
 
+    /// The packages that depend on this one, directly or transitively, either
+    /// as a Cargo dependency or through the base image of their Docker images.
+    ///
+    /// Each package is returned once, after the packages it depends on.
     pub fn dependant_packages(&self) -> Result<Vec<Package<'g>>> {
-        self.directly_dependant_packages()?
-            .into_iter()
-            .map(|package| {
-                package
-                    .directly_dependant_packages()
-                    .map(|packages| std::iter::once(package).chain(packages.into_iter()))
+        let package_graph = self.context.package_graph();
+        let query_error = |err| Error::new("failed to query package graph").with_source(err);
+
+        // Base images are not part of the package graph: the packages
+        // extending them are added to the reverse queries until none is left.
+        let base_image_dependants = self.context.base_image_dependants()?;
+
+        let mut ids: HashSet<&guppy::PackageId> = HashSet::new();
+        let mut pending = vec![self.id()];
+
+        while !pending.is_empty() {
+            let package_set = package_graph
+                .query_reverse(std::mem::take(&mut pending))
+                .map_err(query_error)?
+                .resolve();
+
+            for package_metadata in package_set.packages(DependencyDirection::Forward) {
+                ids.insert(package_metadata.id());
+
+                for id in base_image_dependants
+                    .get(package_metadata.name())
+                    .into_iter()
+                    .flatten()
+                {
+                    if !ids.contains(id) {
+                        pending.push(id);
+                    }
+                }
+            }
+        }
+
+        package_graph
+            .resolve_ids(ids)
+            .map_err(query_error)?
+            .packages(DependencyDirection::Reverse)
+            .filter(|package_metadata| {
+                package_metadata.in_workspace() && package_metadata.id() != self.id()
             })
-            .collect::<Result<Vec<_>>>()
-            .map(|packages| packages.into_iter().flatten().collect())
+            .map(|package_metadata| Package::new(self.context, package_metadata))
+            .collect()
     }
 
-    pub fn sources(&self) -> &Sources {
-        &self.sources
+    /// The source files of the package.
+    ///
+    /// Listing them requires walking the package directory, so they are only
+    /// listed the first time they are needed, like to compute the hash.
+    pub fn sources(&self) -> Result<&Sources> {
+        self.sources
+            .get_or_try_init(|| Sources::from_package(self.context, &self.package_metadata))
     }
 
     pub fn root(&self) -> &Path {
@@ -98,10 +191,82 @@ impl<'g> Package<'g> {
     }
 
     pub fn build_dist_targets(&self) -> Result<()> {
+        let mut staging_directories = StagingDirectories::load(self.context)?;
+        let hash = self.hash()?;
+        let options = self.context.options();
+        let mut dist_targets = Vec::new();
+
         for dist_target in self.monorepo_metadata.dist_targets(self) {
+            if let Some(staging_dir) = dist_target.staging_dir() {
+                if !options.force && dist_target.is_up_to_date(&staging_directories, &hash)? {
+                    ignore_step!(
+                        "Skipping",
+                        "distribution {} as it was already built with hash `{}`",
+                        dist_target,
+                        hash
+                    );
+                    staging_directories.touch(
+                        dist_target.to_string(),
+                        staging_dir,
+                        Some(hash.clone()),
+                    )?;
+
+                    continue;
+                }
+            }
+
+            dist_targets.push(dist_target);
+        }
+
+        // With parallel jobs, compile all the target runtimes at once so that
+        // Cargo can interleave their compilation. Isolated target directories
+        // are compiled separately.
+        if options.jobs.map_or(false, |jobs| jobs > 1)
+            && !options.no_compile
+            && !options.isolated_target_dirs
+        {
+            let mut target_runtimes: Vec<&str> = dist_targets
+                .iter()
+                .filter_map(|dist_target| dist_target.host_target_runtime())
+                .collect();
+            target_runtimes.sort_unstable();
+            target_runtimes.dedup();
+
+            if target_runtimes.len() > 1 {
+                let _group = LogGroup::start(format!("Compiling {}", self.name()));
+
+                action_step!(
+                    "Compiling",
+                    "{} for {}",
+                    self.name(),
+                    target_runtimes.join(", ")
+                );
+                crate::rust::build_target_runtimes(self, &target_runtimes)?;
+            }
+        }
+
+        for dist_target in dist_targets {
+            let staging_dir = dist_target.staging_dir();
+            let _group = LogGroup::start(format!("Building {}", dist_target));
+
             action_step!("Building", "distribution {}", dist_target);
             let before = std::time::Instant::now();
-            dist_target.build()?;
+            in_span(
+                &phase_span!("build", package = self.name(), dist_target = %dist_target),
+                || dist_target.build(),
+            )?;
+
+            if let Some(staging_dir) = staging_dir {
+                // Partial builds must not be mistaken for complete ones.
+                let complete = !options.no_compile && !options.no_image;
+
+                staging_directories.touch(
+                    dist_target.to_string(),
+                    staging_dir,
+                    complete.then(|| hash.clone()),
+                )?;
+            }
+
             let duration = before.elapsed();
             action_step!("Finished", "distribution in {:.2}s", duration.as_secs_f64());
         }
@@ -109,22 +274,237 @@ impl<'g> Package<'g> {
         Ok(())
     }
 
-    pub fn publish_dist_targets(&self) -> Result<()> {
+    /// Publish all the distribution targets of the package.
+    ///
+    /// Distribution targets that were already published during the run
+    /// tracked by `state` are skipped and their previous results reported.
+    pub fn publish_dist_targets(&self, state: &mut PublishState) -> Result<Vec<PublishedArtifact>> {
+        if !self.is_publishable()? {
+            return Ok(vec![]);
+        }
+
+        let mut artifacts = Vec::new();
+        let provenance = self.provenance()?;
+
+        for dist_target in self.monorepo_metadata.dist_targets(self) {
+            artifacts.extend(self.publish_dist_target(&dist_target, &provenance, state)?);
+        }
+
+        Ok(artifacts)
+    }
+
+    /// Check whether the package can be published, which is the case when its
+    /// current hash matches the registered one for its version.
+    pub(crate) fn is_publishable(&self) -> Result<bool> {
         if !self.tag_matches()? {
             ignore_step!(
                 "Skipping",
-                "publication as current hash does not match the registered one for this version"
+                "publication of {} as current hash does not match the registered one for this version",
+                self.name()
             );
 
-            return Ok(());
+            return Ok(false);
         }
 
+        Ok(true)
+    }
+
+    /// Publish a distribution target of the package, unless the publish state
+    /// records it as published by a previous run.
+    pub(crate) fn publish_dist_target(
+        &self,
+        dist_target: &DistTarget<'_>,
+        provenance: &Provenance,
+        state: &mut PublishState,
+    ) -> Result<Option<PublishedArtifact>> {
+        if !dist_target.is_enabled() {
+            ignore_step!(
+                "Skipping",
+                "distribution {} as it is disabled by `enabled = {}`",
+                dist_target,
+                dist_target.enabled()
+            );
+
+            return Ok(None);
+        }
+
+        // The hash is part of the key so that a package changed since the
+        // previous run is published again, even if its version did not change.
+        let key = format!("{} v{} ({})", dist_target, self.version(), self.hash()?);
+
+        if let Some(artifact) = state.completed(&key) {
+            ignore_step!(
+                "Skipping",
+                "distribution {} as it was published by the previous run",
+                dist_target
+            );
+
+            return Ok(artifact.clone());
+        }
+
+        let _group = LogGroup::start(format!("Publishing {}", dist_target));
+        let span = phase_span!("publish", package = self.name(), dist_target = %dist_target);
+
+        in_span(&span, || dist_target.test(self))?;
+
+        action_step!("Publishing", "distribution {}", dist_target);
+        let before = std::time::Instant::now();
+        let artifact = in_span(&span, || dist_target.publish())?.map(|mut artifact| {
+            if !artifact.up_to_date {
+                artifact.provenance = Some(provenance.clone());
+            }
+
+            artifact
+        });
+        state.complete(key, artifact.clone())?;
+        self.context
+            .audit_publication(self, dist_target, provenance, artifact.as_ref())?;
+        let duration = before.elapsed();
+        action_step!("Finished", "publication in {:.2}s", duration.as_secs_f64());
+
+        Ok(artifact)
+    }
+
+    /// Check whether the artifacts of all the distribution targets exist for
+    /// the current version.
+    ///
+    /// All distribution targets are checked, even if some are found missing.
+    pub fn dist_targets_exist(&self) -> Result<bool> {
+        let mut all_exist = true;
+
+        for dist_target in self.dist_targets() {
+            let exists = self
+                .context
+                .artifact_exists(&dist_target.artifact_reference()?, dist_target.exists())?;
+
+            if exists {
+                action_step!("Exists", "distribution {} v{}", dist_target, self.version());
+            } else {
+                ignore_step!(
+                    "Missing",
+                    "distribution {} v{}",
+                    dist_target,
+                    self.version()
+                );
+                all_exist = false;
+            }
+        }
+
+        Ok(all_exist)
+    }
+
+    /// Print the versions of the artifacts of all the distribution targets
+    /// that exist in their destinations, with their dates and digests.
+    pub fn list_published_versions(&self) -> Result<()> {
+        for dist_target in self.dist_targets() {
+            let versions = match self.context.block_on(dist_target.published_versions())? {
+                Some(versions) => versions,
+                None => {
+                    ignore_step!(
+                        "Unsupported",
+                        "listing the published versions of distribution {}",
+                        dist_target
+                    );
+
+                    continue;
+                }
+            };
+
+            if versions.is_empty() {
+                ignore_step!(
+                    "Missing",
+                    "distribution {} has no published version",
+                    dist_target
+                );
+
+                continue;
+            }
+
+            for version in versions
+                .into_iter()
+                .sorted_by(|a, b| a.version.cmp(&b.version))
+            {
+                println!(
+                    "{} v{}\t{}\t{}",
+                    dist_target,
+                    version.version,
+                    version.date.as_deref().unwrap_or("-"),
+                    version.digest.as_deref().unwrap_or("-"),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete the published versions of all the distribution targets that are
+    /// not retained by the specified rules.
+    ///
+    /// The current version of the package and the versions referenced by its
+    /// tags are always kept. If `--dry-run` was specified, the versions that
+    /// would be deleted are only listed.
+    pub fn prune_dist_targets(&self, rules: &RetentionRules) -> Result<()> {
+        for dist_target in self.dist_targets() {
+            let versions = match self.context.block_on(dist_target.published_versions())? {
+                Some(versions) => versions,
+                None => {
+                    ignore_step!(
+                        "Unsupported",
+                        "pruning the published versions of distribution {}",
+                        dist_target
+                    );
+
+                    continue;
+                }
+            };
+
+            let versions = versions_to_prune(versions, rules, |version| {
+                version == self.version() || self.get_tag(version).is_some()
+            });
+
+            if versions.is_empty() {
+                ignore_step!(
+                    "Skipping",
+                    "distribution {} as it has no version to prune",
+                    dist_target
+                );
+
+                continue;
+            }
+
+            for version in versions {
+                if self.context.options().dry_run {
+                    warn!(
+                        "Would now delete distribution {} v{} but `--dry-run` was specified",
+                        dist_target, version.version
+                    );
+
+                    continue;
+                }
+
+                action_step!(
+                    "Deleting",
+                    "distribution {} v{}",
+                    dist_target,
+                    version.version
+                );
+                self.context
+                    .block_on(dist_target.delete_published_version(&version.version))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Promote the current version of all the distribution targets to the
+    /// specified channel.
+    pub fn promote_dist_targets(&self, channel: &str) -> Result<()> {
         for dist_target in self.monorepo_metadata.dist_targets(self) {
-            action_step!("Publishing", "distribution {}", dist_target);
+            action_step!("Promoting", "distribution {} to `{}`", dist_target, channel);
             let before = std::time::Instant::now();
-            dist_target.publish()?;
+            dist_target.promote(channel)?;
             let duration = before.elapsed();
-            action_step!("Finished", "publication in {:.2}s", duration.as_secs_f64());
+            action_step!("Finished", "promotion in {:.2}s", duration.as_secs_f64());
         }
 
         Ok(())
@@ -134,6 +514,17 @@ impl<'g> Package<'g> {
         &self,
         args: impl IntoIterator<Item = impl AsRef<OsStr>>,
     ) -> Result<std::process::ExitStatus> {
+        self.command(args)?
+            .status()
+            .map_err(|err| Error::new("failed to execute command").with_source(err))
+    }
+
+    /// Build the command running the specified arguments in the directory of
+    /// the package.
+    pub(crate) fn command(
+        &self,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    ) -> Result<Command> {
         let args: Vec<_> = args.into_iter().collect();
 
         if args.is_empty() {
@@ -154,25 +545,116 @@ impl<'g> Package<'g> {
         cmd.args(program_args)
             .current_dir(&self.package_metadata.manifest_path().parent().unwrap());
 
-        cmd.status()
-            .map_err(|err| Error::new("failed to execute command").with_source(err))
+        Ok(cmd)
+    }
+
+    /// Check that the working tree has no uncommitted changes affecting the
+    /// package or the workspace packages it depends on, unless
+    /// `--allow-dirty` was specified.
+    ///
+    /// Hashes computed from a dirty working tree do not correspond to any
+    /// commit.
+    pub fn ensure_clean_working_tree(&self) -> Result<()> {
+        if self.context.options().allow_dirty {
+            return Ok(());
+        }
+
+        let roots = self
+            .context
+            .package_graph()
+            .query_forward(std::iter::once(self.id()))
+            .map_err(|err| Error::new("failed to resolve dependencies").with_source(err))?
+            .resolve()
+            .packages(DependencyDirection::Forward)
+            .filter(guppy::graph::PackageMetadata::in_workspace)
+            .filter_map(|package_metadata| {
+                let root = package_metadata.manifest_path().parent()?.as_std_path();
+
+                // The dirty files are canonical, so the roots must be too, as
+                // the workspace may be reached through a symbolic link.
+                Some(comparable_path(
+                    &std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf()),
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let dirty_files: Vec<_> = self
+            .context
+            .get_dirty_files()?
+            .iter()
+            .filter(|path| roots.iter().any(|root| path.starts_with(root)))
+            .collect();
+
+        if dirty_files.is_empty() {
+            return Ok(());
+        }
+
+        Err(Error::new("working tree has uncommitted changes")
+            .with_kind(ErrorKind::Validation)
+            .with_code(ErrorCode::UncommittedChanges)
+            .with_explanation(format!(
+                "The hash of {} would not correspond to any commit. Commit or stash the changes, or specify `--allow-dirty` to proceed anyway.",
+                self.name()
+            ))
+            .with_output(
+                dirty_files
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .join("\n"),
+            ))
+    }
+
+    /// The provenance of the artifacts built from the package.
+    pub(crate) fn provenance(&self) -> Result<Provenance> {
+        Ok(self.context.provenance(self.hash()?))
     }
 
     pub fn hash(&self) -> Result<String> {
         Ok(HashSource::new(self)?.hash())
     }
 
+    pub fn summary(&self) -> Result<PackageSummary> {
+        Ok(PackageSummary {
+            name: self.name().to_string(),
+            version: self.version().clone(),
+            hash: self.hash()?,
+            dist_target_types: self
+                .dist_targets()
+                .iter()
+                .map(|dist_target| dist_target.target_type().to_string())
+                .sorted()
+                .dedup()
+                .collect(),
+        })
+    }
+
     pub fn get_tag(&self, version: &semver::Version) -> Option<&String> {
         self.monorepo_metadata.tags.get(version)
     }
 
     /// Check that the current tag matches the current hash.
+    ///
+    /// A tag computed with a different hash schema version cannot be checked
+    /// and is considered mismatching until it is explicitly re-tagged.
     pub fn tag_matches(&self) -> Result<bool> {
         let version = self.version();
         let hash = self.hash()?;
 
         if let Some(current_hash) = self.get_tag(version) {
-            return Ok(current_hash == &hash);
+            return Ok(match HashComparison::new(current_hash, &hash) {
+                HashComparison::Identical => true,
+                HashComparison::Different => false,
+                HashComparison::Unknown => {
+                    warn!(
+                        "The tag of {} v{} was computed with a different hash format and cannot be verified: re-tag it with `cargo monorepo retag {} --force`",
+                        self.name(),
+                        version,
+                        self.name()
+                    );
+
+                    false
+                }
+            });
         }
 
         Ok(false)
@@ -180,13 +662,17 @@ impl<'g> Package<'g> {
 
     /// Tag the package with its current version and hash.
     ///
-    /// If a tag already exist for the version, the call will fail.
+    /// If a tag already exist for the version, the call will fail unless
+    /// `--force` is specified, including when the existing tag was computed
+    /// with a different hash format.
     pub fn tag(&self) -> Result<()> {
         let version = self.version();
         let hash = self.hash()?;
 
         if let Some(current_hash) = self.get_tag(version) {
-            if current_hash == &hash {
+            let comparison = HashComparison::new(current_hash, &hash);
+
+            if comparison == HashComparison::Identical {
                 ignore_step!(
                     "Skipping",
                     "tagging {} as a tag with an identical hash `{}` exists already",
@@ -197,17 +683,39 @@ impl<'g> Package<'g> {
                 return Ok(());
             }
 
-            if self.context.options().force {
-                action_step!("Re-tagging", "{} with hash `{}`", self.id(), &hash);
-                Ok(())
-            } else {
+            if !self.context.options().force {
+                if comparison == HashComparison::Unknown {
+                    return Err(Error::new("tag has a different hash format")
+                        .with_kind(ErrorKind::AlreadyExists)
+                        .with_code(ErrorCode::TagAlreadyExists)
+                        .with_explanation(format!(
+                            "A tag for version `{}` already exists with hash `{}`, computed with a different hash format that cannot be compared to the current hash `{}`. Specify `--force` to migrate it to the new hash format.",
+                            version,
+                            current_hash,
+                            hash,
+                        )));
+                }
+
                 Err(Error::new("tag already exists for version")
+                    .with_kind(ErrorKind::AlreadyExists)
+                    .with_code(ErrorCode::TagAlreadyExists)
                     .with_explanation(format!(
                         "A tag for version `{}` already exists with a different hash `{}`. You may need to increment the package version number and try again.",
                         version,
                         current_hash,
                     ))
                 )
+            } else if comparison == HashComparison::Unknown {
+                action_step!(
+                    "Migrating",
+                    "tag of {} to the new hash format `{}`",
+                    self.id(),
+                    &hash
+                );
+                Ok(())
+            } else {
+                action_step!("Re-tagging", "{} with hash `{}`", self.id(), &hash);
+                Ok(())
             }
         } else {
             action_step!("Tagging", "{} with hash `{}`", self.id(), &hash);
@@ -215,6 +723,85 @@ impl<'g> Package<'g> {
             Ok(())
         }?;
 
+        self.write_tag(version, Some(hash))
+    }
+
+    /// Record the current hash for the current version, replacing its
+    /// existing tag.
+    ///
+    /// As this changes what was recorded for an already tagged version,
+    /// `--force` must be specified if the hashes differ.
+    pub fn retag(&self) -> Result<()> {
+        let version = self.version();
+        let hash = self.hash()?;
+
+        let current_hash = self.get_tag(version).ok_or_else(|| {
+            Error::new("no tag exists for version").with_explanation(format!(
+                "There is no tag for version `{}` of {}. You may want to use `cargo monorepo tag` instead.",
+                version,
+                self.name(),
+            ))
+        })?;
+
+        if current_hash == &hash {
+            ignore_step!(
+                "Skipping",
+                "re-tagging {} as the tag has an identical hash `{}` already",
+                self.id(),
+                hash,
+            );
+
+            return Ok(());
+        }
+
+        if !self.context.options().force {
+            return Err(Error::new("re-tagging requires `--force`")
+                .with_kind(ErrorKind::Validation)
+                .with_explanation(format!(
+                    "The tag for version `{}` of {} has hash `{}` but the current hash is `{}`. Specify `--force` to confirm that the tag must be replaced.",
+                    version,
+                    self.name(),
+                    current_hash,
+                    hash,
+                )));
+        }
+
+        action_step!("Re-tagging", "{} with hash `{}`", self.id(), &hash);
+
+        self.write_tag(version, Some(hash))
+    }
+
+    /// Remove the tag of the specified version.
+    ///
+    /// `--force` must be specified.
+    pub fn untag(&self, version: &semver::Version) -> Result<()> {
+        let current_hash = self.get_tag(version).ok_or_else(|| {
+            Error::new("no tag exists for version").with_explanation(format!(
+                "There is no tag for version `{}` of {}.",
+                version,
+                self.name(),
+            ))
+        })?;
+
+        if !self.context.options().force {
+            return Err(Error::new("untagging requires `--force`")
+                .with_kind(ErrorKind::Validation)
+                .with_explanation(format!(
+                    "The tag for version `{}` of {} has hash `{}`. Specify `--force` to confirm that it must be removed.",
+                    version,
+                    self.name(),
+                    current_hash,
+                )));
+        }
+
+        action_step!("Untagging", "{} v{}", self.name(), version);
+
+        self.write_tag(version, None)
+    }
+
+    /// Write the tag of a version in the manifest, or remove it if `hash` is
+    /// `None`.
+    fn write_tag(&self, version: &semver::Version, hash: Option<String>) -> Result<()> {
         let manifest_path = &self.package_metadata.manifest_path();
         let mut manifest_file = std::fs::OpenOptions::new()
             .read(true)
@@ -233,13 +820,29 @@ impl<'g> Package<'g> {
             .parse::<toml_edit::Document>()
             .map_err(|err| Error::new("failed to parse manifest").with_source(err))?;
 
-        document["package"]["metadata"]["monorepo"]["tags"][&version.to_string()] =
-            toml_edit::value(hash);
+        match hash {
+            Some(hash) => {
+                document["package"]["metadata"]["monorepo"]["tags"][&version.to_string()] =
+                    toml_edit::value(hash);
+            }
+            None => {
+                if let Some(tags) =
+                    document["package"]["metadata"]["monorepo"]["tags"].as_table_like_mut()
+                {
+                    tags.remove(&version.to_string());
+                }
+            }
+        }
 
         manifest_file
             .seek(std::io::SeekFrom::Start(0))
             .map_err(|err| Error::new("failed to rewind in manifest file").with_source(err))?;
 
+        // The manifest may get shorter.
+        manifest_file
+            .set_len(0)
+            .map_err(|err| Error::new("failed to truncate manifest").with_source(err))?;
+
         manifest_file
             .write_all(document.to_string().as_bytes())
             .map_err(|err| Error::new("failed to write manifest").with_source(err))
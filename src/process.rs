@@ -0,0 +1,40 @@
+//! Helpers to run external commands.
+
+use std::process::Command;
+
+use crate::{Error, ErrorContext, Result};
+
+/// Run a command, streaming its output in verbose mode or capturing it to
+/// report it in case of failure otherwise.
+///
+/// `failure` describes the error to report should the command fail, like
+/// `failed to publish crate`.
+pub(crate) fn run_command(cmd: &mut Command, verbose: bool, failure: &str) -> Result<()> {
+    if verbose {
+        let status = cmd.status().map_err(Error::from_source).with_full_context(
+            failure,
+            "The command could not be executed which could indicate a configuration problem.",
+        )?;
+
+        if !status.success() {
+            return Err(Error::new(failure).with_explanation(
+                "The command failed. Check the logs above to determine the cause.",
+            ));
+        }
+    } else {
+        let output = cmd.output().map_err(Error::from_source).with_full_context(
+            failure,
+            "The command could not be executed which could indicate a configuration problem. You may want to re-run the command with `--verbose` to get more information.",
+        )?;
+
+        if !output.status.success() {
+            return Err(Error::new(failure)
+                .with_explanation(
+                    "The command failed. Check the logs below to determine the cause.",
+                )
+                .with_output(String::from_utf8_lossy(&output.stderr)));
+        }
+    }
+
+    Ok(())
+}
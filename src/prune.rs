@@ -0,0 +1,142 @@
+//! Retention rules for the published artifacts of distribution targets.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+
+use crate::{Error, ErrorKind, PublishedVersion, Result};
+
+/// The rules deciding which published versions of a distribution target are
+/// kept when pruning.
+///
+/// A published version is kept if it matches any of the rules. The current
+/// version of the package and the versions referenced by its tags are always
+/// kept.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionRules {
+    /// Keep the last N versions, by semantic version order.
+    pub keep_last: Option<usize>,
+    /// Keep the versions published more recently than this.
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionRules {
+    /// Check that at least one rule is specified, as pruning would otherwise
+    /// delete every version that is not protected.
+    pub fn validate(&self) -> Result<()> {
+        if self.keep_last.is_none() && self.max_age.is_none() {
+            return Err(Error::new("no retention rule specified")
+                .with_kind(ErrorKind::Validation)
+                .with_explanation(
+                    "At least one of `--keep-last` or `--max-age` must be specified.",
+                ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Select the published versions that are not retained by the rules.
+///
+/// Versions for which `is_protected` returns `true` are never selected.
+/// Versions whose publication date is unknown are not considered expired.
+pub(crate) fn versions_to_prune(
+    versions: Vec<PublishedVersion>,
+    rules: &RetentionRules,
+    is_protected: impl Fn(&semver::Version) -> bool,
+) -> Vec<PublishedVersion> {
+    let now = unix_timestamp();
+
+    let versions: Vec<_> = versions
+        .into_iter()
+        .sorted_by(|a, b| b.version.cmp(&a.version))
+        .collect();
+
+    // Only count distinct versions: a version may be published more than once.
+    let kept_last: Vec<semver::Version> = versions
+        .iter()
+        .map(|version| version.version.clone())
+        .dedup()
+        .take(rules.keep_last.unwrap_or(0))
+        .collect();
+
+    versions
+        .into_iter()
+        .filter(|version| {
+            if is_protected(&version.version) || kept_last.contains(&version.version) {
+                return false;
+            }
+
+            match (rules.max_age, version.timestamp) {
+                (Some(max_age), Some(timestamp)) => {
+                    now.saturating_sub(timestamp)
+                        > i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX)
+                }
+                (Some(_), None) => false,
+                (None, _) => true,
+            }
+        })
+        .collect()
+}
+
+/// The current time, as a number of seconds since the Unix epoch.
+pub(crate) fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            i64::try_from(duration.as_secs()).unwrap_or(i64::MAX)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn published_version(version: &str, age_in_days: i64) -> PublishedVersion {
+        PublishedVersion {
+            version: semver::Version::parse(version).unwrap(),
+            date: None,
+            timestamp: Some(unix_timestamp() - age_in_days * 24 * 3600),
+            digest: None,
+        }
+    }
+
+    fn pruned(rules: &RetentionRules, protected: &[&str]) -> Vec<String> {
+        let versions = vec![
+            published_version("1.0.0", 40),
+            published_version("1.1.0", 30),
+            published_version("1.2.0", 20),
+            published_version("2.0.0", 10),
+            published_version("2.1.0", 1),
+        ];
+
+        versions_to_prune(versions, rules, |version| {
+            protected.contains(&version.to_string().as_str())
+        })
+        .into_iter()
+        .map(|version| version.version.to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_versions_to_prune() {
+        let rules = RetentionRules {
+            keep_last: Some(2),
+            max_age: None,
+        };
+        assert_eq!(pruned(&rules, &[]), vec!["1.2.0", "1.1.0", "1.0.0"]);
+        assert_eq!(pruned(&rules, &["1.1.0"]), vec!["1.2.0", "1.0.0"]);
+
+        let rules = RetentionRules {
+            keep_last: None,
+            max_age: Some(Duration::from_secs(25 * 24 * 3600)),
+        };
+        assert_eq!(pruned(&rules, &[]), vec!["1.1.0", "1.0.0"]);
+
+        let rules = RetentionRules {
+            keep_last: Some(4),
+            max_age: Some(Duration::from_secs(25 * 24 * 3600)),
+        };
+        assert_eq!(pruned(&rules, &[]), vec!["1.0.0"]);
+    }
+}
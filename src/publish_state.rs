@@ -0,0 +1,149 @@
+//! Persistent state of a publication run, used to resume failed runs.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{Context, Error, ErrorContext, PublishedArtifact, Result};
+
+/// The name of the state file, relative to the target root.
+const PUBLISH_STATE_FILE_NAME: &str = "publish-state.json";
+
+/// The distribution targets that were published successfully during a run.
+///
+/// The state is written to disk after every successful publication so that a
+/// failed run can be resumed without publishing everything again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublishState {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    dry_run: bool,
+    mode: String,
+    completed: BTreeMap<String, Option<PublishedArtifact>>,
+}
+
+impl PublishState {
+    /// Start a new publication run, discarding the state of any previous run.
+    pub fn new(context: &Context) -> Result<Self> {
+        let state = Self {
+            path: Self::path(context)?,
+            dry_run: context.options().dry_run,
+            mode: context.options().mode.to_string(),
+            completed: BTreeMap::new(),
+        };
+
+        state.save()?;
+
+        Ok(state)
+    }
+
+    /// Resume the previous publication run.
+    ///
+    /// If there is no previous run, or if it was made in a different build
+    /// mode, a new run is started instead.
+    pub fn resume(context: &Context) -> Result<Self> {
+        let path = Self::path(context)?;
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                warn!("No previous publication run to resume: publishing everything");
+
+                return Self::new(context);
+            }
+            Err(err) => {
+                return Err(Error::new("failed to read publish state")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The publish state could not be read from `{}`. You may want to verify permissions.",
+                        path.display()
+                    )));
+            }
+        };
+
+        let mut state: Self = serde_json::from_slice(&data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to parse publish state",
+                format!(
+                    "The publish state in `{}` is invalid. You may want to delete it and publish everything again.",
+                    path.display()
+                ),
+            )?;
+
+        if state.mode != context.options().mode.to_string() {
+            warn!(
+                "The previous publication run was made in {} mode: publishing everything",
+                state.mode
+            );
+
+            return Self::new(context);
+        }
+
+        debug!(
+            "Resuming publication run with {} completed distribution target(s)",
+            state.completed.len()
+        );
+
+        state.path = path;
+        state.dry_run = context.options().dry_run;
+
+        Ok(state)
+    }
+
+    /// Get the result of the publication of a distribution target, if it
+    /// completed during the run.
+    pub(crate) fn completed(&self, key: &str) -> Option<&Option<PublishedArtifact>> {
+        self.completed.get(key)
+    }
+
+    /// Record the successful publication of a distribution target.
+    pub(crate) fn complete(
+        &mut self,
+        key: String,
+        artifact: Option<PublishedArtifact>,
+    ) -> Result<()> {
+        self.completed.insert(key, artifact);
+
+        self.save()
+    }
+
+    fn path(context: &Context) -> Result<PathBuf> {
+        Ok(context.target_root()?.join(PUBLISH_STATE_FILE_NAME))
+    }
+
+    /// Write the state to disk, unless `--dry-run` was specified.
+    fn save(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let data = serde_json::to_string_pretty(self)
+            .map_err(Error::from_source)
+            .with_context("failed to serialize publish state")?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to create publish state directory",
+                    format!(
+                        "The directory `{}` could not be created. You may want to verify permissions.",
+                        parent.display()
+                    ),
+                )?;
+        }
+
+        std::fs::write(&self.path, data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to write publish state",
+                format!(
+                    "The publish state could not be written to `{}`. You may want to verify permissions.",
+                    self.path.display()
+                ),
+            )
+    }
+}
@@ -0,0 +1,245 @@
+//! Credentials for container registries.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use log::debug;
+use serde::Deserialize;
+
+use crate::{Error, ErrorCode, ErrorContext, ErrorKind, Result};
+
+/// The prefix of the environment variables holding registry credentials.
+pub const REGISTRY_CREDENTIALS_ENV_VAR_PREFIX: &str = "CARGO_MONOREPO_REGISTRY_";
+
+/// The credentials used to authenticate against a registry.
+#[derive(Clone)]
+pub(crate) struct RegistryCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for RegistryCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegistryCredentials")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+impl RegistryCredentials {
+    /// Resolve the credentials for the specified registry host.
+    ///
+    /// The `CARGO_MONOREPO_REGISTRY_<HOST>_USERNAME` and
+    /// `CARGO_MONOREPO_REGISTRY_<HOST>_PASSWORD` environment variables take
+    /// precedence, where `<HOST>` is the registry host in uppercase with all
+    /// non-alphanumeric characters replaced by `_`. Otherwise the specified
+    /// Docker credential helper is queried, if any.
+    ///
+    /// Returns `None` if no credentials are configured, in which case the
+    /// existing Docker sessions are used.
+    pub fn resolve(host: &str, credential_helper: Option<&str>) -> Result<Option<Self>> {
+        if let Some(credentials) = Self::from_env(host)? {
+            debug!(
+                "Using the credentials from the environment for registry `{}`",
+                host
+            );
+
+            return Ok(Some(credentials));
+        }
+
+        match credential_helper {
+            Some(credential_helper) => {
+                debug!(
+                    "Using the Docker credential helper `{}` for registry `{}`",
+                    credential_helper, host
+                );
+
+                Self::from_credential_helper(credential_helper, host).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn from_env(host: &str) -> Result<Option<Self>> {
+        let prefix = env_var_prefix(host);
+        let username_env = format!("{}_USERNAME", prefix);
+        let password_env = format!("{}_PASSWORD", prefix);
+
+        match (std::env::var(&username_env), std::env::var(&password_env)) {
+            (Ok(username), Ok(password)) => Ok(Some(Self { username, password })),
+            (Err(_), Err(_)) => Ok(None),
+            _ => Err(Error::new("incomplete registry credentials")
+                .with_kind(ErrorKind::Configuration)
+                .with_code(ErrorCode::IncompleteRegistryCredentials)
+                .with_explanation(format!(
+                    "Both `{}` and `{}` must be set to authenticate against the registry `{}`.",
+                    username_env, password_env, host
+                ))),
+        }
+    }
+
+    /// Query a Docker credential helper, following the protocol described in
+    /// <https://github.com/docker/docker-credential-helpers>.
+    fn from_credential_helper(credential_helper: &str, host: &str) -> Result<Self> {
+        let program = format!("docker-credential-{}", credential_helper);
+
+        let mut child = Command::new(&program)
+            .arg("get")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to run Docker credential helper",
+                format!(
+                    "The command `{}` could not be executed. Is it installed?",
+                    program
+                ),
+            )?;
+
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(host.as_bytes())
+            .map_err(Error::from_source)
+            .with_context("failed to write to Docker credential helper")?;
+
+        let output = child
+            .wait_with_output()
+            .map_err(Error::from_source)
+            .with_context("failed to run Docker credential helper")?;
+
+        if !output.status.success() {
+            return Err(Error::new("failed to read registry credentials")
+                .with_kind(ErrorKind::RegistryAuth)
+                .with_explanation(format!(
+                    "The Docker credential helper `{}` could not provide credentials for the registry `{}`.",
+                    credential_helper, host
+                ))
+                .with_output(String::from_utf8_lossy(&output.stdout)));
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct HelperCredentials {
+            username: String,
+            secret: String,
+        }
+
+        serde_json::from_slice::<HelperCredentials>(&output.stdout)
+            .map(|credentials| Self {
+                username: credentials.username,
+                password: credentials.secret,
+            })
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read registry credentials",
+                format!(
+                    "The output of the Docker credential helper `{}` could not be parsed.",
+                    credential_helper
+                ),
+            )
+    }
+
+    /// Log the Docker CLI in to the specified registry host.
+    ///
+    /// The password is passed on the standard input so that it does not appear
+    /// in the process list.
+    pub fn docker_login(&self, host: &str) -> Result<()> {
+        let mut child = Command::new("docker")
+            .args(["login", "--username", &self.username, "--password-stdin", host])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to log in to registry",
+                "The Docker command could not be executed which could indicate a configuration problem.",
+            )?;
+
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(self.password.as_bytes())
+            .map_err(Error::from_source)
+            .with_context("failed to log in to registry")?;
+
+        let output = child
+            .wait_with_output()
+            .map_err(Error::from_source)
+            .with_context("failed to log in to registry")?;
+
+        if !output.status.success() {
+            return Err(Error::new("failed to log in to registry")
+                .with_kind(ErrorKind::RegistryAuth)
+                .with_code(ErrorCode::RegistryLoginFailed)
+                .with_explanation(format!(
+                    "The login to the registry `{}` failed. Check the logs below to determine the cause.",
+                    host
+                ))
+                .with_output(String::from_utf8_lossy(&output.stderr)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The host of the registry of an image or artifact reference.
+///
+/// Like Docker, the first component of the reference is only considered to be
+/// a host if it contains a `.` or a `:` or is `localhost`.
+pub(crate) fn registry_host(reference: &str) -> Option<&str> {
+    let (host, _) = reference.split_once('/')?;
+
+    (host.contains('.') || host.contains(':') || host == "localhost").then(|| host)
+}
+
+fn env_var_prefix(host: &str) -> String {
+    let host: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("{}{}", REGISTRY_CREDENTIALS_ENV_VAR_PREFIX, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_host() {
+        assert_eq!(registry_host("ghcr.io/org/image:1.0.0"), Some("ghcr.io"));
+        assert_eq!(
+            registry_host("localhost:5000/image:1.0.0"),
+            Some("localhost:5000")
+        );
+        assert_eq!(registry_host("org/image:1.0.0"), None);
+        assert_eq!(registry_host("image:1.0.0"), None);
+    }
+
+    #[test]
+    fn test_env_var_prefix() {
+        assert_eq!(
+            env_var_prefix("123456789012.dkr.ecr.eu-west-1.amazonaws.com"),
+            "CARGO_MONOREPO_REGISTRY_123456789012_DKR_ECR_EU_WEST_1_AMAZONAWS_COM"
+        );
+        assert_eq!(
+            env_var_prefix("localhost:5000"),
+            "CARGO_MONOREPO_REGISTRY_LOCALHOST_5000"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Machine-readable reports of the actions performed during a run.
+
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, ErrorContext, Result};
+
+/// An artifact that was published by a distribution target.
+///
+/// Artifacts that were found to exist already in their destination are also
+/// reported, so that downstream systems always get a complete picture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishedArtifact {
+    pub package: String,
+    pub version: semver::Version,
+    pub dist_target: String,
+    pub reference: String,
+    pub digest: Option<String>,
+    /// An immutable reference to the artifact, derived from the hash of the
+    /// package, like the hash tag of Docker images.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_reference: Option<String>,
+    pub up_to_date: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Where and how an artifact was built, for traceability from the artifact
+/// back to the commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub git_commit: Option<String>,
+    pub git_branch: Option<String>,
+    pub ci_run_url: Option<String>,
+    pub builder: Option<String>,
+    pub package_hash: String,
+}
+
+impl Provenance {
+    /// The provenance as key-value pairs, suitable for S3 object metadata.
+    pub(crate) fn metadata(&self) -> BTreeMap<String, String> {
+        [
+            ("git-commit", self.git_commit.as_deref()),
+            ("git-branch", self.git_branch.as_deref()),
+            ("ci-run-url", self.ci_run_url.as_deref()),
+            ("builder", self.builder.as_deref()),
+            ("package-hash", Some(self.package_hash.as_str())),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key.to_string(), value.to_string())))
+        .collect()
+    }
+
+    /// The provenance as Docker image labels, using the OCI annotation for the
+    /// commit.
+    pub(crate) fn docker_labels(&self) -> BTreeMap<String, String> {
+        self.metadata()
+            .into_iter()
+            .map(|(key, value)| match key.as_str() {
+                "git-commit" => ("org.opencontainers.image.revision".to_string(), value),
+                _ => (format!("cargo-monorepo.{}", key), value),
+            })
+            .collect()
+    }
+}
+
+/// A package as listed for CI systems, for instance to generate a matrix of
+/// jobs.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageSummary {
+    pub name: String,
+    pub version: semver::Version,
+    pub hash: String,
+    /// The types of the distribution targets of the package, like `docker`.
+    pub dist_target_types: Vec<String>,
+}
+
+/// A version of an artifact that exists in its destination.
+#[derive(Debug, Clone, Serialize)]
+pub struct PublishedVersion {
+    pub version: semver::Version,
+    pub date: Option<String>,
+    /// The date, as a number of seconds since the Unix epoch.
+    pub timestamp: Option<i64>,
+    pub digest: Option<String>,
+}
+
+/// A report of all the artifacts published during a run.
+#[derive(Debug, Default, Serialize)]
+pub struct PublishReport {
+    pub artifacts: Vec<PublishedArtifact>,
+}
+
+impl PublishReport {
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let data = serde_json::to_string_pretty(self)
+            .map_err(Error::from_source)
+            .with_context("failed to serialize publish report")?;
+
+        std::fs::write(path, data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to write publish report",
+                format!(
+                    "The publish report could not be written to `{}`. You may want to verify permissions.",
+                    path.display()
+                ),
+            )
+    }
+}
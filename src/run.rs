@@ -0,0 +1,42 @@
+//! Build and run Docker distribution targets locally.
+
+use crate::{action_step, dist_target::DistTarget, Error, Package, Result};
+
+/// Build the Docker distribution target of a package and run it locally, as
+/// a smoke test of exactly what would be published.
+///
+/// The package must have exactly one Docker distribution target.
+pub fn run_docker_dist_target(package: &Package<'_>) -> Result<()> {
+    let dist_targets = package.dist_targets();
+    let mut docker_dist_targets = dist_targets
+        .iter()
+        .filter_map(|dist_target| match dist_target {
+            DistTarget::Docker(docker) => Some(docker),
+            _ => None,
+        });
+
+    let docker = match (docker_dist_targets.next(), docker_dist_targets.next()) {
+        (Some(docker), None) => docker,
+        (None, _) => {
+            return Err(
+                Error::new("no Docker distribution target to run").with_explanation(format!(
+                    "The package `{}` has no distribution target of type `docker`.",
+                    package.name()
+                )),
+            );
+        }
+        (Some(_), Some(_)) => {
+            return Err(Error::new("ambiguous Docker distribution target").with_explanation(
+                format!(
+                    "The package `{}` has several distribution targets of type `docker`, which is not supported by `run`.",
+                    package.name()
+                ),
+            ));
+        }
+    };
+
+    action_step!("Building", "distribution {}", docker);
+    docker.build()?;
+
+    docker.run_container()
+}
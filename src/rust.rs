@@ -1,8 +1,655 @@
-use std::process::Command;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    process::Command,
+};
 
+use cargo::{
+    core::compiler::{CompileKind, CompileMode, CompileTarget},
+    ops::{compile, fetch, CompileOptions, FetchOptions},
+    util::Filesystem,
+};
+use guppy::graph::{BuildTargetId, BuildTargetKind};
 use log::debug;
 
-use crate::{Error, ErrorContext, Result};
+use crate::{
+    action_step,
+    hash::resolved_features,
+    telemetry::{in_span, phase_span},
+    Context, Error, ErrorCode, ErrorContext, ErrorKind, Package, Result, StagingDirectories,
+};
+
+/// The name of the directory of the Cargo target directory containing the
+/// isolated target directories of the distribution targets.
+const ISOLATED_TARGET_DIRS_DIR_NAME: &str = "monorepo-isolated";
+
+/// Build the binaries of a package for the specified distribution target and
+/// target runtime, returning their paths by target name.
+///
+/// If `include_cdylibs` is set, the `cdylib` libraries are returned as well.
+///
+/// If `--no-compile` was specified, the outputs of a previous build are
+/// reused instead of compiling the package.
+pub(crate) fn build_binaries(
+    package: &Package<'_>,
+    dist_target_name: &str,
+    target_runtime: &str,
+    include_cdylibs: bool,
+) -> Result<HashMap<String, PathBuf>> {
+    let context = package.context();
+    let target_dir = cargo_target_dir(package, dist_target_name, target_runtime)?;
+
+    if context.options().no_compile {
+        debug!("`--no-compile` specified: reusing existing binaries");
+
+        return find_existing_binaries(package, &target_dir, target_runtime, include_cdylibs);
+    }
+
+    let mut ws = context.workspace()?;
+
+    if context.options().isolated_target_dirs {
+        debug!(
+            "Compiling `{}` in the isolated target directory `{}`",
+            dist_target_name,
+            target_dir.display()
+        );
+
+        ws.set_target_dir(Filesystem::new(target_dir));
+    } else if let Some(compiled_binaries) =
+        context.compiled_binaries(package.name(), target_runtime)
+    {
+        debug!(
+            "Reusing the binaries of `{}` compiled for `{}` with other packages",
+            package.name(),
+            target_runtime
+        );
+
+        return Ok(compiled_binaries.select(include_cdylibs));
+    }
+
+    let mut compile_options = compile_options(context, &[package], &ws);
+
+    if !is_current_target_runtime(target_runtime)? {
+        compile_options.build_config.requested_kinds = vec![compile_kind(target_runtime)?];
+    }
+
+    let span = phase_span!("compile", package = package.name(), target_runtime);
+
+    in_span(&span, || {
+        with_build_info(package, || {
+            compile(&ws, &compile_options)
+                .map(|compilation| {
+                    compilation
+                        .binaries
+                        .iter()
+                        .chain(compilation.cdylibs.iter().filter(|_| include_cdylibs))
+                        .map(|b| (b.unit.target.name().to_string(), b.path.clone()))
+                        .collect()
+                })
+                .map_err(|err| {
+                    Error::new("failed to compile binaries")
+                        .with_kind(ErrorKind::Compilation)
+                        .with_code(ErrorCode::CompilationFailed)
+                        .with_source(err)
+                })
+        })
+    })
+}
+
+/// Compile a package for several target runtimes at once, letting Cargo
+/// parallelize the compilation across them.
+///
+/// Subsequent calls to `build_binaries` for these target runtimes then find
+/// the compilation up-to-date.
+pub(crate) fn build_target_runtimes(package: &Package<'_>, target_runtimes: &[&str]) -> Result<()> {
+    let ws = package.context().workspace()?;
+    let mut compile_options = compile_options(package.context(), &[package], &ws);
+
+    compile_options.build_config.requested_kinds = target_runtimes
+        .iter()
+        .map(|target_runtime| compile_kind(target_runtime))
+        .collect::<Result<_>>()?;
+
+    let span = phase_span!(
+        "compile",
+        package = package.name(),
+        target_runtime = %target_runtimes.join(", ")
+    );
+
+    in_span(&span, || {
+        with_build_info(package, || {
+            compile(&ws, &compile_options).map(|_| ()).map_err(|err| {
+                Error::new("failed to compile binaries")
+                    .with_kind(ErrorKind::Compilation)
+                    .with_code(ErrorCode::CompilationFailed)
+                    .with_source(err)
+            })
+        })
+    })
+}
+
+/// The binaries of a package compiled for a target runtime along with other
+/// packages, by target name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompiledBinaries {
+    binaries: HashMap<String, PathBuf>,
+    cdylibs: HashMap<String, PathBuf>,
+}
+
+impl CompiledBinaries {
+    fn select(&self, include_cdylibs: bool) -> HashMap<String, PathBuf> {
+        self.binaries
+            .iter()
+            .chain(self.cdylibs.iter().filter(|_| include_cdylibs))
+            .map(|(name, path)| (name.clone(), path.clone()))
+            .collect()
+    }
+}
+
+/// Download the dependencies of the workspace for the current target runtime
+/// and for the target runtimes of the distribution targets of the specified
+/// packages, so that they can be built offline.
+///
+/// With `--offline`, nothing is downloaded: this checks that the dependencies
+/// were fetched beforehand.
+pub fn fetch_dependencies(context: &Context, packages: &[Package<'_>]) -> Result<()> {
+    // Build scripts and procedural macros run on the current target runtime.
+    let mut target_runtimes = vec![get_current_target_runtime()?];
+
+    for package in packages {
+        for dist_target in package.dist_targets() {
+            if let Some(target_runtime) = dist_target.host_target_runtime() {
+                target_runtimes.push(target_runtime.to_string());
+            }
+        }
+    }
+
+    target_runtimes.sort();
+    target_runtimes.dedup();
+
+    if context.options().offline {
+        action_step!(
+            "Checking",
+            "the dependencies are fetched for {}",
+            target_runtimes.join(", ")
+        );
+    } else {
+        action_step!(
+            "Fetching",
+            "dependencies for {}",
+            target_runtimes.join(", ")
+        );
+    }
+
+    let ws = context.workspace()?;
+    let fetch_options = FetchOptions {
+        config: ws.config(),
+        targets: target_runtimes,
+    };
+
+    fetch(&ws, &fetch_options).map(|_| ()).map_err(|err| {
+        if context.options().offline {
+            Error::new("dependencies not fetched")
+                .with_kind(ErrorKind::Configuration)
+                .with_code(ErrorCode::DependenciesNotFetched)
+                .with_source(err)
+                .with_explanation(
+                    "`--offline` was specified but some dependencies are not in the Cargo cache. Run `cargo monorepo fetch` for the same packages with network access first.",
+                )
+        } else {
+            Error::new("failed to fetch dependencies")
+                .with_kind(ErrorKind::Network)
+                .with_source(err)
+        }
+    })
+}
+
+/// Compile the packages whose distribution targets are about to be built
+/// with a single Cargo invocation per target runtime, rather than one per
+/// distribution target.
+///
+/// The binaries are recorded in the context, where `build_binaries` finds
+/// them. Only the target runtimes shared by several packages are compiled:
+/// the other ones are compiled by their distribution targets as usual, like
+/// everything with `--no-compile` or `--isolated-target-dirs`. As the build
+/// information differs between packages, nothing is compiled in advance when
+/// it is enabled.
+///
+/// Cargo unifies the features of the dependencies of the packages it compiles
+/// together, so only the packages resolving their shared dependencies with the
+/// same features are compiled together: the binaries are then identical to
+/// the ones of a compilation of each package alone.
+pub fn compile_packages(context: &Context, packages: &[Package<'_>]) -> Result<()> {
+    let options = context.options();
+
+    if options.no_compile || options.isolated_target_dirs || context.build_info().is_some() {
+        return Ok(());
+    }
+
+    let staging_directories = StagingDirectories::load(context)?;
+    let mut packages_by_target_runtime: BTreeMap<String, Vec<&Package<'_>>> = BTreeMap::new();
+
+    for package in packages {
+        let hash = package.hash()?;
+
+        for dist_target in package.dist_targets() {
+            // Like `build_dist_targets`, skip the ones that are up-to-date.
+            if !options.force && dist_target.is_up_to_date(&staging_directories, &hash)? {
+                continue;
+            }
+
+            if let Some(target_runtime) = dist_target.host_target_runtime() {
+                let packages = packages_by_target_runtime
+                    .entry(target_runtime.to_string())
+                    .or_default();
+
+                if packages.iter().all(|p| p.id() != package.id()) {
+                    packages.push(package);
+                }
+            }
+        }
+    }
+
+    let mut features_by_package = HashMap::new();
+
+    for (target_runtime, packages) in packages_by_target_runtime {
+        if packages.len() < 2 {
+            continue;
+        }
+
+        for package in &packages {
+            if !features_by_package.contains_key(package.id()) {
+                features_by_package.insert(package.id().clone(), resolved_features(package)?);
+            }
+        }
+
+        let features: Vec<_> = packages
+            .iter()
+            .map(|package| &features_by_package[package.id()])
+            .collect();
+
+        for group in feature_compatible_groups(&features) {
+            if group.len() > 1 {
+                let packages: Vec<_> = group.into_iter().map(|index| packages[index]).collect();
+
+                action_step!(
+                    "Compiling",
+                    "{} packages for {}",
+                    packages.len(),
+                    target_runtime
+                );
+
+                build_packages(context, &packages, &target_runtime)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A group of packages that can be compiled together, with the resolved
+/// features of all their dependencies, by dependency.
+struct FeatureGroup {
+    indices: Vec<usize>,
+    features: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl FeatureGroup {
+    /// Whether the resolved features of a package agree with the ones of the
+    /// group on all the dependencies they share.
+    fn is_compatible(&self, features: &BTreeMap<String, BTreeSet<String>>) -> bool {
+        features.iter().all(|(id, features)| {
+            self.features
+                .get(id)
+                .map_or(true, |group_features| group_features == features)
+        })
+    }
+
+    fn add(&mut self, index: usize, features: &BTreeMap<String, BTreeSet<String>>) {
+        self.indices.push(index);
+        self.features.extend(
+            features
+                .iter()
+                .map(|(id, features)| (id.clone(), features.clone())),
+        );
+    }
+}
+
+/// Group the packages whose resolved features, by dependency, agree on all
+/// the dependencies they share, returning the indices of each group.
+///
+/// Packages are added to the first compatible group, in order.
+fn feature_compatible_groups(features: &[&BTreeMap<String, BTreeSet<String>>]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<FeatureGroup> = Vec::new();
+
+    for (index, package_features) in features.iter().enumerate() {
+        match groups
+            .iter_mut()
+            .find(|group| group.is_compatible(package_features))
+        {
+            Some(group) => group.add(index, package_features),
+            None => groups.push(FeatureGroup {
+                indices: vec![index],
+                features: (*package_features).clone(),
+            }),
+        }
+    }
+
+    groups.into_iter().map(|group| group.indices).collect()
+}
+
+/// Compile several packages for a target runtime in a single Cargo
+/// invocation, recording their binaries in the context.
+///
+/// Like with `cargo build -p a -p b`, the features of the dependencies are
+/// unified across the packages, which must therefore resolve them
+/// identically.
+fn build_packages(
+    context: &Context,
+    packages: &[&Package<'_>],
+    target_runtime: &str,
+) -> Result<()> {
+    let ws = context.workspace()?;
+    let mut compile_options = compile_options(context, packages, &ws);
+
+    compile_options.build_config.requested_kinds = vec![compile_kind(target_runtime)?];
+
+    let package_names: Vec<_> = packages.iter().map(|package| package.name()).collect();
+    let span = phase_span!(
+        "compile",
+        package = %package_names.join(", "),
+        target_runtime
+    );
+
+    let compilation = in_span(&span, || {
+        compile(&ws, &compile_options).map_err(|err| {
+            Error::new("failed to compile binaries")
+                .with_kind(ErrorKind::Compilation)
+                .with_code(ErrorCode::CompilationFailed)
+                .with_source(err)
+        })
+    })?;
+
+    // Packages without binaries are recorded as well, so that they are not
+    // compiled again.
+    let mut compiled_binaries: HashMap<String, CompiledBinaries> = packages
+        .iter()
+        .map(|package| (package.name().to_string(), CompiledBinaries::default()))
+        .collect();
+
+    for output in &compilation.binaries {
+        if let Some(compiled) = compiled_binaries.get_mut(output.unit.pkg.name().as_str()) {
+            compiled
+                .binaries
+                .insert(output.unit.target.name().to_string(), output.path.clone());
+        }
+    }
+
+    for output in &compilation.cdylibs {
+        if let Some(compiled) = compiled_binaries.get_mut(output.unit.pkg.name().as_str()) {
+            compiled
+                .cdylibs
+                .insert(output.unit.target.name().to_string(), output.path.clone());
+        }
+    }
+
+    for (package_name, compiled) in compiled_binaries {
+        context.record_compiled_binaries(package_name, target_runtime, compiled);
+    }
+
+    Ok(())
+}
+
+/// The Cargo target directory in which the binaries of a distribution target
+/// are compiled.
+///
+/// With `--isolated-target-dirs`, each distribution target and target runtime
+/// has its own directory in the Cargo target directory, at the cost of
+/// compiling the dependencies once per directory.
+fn cargo_target_dir(
+    package: &Package<'_>,
+    dist_target_name: &str,
+    target_runtime: &str,
+) -> Result<PathBuf> {
+    let context = package.context();
+    let cargo_target_dir = context.cargo_target_dir()?;
+
+    if context.options().isolated_target_dirs {
+        Ok(cargo_target_dir
+            .join(ISOLATED_TARGET_DIRS_DIR_NAME)
+            .join(package.name())
+            .join(dist_target_name)
+            .join(target_runtime))
+    } else {
+        Ok(cargo_target_dir)
+    }
+}
+
+/// Run a compilation of a package with its build information in the
+/// environment, if the workspace enables it.
+fn with_build_info<T>(package: &Package<'_>, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    match package.context().build_info() {
+        Some(build_info) => build_info.with_build_info(package, f),
+        None => f(),
+    }
+}
+
+fn compile_options(
+    context: &Context,
+    packages: &[&Package<'_>],
+    ws: &cargo::core::Workspace<'_>,
+) -> CompileOptions {
+    let mut compile_options = CompileOptions::new(ws.config(), CompileMode::Build).unwrap();
+
+    compile_options.spec = cargo::ops::Packages::Packages(
+        packages
+            .iter()
+            .map(|package| package.name().to_string())
+            .collect(),
+    );
+    compile_options.build_config.requested_profile =
+        cargo::util::interning::InternedString::new(&context.options().mode.to_string());
+
+    if let Some(jobs) = context.options().jobs {
+        compile_options.build_config.jobs = jobs;
+    }
+
+    compile_options
+}
+
+fn compile_kind(target_runtime: &str) -> Result<CompileKind> {
+    if is_current_target_runtime(target_runtime)? {
+        return Ok(CompileKind::Host);
+    }
+
+    check_target_runtime(target_runtime)?;
+
+    CompileTarget::new(target_runtime)
+        .map(CompileKind::Target)
+        .map_err(|err| {
+            Error::new("invalid target runtime")
+                .with_kind(ErrorKind::Configuration)
+                .with_source(err)
+                .with_explanation(format!(
+                    "The target runtime `{}` is not supported by Cargo.",
+                    target_runtime
+                ))
+        })
+}
+
+/// Check whether a target runtime is a custom target specification file,
+/// like `targets/my-kernel.json`, rather than a target triple.
+pub(crate) fn is_target_spec_file(target_runtime: &str) -> bool {
+    Path::new(target_runtime)
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Check that a target runtime is a valid target triple, installed for the
+/// current toolchain.
+///
+/// Target specification files (ending with `.json`) are not checked. The
+/// installation is only checked when the toolchain is managed by rustup.
+pub fn check_target_runtime(target_runtime: &str) -> Result<()> {
+    if is_target_spec_file(target_runtime) {
+        return Ok(());
+    }
+
+    if !is_valid_target_runtime(target_runtime) {
+        return Err(Error::new("invalid target runtime")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(format!(
+                "The target runtime `{}` is not a valid target triple. Target triples look like `x86_64-unknown-linux-gnu`: run `rustc --print target-list` for the supported ones.",
+                target_runtime
+            )));
+    }
+
+    let installed_target_runtimes = match get_installed_target_runtimes()? {
+        Some(installed_target_runtimes) => installed_target_runtimes,
+        None => return Ok(()),
+    };
+
+    if installed_target_runtimes
+        .iter()
+        .any(|installed| installed == target_runtime)
+    {
+        return Ok(());
+    }
+
+    Err(
+        Error::new("target runtime not installed")
+            .with_kind(ErrorKind::Configuration)
+            .with_code(ErrorCode::TargetRuntimeNotInstalled)
+            .with_explanation(format!(
+                "The target runtime `{}` is not installed for the current toolchain, which has the following targets installed: {}. Run `rustup target add {}` to install it.",
+                target_runtime,
+                installed_target_runtimes
+                    .iter()
+                    .map(|installed| format!("`{}`", installed))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                target_runtime
+            )),
+    )
+}
+
+/// Whether a target runtime has the shape of a target triple, such as
+/// `x86_64-unknown-linux-gnu` or `wasm32-wasi`.
+fn is_valid_target_runtime(target_runtime: &str) -> bool {
+    let components: Vec<&str> = target_runtime.split('-').collect();
+
+    (2..=4).contains(&components.len())
+        && components.iter().all(|component| {
+            !component.is_empty()
+                && component
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.')
+        })
+}
+
+/// The target runtimes installed for the current toolchain, or `None` if the
+/// toolchain is not managed by rustup.
+fn get_installed_target_runtimes() -> Result<Option<Vec<String>>> {
+    let output = match Command::new("rustup")
+        .args(["target", "list", "--installed"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            debug!(
+                "Could not run rustup ({}): not checking the installed target runtimes",
+                err
+            );
+
+            return Ok(None);
+        }
+    };
+
+    if !output.status.success() {
+        return Err(Error::new("failed to list installed target runtimes")
+            .with_explanation("The command `rustup target list --installed` failed.")
+            .with_output(String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    ))
+}
+
+/// Find the outputs of a previous build of a package in the specified Cargo
+/// target directory, following its layout.
+fn find_existing_binaries(
+    package: &Package<'_>,
+    target_dir: &Path,
+    target_runtime: &str,
+    include_cdylibs: bool,
+) -> Result<HashMap<String, PathBuf>> {
+    let context = package.context();
+    let mut output_dir = target_dir.to_path_buf();
+
+    if !is_current_target_runtime(target_runtime)? {
+        output_dir = output_dir.join(target_runtime);
+    }
+
+    let output_dir = output_dir.join(context.options().mode.to_string());
+    let mut binaries = HashMap::new();
+
+    for build_target in package.package_metadata().build_targets() {
+        let file_name = match (build_target.id(), build_target.kind()) {
+            (BuildTargetId::Binary(name), _) => {
+                format!("{}{}", name, executable_suffix(target_runtime))
+            }
+            (BuildTargetId::Library, BuildTargetKind::LibraryOrExample(crate_types))
+                if include_cdylibs && crate_types.iter().any(|t| t == "cdylib") =>
+            {
+                cdylib_file_name(&build_target.name().replace('-', "_"), target_runtime)
+            }
+            _ => continue,
+        };
+
+        let path = output_dir.join(file_name);
+
+        if !path.exists() {
+            return Err(Error::new("failed to find existing binary").with_explanation(format!(
+                "`--no-compile` was specified but `{}` does not exist. Was the package built for the `{}` target runtime in {} mode?",
+                path.display(),
+                target_runtime,
+                context.options().mode
+            )));
+        }
+
+        binaries.insert(build_target.name().to_string(), path);
+    }
+
+    Ok(binaries)
+}
+
+pub(crate) fn executable_suffix(target_runtime: &str) -> &'static str {
+    if target_runtime.starts_with("wasm32") {
+        ".wasm"
+    } else if target_runtime.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    }
+}
+
+fn cdylib_file_name(name: &str, target_runtime: &str) -> String {
+    if target_runtime.starts_with("wasm32") {
+        format!("{}.wasm", name)
+    } else if target_runtime.contains("windows") {
+        format!("{}.dll", name)
+    } else if target_runtime.contains("apple") {
+        format!("lib{}.dylib", name)
+    } else {
+        format!("lib{}.so", name)
+    }
+}
 
 pub fn is_current_target_runtime(target_runtime: &str) -> Result<bool> {
     let current_target_runtime = get_current_target_runtime()?;
@@ -95,6 +742,73 @@ mod tests {
         assert!(get_current_target_runtime().is_ok());
     }
 
+    #[test]
+    fn test_is_valid_target_runtime() {
+        assert!(is_valid_target_runtime("x86_64-unknown-linux-gnu"));
+        assert!(is_valid_target_runtime("aarch64-apple-darwin"));
+        assert!(is_valid_target_runtime("wasm32-wasi"));
+        assert!(is_valid_target_runtime("thumbv7neon-linux-androideabi"));
+        assert!(!is_valid_target_runtime(""));
+        assert!(!is_valid_target_runtime("linux"));
+        assert!(!is_valid_target_runtime("x86_64--linux-gnu"));
+        assert!(!is_valid_target_runtime("x86_64-unknown-linux-gnu "));
+        assert!(!is_valid_target_runtime("X86_64-unknown-linux-gnu"));
+        assert!(!is_valid_target_runtime("a-b-c-d-e"));
+    }
+
+    #[test]
+    fn test_is_target_spec_file() {
+        assert!(is_target_spec_file("targets/my-kernel.json"));
+        assert!(is_target_spec_file("MY-KERNEL.JSON"));
+        assert!(!is_target_spec_file("x86_64-unknown-linux-gnu"));
+        assert!(!is_target_spec_file("targets/json"));
+    }
+
+    #[test]
+    fn test_feature_compatible_groups() {
+        let features = |entries: &[(&str, &[&str])]| -> BTreeMap<String, BTreeSet<String>> {
+            entries
+                .iter()
+                .map(|(id, features)| {
+                    (
+                        id.to_string(),
+                        features.iter().map(ToString::to_string).collect(),
+                    )
+                })
+                .collect()
+        };
+
+        let a = features(&[("a", &[]), ("serde", &["derive", "std"])]);
+        let b = features(&[("b", &[]), ("serde", &["std"])]);
+        let c = features(&[
+            ("c", &[]),
+            ("serde", &["derive", "std"]),
+            ("tokio", &["rt"]),
+        ]);
+        let d = features(&[("d", &[]), ("tokio", &["rt", "macros"])]);
+        let e = features(&[("e", &[]), ("log", &[])]);
+
+        assert_eq!(feature_compatible_groups(&[]), Vec::<Vec<usize>>::new());
+        assert_eq!(
+            feature_compatible_groups(&[&a, &b, &c, &d, &e]),
+            vec![vec![0, 2, 4], vec![1, 3]]
+        );
+    }
+
+    #[test]
+    fn test_cdylib_file_name() {
+        assert_eq!(cdylib_file_name("foo", "wasm32-wasi"), "foo.wasm");
+        assert_eq!(cdylib_file_name("foo", "x86_64-pc-windows-msvc"), "foo.dll");
+        assert_eq!(
+            cdylib_file_name("foo", "aarch64-apple-darwin"),
+            "libfoo.dylib"
+        );
+        assert_eq!(
+            cdylib_file_name("foo", "x86_64-unknown-linux-gnu"),
+            "libfoo.so"
+        );
+    }
+
     #[test]
     fn test_unquote() {
         assert_eq!(unquote("\"foo\"").unwrap(), "foo");
@@ -0,0 +1,491 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::{debug, warn};
+use walkdir::WalkDir;
+
+use crate::{
+    action_step,
+    aws::{aws_settings, s3_object_exists, AwsSettings},
+    dist_target::{clean_dir, copy_extra_files, published_artifact},
+    ignore_step,
+    metadata::CopyPlan,
+    process::run_command,
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, Result,
+};
+
+use super::{CacheControlOverride, S3SyncMetadata};
+
+/// The prefix, relative to the bucket prefix, under which the published
+/// versions are recorded.
+const VERSION_MARKERS_PREFIX: &str = ".cargo-monorepo";
+
+pub struct S3SyncDistTarget<'g> {
+    pub package: &'g Package<'g>,
+    pub metadata: S3SyncMetadata,
+}
+
+impl Display for S3SyncDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "s3-sync[{}]", self.package.name())
+    }
+}
+
+impl<'g> S3SyncDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    pub fn build(&self) -> Result<()> {
+        self.clean()?;
+
+        let copy_plan = self.copy_plan()?;
+
+        if let Some(build_command) = &self.metadata.build_command {
+            self.run_build_command(build_command)?;
+        }
+
+        // Extra files may purposely replace the outputs of the build command.
+        copy_extra_files(self.package, &copy_plan, &[])?;
+
+        Ok(())
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!("`--force` specified: not checking for the version existence before syncing");
+        } else if self.context().artifact_exists(&reference, self.exists())? {
+            ignore_step!(
+                "Up-to-date",
+                "version {} was already synced to S3 bucket `{}`",
+                self.package.version(),
+                &self.metadata.s3_bucket
+            );
+
+            return Ok(Some(published_artifact(
+                self.package,
+                self,
+                reference,
+                None,
+                true,
+            )));
+        }
+
+        let files = self.files()?;
+
+        if self.context().options().dry_run {
+            warn!(
+                "`--dry-run` specified, will not really sync {} file(s) to S3",
+                files.len()
+            );
+
+            return Ok(None);
+        }
+
+        self.context().block_on(async move {
+            let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+            action_step!(
+                "Uploading",
+                "{} file(s) to S3 bucket `{}`",
+                files.len(),
+                &self.metadata.s3_bucket
+            );
+
+            for file in &files {
+                self.upload_file(&client, file).await?;
+            }
+
+            // The marker is written last so that an interrupted sync is
+            // retried on the next publication.
+            client
+                .put_object()
+                .bucket(&self.metadata.s3_bucket)
+                .key(self.version_marker_key())
+                .body(self.package.hash()?.into_bytes().into())
+                .send()
+                .await
+                .map_err(|err| self.upload_error(err))?;
+
+            if let Some(distribution_id) = &self.metadata.cloudfront_distribution_id {
+                self.invalidate_cloudfront_distribution(distribution_id)
+                    .await?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            reference,
+            None,
+            false,
+        )))
+    }
+
+    /// Check whether the current version was synced to the S3 bucket.
+    ///
+    /// As the synced files are overwritten by every version, a marker object
+    /// is written for each published version.
+    pub async fn exists(&self) -> Result<bool> {
+        let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+        s3_object_exists(
+            &client,
+            &self.metadata.s3_bucket,
+            &self.version_marker_key(),
+        )
+        .await
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(format!(
+            "s3://{}/{}",
+            &self.metadata.s3_bucket,
+            self.version_marker_key()
+        ))
+    }
+
+    /// Synced directories have no notion of channels: promotion is a no-op.
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    pub fn promote(&self, _channel: &str) -> Result<()> {
+        ignore_step!(
+            "Unsupported",
+            "synced directories can't be promoted as they have no notion of channels"
+        );
+
+        Ok(())
+    }
+
+    async fn upload_file(&self, client: &aws_sdk_s3::Client, path: &Path) -> Result<()> {
+        let relative_path = self.relative_path(path)?;
+        let s3_key = format!("{}{}", self.s3_bucket_prefix(), relative_path);
+        let content_type = mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string();
+
+        debug!(
+            "Uploading `{}` to `{}` with content type `{}`",
+            path.display(),
+            s3_key,
+            content_type
+        );
+
+        let data = aws_sdk_s3::ByteStream::from_path(path)
+            .await
+            .map_err(|err| Error::new("failed to read file on disk").with_source(err))?;
+
+        let mut request = client
+            .put_object()
+            .bucket(&self.metadata.s3_bucket)
+            .key(&s3_key)
+            .content_type(content_type)
+            .body(data);
+
+        if let Some(cache_control) = cache_control(
+            &self.metadata.cache_control_overrides,
+            self.metadata.cache_control.as_deref(),
+            &relative_path,
+        )? {
+            request = request.cache_control(cache_control);
+        }
+
+        request.send().await.map_err(|err| self.upload_error(err))?;
+
+        Ok(())
+    }
+
+    fn upload_error(&self, err: impl Into<anyhow::Error>) -> Error {
+        Error::new("failed to upload file on S3")
+            .with_kind(ErrorKind::Network)
+            .with_source(err)
+            .with_explanation(format!(
+                "Please check that the S3 bucket `{}` exists and that you have the correct permissions.",
+                &self.metadata.s3_bucket
+            ))
+    }
+
+    async fn invalidate_cloudfront_distribution(&self, distribution_id: &str) -> Result<()> {
+        let client = self
+            .context()
+            .aws()
+            .cloudfront_client(&self.aws_settings())
+            .await?;
+
+        let path = format!("/{}*", self.s3_bucket_prefix());
+
+        action_step!(
+            "Invalidating",
+            "CloudFront distribution `{}` for `{}`",
+            distribution_id,
+            &path
+        );
+
+        client
+            .create_invalidation()
+            .distribution_id(distribution_id)
+            .invalidation_batch(
+                aws_sdk_cloudfront::model::InvalidationBatch::builder()
+                    .caller_reference(format!(
+                        "cargo-monorepo-{}-{}",
+                        self.package.name(),
+                        self.package.hash()?
+                    ))
+                    .paths(
+                        aws_sdk_cloudfront::model::Paths::builder()
+                            .quantity(1)
+                            .items(path)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| {
+                Error::new("failed to invalidate CloudFront distribution")
+                    .with_kind(ErrorKind::Network)
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "Please check that the CloudFront distribution `{}` exists and that you have the correct permissions.",
+                        distribution_id
+                    ))
+            })?;
+
+        Ok(())
+    }
+
+    fn aws_settings(&self) -> AwsSettings {
+        aws_settings!(self.metadata)
+    }
+
+    /// The prefix of the S3 keys, qualified with the preview identifier, if
+    /// any.
+    fn s3_bucket_prefix(&self) -> String {
+        self.context()
+            .options()
+            .preview_s3_prefix(&self.metadata.s3_bucket_prefix)
+    }
+
+    fn version_marker_key(&self) -> String {
+        version_marker_key(
+            &self.s3_bucket_prefix(),
+            self.package.name(),
+            self.package.version(),
+        )
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let sync_root = self.sync_root();
+        let mut files = Vec::new();
+
+        for entry in WalkDir::new(&sync_root) {
+            let entry = entry.map_err(|err| {
+                Error::new("failed to walk sync root directory")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The directory `{}` could not be read. Has this target been built before attempting its publication?",
+                        sync_root.display()
+                    ))
+            })?;
+
+            if entry.file_type().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Get the path of a file relative to the sync root, with forward slashes
+    /// as S3 keys require.
+    fn relative_path(&self, path: &Path) -> Result<String> {
+        let relative_path = path
+            .strip_prefix(self.sync_root())
+            .map_err(|err| Error::new("failed to strip sync root directory").with_source(err))?;
+
+        Ok(to_s3_path(relative_path))
+    }
+
+    fn run_build_command(&self, build_command: &[String]) -> Result<()> {
+        let (program, args) = build_command.split_first().ok_or_else(|| {
+            Error::new("invalid build command")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation("The field `build_command` cannot be empty.")
+        })?;
+
+        let mut cmd = Command::new(program);
+
+        cmd.args(args);
+        cmd.current_dir(self.package.root());
+
+        action_step!("Running", "`{}`", build_command.join(" "));
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to run build command",
+        )
+    }
+
+    fn clean(&self) -> Result<()> {
+        clean_dir(&self.sync_root(), "sync root directory")?;
+
+        std::fs::create_dir_all(&self.sync_root())
+            .map_err(Error::from_source)
+            .with_full_context(
+                "could not create the sync root directory",
+                format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", self.sync_root().display()),
+            )
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.sync_root()
+    }
+
+    fn sync_root(&self) -> PathBuf {
+        self.context()
+            .target_root()
+            .unwrap()
+            .join("s3-sync")
+            .join(self.context().options().mode.to_string())
+            .join(self.package.name())
+    }
+
+    fn copy_plan(&self) -> Result<CopyPlan> {
+        CopyPlan::new(
+            &self.metadata.extra_files,
+            self.package.root(),
+            &self.sync_root(),
+        )
+    }
+}
+
+/// Get the `Cache-Control` header for a file.
+///
+/// The overrides are tried in their declaration order and the first one whose
+/// pattern matches the relative path of the file wins. Otherwise, the default
+/// value is used, if any.
+fn cache_control<'a>(
+    overrides: &'a [CacheControlOverride],
+    default: Option<&'a str>,
+    relative_path: &str,
+) -> Result<Option<&'a str>> {
+    for cache_control_override in overrides {
+        let pattern = glob::Pattern::new(&cache_control_override.pattern).map_err(|err| {
+            Error::new("failed to parse cache control pattern")
+                .with_kind(ErrorKind::Configuration)
+                .with_source(err)
+                .with_explanation(format!(
+                    "The pattern `{}` in `cache_control_overrides` could not be parsed. You may want to double-check for syntax errors.",
+                    &cache_control_override.pattern
+                ))
+        })?;
+
+        if pattern.matches(relative_path) {
+            return Ok(Some(&cache_control_override.cache_control));
+        }
+    }
+
+    Ok(default)
+}
+
+/// The key of the marker object recording the publication of a version.
+fn version_marker_key(
+    s3_bucket_prefix: &str,
+    package_name: &str,
+    version: &semver::Version,
+) -> String {
+    format!(
+        "{}{}/{}/v{}",
+        s3_bucket_prefix, VERSION_MARKERS_PREFIX, package_name, version
+    )
+}
+
+/// Join the components of a relative path with forward slashes, as S3 keys
+/// require.
+fn to_s3_path(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_control() {
+        let metadata: S3SyncMetadata = toml::from_str(
+            r#"
+            s3_bucket = "some-s3-bucket"
+            cache_control = "max-age=31536000, immutable"
+
+            [[cache_control_overrides]]
+            pattern = "index.html"
+            cache_control = "no-store"
+
+            [[cache_control_overrides]]
+            pattern = "*.html"
+            cache_control = "no-cache"
+            "#,
+        )
+        .unwrap();
+
+        let header = |relative_path| {
+            cache_control(
+                &metadata.cache_control_overrides,
+                metadata.cache_control.as_deref(),
+                relative_path,
+            )
+            .unwrap()
+        };
+
+        // The first matching override wins, in declaration order.
+        assert_eq!(header("index.html"), Some("no-store"));
+        assert_eq!(header("about.html"), Some("no-cache"));
+        assert_eq!(
+            header("assets/main.js"),
+            Some("max-age=31536000, immutable")
+        );
+
+        assert!(cache_control(
+            &[CacheControlOverride {
+                pattern: "[".to_string(),
+                cache_control: "no-cache".to_string(),
+            }],
+            None,
+            "index.html",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_version_marker_key() {
+        assert_eq!(
+            version_marker_key("site/", "my-site", &semver::Version::new(1, 2, 3)),
+            "site/.cargo-monorepo/my-site/v1.2.3"
+        );
+        assert_eq!(
+            version_marker_key("", "my-site", &semver::Version::new(0, 1, 0)),
+            ".cargo-monorepo/my-site/v0.1.0"
+        );
+    }
+
+    #[test]
+    fn test_to_s3_path() {
+        assert_eq!(
+            to_s3_path(&Path::new("assets").join("css").join("main.css")),
+            "assets/css/main.css"
+        );
+        assert_eq!(to_s3_path(Path::new("index.html")), "index.html");
+    }
+}
@@ -0,0 +1,61 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::AwsCredentialsEnv, condition::Enabled, dist_target::DistTarget, metadata::CopyCommand,
+    s3_sync::S3SyncDistTarget, Package,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct S3SyncMetadata {
+    pub s3_bucket: String,
+    #[serde(default)]
+    pub s3_bucket_prefix: String,
+    #[serde(default)]
+    pub build_command: Option<Vec<String>>,
+    #[serde(default)]
+    pub cache_control: Option<String>,
+    #[serde(default)]
+    pub cache_control_overrides: Vec<CacheControlOverride>,
+    #[serde(default)]
+    pub cloudfront_distribution_id: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default)]
+    pub aws_profile: Option<String>,
+    #[serde(default)]
+    pub assume_role_arn: Option<String>,
+    #[serde(default)]
+    pub assume_role_external_id: Option<String>,
+    #[serde(default)]
+    pub credentials_env: Option<AwsCredentialsEnv>,
+    #[serde(default)]
+    pub extra_files: Vec<CopyCommand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+/// A `Cache-Control` header for the files whose relative path matches a
+/// pattern.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CacheControlOverride {
+    pub pattern: String,
+    pub cache_control: String,
+}
+
+impl S3SyncMetadata {
+    pub(crate) fn into_dist_target<'g>(self, package: &'g Package<'g>) -> DistTarget<'g> {
+        DistTarget::S3Sync(S3SyncDistTarget {
+            package,
+            metadata: self,
+        })
+    }
+}
@@ -0,0 +1,5 @@
+mod dist_target;
+mod metadata;
+
+pub use dist_target::S3SyncDistTarget;
+pub use metadata::{CacheControlOverride, S3SyncMetadata};
@@ -0,0 +1,295 @@
+//! Ordering of the publication of distribution targets across packages.
+
+use log::debug;
+
+use crate::{
+    action_step, metadata::DistTargetMetadata, Error, ErrorKind, Package, PublishState,
+    PublishedArtifact, Result,
+};
+
+/// A distribution target scheduled for publication.
+///
+/// The distribution targets expanded from a matrix are scheduled separately.
+struct ScheduledDistTarget {
+    /// The index of the package of the distribution target.
+    package: usize,
+    name: String,
+    metadata: DistTargetMetadata,
+}
+
+/// The outcome of the publication of the distribution targets of several
+/// packages.
+pub struct Publication {
+    /// The published artifacts of each package, in the order of the packages.
+    pub artifacts: Vec<Vec<PublishedArtifact>>,
+    /// The error that interrupted the publication, if any, in which case
+    /// `artifacts` holds the artifacts published before it.
+    pub error: Option<Error>,
+}
+
+/// Publish the distribution targets of the specified packages, making sure the
+/// distribution targets they depend on are published first.
+///
+/// Distribution targets are published in batches: the distribution targets of
+/// a batch only depend on distribution targets of the previous batches.
+/// Dependencies on packages that are not selected must already be published.
+///
+/// A failed publication still returns the artifacts published before the
+/// failure, so that they can be reported.
+pub fn publish_packages(packages: &[Package<'_>], state: &mut PublishState) -> Result<Publication> {
+    let mut provenances = Vec::with_capacity(packages.len());
+
+    for package in packages {
+        provenances.push(if package.is_publishable()? {
+            Some(package.provenance()?)
+        } else {
+            None
+        });
+    }
+
+    let dist_targets: Vec<ScheduledDistTarget> = packages
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| provenances[*index].is_some())
+        .flat_map(|(index, package)| {
+            package
+                .monorepo_metadata()
+                .dist_targets
+                .iter()
+                .flat_map(move |(name, metadata)| {
+                    metadata
+                        .expand()
+                        .into_iter()
+                        .map(move |metadata| ScheduledDistTarget {
+                            package: index,
+                            name: name.clone(),
+                            metadata,
+                        })
+                })
+        })
+        .collect();
+
+    let mut dependencies = Vec::with_capacity(dist_targets.len());
+
+    for dist_target in &dist_targets {
+        let mut dist_target_dependencies = Vec::new();
+
+        for dependency in dist_target.metadata.depends_on() {
+            dist_target_dependencies.extend(resolve_dependency(
+                packages,
+                &dist_targets,
+                dist_target,
+                dependency,
+            )?);
+        }
+
+        dependencies.push(dist_target_dependencies);
+    }
+
+    let batches = batches(&dependencies).map_err(|cycle| {
+        Error::new("circular distribution target dependencies")
+            .with_kind(ErrorKind::Configuration)
+            .with_explanation(format!(
+                "The following distribution targets depend on each other: {}.",
+                cycle
+                    .into_iter()
+                    .map(|index| dist_target_id(packages, &dist_targets[index]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+    })?;
+
+    let mut artifacts = vec![Vec::new(); packages.len()];
+
+    for (batch_index, batch) in batches.iter().enumerate() {
+        if batches.len() > 1 {
+            action_step!(
+                "Batch",
+                "{}/{}: {}",
+                batch_index + 1,
+                batches.len(),
+                batch
+                    .iter()
+                    .map(|index| dist_target_id(packages, &dist_targets[*index]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        for index in batch {
+            let scheduled = &dist_targets[*index];
+            let package = &packages[scheduled.package];
+            let provenance = provenances[scheduled.package]
+                .as_ref()
+                .expect("only publishable packages are scheduled");
+            let dist_target = scheduled
+                .metadata
+                .to_dist_target(scheduled.name.clone(), package);
+
+            match package.publish_dist_target(&dist_target, provenance, state) {
+                Ok(artifact) => artifacts[scheduled.package].extend(artifact),
+                Err(err) => {
+                    return Ok(Publication {
+                        artifacts,
+                        error: Some(err),
+                    })
+                }
+            }
+        }
+    }
+
+    Ok(Publication {
+        artifacts,
+        error: None,
+    })
+}
+
+/// Resolve a dependency of a distribution target to the scheduled
+/// distribution targets it designates.
+///
+/// If the dependency designates no scheduled distribution target, it must
+/// designate existing and already published distribution targets.
+fn resolve_dependency(
+    packages: &[Package<'_>],
+    dist_targets: &[ScheduledDistTarget],
+    dependant: &ScheduledDistTarget,
+    dependency: &str,
+) -> Result<Vec<usize>> {
+    let (package_name, name) = match dependency.split_once('/') {
+        Some((package_name, name)) => (package_name, Some(name)),
+        None => (dependency, None),
+    };
+
+    let scheduled: Vec<usize> = dist_targets
+        .iter()
+        .enumerate()
+        .filter(|(_, dist_target)| {
+            packages[dist_target.package].name() == package_name
+                && name.map_or(true, |name| name == dist_target.name)
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    if !scheduled.is_empty() {
+        return Ok(scheduled);
+    }
+
+    let dependant_package = &packages[dependant.package];
+    let context = dependant_package.context();
+    let package = context.resolve_package_by_name(package_name)?;
+    let dist_targets: Vec<_> = package
+        .monorepo_metadata()
+        .dist_targets
+        .iter()
+        .filter(|(dist_target_name, _)| name.map_or(true, |name| name == dist_target_name.as_str()))
+        .flat_map(|(dist_target_name, metadata)| {
+            metadata
+                .expand()
+                .into_iter()
+                .map(|metadata| metadata.to_dist_target(dist_target_name.clone(), &package))
+        })
+        .collect();
+
+    if dist_targets.is_empty() {
+        return Err(
+            Error::new("unknown distribution target dependency")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The distribution target `{}` depends on `{}` which does not designate any distribution target.",
+                    dist_target_id(packages, dependant),
+                    dependency
+                )),
+        );
+    }
+
+    if context.options().dry_run {
+        debug!(
+            "`--dry-run` specified: not checking that `{}` is published",
+            dependency
+        );
+
+        return Ok(vec![]);
+    }
+
+    // Disabled distribution targets are never published.
+    for dist_target in dist_targets
+        .iter()
+        .filter(|dist_target| dist_target.is_enabled())
+    {
+        if !context.artifact_exists(&dist_target.artifact_reference()?, dist_target.exists())? {
+            return Err(
+                Error::new("unpublished distribution target dependency")
+                    .with_kind(ErrorKind::Validation)
+                    .with_explanation(format!(
+                        "The distribution target `{}` depends on {} v{} which is not published. Select the package `{}` to publish it first.",
+                        dist_target_id(packages, dependant),
+                        dist_target,
+                        package.version(),
+                        package.name()
+                    )),
+            );
+        }
+    }
+
+    Ok(vec![])
+}
+
+fn dist_target_id(packages: &[Package<'_>], dist_target: &ScheduledDistTarget) -> String {
+    format!(
+        "{}/{}",
+        packages[dist_target.package].name(),
+        dist_target.name
+    )
+}
+
+/// Group nodes in batches, so that the nodes of a batch only depend on nodes
+/// of the previous batches.
+///
+/// Nodes keep their relative order within a batch. If some nodes depend on
+/// each other, they are returned as an error.
+fn batches(dependencies: &[Vec<usize>]) -> std::result::Result<Vec<Vec<usize>>, Vec<usize>> {
+    let mut done = vec![false; dependencies.len()];
+    let mut remaining: Vec<usize> = (0..dependencies.len()).collect();
+    let mut batches = Vec::new();
+
+    while !remaining.is_empty() {
+        let (ready, blocked): (Vec<usize>, Vec<usize>) = remaining.into_iter().partition(|index| {
+            dependencies[*index]
+                .iter()
+                .all(|dependency| done[*dependency])
+        });
+
+        if ready.is_empty() {
+            return Err(blocked);
+        }
+
+        for index in &ready {
+            done[*index] = true;
+        }
+
+        batches.push(ready);
+        remaining = blocked;
+    }
+
+    Ok(batches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batches() {
+        assert_eq!(batches(&[]), Ok(vec![]));
+        assert_eq!(batches(&[vec![], vec![]]), Ok(vec![vec![0, 1]]));
+        assert_eq!(
+            batches(&[vec![2], vec![], vec![1], vec![]]),
+            Ok(vec![vec![1, 3], vec![2], vec![0]])
+        );
+        assert_eq!(
+            batches(&[vec![], vec![2], vec![1], vec![0]]),
+            Err(vec![1, 2])
+        );
+        assert_eq!(batches(&[vec![0]]), Err(vec![0]));
+    }
+}
@@ -0,0 +1,221 @@
+//! Reporting of the sizes of the built artifacts, and enforcement of their
+//! size budgets.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{action_step, staging::format_size, Error, ErrorCode, ErrorContext, ErrorKind, Result};
+
+/// A size in bytes, written either as a number of bytes or as a string with
+/// a unit, like `50 MiB` or `1.5GB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (value, unit) = s.split_at(split);
+
+        let value: f64 = value
+            .parse()
+            .map_err(|err| format!("invalid size `{}`: {}", s, err))?;
+
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1000,
+            "kib" => 1 << 10,
+            "mb" => 1000 * 1000,
+            "mib" => 1 << 20,
+            "gb" => 1000 * 1000 * 1000,
+            "gib" => 1 << 30,
+            unit => {
+                return Err(format!(
+                    "unknown size unit `{}`, expected one of `B`, `kB`, `KiB`, `MB`, `MiB`, `GB` or `GiB`",
+                    unit
+                ))
+            }
+        };
+
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        Ok(Self((value * multiplier as f64) as u64))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format_size(self.0))
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Helper {
+            Bytes(u64),
+            WithUnit(String),
+        }
+
+        match Helper::deserialize(deserializer)? {
+            Helper::Bytes(bytes) => Ok(Self(bytes)),
+            Helper::WithUnit(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl JsonSchema for ByteSize {
+    fn schema_name() -> String {
+        "ByteSize".to_string()
+    }
+
+    /// A number of bytes, or a string with a unit.
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![
+                    gen.subschema_for::<u64>(),
+                    gen.subschema_for::<String>(),
+                ]),
+                ..SubschemaValidation::default()
+            })),
+            ..SchemaObject::default()
+        }
+        .into()
+    }
+}
+
+/// The maximum sizes of the outputs of a distribution target, which fail the
+/// build when exceeded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SizeBudget {
+    /// The maximum size of each binary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<ByteSize>,
+    /// The maximum size of the artifact: the Docker image or the AWS Lambda
+    /// archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<ByteSize>,
+}
+
+impl SizeBudget {
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.binary.is_none() && self.artifact.is_none()
+    }
+
+    /// Report the size of the binaries built for a distribution target,
+    /// failing if any exceeds the budget.
+    pub(crate) fn check_binaries<'a>(
+        &self,
+        dist_target: impl Display,
+        binaries: impl IntoIterator<Item = (&'a String, &'a PathBuf)>,
+    ) -> Result<()> {
+        let mut binaries: Vec<_> = binaries.into_iter().collect();
+        binaries.sort();
+
+        for (name, path) in binaries {
+            let size = file_size(path)?;
+
+            action_step!("Measured", "binary `{}`: {}", name, format_size(size));
+
+            check_budget(
+                &format!("The binary `{}` of {}", name, dist_target),
+                size,
+                self.binary,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Report the size of the artifact of a distribution target, failing if
+    /// it exceeds the budget.
+    pub(crate) fn check_artifact(
+        &self,
+        dist_target: impl Display,
+        description: &str,
+        size: u64,
+    ) -> Result<()> {
+        action_step!(
+            "Measured",
+            "{} of {}: {}",
+            description,
+            dist_target,
+            format_size(size)
+        );
+
+        check_budget(
+            &format!("The {} of {}", description, dist_target),
+            size,
+            self.artifact,
+        )
+    }
+}
+
+fn check_budget(subject: &str, size: u64, budget: Option<ByteSize>) -> Result<()> {
+    match budget {
+        Some(budget) if size > budget.0 => Err(Error::new("size budget exceeded")
+            .with_kind(ErrorKind::Validation)
+            .with_code(ErrorCode::SizeBudgetExceeded)
+            .with_explanation(format!(
+                "{} weighs {}, which exceeds its size budget of {}. Reduce its size, for instance by stripping the binaries (`strip = true` in the Cargo profile), or raise the budget in `size_budget`.",
+                subject,
+                format_size(size),
+                budget
+            ))),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn file_size(path: &Path) -> Result<u64> {
+    Ok(std::fs::metadata(path)
+        .map_err(Error::from_source)
+        .with_context("failed to read file metadata")?
+        .len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_size_from_str() {
+        assert_eq!("1024".parse(), Ok(ByteSize(1024)));
+        assert_eq!("50 MiB".parse(), Ok(ByteSize(50 << 20)));
+        assert_eq!("1.5GB".parse(), Ok(ByteSize(1_500_000_000)));
+        assert_eq!("2 kib".parse(), Ok(ByteSize(2048)));
+        assert!("50 MiBs".parse::<ByteSize>().is_err());
+        assert!("MiB".parse::<ByteSize>().is_err());
+    }
+}
@@ -1,13 +1,21 @@
 use std::{
     collections::BTreeMap,
+    io::Read,
     iter::once,
     path::{Path, PathBuf},
 };
 
 use cargo::core::Source;
-use serde::Serialize;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use itertools::Itertools;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
 
-use crate::{context::Context, Error, Result};
+use crate::{context::Context, Error, ErrorContext, Result};
+
+/// The name of the file listing the files to exclude from the sources of a
+/// package, with the `.gitignore` syntax.
+pub const IGNORE_FILE_NAME: &str = ".monorepoignore";
 
 /// Represent the sources files for a package.
 ///
@@ -15,8 +23,43 @@ use crate::{context::Context, Error, Result};
 /// belong to - and that can possibly be used by - the package.
 ///
 /// As an exception, the manifest file is never included in this structure.
+///
+/// Files are listed like `cargo package` does, which honors the `include` and
+/// `exclude` fields of the manifest and the `.gitignore` files in Git
+/// repositories. The `.gitignore` and `.monorepoignore` files at the root of
+/// the package are honored in all cases.
+#[derive(Debug, Clone)]
+pub struct Sources {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, SourceFile>,
+}
+
+/// The Git mode of executable files.
+const GIT_EXECUTABLE_MODE: u32 = 0o100_755;
+
+/// The Git mode of symbolic links.
+const GIT_SYMLINK_MODE: u32 = 0o120_000;
+
+/// A source file.
+///
+/// Symbolic links are not followed: their target is recorded instead, so that
+/// replacing a link changes the hash of the package.
 #[derive(Debug, Clone, Serialize)]
-pub struct Sources(BTreeMap<PathBuf, Vec<u8>>);
+#[serde(rename_all = "snake_case")]
+pub enum SourceFile {
+    File {
+        /// Whether the file is executable, read from the Git index for
+        /// tracked files so that it does not depend on the platform.
+        executable: bool,
+        /// The SHA-256 digest of the content of the file.
+        ///
+        /// Only the digest is kept, as packages may embed large assets.
+        digest: String,
+    },
+    Symlink {
+        target: String,
+    },
+}
 
 impl Sources {
     pub fn from_package(
@@ -34,10 +77,14 @@ impl Sources {
                 ))
             })?;
 
-        Self::new(workspace, core_package)
+        Self::new(workspace, core_package, context.normalize_line_endings())
     }
 
-    fn new(workspace: &cargo::core::Workspace<'_>, pkg: &cargo::core::Package) -> Result<Self> {
+    fn new(
+        workspace: &cargo::core::Workspace<'_>,
+        pkg: &cargo::core::Package,
+        normalize_line_endings: bool,
+    ) -> Result<Self> {
         let mut path_source = cargo::sources::PathSource::new(
             pkg.root(),
             pkg.package_id().source_id(),
@@ -48,28 +95,449 @@ impl Sources {
             .update()
             .map_err(|err| Error::new("failed to update path source").with_source(err))?;
 
-        Ok(Self(
-            path_source
-                .list_files(pkg)
-                .map_err(|err| Error::new("failed to list files").with_source(err))?
-                .into_iter()
-                .chain(once(pkg.manifest_path().to_path_buf()))
-                .filter_map(|path| {
-                    (path != pkg.manifest_path()).then(|| Self::read_generic_file(path))
+        let root = comparable_path(pkg.root());
+        let ignore = Self::ignore_rules(pkg.root())?;
+        let git_index = GitIndex::open(pkg.root());
+
+        let files = path_source
+            .list_files(pkg)
+            .map_err(|err| Error::new("failed to list files").with_source(err))?
+            .into_iter()
+            .filter(|path| !ignore.matched_path_or_any_parents(path, false).is_ignore())
+            .chain(once(pkg.manifest_path().to_path_buf()))
+            .filter_map(|path| {
+                (path != pkg.manifest_path()).then(|| {
+                    Self::read_source_file(path, git_index.as_ref(), normalize_line_endings)
                 })
-                .collect::<Result<Vec<(PathBuf, Vec<u8>)>>>()?
-                .into_iter()
-                .collect(),
-        ))
+            })
+            .collect::<Result<Vec<(PathBuf, SourceFile)>>>()?
+            .into_iter()
+            .map(|(path, source_file)| (comparable_path(&path), source_file))
+            .collect();
+
+        Ok(Self { root, files })
+    }
+
+    /// Build the ignore rules from the `.gitignore` and `.monorepoignore`
+    /// files at the root of the package.
+    fn ignore_rules(root: &Path) -> Result<Gitignore> {
+        let mut builder = GitignoreBuilder::new(root);
+
+        for file_name in [".gitignore", IGNORE_FILE_NAME] {
+            let path = root.join(file_name);
+
+            if path.exists() {
+                if let Some(err) = builder.add(&path) {
+                    return Err(Error::from_source(err)).with_full_context(
+                        "failed to parse ignore file",
+                        format!(
+                            "The ignore file `{}` is invalid. You may want to double-check for syntax errors.",
+                            path.display()
+                        ),
+                    );
+                }
+            }
+        }
+
+        builder
+            .build()
+            .map_err(Error::from_source)
+            .with_context("failed to build ignore rules")
     }
 
     pub fn contains(&self, path: &Path) -> bool {
-        self.0.contains_key(path)
+        self.files.contains_key(&comparable_path(path))
+    }
+
+    /// The paths of the source files.
+    pub(crate) fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    fn read_source_file(
+        path: PathBuf,
+        git_index: Option<&GitIndex>,
+        normalize_line_endings: bool,
+    ) -> Result<(PathBuf, SourceFile)> {
+        let metadata = std::fs::symlink_metadata(&path)
+            .map_err(|err| Error::new("failed to read file metadata").with_source(err))?;
+        let git_mode = git_index.and_then(|git_index| git_index.mode(&path));
+
+        let source_file = if metadata.file_type().is_symlink() {
+            let target = std::fs::read_link(&path)
+                .map_err(|err| Error::new("failed to read symbolic link").with_source(err))?;
+
+            SourceFile::Symlink {
+                target: normalize_path(&target),
+            }
+        } else if git_mode == Some(GIT_SYMLINK_MODE) {
+            // Symbolic links are checked out as plain files containing their
+            // target on platforms that do not support them.
+            let target = std::fs::read_to_string(&path)
+                .map_err(|err| Error::new("failed to read file").with_source(err))?;
+
+            SourceFile::Symlink {
+                target: normalize_path(Path::new(&target)),
+            }
+        } else {
+            SourceFile::File {
+                executable: git_mode.map_or_else(
+                    || is_executable(&metadata),
+                    |mode| mode == GIT_EXECUTABLE_MODE,
+                ),
+                digest: file_digest(&path, normalize_line_endings)?,
+            }
+        };
+
+        Ok((path, source_file))
+    }
+}
+
+impl Serialize for Sources {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Paths are made relative to the package root and use forward slashes
+        // so that hashes are identical on all platforms and checkouts.
+        serializer.collect_map(self.files.iter().map(|(path, source_file)| {
+            (
+                normalize_path(path.strip_prefix(&self.root).unwrap_or(path)),
+                source_file,
+            )
+        }))
+    }
+}
+
+/// The Git index of the repository containing a package, used to read the
+/// file modes identically on all platforms.
+struct GitIndex {
+    workdir: PathBuf,
+    index: git2::Index,
+}
+
+impl GitIndex {
+    fn open(root: &Path) -> Option<Self> {
+        let repository = git2::Repository::discover(root).ok()?;
+        let workdir = repository.workdir()?.to_path_buf();
+        let index = repository.index().ok()?;
+
+        Some(Self { workdir, index })
     }
 
-    pub fn read_generic_file(path: PathBuf) -> Result<(PathBuf, Vec<u8>)> {
-        std::fs::read(&path)
-            .map(|bytes| (path, bytes))
-            .map_err(|err| Error::new("failed to read file").with_source(err))
+    /// The mode of a file, if it is tracked.
+    fn mode(&self, path: &Path) -> Option<u32> {
+        let relative_path = path.strip_prefix(&self.workdir).ok()?;
+
+        self.index
+            .get_path(Path::new(&normalize_path(relative_path)), 0)
+            .map(|entry| entry.mode)
+    }
+}
+
+/// The packages of the workspace that own source files.
+///
+/// Unlike `Sources`, this does not list the files of the packages: the owner
+/// of a path is determined from the package roots and from the `include` and
+/// `exclude` fields of the manifests, which is cheap enough to find the
+/// packages affected by changed files in large workspaces.
+pub(crate) struct SourceOwners {
+    /// Sorted from the deepest root, so that nested packages own their files
+    /// rather than the packages around them, like Cargo does.
+    roots: Vec<SourceRoot>,
+}
+
+struct SourceRoot {
+    package_name: String,
+    root: PathBuf,
+    include: Option<Gitignore>,
+    exclude: Gitignore,
+    ignore: Gitignore,
+}
+
+impl SourceOwners {
+    pub(crate) fn new(context: &Context) -> Result<Self> {
+        let workspace = context.workspace()?;
+
+        let mut roots = workspace
+            .members()
+            .map(|pkg| {
+                // The rules are rooted at the comparable root, so that they
+                // match the comparable paths given to `owner`.
+                let root = comparable_path(pkg.root());
+                let manifest = pkg.manifest();
+
+                Ok(SourceRoot {
+                    package_name: pkg.name().to_string(),
+                    include: if manifest.include().is_empty() {
+                        None
+                    } else {
+                        Some(Self::patterns(&root, manifest.include())?)
+                    },
+                    exclude: Self::patterns(&root, manifest.exclude())?,
+                    ignore: Sources::ignore_rules(&root)?,
+                    root,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        roots.sort_by_key(|root| std::cmp::Reverse(root.root.components().count()));
+
+        Ok(Self { roots })
+    }
+
+    /// Build the rules matching the `include` or `exclude` patterns of a
+    /// manifest, which Cargo interprets with the `.gitignore` syntax.
+    fn patterns(root: &Path, patterns: &[String]) -> Result<Gitignore> {
+        let mut builder = GitignoreBuilder::new(root);
+
+        for pattern in patterns {
+            builder
+                .add_line(None, pattern)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to parse manifest patterns",
+                    format!(
+                        "The pattern `{}` in the manifest at `{}` is invalid.",
+                        pattern,
+                        root.display()
+                    ),
+                )?;
+        }
+
+        builder
+            .build()
+            .map_err(Error::from_source)
+            .with_context("failed to build manifest patterns")
+    }
+
+    /// The name of the package that owns the specified path, if any.
+    ///
+    /// Contrary to `Sources`, the manifest of a package belongs to it, as
+    /// changing it can change the package. The path can come from Git or
+    /// from `std::fs::canonicalize`: it is made comparable first.
+    pub(crate) fn owner(&self, path: &Path) -> Option<&str> {
+        let path = &comparable_path(path);
+        let source_root = self
+            .roots
+            .iter()
+            .find(|source_root| path.starts_with(&source_root.root))?;
+
+        let matches =
+            |rules: &Gitignore| rules.matched_path_or_any_parents(path, false).is_ignore();
+
+        let included = source_root.include.as_ref().map_or(true, matches)
+            && !matches(&source_root.exclude)
+            && !matches(&source_root.ignore);
+
+        (included || *path == source_root.root.join("Cargo.toml"))
+            .then(|| source_root.package_name.as_str())
+    }
+}
+
+/// Convert a path to a string with forward slashes as separators.
+fn normalize_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .join("/")
+}
+
+/// Normalize a path so that the paths of a file compare equal whatever their
+/// origin: Cargo, Git, the file system watcher or `std::fs::canonicalize`.
+///
+/// On Windows, `std::fs::canonicalize` returns verbatim paths, like
+/// `\\?\C:\repo`, in which forward slashes are not separators, while Git
+/// reports paths relative to the repository with forward slashes and Cargo
+/// reports paths like `C:\repo`. The verbatim prefix is removed when the path
+/// can be written without it, and the path is rebuilt from its components,
+/// with backslashes as separators and the drive letter in uppercase.
+///
+/// Paths are returned as-is on the other platforms.
+#[cfg(windows)]
+pub(crate) fn comparable_path(path: &Path) -> PathBuf {
+    use std::path::{Component, Prefix};
+
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::VerbatimDisk(disk) | Prefix::Disk(disk) => {
+                    result.push(format!("{}:", char::from(disk).to_ascii_uppercase()));
+                }
+                Prefix::VerbatimUNC(server, share) | Prefix::UNC(server, share) => {
+                    result.push(format!(
+                        r"\\{}\{}",
+                        server.to_string_lossy(),
+                        share.to_string_lossy()
+                    ));
+                }
+                _ => result.push(prefix.as_os_str()),
+            },
+            // Forward slashes were not split in verbatim paths.
+            Component::Normal(name) => result.extend(Path::new(name).components()),
+            component => result.push(component),
+        }
+    }
+
+    result
+}
+
+/// Normalize a path so that the paths of a file compare equal whatever their
+/// origin.
+///
+/// Paths are already comparable on this platform.
+#[cfg(not(windows))]
+pub(crate) fn comparable_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Compute the digest of a file, streaming its content through the hasher.
+///
+/// If `normalize_text` is set, the line endings of text files are
+/// normalized to `\n`, so that checkouts with CRLF line endings hash
+/// identically. Like Git, files are considered to be text if their first bytes
+/// do not contain any NUL byte.
+fn file_digest(path: &Path, normalize_text: bool) -> Result<String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|err| Error::new("failed to open file").with_source(err))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 8192];
+    let mut is_text = None;
+    let mut pending_cr = false;
+
+    loop {
+        let size = file
+            .read(&mut buf)
+            .map_err(|err| Error::new("failed to read file").with_source(err))?;
+
+        if size == 0 {
+            break;
+        }
+
+        let chunk = &buf[..size];
+
+        if normalize_text && *is_text.get_or_insert_with(|| !chunk.contains(&0)) {
+            hasher.update(normalize_line_endings(chunk, &mut pending_cr));
+        } else {
+            hasher.update(chunk);
+        }
+    }
+
+    if pending_cr {
+        hasher.update(b"\r");
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Replace the CRLF sequences of a chunk of text by LF.
+///
+/// `pending_cr` tracks whether the previous chunk ended with a CR, whose
+/// output is deferred until the next byte is known.
+fn normalize_line_endings(chunk: &[u8], pending_cr: &mut bool) -> Vec<u8> {
+    let mut output = Vec::with_capacity(chunk.len() + 1);
+
+    for &byte in chunk {
+        if std::mem::take(pending_cr) && byte != b'\n' {
+            output.push(b'\r');
+        }
+
+        if byte == b'\r' {
+            *pending_cr = true;
+        } else {
+            output.push(byte);
+        }
+    }
+
+    output
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(chunks: &[&str]) -> String {
+        let mut pending_cr = false;
+        let mut output: Vec<u8> = chunks
+            .iter()
+            .flat_map(|chunk| normalize_line_endings(chunk.as_bytes(), &mut pending_cr))
+            .collect();
+
+        if pending_cr {
+            output.push(b'\r');
+        }
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        assert_eq!(normalize(&["a\r\nb\n"]), "a\nb\n");
+        assert_eq!(normalize(&["a\r", "\nb"]), "a\nb");
+        assert_eq!(normalize(&["a\rb\r"]), "a\rb\r");
+        assert_eq!(normalize(&["a\r", "b"]), "a\rb");
+        assert_eq!(normalize(&["\r\r\n"]), "\r\n");
+    }
+
+    #[test]
+    fn test_file_digest() {
+        let root =
+            std::env::temp_dir().join(format!("cargo-monorepo-digest-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("lf.txt"), "a\nb\n").unwrap();
+        std::fs::write(root.join("crlf.txt"), "a\r\nb\r\n").unwrap();
+        std::fs::write(root.join("crlf.bin"), "a\r\n\0b\r\n").unwrap();
+
+        let digest = |name: &str, normalize_text: bool| {
+            file_digest(&root.join(name), normalize_text).unwrap()
+        };
+
+        assert_ne!(digest("lf.txt", false), digest("crlf.txt", false));
+        assert_eq!(digest("lf.txt", true), digest("crlf.txt", true));
+        assert_eq!(digest("crlf.bin", true), digest("crlf.bin", false));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(Path::new("src/bin/main.rs")),
+            "src/bin/main.rs"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_comparable_path() {
+        let expected = Path::new(r"C:\repo\src\lib.rs");
+
+        assert_eq!(comparable_path(Path::new(r"C:\repo\src\lib.rs")), expected);
+        assert_eq!(comparable_path(Path::new(r"c:\repo\src/lib.rs")), expected);
+        assert_eq!(
+            comparable_path(Path::new(r"\\?\C:\repo\src/lib.rs")),
+            expected
+        );
+        assert_eq!(
+            comparable_path(&Path::new(r"\\?\C:\repo").join("src/lib.rs")),
+            expected
+        );
+        assert_eq!(
+            comparable_path(Path::new(r"\\?\UNC\server\share\repo")),
+            Path::new(r"\\server\share\repo")
+        );
     }
 }
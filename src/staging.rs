@@ -0,0 +1,286 @@
+//! Tracking and garbage collection of the staging directories of the
+//! distribution targets.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::{
+    action_step, ignore_step, prune::unix_timestamp, Context, Error, ErrorContext, Result,
+};
+
+/// The name of the registry file, relative to the target root.
+const STAGING_DIRECTORIES_FILE_NAME: &str = "staging-directories.json";
+
+/// The staging directories created by the distribution targets.
+///
+/// Staging directories accumulate across branches and build modes: tracking
+/// them allows removing the ones that were not used for a while.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StagingDirectories {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    dry_run: bool,
+    directories: BTreeMap<PathBuf, StagingDirectory>,
+}
+
+/// A staging directory of a distribution target.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagingDirectory {
+    pub dist_target: String,
+    /// The last time the directory was used, as a number of seconds since
+    /// the Unix epoch.
+    pub last_used: i64,
+    /// The hash of the package when the artifact was last built completely
+    /// in the directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl StagingDirectories {
+    /// Load the staging directories registry of the workspace.
+    ///
+    /// If the registry does not exist, an empty one is returned.
+    pub fn load(context: &Context) -> Result<Self> {
+        let path = context.target_root()?.join(STAGING_DIRECTORIES_FILE_NAME);
+
+        let mut staging_directories: Self = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to parse staging directories registry",
+                    format!(
+                        "The staging directories registry in `{}` is invalid. You may want to delete it.",
+                        path.display()
+                    ),
+                )?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                debug!(
+                    "No staging directories registry at `{}`: starting afresh",
+                    path.display()
+                );
+
+                Self::default()
+            }
+            Err(err) => {
+                return Err(Error::new("failed to read staging directories registry")
+                    .with_source(err)
+                    .with_explanation(format!(
+                        "The staging directories registry could not be read from `{}`. You may want to verify permissions.",
+                        path.display()
+                    )));
+            }
+        };
+
+        staging_directories.path = path;
+        staging_directories.dry_run = context.options().dry_run;
+
+        Ok(staging_directories)
+    }
+
+    /// Check whether the artifact staged in a directory was built completely
+    /// from the specified package hash and still exists.
+    pub(crate) fn is_up_to_date(&self, directory: &Path, hash: &str) -> bool {
+        self.directories
+            .get(directory)
+            .map_or(false, |staging_directory| {
+                staging_directory.hash.as_deref() == Some(hash)
+            })
+            && directory.exists()
+    }
+
+    /// Record the use of the staging directory of a distribution target.
+    ///
+    /// `hash` is the hash of the package if the artifact was built
+    /// completely.
+    pub(crate) fn touch(
+        &mut self,
+        dist_target: String,
+        directory: PathBuf,
+        hash: Option<String>,
+    ) -> Result<()> {
+        self.directories.insert(
+            directory,
+            StagingDirectory {
+                dist_target,
+                last_used: unix_timestamp(),
+                hash,
+            },
+        );
+
+        self.save()
+    }
+
+    /// Print the space used by each tracked staging directory.
+    pub fn report(&self) -> Result<()> {
+        let mut total_size = 0;
+
+        for (directory, staging_directory) in &self.directories {
+            if !directory.exists() {
+                continue;
+            }
+
+            let size = directory_size(directory)?;
+            total_size += size;
+
+            println!(
+                "{}\t{}\t{} day(s) ago\t{}",
+                staging_directory.dist_target,
+                format_size(size),
+                (unix_timestamp() - staging_directory.last_used) / (24 * 3600),
+                directory.display(),
+            );
+        }
+
+        println!("total\t{}", format_size(total_size));
+
+        Ok(())
+    }
+
+    /// Remove the staging directories that were not used since `max_age`.
+    ///
+    /// If `--dry-run` was specified, the directories are only listed.
+    pub fn collect_garbage(&mut self, max_age: Duration) -> Result<()> {
+        let max_age = i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX);
+        let now = unix_timestamp();
+
+        let stale_directories: Vec<_> = self
+            .directories
+            .iter()
+            .filter(|(_, staging_directory)| {
+                now.saturating_sub(staging_directory.last_used) > max_age
+            })
+            .map(|(directory, staging_directory)| {
+                (directory.clone(), staging_directory.dist_target.clone())
+            })
+            .collect();
+
+        if stale_directories.is_empty() {
+            ignore_step!(
+                "Skipping",
+                "garbage collection as no staging directory is stale"
+            );
+
+            return Ok(());
+        }
+
+        for (directory, dist_target) in stale_directories {
+            if self.dry_run {
+                warn!(
+                    "Would now remove the staging directory `{}` of distribution {} but `--dry-run` was specified",
+                    directory.display(),
+                    dist_target
+                );
+
+                continue;
+            }
+
+            action_step!(
+                "Removing",
+                "staging directory `{}` of distribution {}",
+                directory.display(),
+                dist_target
+            );
+
+            match std::fs::remove_dir_all(&directory) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(Error::new("failed to remove staging directory")
+                        .with_source(err)
+                        .with_explanation(format!(
+                            "The directory `{}` could not be removed. You may want to verify permissions.",
+                            directory.display()
+                        )));
+                }
+            }
+
+            self.directories.remove(&directory);
+        }
+
+        self.save()
+    }
+
+    /// Write the registry to disk, unless `--dry-run` was specified.
+    fn save(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let data = serde_json::to_string_pretty(self)
+            .map_err(Error::from_source)
+            .with_context("failed to serialize staging directories registry")?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(Error::from_source)
+                .with_full_context(
+                    "failed to create staging directories registry directory",
+                    format!(
+                        "The directory `{}` could not be created. You may want to verify permissions.",
+                        parent.display()
+                    ),
+                )?;
+        }
+
+        std::fs::write(&self.path, data)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to write staging directories registry",
+                format!(
+                    "The staging directories registry could not be written to `{}`. You may want to verify permissions.",
+                    self.path.display()
+                ),
+            )
+    }
+}
+
+pub(crate) fn directory_size(directory: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in WalkDir::new(directory) {
+        let entry = entry.map_err(Error::from_source).with_full_context(
+            "failed to walk staging directory",
+            format!(
+                "The directory `{}` could not be read. You may want to verify permissions.",
+                directory.display()
+            ),
+        )?;
+
+        if entry.file_type().is_file() {
+            size += entry
+                .metadata()
+                .map_err(Error::from_source)
+                .with_context("failed to read file metadata")?
+                .len();
+        }
+    }
+
+    Ok(size)
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub(crate) fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,100 @@
+//! Tracing of the build and publication phases, optionally exported to an
+//! OpenTelemetry collector.
+
+#[cfg(feature = "otlp")]
+use crate::ErrorKind;
+use crate::{Error, Result};
+
+/// Create a span for a phase of the build or publication, with the fields
+/// `in_span` records its failure in.
+macro_rules! phase_span {
+    ($name:expr, $($field:tt)*) => {
+        tracing::info_span!(
+            $name,
+            otel.status_code = tracing::field::Empty,
+            error = tracing::field::Empty,
+            $($field)*
+        )
+    };
+}
+
+pub(crate) use phase_span;
+
+/// Run a phase of the build or publication in the specified span, created
+/// with `phase_span!`, marking the span as failed if the phase fails.
+pub(crate) fn in_span<T>(span: &tracing::Span, phase: impl FnOnce() -> Result<T>) -> Result<T> {
+    let result = span.in_scope(phase);
+
+    if let Err(err) = &result {
+        span.record("otel.status_code", &"ERROR");
+        span.record("error", &describe_error(err).as_str());
+    }
+
+    result
+}
+
+fn describe_error(err: &Error) -> String {
+    match err.source() {
+        Some(source) => format!("{}: {}", err.description(), source),
+        None => err.description().to_string(),
+    }
+}
+
+/// The export of the spans of the run to an OpenTelemetry collector, which
+/// ends when it is dropped.
+#[cfg(feature = "otlp")]
+pub struct Telemetry {
+    // The batches of spans are exported in the background by this runtime.
+    _runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "otlp")]
+impl Telemetry {
+    /// Export the spans of the run to the OpenTelemetry collector listening
+    /// at the specified OTLP endpoint.
+    pub fn init(endpoint: &str) -> Result<Self> {
+        use opentelemetry_otlp::WithExportConfig;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let runtime = tokio::runtime::Runtime::new()
+            .map_err(|err| Error::new("failed to create async runtime").with_source(err))?;
+        let _guard = runtime.enter();
+
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(opentelemetry::sdk::trace::config().with_resource(
+                opentelemetry::sdk::Resource::new(vec![opentelemetry::KeyValue::new(
+                    "service.name",
+                    "cargo-monorepo",
+                )]),
+            ))
+            .install_batch(opentelemetry::runtime::Tokio)
+            .map_err(|err| {
+                Error::new("failed to set up OpenTelemetry exporter")
+                    .with_kind(ErrorKind::Configuration)
+                    .with_source(err)
+            })?;
+
+        // Logs still go through `env_logger`: only spans are exported.
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        tracing::subscriber::set_global_default(subscriber)
+            .map_err(|err| Error::new("failed to set up tracing").with_source(err))?;
+
+        Ok(Self { _runtime: runtime })
+    }
+}
+
+#[cfg(feature = "otlp")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        // Flushes the spans that were not exported yet.
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
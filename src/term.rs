@@ -1,6 +1,8 @@
 use std::{fmt::Display, io::Write};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+use crate::Error;
+
 pub(crate) const ACTION_STEP_COLOR: Color = Color::Green;
 pub(crate) const IGNORE_STEP_COLOR: Color = Color::Yellow;
 
@@ -55,3 +57,76 @@ macro_rules! ignore_step {
         ignore_step!($action, format!($fmt, $($arg)*))
     };
 }
+
+/// Whether the tool runs in a GitHub Actions workflow, in which case it emits
+/// workflow commands so that errors and steps stand out in the logs.
+pub fn is_github_actions() -> bool {
+    std::env::var("GITHUB_ACTIONS").map_or(false, |value| value == "true")
+}
+
+/// A group of log lines, folded in the logs of GitHub Actions until it is
+/// dropped.
+///
+/// GitHub Actions does not support nested groups.
+pub(crate) struct LogGroup {
+    active: bool,
+}
+
+impl LogGroup {
+    pub(crate) fn start(title: impl Display) -> Self {
+        let active = is_github_actions();
+
+        if active {
+            println!("::group::{}", escape_workflow_data(&title.to_string()));
+        }
+
+        Self { active }
+    }
+}
+
+impl Drop for LogGroup {
+    fn drop(&mut self) {
+        if self.active {
+            println!("::endgroup::");
+        }
+    }
+}
+
+/// Print an error as a GitHub Actions annotation, which shows inline in the
+/// checks of pull requests.
+pub fn print_github_error(err: &Error) {
+    let mut message = err.description().to_string();
+
+    if let Some(source) = err.source() {
+        message = format!("{}: {}", message, source);
+    }
+
+    if let Some(explanation) = err.explanation() {
+        message = format!("{}\n\n{}", message, explanation);
+    }
+
+    let title = match err.code() {
+        Some(code) => format!("cargo monorepo ({} error {})", err.kind(), code),
+        None => format!("cargo monorepo ({} error)", err.kind()),
+    };
+
+    println!(
+        "::error title={}::{}",
+        escape_workflow_property(&title),
+        escape_workflow_data(&message)
+    );
+}
+
+/// Escape the message of a workflow command.
+fn escape_workflow_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape the value of a property of a workflow command.
+fn escape_workflow_property(property: &str) -> String {
+    escape_workflow_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
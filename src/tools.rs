@@ -0,0 +1,157 @@
+//! Probing of the external tools required by distribution targets.
+
+use std::collections::BTreeSet;
+
+use crate::{rust::check_target_runtime, Error, ErrorCode, ErrorKind, Package, Result};
+
+/// An external tool required by some distribution targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Tool {
+    /// The program looked up in the `PATH`.
+    pub program: &'static str,
+    /// How to install the tool.
+    pub hint: &'static str,
+}
+
+pub(crate) const DOCKER: Tool = Tool {
+    program: "docker",
+    hint: "Install Docker from https://docs.docker.com/get-docker/.",
+};
+
+pub(crate) const NPM: Tool = Tool {
+    program: "npm",
+    hint: "Install Node.js, which includes npm, from https://nodejs.org/.",
+};
+
+pub(crate) const WASM_PACK: Tool = Tool {
+    program: "wasm-pack",
+    hint: "Run `cargo install wasm-pack`.",
+};
+
+pub(crate) const WASM_OPT: Tool = Tool {
+    program: "wasm-opt",
+    hint:
+        "Install Binaryen, which includes wasm-opt, from https://github.com/WebAssembly/binaryen.",
+};
+
+pub(crate) const WASM_TO_OCI: Tool = Tool {
+    program: "wasm-to-oci",
+    hint: "Install wasm-to-oci from https://github.com/engineerd/wasm-to-oci.",
+};
+
+pub(crate) const CANDLE: Tool = Tool {
+    program: "candle",
+    hint: "Install the WiX Toolset from https://wixtoolset.org/ and add its `bin` directory to the `PATH`.",
+};
+
+pub(crate) const LIGHT: Tool = Tool {
+    program: "light",
+    hint: "Install the WiX Toolset from https://wixtoolset.org/ and add its `bin` directory to the `PATH`.",
+};
+
+pub(crate) const SIGNTOOL: Tool = Tool {
+    program: "signtool",
+    hint: "Install the Windows SDK, which includes signtool, and add its `bin` directory to the `PATH`.",
+};
+
+pub(crate) const CODESIGN: Tool = Tool {
+    program: "codesign",
+    hint: "Install the Xcode command line tools with `xcode-select --install`.",
+};
+
+pub(crate) const XCRUN: Tool = Tool {
+    program: "xcrun",
+    hint: "Install the Xcode command line tools with `xcode-select --install`.",
+};
+
+pub(crate) const DITTO: Tool = Tool {
+    program: "ditto",
+    hint: "Install the Xcode command line tools with `xcode-select --install`.",
+};
+
+/// Check that the prerequisites of the distribution targets of the specified
+/// packages are available before building or publishing them.
+///
+/// The prerequisites are the external tools the distribution targets run
+/// and, when building with compilation, their installed target runtimes.
+/// Distribution targets that are not supported on the current host are
+/// ignored.
+///
+/// All the missing prerequisites are reported together.
+pub fn check_prerequisites(packages: &[Package<'_>], publish: bool) -> Result<()> {
+    let mut tools = BTreeSet::new();
+    let mut target_runtimes = BTreeSet::new();
+
+    for package in packages {
+        let compile = !publish && !package.context().options().no_compile;
+
+        for dist_target in package.dist_targets() {
+            if !dist_target.is_supported() {
+                continue;
+            }
+
+            tools.extend(dist_target.required_tools(publish));
+
+            if compile {
+                if let Some(target_runtime) = dist_target.host_target_runtime() {
+                    target_runtimes.insert(target_runtime.to_string());
+                }
+            }
+        }
+    }
+
+    let mut missing = Vec::new();
+
+    for tool in tools {
+        if !is_installed(tool.program) {
+            missing.push(format!("- `{}`: {}", tool.program, tool.hint));
+        }
+    }
+
+    for target_runtime in target_runtimes {
+        if let Err(err) = check_target_runtime(&target_runtime) {
+            missing.push(format!(
+                "- target runtime `{}`: {}",
+                target_runtime,
+                err.explanation().unwrap_or_else(|| err.description())
+            ));
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::new("missing prerequisites")
+        .with_kind(ErrorKind::Configuration)
+        .with_code(ErrorCode::MissingPrerequisites)
+        .with_explanation(format!(
+            "The selected distribution targets require the following prerequisites which are missing:\n{}",
+            missing.join("\n")
+        )))
+}
+
+/// Whether a program can be found in the `PATH`.
+fn is_installed(program: &str) -> bool {
+    let path = match std::env::var_os("PATH") {
+        Some(path) => path,
+        None => return false,
+    };
+
+    std::env::split_paths(&path).any(|dir| {
+        let candidate = dir.join(program);
+
+        candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_installed() {
+        assert!(is_installed("cargo"));
+        assert!(!is_installed("cargo-monorepo-nonexistent-tool"));
+    }
+}
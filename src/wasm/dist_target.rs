@@ -0,0 +1,471 @@
+use std::{fmt::Display, path::PathBuf, process::Command};
+
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    action_step,
+    aws::{
+        aws_settings, delete_s3_object, list_s3_versions, promote_s3_object, s3_object_exists,
+        AwsSettings, S3ArtifactKeys,
+    },
+    dist_target::{check_channel_name, clean_dir, published_artifact},
+    ignore_step,
+    process::run_command,
+    rust::build_binaries,
+    tools::{self, Tool},
+    Context, Error, ErrorContext, ErrorKind, Package, PublishedArtifact, PublishedVersion, Result,
+};
+
+use super::WasmMetadata;
+
+pub const SUPPORTED_WASM_TARGET_RUNTIMES: &[&str] = &["wasm32-wasi", "wasm32-unknown-unknown"];
+
+/// Where the WASM module gets published.
+enum Destination<'a> {
+    S3 { s3_bucket: &'a str },
+    Oci { repository: &'a str },
+}
+
+pub struct WasmDistTarget<'g> {
+    pub name: String,
+    pub package: &'g Package<'g>,
+    pub metadata: WasmMetadata,
+}
+
+impl Display for WasmDistTarget<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "wasm[{}]", self.package.name())
+    }
+}
+
+impl<'g> WasmDistTarget<'g> {
+    pub fn context(&self) -> &'g Context {
+        self.package.context()
+    }
+
+    pub fn build(&self) -> Result<()> {
+        if !SUPPORTED_WASM_TARGET_RUNTIMES.contains(&self.metadata.target_runtime.as_str()) {
+            return Err(Error::new("unsupported WASM target runtime")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(format!(
+                    "The target runtime `{}` is not supported. Supported target runtimes are: {}.",
+                    self.metadata.target_runtime,
+                    SUPPORTED_WASM_TARGET_RUNTIMES.join(", ")
+                )));
+        }
+
+        clean_dir(&self.target_dir(), "WASM target directory")?;
+
+        let module = self.build_module()?;
+        self.copy_module(&module)?;
+
+        if let Some(wasm_opt_args) = &self.metadata.wasm_opt {
+            self.optimize_module(wasm_opt_args)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn publish(&self) -> Result<Option<PublishedArtifact>> {
+        if self.context().options().mode.is_debug() && !self.context().options().force {
+            ignore_step!(
+                "Unsupported",
+                "WASM modules can't be published in debug mode unless `--force` is specified"
+            );
+            return Ok(None);
+        }
+
+        let reference = self.artifact_reference()?;
+
+        if self.context().options().force {
+            debug!(
+                "`--force` specified: not checking for the WASM module existence before publishing"
+            );
+        } else if self.context().artifact_exists(&reference, self.exists())? {
+            ignore_step!("Up-to-date", "WASM module `{}` already exists", &reference);
+
+            return Ok(Some(published_artifact(
+                self.package,
+                self,
+                reference,
+                None,
+                true,
+            )));
+        }
+
+        let module_path = self.module_path();
+        let data = std::fs::read(&module_path)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to read WASM module on disk",
+                format!(
+                    "The WASM module `{}` could not be read. Has this target been built before attempting its publication?",
+                    module_path.display()
+                ),
+            )?;
+
+        let digest = format!("sha256:{:x}", Sha256::digest(&data));
+
+        if self.context().options().dry_run {
+            warn!("`--dry-run` specified, will not really publish the WASM module");
+
+            return Ok(None);
+        }
+
+        action_step!("Uploading", "WASM module `{}`", &reference);
+
+        match self.destination()? {
+            Destination::S3 { s3_bucket } => {
+                let s3_key = self.s3_key();
+                let metadata = self.package.provenance()?.metadata();
+
+                self.context().block_on(async move {
+                    let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+                    client
+                        .put_object()
+                        .bucket(s3_bucket)
+                        .key(&s3_key)
+                        .content_type("application/wasm")
+                        .set_metadata(Some(metadata.into_iter().collect()))
+                        .body(data.into())
+                        .send()
+                        .await
+                        .map_err(|err| {
+                            Error::new("failed to upload WASM module on S3")
+                                .with_kind(ErrorKind::Network)
+                                .with_source(err)
+                                .with_explanation(format!(
+                                    "Please check that the S3 bucket `{}` exists and that you have the correct permissions.",
+                                    s3_bucket
+                                ))
+                        })?;
+
+                    Ok(())
+                })?;
+            }
+            Destination::Oci { .. } => {
+                self.registry_login()?;
+
+                let mut cmd = Command::new("wasm-to-oci");
+
+                cmd.arg("push");
+                cmd.arg(&module_path);
+                cmd.arg(&reference);
+
+                run_command(
+                    &mut cmd,
+                    self.context().options().verbose,
+                    "failed to push WASM module",
+                )?;
+            }
+        }
+
+        Ok(Some(published_artifact(
+            self.package,
+            self,
+            reference,
+            Some(digest),
+            false,
+        )))
+    }
+
+    /// Check whether the WASM module for the current version exists in its
+    /// destination.
+    ///
+    /// For OCI registries, `wasm-to-oci` offers no way of inspecting a
+    /// reference so the module is pulled instead and any failure to do so is
+    /// considered as the module not existing.
+    pub async fn exists(&self) -> Result<bool> {
+        match self.destination()? {
+            Destination::S3 { s3_bucket } => {
+                let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+                s3_object_exists(&client, s3_bucket, &self.s3_key()).await
+            }
+            Destination::Oci { .. } => {
+                let reference = self.artifact_reference()?;
+
+                tokio::fs::create_dir_all(self.target_dir())
+                    .await
+                    .map_err(Error::from_source)
+                    .with_context("failed to create the WASM target directory")?;
+
+                let output = tokio::process::Command::new("wasm-to-oci")
+                    .arg("pull")
+                    .arg(&reference)
+                    .arg("--out")
+                    .arg(self.target_dir().join("existence-check.wasm"))
+                    .output()
+                    .await
+                    .map_err(Error::from_source)
+                    .with_full_context(
+                        "failed to check for WASM module existence",
+                        "The `wasm-to-oci` command could not be executed. Is it installed?",
+                    )?;
+
+                if !output.status.success() {
+                    debug!(
+                        "Could not pull `{}`, considering it does not exist: {}",
+                        reference,
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+
+                Ok(output.status.success())
+            }
+        }
+    }
+
+    /// List the versions published in the S3 bucket.
+    ///
+    /// Listing the versions published to an OCI registry is not supported.
+    pub async fn published_versions(&self) -> Result<Option<Vec<PublishedVersion>>> {
+        match self.destination()? {
+            Destination::S3 { s3_bucket } => {
+                let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+                let s3_keys = self.s3_keys();
+
+                list_s3_versions(
+                    &client,
+                    s3_bucket,
+                    &s3_keys.versions_prefix(),
+                    &s3_keys.suffix(),
+                )
+                .await
+                .map(Some)
+            }
+            Destination::Oci { .. } => Ok(None),
+        }
+    }
+
+    /// Delete the specified version of the WASM module from the S3 bucket.
+    pub async fn delete_published_version(&self, version: &semver::Version) -> Result<()> {
+        match self.destination()? {
+            Destination::S3 { s3_bucket } => {
+                let client = self.context().aws().s3_client(&self.aws_settings()).await?;
+
+                delete_s3_object(&client, s3_bucket, &self.s3_keys().version_key(version)).await
+            }
+            Destination::Oci { .. } => Err(Error::new("unsupported WASM module deletion")
+                .with_explanation("WASM modules published to OCI registries cannot be deleted.")),
+        }
+    }
+
+    pub fn artifact_reference(&self) -> Result<String> {
+        Ok(match self.destination()? {
+            Destination::S3 { s3_bucket } => format!("s3://{}/{}", s3_bucket, self.s3_key()),
+            Destination::Oci { repository } => {
+                format!("{}:{}", repository, self.package.version())
+            }
+        })
+    }
+
+    /// Promote the current version of the WASM module to the specified
+    /// channel.
+    pub fn promote(&self, channel: &str) -> Result<()> {
+        check_channel_name(channel)?;
+
+        match self.destination()? {
+            Destination::S3 { s3_bucket } => promote_s3_object(
+                self.context(),
+                &self.aws_settings(),
+                s3_bucket,
+                &self.s3_key(),
+                &self.s3_keys().channel_key(channel),
+                "WASM module",
+            ),
+            Destination::Oci { repository } => {
+                let reference = self.artifact_reference()?;
+
+                if self.context().options().dry_run {
+                    warn!(
+                        "`--dry-run` specified, will not really promote `{}` to `{}`",
+                        reference, channel
+                    );
+
+                    return Ok(());
+                }
+
+                action_step!("Promoting", "WASM module `{}` to `{}`", &reference, channel);
+
+                self.registry_login()?;
+
+                let module_path = self.target_dir().join("promote.wasm");
+
+                std::fs::create_dir_all(self.target_dir())
+                    .map_err(Error::from_source)
+                    .with_context("failed to create the WASM target directory")?;
+
+                let mut cmd = Command::new("wasm-to-oci");
+
+                cmd.arg("pull");
+                cmd.arg(&reference);
+                cmd.arg("--out");
+                cmd.arg(&module_path);
+
+                run_command(
+                    &mut cmd,
+                    self.context().options().verbose,
+                    "failed to pull WASM module",
+                )?;
+
+                let mut cmd = Command::new("wasm-to-oci");
+
+                cmd.arg("push");
+                cmd.arg(&module_path);
+                cmd.arg(format!("{}:{}", repository, channel));
+
+                run_command(
+                    &mut cmd,
+                    self.context().options().verbose,
+                    "failed to push WASM module",
+                )
+            }
+        }
+    }
+
+    pub(crate) fn registry_login(&self) -> Result<()> {
+        match self.destination()? {
+            Destination::S3 { .. } => Ok(()),
+            Destination::Oci { repository } => self
+                .context()
+                .registry_login(repository, self.metadata.credential_helper.as_deref()),
+        }
+    }
+
+    fn destination(&self) -> Result<Destination<'_>> {
+        match (&self.metadata.s3_bucket, &self.metadata.oci_repository) {
+            (Some(s3_bucket), None) => Ok(Destination::S3 { s3_bucket }),
+            (None, Some(repository)) => Ok(Destination::Oci { repository }),
+            _ => Err(Error::new("invalid WASM module destination")
+                .with_kind(ErrorKind::Configuration)
+                .with_explanation(
+                    "Exactly one of the fields `s3_bucket` and `oci_repository` must be specified.",
+                )),
+        }
+    }
+
+    fn aws_settings(&self) -> AwsSettings {
+        aws_settings!(self.metadata)
+    }
+
+    fn s3_key(&self) -> String {
+        self.s3_keys().version_key(self.package.version())
+    }
+
+    /// The S3 keys of the module, prefixed with the preview identifier, if
+    /// any.
+    fn s3_keys(&self) -> S3ArtifactKeys {
+        S3ArtifactKeys::new(
+            &self
+                .context()
+                .options()
+                .preview_s3_prefix(&self.metadata.s3_bucket_prefix),
+            self.package.name(),
+            "wasm",
+        )
+    }
+
+    fn build_module(&self) -> Result<PathBuf> {
+        // Both binaries and `cdylib` libraries can result in WASM modules.
+        let mut modules = build_binaries(
+            self.package,
+            &self.name,
+            &self.metadata.target_runtime,
+            true,
+        )?;
+
+        match &self.metadata.module {
+            Some(module) => modules.remove(module).ok_or_else(|| {
+                Error::new("failed to find the specified module in the compiled modules")
+                    .with_explanation(format!("The configuration requires the `{}` module but no such binary or `cdylib` library is declared in the crate. Was the name perhaps mistyped?", module))
+            }),
+            None => {
+                if modules.len() != 1 {
+                    return Err(Error::new("failed to determine the WASM module").with_explanation(format!(
+                        "The crate produces {} WASM modules: the field `module` must be specified to select one.",
+                        modules.len()
+                    )));
+                }
+
+                Ok(modules.into_values().next().unwrap())
+            }
+        }
+    }
+
+    fn copy_module(&self, source: &std::path::Path) -> Result<()> {
+        let module_path = self.module_path();
+
+        std::fs::create_dir_all(self.target_dir())
+            .map_err(Error::from_source)
+            .with_full_context(
+                "could not create the WASM target directory",
+                format!("The build process needed to create `{}` but it could not. You may want to verify permissions.", self.target_dir().display()),
+            )?;
+
+        debug!("Copying {} to {}", source.display(), module_path.display());
+
+        std::fs::copy(source, &module_path)
+            .map_err(Error::from_source)
+            .with_full_context(
+                "failed to copy WASM module",
+                format!(
+                    "The WASM module `{}` could not be copied.",
+                    source.display(),
+                ),
+            )?;
+
+        Ok(())
+    }
+
+    fn optimize_module(&self, wasm_opt_args: &[String]) -> Result<()> {
+        let module_path = self.module_path();
+        let mut cmd = Command::new("wasm-opt");
+
+        cmd.args(wasm_opt_args);
+        cmd.arg(&module_path);
+        cmd.arg("-o");
+        cmd.arg(&module_path);
+
+        action_step!("Running", "`wasm-opt {}`", wasm_opt_args.join(" "));
+
+        run_command(
+            &mut cmd,
+            self.context().options().verbose,
+            "failed to optimize WASM module",
+        )
+    }
+
+    /// The external tools required to build or publish the module.
+    pub(crate) fn required_tools(&self, publish: bool) -> Vec<Tool> {
+        if publish && self.metadata.oci_repository.is_some() {
+            vec![tools::WASM_TO_OCI]
+        } else if !publish && self.metadata.wasm_opt.is_some() {
+            vec![tools::WASM_OPT]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The directory in which the artifact is staged during the build.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.target_dir()
+    }
+
+    fn target_dir(&self) -> PathBuf {
+        self.context()
+            .target_root()
+            .unwrap()
+            .join(&self.metadata.target_runtime)
+            .join(self.context().options().mode.to_string())
+            .join("wasm")
+            .join(self.package.name())
+    }
+
+    fn module_path(&self) -> PathBuf {
+        self.target_dir()
+            .join(format!("{}.wasm", self.package.name()))
+    }
+}
@@ -0,0 +1,62 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aws::AwsCredentialsEnv, condition::Enabled, dist_target::DistTarget, wasm::WasmDistTarget,
+    Package,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WasmMetadata {
+    #[serde(default = "default_target_runtime")]
+    pub target_runtime: String,
+    #[serde(default)]
+    pub module: Option<String>,
+    #[serde(default)]
+    pub wasm_opt: Option<Vec<String>>,
+    #[serde(default)]
+    pub s3_bucket: Option<String>,
+    #[serde(default)]
+    pub s3_bucket_prefix: String,
+    #[serde(default)]
+    pub oci_repository: Option<String>,
+    #[serde(default)]
+    pub credential_helper: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub aws_endpoint_url: Option<String>,
+    #[serde(default)]
+    pub aws_profile: Option<String>,
+    #[serde(default)]
+    pub assume_role_arn: Option<String>,
+    #[serde(default)]
+    pub assume_role_external_id: Option<String>,
+    #[serde(default)]
+    pub credentials_env: Option<AwsCredentialsEnv>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_command: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Enabled::is_default")]
+    pub enabled: Enabled,
+}
+
+fn default_target_runtime() -> String {
+    "wasm32-wasi".to_string()
+}
+
+impl WasmMetadata {
+    pub(crate) fn into_dist_target<'g>(
+        self,
+        name: String,
+        package: &'g Package<'g>,
+    ) -> DistTarget<'g> {
+        DistTarget::Wasm(WasmDistTarget {
+            name,
+            package,
+            metadata: self,
+        })
+    }
+}
@@ -0,0 +1,279 @@
+//! Rebuild distribution targets whenever the sources of their package change.
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+
+use crate::{
+    action_step, dist_target::DistTarget, ignore_step, sources::comparable_path, Error,
+    ErrorContext, Package, Result,
+};
+
+/// The delay during which file system events are aggregated before
+/// triggering a rebuild.
+const WATCH_DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/// Watch the sources of a package and rebuild its distribution targets -
+/// without publishing them - whenever they change.
+///
+/// If `target_type` is specified, only the distribution targets of that type
+/// are rebuilt. If `restart_container` is specified, the Docker container with
+/// that name is replaced after each successful rebuild of a Docker target.
+///
+/// This function only returns in case of a watch error: build failures are
+/// reported and the watch resumes.
+pub fn watch(
+    package: &Package<'_>,
+    target_type: Option<&str>,
+    restart_container: Option<&str>,
+) -> Result<()> {
+    if selected_dist_targets(package, target_type).is_empty() {
+        return Err(
+            Error::new("no distribution target to watch").with_explanation(format!(
+                "The package `{}` has no distribution target{}.",
+                package.name(),
+                target_type.map_or_else(String::new, |t| format!(" of type `{}`", t)),
+            )),
+        );
+    }
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx, WATCH_DEBOUNCE_DELAY)
+        .map_err(Error::from_source)
+        .with_context("failed to create file system watcher")?;
+
+    watcher
+        .watch(package.root(), RecursiveMode::Recursive)
+        .map_err(Error::from_source)
+        .with_full_context(
+            "failed to watch package sources",
+            format!(
+                "The directory `{}` could not be watched. You may want to verify permissions.",
+                package.root().display()
+            ),
+        )?;
+
+    let manifest_path = comparable_path(&package.root().join("Cargo.toml"));
+    let mut sources = package.sources()?.clone();
+
+    rebuild(
+        &selected_dist_targets(package, target_type),
+        restart_container,
+    );
+
+    loop {
+        action_step!("Watching", "sources of `{}` for changes", package.name());
+
+        let changed_paths = wait_for_changes(&rx)?;
+
+        if !changed_paths
+            .iter()
+            .any(|path| comparable_path(path) == manifest_path || sources.contains(path))
+        {
+            debug!(
+                "Ignoring changes to files that are not sources: {:?}",
+                changed_paths
+            );
+
+            continue;
+        }
+
+        // The sources of a package are computed once per instance, and its
+        // hash from them: files may have been added or removed since the last
+        // build.
+        let package = Package::new(package.context(), *package.package_metadata())?;
+        sources = package.sources()?.clone();
+
+        rebuild(
+            &selected_dist_targets(&package, target_type),
+            restart_container,
+        );
+    }
+}
+
+fn selected_dist_targets<'a>(
+    package: &'a Package<'_>,
+    target_type: Option<&str>,
+) -> Vec<DistTarget<'a>> {
+    package
+        .dist_targets()
+        .into_iter()
+        .filter(|dist_target| target_type.map_or(true, |t| dist_target.target_type() == t))
+        .collect()
+}
+
+fn rebuild(dist_targets: &[DistTarget<'_>], restart_container: Option<&str>) {
+    for dist_target in dist_targets {
+        action_step!("Building", "distribution {}", dist_target);
+        let before = std::time::Instant::now();
+
+        let result = dist_target
+            .build()
+            .and_then(|()| match (dist_target, restart_container) {
+                (DistTarget::Docker(docker), Some(container_name)) => {
+                    action_step!("Restarting", "Docker container `{}`", container_name);
+                    docker.restart_container(container_name)
+                }
+                _ => Ok(()),
+            });
+
+        match result {
+            Ok(()) => {
+                let duration = before.elapsed();
+                action_step!("Finished", "distribution in {:.2}s", duration.as_secs_f64());
+            }
+            Err(err) => {
+                ignore_step!(
+                    "Failed",
+                    "distribution {}: {}",
+                    dist_target,
+                    err.description()
+                );
+
+                if let Some(explanation) = err.explanation() {
+                    warn!("{}", explanation);
+                }
+            }
+        }
+    }
+}
+
+/// Wait for file system changes and return the changed paths.
+fn wait_for_changes(rx: &Receiver<DebouncedEvent>) -> Result<Vec<PathBuf>> {
+    let mut changed_paths = Vec::new();
+    let mut event = rx
+        .recv()
+        .map_err(Error::from_source)
+        .with_context("file system watcher stopped unexpectedly")?;
+
+    loop {
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path) => changed_paths.push(path),
+            DebouncedEvent::Rename(from, to) => {
+                changed_paths.push(from);
+                changed_paths.push(to);
+            }
+            DebouncedEvent::Error(err, path) => {
+                return Err(Error::from_source(err)).with_full_context(
+                    "file system watcher failed",
+                    match path {
+                        Some(path) => {
+                            format!("An error occurred while watching `{}`.", path.display())
+                        }
+                        None => "An error occurred while watching the package sources.".to_string(),
+                    },
+                );
+            }
+            DebouncedEvent::NoticeWrite(_)
+            | DebouncedEvent::NoticeRemove(_)
+            | DebouncedEvent::Chmod(_)
+            | DebouncedEvent::Rescan => {}
+        }
+
+        // Aggregate all the pending events to rebuild only once.
+        match rx.try_recv() {
+            Ok(next_event) => event = next_event,
+            Err(_) => return Ok(changed_paths),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::Sender;
+
+    use super::*;
+
+    fn send_all(tx: &Sender<DebouncedEvent>, events: Vec<DebouncedEvent>) {
+        for event in events {
+            tx.send(event).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_wait_for_changes_aggregates_pending_events() {
+        let (tx, rx) = channel();
+
+        send_all(
+            &tx,
+            vec![
+                DebouncedEvent::NoticeWrite(PathBuf::from("src/lib.rs")),
+                DebouncedEvent::Write(PathBuf::from("src/lib.rs")),
+                DebouncedEvent::Chmod(PathBuf::from("build.rs")),
+                DebouncedEvent::Create(PathBuf::from("src/new.rs")),
+                DebouncedEvent::Rename(
+                    PathBuf::from("src/old.rs"),
+                    PathBuf::from("src/renamed.rs"),
+                ),
+                DebouncedEvent::Remove(PathBuf::from("src/removed.rs")),
+                DebouncedEvent::Rescan,
+            ],
+        );
+
+        assert_eq!(
+            wait_for_changes(&rx).unwrap(),
+            vec![
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/new.rs"),
+                PathBuf::from("src/old.rs"),
+                PathBuf::from("src/renamed.rs"),
+                PathBuf::from("src/removed.rs"),
+            ]
+        );
+
+        // The events sent after the aggregation are part of the next one.
+        send_all(
+            &tx,
+            vec![DebouncedEvent::Write(PathBuf::from("Cargo.toml"))],
+        );
+
+        assert_eq!(
+            wait_for_changes(&rx).unwrap(),
+            vec![PathBuf::from("Cargo.toml")]
+        );
+    }
+
+    #[test]
+    fn test_wait_for_changes_ignores_metadata_only_events() {
+        let (tx, rx) = channel();
+
+        send_all(
+            &tx,
+            vec![
+                DebouncedEvent::Chmod(PathBuf::from("src/lib.rs")),
+                DebouncedEvent::NoticeRemove(PathBuf::from("src/lib.rs")),
+            ],
+        );
+
+        assert!(wait_for_changes(&rx).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wait_for_changes_fails_on_watch_errors() {
+        let (tx, rx) = channel();
+
+        send_all(
+            &tx,
+            vec![
+                DebouncedEvent::Write(PathBuf::from("src/lib.rs")),
+                DebouncedEvent::Error(
+                    notify::Error::Generic("inotify limit reached".to_string()),
+                    Some(PathBuf::from("src")),
+                ),
+            ],
+        );
+
+        assert!(wait_for_changes(&rx).is_err());
+
+        drop(tx);
+
+        assert!(wait_for_changes(&rx).is_err());
+    }
+}